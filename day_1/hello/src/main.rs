@@ -7,7 +7,7 @@ fn main() {
 
     let n = 5;
     let mut m = n;
-    m = m + 2;
+    m += 2;
 
     println!("Sum: {}", sum::sum(n, m));
     println!("Add: {}", util::add::add(n, m));
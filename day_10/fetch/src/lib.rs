@@ -0,0 +1,365 @@
+//! Day 10: Async với tokio qua việc tải nhiều URL cùng lúc
+//!
+//! Mỗi `fetch` phần lớn thời gian là chờ mạng. Chạy tuần tự thì tổng thời
+//! gian là tổng các lần chờ; chạy đồng thời (concurrently) thì gần bằng
+//! lần chờ lâu nhất. Các service trong material/dependency_inversion đều
+//! là `async fn` vì lý do này.
+
+use std::time::Duration;
+
+use reqwest::Client;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub url: String,
+    pub status: u16,
+    pub bytes: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("{url}: timed out after {timeout:?}")]
+    Timeout { url: String, timeout: Duration },
+    #[error("{url}: {source}")]
+    Http {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    // Task panic hoặc bị hủy, xem `fetch_all`
+    #[error("{url}: task failed: {source}")]
+    Task {
+        url: String,
+        #[source]
+        source: tokio::task::JoinError,
+    },
+}
+
+// ===================================================================
+// 1. Một request, có timeout
+// ===================================================================
+
+/// `async fn` không chạy gì cả cho tới khi được `.await`: gọi nó chỉ tạo
+/// ra một Future.
+///
+/// `time::timeout` bọc Future khác: hết giờ mà chưa xong thì trả về `Err`
+/// và Future bên trong bị drop, tức là request bị hủy.
+pub async fn fetch(client: &Client, url: &str, timeout: Duration) -> Result<Page, FetchError> {
+    let request = async {
+        let response = client.get(url).send().await?;
+        let status = response.status().as_u16();
+        let body = response.bytes().await?;
+        Ok(Page {
+            url: url.to_string(),
+            status,
+            bytes: body.len(),
+        })
+    };
+
+    match time::timeout(timeout, request).await {
+        Ok(result) => result.map_err(|source| FetchError::Http {
+            url: url.to_string(),
+            source,
+        }),
+        Err(_elapsed) => Err(FetchError::Timeout {
+            url: url.to_string(),
+            timeout,
+        }),
+    }
+}
+
+// ===================================================================
+// 2. Tuần tự vs đồng thời
+// ===================================================================
+
+/// `.await` trong vòng lặp: request sau chỉ bắt đầu khi request trước xong
+pub async fn fetch_sequential(
+    client: &Client,
+    urls: &[&str],
+    timeout: Duration,
+) -> Vec<Result<Page, FetchError>> {
+    let mut pages = Vec::new();
+    for url in urls {
+        pages.push(fetch(client, url, timeout).await);
+    }
+    pages
+}
+
+/// `tokio::spawn`: mỗi URL là một task chạy độc lập trên runtime, giống
+/// `thread::spawn` ở day 9 nhưng rẻ hơn nhiều (hàng nghìn task là bình
+/// thường).
+///
+/// Task phải `'static`: không mượn `client` hay `urls` được, nên clone
+/// vào (`Client` bên trong là `Arc`, clone rẻ). Kết quả trả về theo thứ
+/// tự input.
+pub async fn fetch_all(
+    client: &Client,
+    urls: &[&str],
+    timeout: Duration,
+) -> Vec<Result<Page, FetchError>> {
+    let handles: Vec<(String, JoinHandle<Result<Page, FetchError>>)> = urls
+        .iter()
+        .map(|url| {
+            let client = client.clone();
+            let url = url.to_string();
+            let task_url = url.clone();
+            let handle = tokio::spawn(async move { fetch(&client, &task_url, timeout).await });
+            (url, handle)
+        })
+        .collect();
+
+    // Mọi task đã chạy rồi; await từng cái chỉ là chờ lấy kết quả
+    let mut pages = Vec::new();
+    for (url, handle) in handles {
+        pages.push(match handle.await {
+            Ok(result) => result,
+            Err(source) => Err(FetchError::Task { url, source }),
+        });
+    }
+    pages
+}
+
+/// `join!`: chờ nhiều Future cùng lúc ngay trong task hiện tại, không
+/// spawn. Số Future cố định lúc compile, mỗi cái một kiểu riêng cũng được.
+pub async fn fetch_pair(
+    client: &Client,
+    first: &str,
+    second: &str,
+    timeout: Duration,
+) -> (Result<Page, FetchError>, Result<Page, FetchError>) {
+    tokio::join!(
+        fetch(client, first, timeout),
+        fetch(client, second, timeout)
+    )
+}
+
+// ===================================================================
+// 3. select!: lấy cái xong trước
+// ===================================================================
+
+/// Hỏi cả server chính và mirror, dùng câu trả lời tới trước. Nhánh còn
+/// lại bị drop, tức là request của nó bị hủy.
+///
+/// Cái tới trước mà lỗi thì vẫn chờ cái còn lại: `select!` chọn theo
+/// "xong trước", không theo "thành công".
+pub async fn fetch_fastest(
+    client: &Client,
+    primary: &str,
+    mirror: &str,
+    timeout: Duration,
+) -> Result<Page, FetchError> {
+    let primary_request = fetch(client, primary, timeout);
+    let mirror_request = fetch(client, mirror, timeout);
+    // select! cần poll lại Future ở vòng sau: pin để nó không bị move
+    tokio::pin!(primary_request, mirror_request);
+
+    tokio::select! {
+        result = &mut primary_request => match result {
+            Ok(page) => Ok(page),
+            Err(_) => mirror_request.await,
+        },
+        result = &mut mirror_request => match result {
+            Ok(page) => Ok(page),
+            Err(_) => primary_request.await,
+        },
+    }
+}
+
+/// Tải cho tới khi `stop` được gọi: kiểu vòng lặp của `BackgroundTask`
+/// trong material, `select!` giữa việc chính và tín hiệu dừng
+pub async fn poll_until_stopped(
+    client: &Client,
+    url: &str,
+    every: Duration,
+    stop: tokio::sync::oneshot::Receiver<()>,
+) -> usize {
+    let mut polls = 0;
+    let mut interval = time::interval(every);
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if fetch(client, url, every).await.is_ok() {
+                    polls += 1;
+                }
+            }
+            _ = &mut stop => break,
+        }
+    }
+    polls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use wiremock::matchers::path;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const DELAY: Duration = Duration::from_millis(200);
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Server giả trên localhost: /slow chờ DELAY, /fast trả lời ngay,
+    // /missing trả 404
+    async fn server() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(path("/slow"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("slow")
+                    .set_delay(DELAY),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(path("/fast"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("fast!"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn url(server: &MockServer, path: &str) -> String {
+        format!("{}{}", server.uri(), path)
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let server = server().await;
+        let page = fetch(&Client::new(), &url(&server, "/fast"), TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(page.status, 200);
+        assert_eq!(page.bytes, 5);
+
+        // 404 vẫn là một response, không phải lỗi
+        let page = fetch(&Client::new(), &url(&server, "/missing"), TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(page.status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_cancels_the_request() {
+        let server = server().await;
+        let started = Instant::now();
+        let error = fetch(&Client::new(), &url(&server, "/slow"), DELAY / 4)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, FetchError::Timeout { .. }));
+        assert!(started.elapsed() < DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_http_errors() {
+        // Port 9 (discard) trên localhost: không có ai nghe
+        let error = fetch(&Client::new(), "http://127.0.0.1:9/", TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, FetchError::Http { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_spawned_fetches_overlap() {
+        let server = server().await;
+        let slow = url(&server, "/slow");
+        let urls = [slow.as_str(); 4];
+        let client = Client::new();
+
+        let started = Instant::now();
+        let pages = fetch_sequential(&client, &urls, TIMEOUT).await;
+        let sequential = started.elapsed();
+        assert!(pages.iter().all(Result::is_ok));
+        assert!(sequential >= DELAY * 4);
+
+        let started = Instant::now();
+        let pages = fetch_all(&client, &urls, TIMEOUT).await;
+        let concurrent = started.elapsed();
+        assert!(pages.iter().all(Result::is_ok));
+        // Bốn lần chờ chồng lên nhau: gần một DELAY, xa bốn DELAY
+        assert!(concurrent < DELAY * 2, "{:?}", concurrent);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_keeps_order_and_errors() {
+        let server = server().await;
+        let slow = url(&server, "/slow");
+        let fast = url(&server, "/fast");
+        let pages = fetch_all(
+            &Client::new(),
+            &[&slow, "http://127.0.0.1:9/", &fast],
+            TIMEOUT,
+        )
+        .await;
+        assert_eq!(pages[0].as_ref().unwrap().bytes, 4);
+        assert!(pages[1].is_err());
+        assert_eq!(pages[2].as_ref().unwrap().bytes, 5);
+    }
+
+    #[tokio::test]
+    async fn test_join_waits_for_both() {
+        let server = server().await;
+        let started = Instant::now();
+        let (slow, fast) = fetch_pair(
+            &Client::new(),
+            &url(&server, "/slow"),
+            &url(&server, "/fast"),
+            TIMEOUT,
+        )
+        .await;
+        assert_eq!(slow.unwrap().bytes, 4);
+        assert_eq!(fast.unwrap().bytes, 5);
+        assert!(started.elapsed() >= DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_select_takes_the_first_answer() {
+        let server = server().await;
+        let started = Instant::now();
+        let page = fetch_fastest(
+            &Client::new(),
+            &url(&server, "/slow"),
+            &url(&server, "/fast"),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.url, url(&server, "/fast"));
+        assert!(started.elapsed() < DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_select_falls_back_when_the_first_answer_fails() {
+        let server = server().await;
+        let page = fetch_fastest(
+            &Client::new(),
+            "http://127.0.0.1:9/",
+            &url(&server, "/slow"),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.url, url(&server, "/slow"));
+    }
+
+    #[tokio::test]
+    async fn test_poll_until_stopped() {
+        let server = server().await;
+        let (stop, stopped) = tokio::sync::oneshot::channel();
+        let client = Client::new();
+        let fast = url(&server, "/fast");
+        let poller = tokio::spawn(async move {
+            poll_until_stopped(&client, &fast, Duration::from_millis(20), stopped).await
+        });
+
+        time::sleep(Duration::from_millis(110)).await;
+        stop.send(()).unwrap();
+        let polls = poller.await.unwrap();
+        assert!(polls >= 2, "{}", polls);
+    }
+}
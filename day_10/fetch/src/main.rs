@@ -0,0 +1,39 @@
+use std::time::{Duration, Instant};
+
+use day_10_fetch::{fetch_all, fetch_fastest, fetch_sequential};
+
+// `#[tokio::main]`: tạo runtime rồi chạy `async fn main` trên đó
+#[tokio::main]
+async fn main() {
+    let mut urls: Vec<String> = std::env::args().skip(1).collect();
+    if urls.is_empty() {
+        urls = vec![
+            "https://www.rust-lang.org".to_string(),
+            "https://crates.io".to_string(),
+            "https://docs.rs".to_string(),
+        ];
+    }
+    let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+    let client = reqwest::Client::new();
+    let timeout = Duration::from_secs(5);
+
+    let started = Instant::now();
+    fetch_sequential(&client, &urls, timeout).await;
+    println!("sequential: {:?}", started.elapsed());
+
+    let started = Instant::now();
+    for result in fetch_all(&client, &urls, timeout).await {
+        match result {
+            Ok(page) => println!("  {} {} ({} bytes)", page.status, page.url, page.bytes),
+            Err(error) => println!("  error: {}", error),
+        }
+    }
+    println!("spawned: {:?}", started.elapsed());
+
+    if let [primary, mirror, ..] = urls[..] {
+        match fetch_fastest(&client, primary, mirror, timeout).await {
+            Ok(page) => println!("fastest of the first two: {}", page.url),
+            Err(error) => println!("both failed: {}", error),
+        }
+    }
+}
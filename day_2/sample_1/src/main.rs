@@ -1,6 +1,6 @@
 fn add_number(mut n: i32) -> i32 {
     // mut: mutuable
-    n = n + 10;
+    n += 10;
     n
 }
 
@@ -0,0 +1,221 @@
+//! Day 3: Lifetimes
+//!
+//! Reference phải luôn trỏ tới value còn sống (valid). Lifetime là cách
+//! compiler (borrow checker) kiểm tra điều đó lúc compile, không tốn gì
+//! lúc chạy.
+//!
+//! Các doctest ` ```compile_fail ` trong file này là những chương trình bị
+//! borrow checker từ chối: `cargo test` pass khi chúng KHÔNG compile được.
+
+// ===================================================================
+// 1. Hàm trả về reference - functions returning references
+// ===================================================================
+
+/// Trả về chuỗi dài hơn trong hai chuỗi.
+///
+/// `'a` nói: kết quả sống được bằng reference NGẮN hơn trong `x` và `y`
+/// (the result lives as long as the shorter of the two).
+///
+/// ```
+/// use day_3_lifetimes::longest;
+///
+/// let a = String::from("xin chao");
+/// let b = "hi";
+/// assert_eq!(longest(&a, b), "xin chao");
+/// ```
+///
+/// Thiếu `'a` thì compiler không biết kết quả mượn từ `x` hay `y`
+/// (two reference parameters, no `self`: no elision rule applies):
+///
+/// ```compile_fail,E0106
+/// fn longest(x: &str, y: &str) -> &str {
+///     if x.len() >= y.len() { x } else { y }
+/// }
+/// ```
+pub fn longest<'a>(x: &'a str, y: &'a str) -> &'a str {
+    if x.len() >= y.len() { x } else { y }
+}
+
+/// Phần của `text` đứng trước `separator`. Kết quả chỉ mượn từ `text`, nên
+/// `separator` có lifetime riêng và không bị ràng buộc
+/// (only `text` is tied to the result).
+///
+/// ```
+/// use day_3_lifetimes::before;
+///
+/// let email = String::from("rust@example.com");
+/// let result;
+/// {
+///     let separator = String::from("@");
+///     result = before(&email, &separator);
+/// } // separator bị drop ở đây, nhưng result không mượn từ nó
+/// assert_eq!(result, "rust");
+/// ```
+pub fn before<'a>(text: &'a str, separator: &str) -> &'a str {
+    // split mượn từ text, không phải từ separator
+    text.split(separator).next().unwrap_or(text)
+}
+
+/// Từ đầu tiên của câu. Chỉ có một tham số reference nên không cần viết
+/// lifetime (elision rule 2): `fn first_word<'a>(s: &'a str) -> &'a str`.
+///
+/// ```
+/// use day_3_lifetimes::first_word;
+///
+/// assert_eq!(first_word("hello world"), "hello");
+/// assert_eq!(first_word("rust"), "rust");
+/// ```
+pub fn first_word(s: &str) -> &str {
+    s.split_whitespace().next().unwrap_or("")
+}
+
+// ===================================================================
+// 2. Struct giữ reference - structs holding references
+// ===================================================================
+
+/// Một đoạn trích mượn từ văn bản gốc, không copy.
+///
+/// `Excerpt<'a>`: một Excerpt không được sống lâu hơn văn bản nó mượn
+/// (an Excerpt can't outlive the text it borrows from).
+///
+/// ```
+/// use day_3_lifetimes::Excerpt;
+///
+/// let text = String::from("Rust an toàn. Rust nhanh. Rust vui.");
+/// let excerpt = Excerpt::first_sentence(&text);
+/// assert_eq!(excerpt.part(), "Rust an toàn");
+/// ```
+#[derive(Debug)]
+pub struct Excerpt<'a> {
+    part: &'a str,
+}
+
+impl<'a> Excerpt<'a> {
+    /// Câu đầu tiên, tới dấu chấm đầu tiên
+    pub fn first_sentence(text: &'a str) -> Self {
+        let part = text.split('.').next().unwrap_or(text);
+        Self { part }
+    }
+
+    /// Elision rule 3: kết quả lấy lifetime của `&self`
+    pub fn part(&self) -> &str {
+        self.part
+    }
+
+    /// Trả về `&'a str`, không phải `&self`: kết quả sống được lâu hơn
+    /// chính Excerpt, miễn là văn bản gốc còn sống
+    /// (outlives the Excerpt, as long as the text lives).
+    ///
+    /// ```
+    /// use day_3_lifetimes::Excerpt;
+    ///
+    /// let text = String::from("Một. Hai.");
+    /// let part;
+    /// {
+    ///     let excerpt = Excerpt::first_sentence(&text);
+    ///     part = excerpt.into_part();
+    /// } // excerpt bị drop, part vẫn dùng được
+    /// assert_eq!(part, "Một");
+    /// ```
+    pub fn into_part(self) -> &'a str {
+        self.part
+    }
+}
+
+/// Các chương trình borrow checker từ chối, và tại sao
+/// (programs the borrow checker rejects, and why).
+///
+/// Mỗi ví dụ có mã lỗi của compiler; tra cứu bằng
+/// `rustc --explain E0597`.
+///
+/// **E0106 - thiếu lifetime (missing lifetime specifier).** Hàm trả về
+/// reference nhưng không có tham số nào để mượn:
+///
+/// ```compile_fail,E0106
+/// fn make_greeting() -> &str {
+///     "xin chao"
+/// }
+/// ```
+///
+/// Sửa: trả về `&'static str` (string literal sống hết chương trình),
+/// hoặc trả về `String` (owned).
+///
+/// **E0515 - dangling reference.** `s` là biến local, bị drop khi hàm kết
+/// thúc, reference tới nó sẽ trỏ vào vùng nhớ đã giải phóng:
+///
+/// ```compile_fail,E0515
+/// fn dangle() -> &'static String {
+///     let s = String::from("hello");
+///     &s
+/// }
+/// ```
+///
+/// Sửa: trả về `s` (move ownership ra ngoài).
+///
+/// **E0597 - value không sống đủ lâu (borrowed value does not live long
+/// enough).** `x` bị drop ở cuối block `{ }`, nhưng `r` vẫn được dùng sau
+/// đó:
+///
+/// ```compile_fail,E0597
+/// let r;
+/// {
+///     let x = 5;
+///     r = &x;
+/// } // x bị drop
+/// println!("{}", r);
+/// ```
+///
+/// **E0597 với `longest`.** Kết quả sống bằng tham số ngắn hơn, ở đây là
+/// `b`. Dù kết quả thực sự là `a`, compiler chỉ nhìn chữ ký hàm
+/// (the signature, not the body, decides):
+///
+/// ```compile_fail,E0597
+/// use day_3_lifetimes::longest;
+///
+/// let a = String::from("một chuỗi dài");
+/// let result;
+/// {
+///     let b = String::from("ngắn");
+///     result = longest(&a, &b);
+/// } // b bị drop
+/// println!("{}", result);
+/// ```
+///
+/// **E0505 - move khi đang bị mượn (move out while borrowed).** `excerpt`
+/// mượn `text`; move `text` đi thì `excerpt` trỏ vào đâu?
+///
+/// ```compile_fail,E0505
+/// use day_3_lifetimes::Excerpt;
+///
+/// let text = String::from("Một. Hai.");
+/// let excerpt = Excerpt::first_sentence(&text);
+/// let moved = text;
+/// println!("{} {}", excerpt.part(), moved);
+/// ```
+///
+/// **E0502 - sửa khi đang bị mượn (mutable borrow while borrowed).** Quy
+/// tắc của day 2: không thể có `&mut T` và `&T` cùng lúc, kể cả khi `&T`
+/// nằm trong một struct:
+///
+/// ```compile_fail,E0502
+/// use day_3_lifetimes::Excerpt;
+///
+/// let mut text = String::from("Một. Hai.");
+/// let excerpt = Excerpt::first_sentence(&text);
+/// text.push_str(" Ba.");
+/// println!("{}", excerpt.part());
+/// ```
+///
+/// Cùng code đó, nhưng `excerpt` được dùng xong trước khi sửa: compile
+/// được, vì borrow kết thúc ở lần dùng cuối (last use, như day 2):
+///
+/// ```
+/// use day_3_lifetimes::Excerpt;
+///
+/// let mut text = String::from("Một. Hai.");
+/// let excerpt = Excerpt::first_sentence(&text);
+/// println!("{}", excerpt.part());
+/// text.push_str(" Ba.");
+/// assert_eq!(text, "Một. Hai. Ba.");
+/// ```
+pub mod rejected {}
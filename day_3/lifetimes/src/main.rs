@@ -0,0 +1,24 @@
+use day_3_lifetimes::{Excerpt, before, first_word, longest};
+
+fn main() {
+    // Hàm trả về reference
+    let a = String::from("rust@example.com");
+    let result;
+    {
+        let b = String::from("@");
+        println!("longest: {}", longest(&a, &b));
+        // result = longest(&a, &b); // uncomment: b không sống đủ lâu (E0597)
+        result = before(&a, &b);
+    }
+    println!("before: {}", result);
+
+    println!("first_word: {}", first_word("hello world"));
+
+    // Struct giữ reference
+    let text = String::from("Rust an toàn. Rust nhanh.");
+    let excerpt = Excerpt::first_sentence(&text);
+    println!("excerpt: {}", excerpt.part());
+
+    // let moved = text; // uncomment: text đang bị excerpt mượn (E0505)
+    println!("{:?}", excerpt);
+}
@@ -0,0 +1,354 @@
+//! Day 4: Enums và pattern matching qua một state machine
+//!
+//! Mỗi state là một variant, mỗi event là một variant, và `match` trên
+//! cặp (state, event) là toàn bộ bảng transition. Compiler kiểm tra giúp
+//! là không bỏ sót trường hợp nào (exhaustive).
+
+use std::fmt;
+use std::str::FromStr;
+
+// ===================================================================
+// 1. Đèn giao thông - enum không mang data
+// ===================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Light {
+    Red,
+    Yellow,
+    Green,
+}
+
+impl Light {
+    pub const ALL: [Light; 3] = [Light::Red, Light::Yellow, Light::Green];
+
+    /// Đèn tiếp theo: Red -> Green -> Yellow -> Red
+    pub fn next(self) -> Light {
+        // Không có `_`: thêm variant mới thì match này lỗi compile
+        match self {
+            Light::Red => Light::Green,
+            Light::Green => Light::Yellow,
+            Light::Yellow => Light::Red,
+        }
+    }
+
+    /// Sáng bao nhiêu giây
+    pub fn duration_secs(self) -> u32 {
+        match self {
+            Light::Red => 30,
+            Light::Yellow => 3,
+            Light::Green => 25,
+        }
+    }
+
+    /// Xe có được đi không? Nhiều variant chung một nhánh với `|`
+    pub fn can_go(self) -> bool {
+        match self {
+            Light::Green => true,
+            Light::Red | Light::Yellow => false,
+        }
+    }
+}
+
+// ===================================================================
+// 2. Đơn hàng - enum mang data
+// ===================================================================
+
+/// Trạng thái của đơn hàng. Data chỉ có ở state cần nó: đơn chưa ship thì
+/// không có tracking number (no "tracking: Option<String>" on every order).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderState {
+    Pending,
+    Paid { amount: u64 },
+    Shipped { amount: u64, tracking: String },
+    Delivered { amount: u64 },
+    Cancelled { reason: String },
+}
+
+/// Những gì có thể xảy ra với một đơn hàng
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderEvent {
+    Pay(u64),
+    Ship(String),
+    Deliver,
+    Cancel(String),
+}
+
+/// Event không hợp lệ ở state hiện tại, ví dụ ship một đơn chưa trả tiền
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionError {
+    pub state: OrderState,
+    pub event: OrderEvent,
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot {:?} an order that is {:?}",
+            self.event, self.state
+        )
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+impl OrderState {
+    /// Toàn bộ bảng transition trong một `match` trên tuple (state, event).
+    ///
+    /// `self` được move vào (by value): state cũ không dùng lại được sau
+    /// transition, giống như ownership ở day 2.
+    pub fn apply(self, event: OrderEvent) -> Result<OrderState, TransitionError> {
+        use OrderEvent::*;
+        use OrderState::*;
+
+        match (self, event) {
+            // Trả tiền: số tiền phải > 0 (match guard `if`)
+            (Pending, Pay(amount)) if amount > 0 => Ok(Paid { amount }),
+            (Paid { amount }, Ship(tracking)) => Ok(Shipped { amount, tracking }),
+            (Shipped { amount, .. }, Deliver) => Ok(Delivered { amount }),
+            // Chỉ hủy được trước khi ship
+            (Pending | Paid { .. }, Cancel(reason)) => Ok(Cancelled { reason }),
+            // Mọi cặp còn lại đều không hợp lệ. Ở đây `_` là cố ý: bảng ở
+            // trên liệt kê những gì ĐƯỢC phép, còn lại là lỗi.
+            (state, event) => Err(TransitionError { state, event }),
+        }
+    }
+
+    /// Áp dụng lần lượt các event, dừng ở lỗi đầu tiên
+    pub fn apply_all(
+        self,
+        events: impl IntoIterator<Item = OrderEvent>,
+    ) -> Result<OrderState, TransitionError> {
+        let mut state = self;
+        for event in events {
+            state = state.apply(event)?;
+        }
+        Ok(state)
+    }
+
+    /// `if let`: chỉ quan tâm một variant
+    pub fn tracking(&self) -> Option<&str> {
+        if let OrderState::Shipped { tracking, .. } = self {
+            Some(tracking)
+        } else {
+            None
+        }
+    }
+
+    /// `let ... else`: lấy data ra hoặc thoát sớm
+    pub fn refund_amount(&self) -> u64 {
+        let OrderState::Paid { amount } = self else {
+            return 0;
+        };
+        *amount
+    }
+
+    /// `matches!`: chỉ cần biết có phải variant đó không
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            OrderState::Delivered { .. } | OrderState::Cancelled { .. }
+        )
+    }
+}
+
+// ===================================================================
+// 3. Role - enum thay cho string
+// ===================================================================
+
+/// `User.role` trong material là `String`. Enum thì gõ sai là lỗi compile;
+/// string chỉ xuất hiện ở biên, khi đọc vào (`FromStr`) và ghi ra
+/// (`Display`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("unknown role: {:?}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::User => write!(f, "user"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl Role {
+    /// Thêm `Role::Moderator` thì compiler bắt sửa hàm này
+    pub fn can_manage_users(self) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::User => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_cycles_back_to_red() {
+        let mut light = Light::Red;
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push(light);
+            light = light.next();
+        }
+        assert_eq!(seen, [Light::Red, Light::Green, Light::Yellow]);
+        assert_eq!(light, Light::Red);
+    }
+
+    #[test]
+    fn test_only_green_means_go() {
+        for light in Light::ALL {
+            assert_eq!(light.can_go(), light == Light::Green, "{:?}", light);
+            assert!(light.duration_secs() > 0);
+        }
+    }
+
+    // Một đại diện cho mỗi state và mỗi event
+    fn states() -> Vec<OrderState> {
+        vec![
+            OrderState::Pending,
+            OrderState::Paid { amount: 100 },
+            OrderState::Shipped {
+                amount: 100,
+                tracking: "VN123".to_string(),
+            },
+            OrderState::Delivered { amount: 100 },
+            OrderState::Cancelled {
+                reason: "changed my mind".to_string(),
+            },
+        ]
+    }
+
+    fn events() -> Vec<OrderEvent> {
+        vec![
+            OrderEvent::Pay(100),
+            OrderEvent::Ship("VN123".to_string()),
+            OrderEvent::Deliver,
+            OrderEvent::Cancel("changed my mind".to_string()),
+        ]
+    }
+
+    // Bảng transition mong đợi, viết lại độc lập với `apply`
+    fn expected(state: &OrderState, event: &OrderEvent) -> Option<OrderState> {
+        match (state, event) {
+            (OrderState::Pending, OrderEvent::Pay(amount)) => {
+                Some(OrderState::Paid { amount: *amount })
+            }
+            (OrderState::Paid { amount }, OrderEvent::Ship(tracking)) => {
+                Some(OrderState::Shipped {
+                    amount: *amount,
+                    tracking: tracking.clone(),
+                })
+            }
+            (OrderState::Shipped { amount, .. }, OrderEvent::Deliver) => {
+                Some(OrderState::Delivered { amount: *amount })
+            }
+            (OrderState::Pending | OrderState::Paid { .. }, OrderEvent::Cancel(reason)) => {
+                Some(OrderState::Cancelled {
+                    reason: reason.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Mọi cặp (state, event): 5 x 4 = 20 trường hợp
+    #[test]
+    fn test_every_transition() {
+        let mut allowed = 0;
+        for state in states() {
+            for event in events() {
+                let result = state.clone().apply(event.clone());
+                match expected(&state, &event) {
+                    Some(next) => {
+                        assert_eq!(result, Ok(next), "{:?} + {:?}", state, event);
+                        allowed += 1;
+                    }
+                    None => assert_eq!(
+                        result,
+                        Err(TransitionError {
+                            state: state.clone(),
+                            event: event.clone()
+                        }),
+                        "{:?} + {:?}",
+                        state,
+                        event
+                    ),
+                }
+            }
+        }
+        assert_eq!(allowed, 5);
+    }
+
+    #[test]
+    fn test_finished_orders_accept_nothing() {
+        for state in states().into_iter().filter(OrderState::is_finished) {
+            for event in events() {
+                assert!(state.clone().apply(event).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_paying_nothing_is_rejected() {
+        assert!(OrderState::Pending.apply(OrderEvent::Pay(0)).is_err());
+    }
+
+    #[test]
+    fn test_happy_path() {
+        let state = OrderState::Pending
+            .apply_all([OrderEvent::Pay(250), OrderEvent::Ship("VN999".to_string())])
+            .unwrap();
+        assert_eq!(state.tracking(), Some("VN999"));
+        assert_eq!(state.refund_amount(), 0);
+
+        let state = state.apply(OrderEvent::Deliver).unwrap();
+        assert_eq!(state, OrderState::Delivered { amount: 250 });
+        assert!(state.is_finished());
+        assert_eq!(state.tracking(), None);
+    }
+
+    #[test]
+    fn test_apply_all_stops_at_the_first_error() {
+        let error = OrderState::Pending
+            .apply_all([OrderEvent::Deliver, OrderEvent::Pay(10)])
+            .unwrap_err();
+        assert_eq!(error.state, OrderState::Pending);
+        assert_eq!(error.event, OrderEvent::Deliver);
+        assert_eq!(error.to_string(), "cannot Deliver an order that is Pending");
+    }
+
+    #[test]
+    fn test_refund_only_for_paid_orders() {
+        assert_eq!(OrderState::Paid { amount: 70 }.refund_amount(), 70);
+        assert_eq!(OrderState::Pending.refund_amount(), 0);
+    }
+
+    #[test]
+    fn test_role_round_trips_through_strings() {
+        for role in [Role::User, Role::Admin] {
+            assert_eq!(role.to_string().parse::<Role>(), Ok(role));
+        }
+        // Chính là lỗi mà `role: String` không bắt được
+        assert!("Admin".parse::<Role>().is_err());
+        assert!(Role::Admin.can_manage_users());
+        assert!(!Role::User.can_manage_users());
+    }
+}
@@ -0,0 +1,42 @@
+use day_4_state_machine::{Light, OrderEvent, OrderState, Role};
+
+fn main() {
+    // Đèn giao thông
+    let mut light = Light::Red;
+    for _ in 0..4 {
+        println!(
+            "{:?}: {}s, can go: {}",
+            light,
+            light.duration_secs(),
+            light.can_go()
+        );
+        light = light.next();
+    }
+
+    // Vòng đời một đơn hàng
+    let mut order = OrderState::Pending;
+    let events = [
+        OrderEvent::Pay(250),
+        OrderEvent::Cancel("too late?".to_string()), // Paid vẫn hủy được
+        OrderEvent::Ship("VN999".to_string()),       // nhưng Cancelled thì không ship được
+    ];
+    for event in events {
+        match order.clone().apply(event) {
+            Ok(next) => {
+                println!("{:?} -> {:?}", order, next);
+                order = next;
+            }
+            Err(error) => println!("error: {}", error),
+        }
+    }
+
+    if let Some(tracking) = order.tracking() {
+        println!("tracking: {}", tracking);
+    }
+
+    // Role: string chỉ ở biên
+    match "amdin".parse::<Role>() {
+        Ok(role) => println!("role: {}", role),
+        Err(error) => println!("error: {}", error),
+    }
+}
@@ -0,0 +1,305 @@
+//! Day 5: Error handling
+//!
+//! Đọc một file CSV user. Input từ bên ngoài luôn có thể sai, nên mọi hàm
+//! parse trả về `Result`, không `unwrap()`.
+//!
+//! Đi từ `Result<User, String>` (v1) tới enum lỗi với thiserror, giống
+//! `enum Error` trong tutorial-core (material/dependency_inversion dùng nó).
+
+use std::collections::HashSet;
+use std::num::ParseIntError;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub id: u32,
+    pub email: String,
+    pub age: u8,
+    pub role: String,
+}
+
+const ROLES: [&str; 2] = ["user", "admin"];
+
+// ===================================================================
+// v1: lỗi là String - errors as strings
+// ===================================================================
+
+/// Dễ viết, nhưng caller chỉ nhận được text: muốn biết lỗi là "thiếu
+/// field" hay "age sai" thì phải so sánh chuỗi (compare strings).
+pub fn parse_line_v1(line: &str) -> Result<User, String> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() != 4 {
+        return Err(format!("expected 4 fields, got {}", fields.len()));
+    }
+    // map_err: đổi lỗi gốc thành String, mất luôn kiểu của nó
+    let id = fields[0].parse().map_err(|e| format!("bad id: {}", e))?;
+    let age = fields[2].parse().map_err(|e| format!("bad age: {}", e))?;
+    Ok(User {
+        id,
+        email: fields[1].to_string(),
+        age,
+        role: fields[3].to_string(),
+    })
+}
+
+// ===================================================================
+// v2: enum lỗi với thiserror - a custom error enum
+// ===================================================================
+
+/// Một dòng sai ở đâu. Mỗi loại lỗi là một variant, caller `match` được.
+///
+/// `#[error("...")]` sinh `impl Display`, `{field}` lấy từ field của
+/// variant.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("expected 4 fields, got {0}")]
+    FieldCount(usize),
+    #[error("missing {0}")]
+    MissingField(&'static str),
+    // #[source]: giữ lỗi gốc, không đổi thành String
+    #[error("invalid {field}: {value:?}")]
+    InvalidNumber {
+        field: &'static str,
+        value: String,
+        #[source]
+        source: ParseIntError,
+    },
+    #[error("invalid email: {0:?}")]
+    InvalidEmail(String),
+    #[error("unknown role: {0:?}, expected user or admin")]
+    UnknownRole(String),
+    #[error("duplicate email: {0}")]
+    DuplicateEmail(String),
+}
+
+/// Lỗi của cả chương trình: đọc file hỏng, hoặc nội dung sai.
+///
+/// `#[from]` sinh `impl From<std::io::Error> for AppError`, nhờ đó `?`
+/// trên một `io::Result` tự đổi lỗi thành `AppError::Io`.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("cannot read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {source}")]
+    Parse {
+        line: usize,
+        #[source]
+        source: ParseError,
+    },
+    #[error("{} invalid line(s)", .0.len())]
+    Invalid(Vec<AppError>),
+}
+
+/// Không dùng thiserror thì phải tự viết `From` như thế này. `?` gọi nó
+/// để đổi `ParseError` thành `AppError` (line 0: chưa biết dòng nào).
+impl From<ParseError> for AppError {
+    fn from(source: ParseError) -> Self {
+        AppError::Parse { line: 0, source }
+    }
+}
+
+fn field<'a>(value: &'a str, name: &'static str) -> Result<&'a str, ParseError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(ParseError::MissingField(name));
+    }
+    Ok(value)
+}
+
+fn number<T>(value: &str, name: &'static str) -> Result<T, ParseError>
+where
+    T: std::str::FromStr<Err = ParseIntError>,
+{
+    let value = field(value, name)?;
+    value.parse().map_err(|source| ParseError::InvalidNumber {
+        field: name,
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// Một dòng `id,email,age,role`. Mỗi `?` return sớm với lỗi đầu tiên.
+pub fn parse_line(line: &str) -> Result<User, ParseError> {
+    let fields: Vec<&str> = line.split(',').collect();
+    let [id, email, age, role] = fields[..] else {
+        return Err(ParseError::FieldCount(fields.len()));
+    };
+
+    let id = number(id, "id")?;
+    let email = field(email, "email")?;
+    if !email.contains('@') {
+        return Err(ParseError::InvalidEmail(email.to_string()));
+    }
+    let age = number(age, "age")?;
+    let role = field(role, "role")?;
+    if !ROLES.contains(&role) {
+        return Err(ParseError::UnknownRole(role.to_string()));
+    }
+
+    Ok(User {
+        id,
+        email: email.to_string(),
+        age,
+        role: role.to_string(),
+    })
+}
+
+/// Cả file: bỏ dòng header, parse từng dòng. Không dừng ở dòng sai đầu
+/// tiên mà gom lỗi của MỌI dòng, để người dùng sửa một lần
+/// (report every bad line at once, like the config loader in material).
+pub fn parse_users(text: &str) -> Result<Vec<User>, AppError> {
+    let mut users = Vec::new();
+    let mut errors = Vec::new();
+    let mut emails = HashSet::new();
+
+    // Dòng 1 là header; enumerate đếm từ 0 nên +1
+    for (index, line) in text.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index + 1;
+        let parsed = parse_line(line).and_then(|user| {
+            if emails.insert(user.email.clone()) {
+                Ok(user)
+            } else {
+                Err(ParseError::DuplicateEmail(user.email))
+            }
+        });
+        match parsed {
+            Ok(user) => users.push(user),
+            Err(source) => errors.push(AppError::Parse {
+                line: line_number,
+                source,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(users)
+    } else {
+        Err(AppError::Invalid(errors))
+    }
+}
+
+/// `?` trên `read_to_string` đổi `io::Error` thành `AppError::Io` nhờ
+/// `#[from]`
+pub fn load_users(path: impl AsRef<Path>) -> Result<Vec<User>, AppError> {
+    let text = std::fs::read_to_string(path)?;
+    parse_users(&text)
+}
+
+/// User đầu tiên, nếu file có. `?` trên `ParseError` dùng `From` tự viết
+/// ở trên, nên lỗi có `line: 0`.
+pub fn first_user(text: &str) -> Result<Option<User>, AppError> {
+    let Some(line) = text.lines().nth(1) else {
+        return Ok(None);
+    };
+    let user = parse_line(line)?;
+    Ok(Some(user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    const CSV: &str = "id,email,age,role\n1,an@example.com,28,admin\n2,binh@example.com,35,user\n";
+
+    #[test]
+    fn test_v1_errors_are_only_text() {
+        assert_eq!(parse_line_v1("1,an@example.com,28,admin").unwrap().id, 1);
+        let error = parse_line_v1("1,an@example.com,abc,admin").unwrap_err();
+        // Chỉ so sánh được chuỗi
+        assert_eq!(error, "bad age: invalid digit found in string");
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("1, an@example.com ,28,admin"),
+            Ok(User {
+                id: 1,
+                email: "an@example.com".to_string(),
+                age: 28,
+                role: "admin".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_each_kind_of_bad_line() {
+        assert_eq!(parse_line("1,a@b.c,28"), Err(ParseError::FieldCount(3)));
+        assert_eq!(
+            parse_line("1,,28,user"),
+            Err(ParseError::MissingField("email"))
+        );
+        assert_eq!(
+            parse_line("1,nobody,28,user"),
+            Err(ParseError::InvalidEmail("nobody".to_string()))
+        );
+        assert_eq!(
+            parse_line("1,a@b.c,28,manager"),
+            Err(ParseError::UnknownRole("manager".to_string()))
+        );
+        // Caller match trên variant, không phải trên text
+        assert!(matches!(
+            parse_line("1,a@b.c,300,user"),
+            Err(ParseError::InvalidNumber { field: "age", .. })
+        ));
+    }
+
+    #[test]
+    fn test_source_keeps_the_original_error() {
+        let error = parse_line("x,a@b.c,28,user").unwrap_err();
+        assert_eq!(error.to_string(), "invalid id: \"x\"");
+        let source = error.source().expect("the ParseIntError is kept");
+        assert_eq!(source.to_string(), "invalid digit found in string");
+    }
+
+    #[test]
+    fn test_parse_users() {
+        let users = parse_users(CSV).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[1].email, "binh@example.com");
+    }
+
+    #[test]
+    fn test_every_bad_line_is_reported() {
+        let text = include_str!("../users.csv");
+        let Err(AppError::Invalid(errors)) = parse_users(text) else {
+            panic!("users.csv has bad lines on purpose");
+        };
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 4: invalid age: \"abc\"",
+                "line 5: missing email",
+                "line 6: unknown role: \"manager\", expected user or admin",
+                "line 7: duplicate email: an@example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_io_errors_convert_with_question_mark() {
+        let error = load_users("does/not/exist.csv").unwrap_err();
+        assert!(matches!(error, AppError::Io(_)));
+        assert!(error.to_string().starts_with("cannot read file: "));
+    }
+
+    #[test]
+    fn test_hand_written_from() {
+        assert_eq!(first_user(CSV).unwrap().unwrap().id, 1);
+        assert_eq!(first_user("id,email,age,role\n").unwrap(), None);
+
+        let error = first_user("id,email,age,role\n1,a@b.c,x,user\n").unwrap_err();
+        assert!(matches!(
+            error,
+            AppError::Parse {
+                line: 0,
+                source: ParseError::InvalidNumber { field: "age", .. }
+            }
+        ));
+    }
+}
@@ -0,0 +1,26 @@
+use day_5_errors::{AppError, load_users};
+
+// main cũng trả về Result được: Err thì in lỗi (Debug) và exit code 1
+fn main() -> Result<(), AppError> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "users.csv".to_string());
+
+    match load_users(&path) {
+        Ok(users) => {
+            for user in users {
+                println!("{:?}", user);
+            }
+        }
+        // Lỗi nội dung: in từng dòng sai, dễ đọc hơn Debug
+        Err(AppError::Invalid(errors)) => {
+            println!("{}: {} invalid line(s)", path, errors.len());
+            for error in errors {
+                println!("  {}", error);
+            }
+        }
+        // Lỗi khác (không đọc được file): trả về cho main
+        Err(error) => return Err(error),
+    }
+    Ok(())
+}
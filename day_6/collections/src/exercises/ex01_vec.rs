@@ -0,0 +1,87 @@
+//! Bài 1: Vec và slice
+//!
+//! Gợi ý (hint): `for n in numbers` duyệt một `&[i32]` cho ra `&i32`, dùng
+//! `*n` để lấy giá trị. `v.sort()` và `v.dedup()` sửa `Vec` tại chỗ, nên
+//! tham số phải là `mut`. Một slice rỗng không có phần tử lớn thứ hai:
+//! trả về `None`.
+
+/// Các số chẵn, giữ nguyên thứ tự
+pub fn evens(numbers: &[i32]) -> Vec<i32> {
+    todo!()
+}
+
+/// Sắp xếp tăng dần và bỏ các số trùng
+pub fn sorted_unique(numbers: Vec<i32>) -> Vec<i32> {
+    todo!()
+}
+
+/// Số lớn thứ hai (không tính trùng): [3, 7, 7, 5] -> Some(5)
+pub fn second_largest(numbers: &[i32]) -> Option<i32> {
+    todo!()
+}
+
+/// Gấp đôi mọi phần tử, sửa trực tiếp trên slice (`&mut`)
+pub fn double_in_place(numbers: &mut [i32]) {
+    todo!()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    // Kiểm tra một cách giải bất kỳ: bài của bạn hoặc lời giải mẫu
+    pub fn check_evens(evens: fn(&[i32]) -> Vec<i32>) {
+        assert_eq!(evens(&[1, 2, 3, 4, 6]), [2, 4, 6]);
+        assert_eq!(evens(&[-2, -1, 0]), [-2, 0]);
+        assert!(evens(&[1, 3]).is_empty());
+        assert!(evens(&[]).is_empty());
+    }
+
+    pub fn check_sorted_unique(sorted_unique: fn(Vec<i32>) -> Vec<i32>) {
+        assert_eq!(sorted_unique(vec![3, 1, 3, 2, 1]), [1, 2, 3]);
+        assert_eq!(sorted_unique(vec![5, 5, 5]), [5]);
+        assert!(sorted_unique(Vec::new()).is_empty());
+    }
+
+    pub fn check_second_largest(second_largest: fn(&[i32]) -> Option<i32>) {
+        assert_eq!(second_largest(&[3, 7, 7, 5]), Some(5));
+        assert_eq!(second_largest(&[1, 2]), Some(1));
+        assert_eq!(second_largest(&[-1, -5, -3]), Some(-3));
+        assert_eq!(second_largest(&[4, 4]), None);
+        assert_eq!(second_largest(&[4]), None);
+        assert_eq!(second_largest(&[]), None);
+    }
+
+    pub fn check_double_in_place(double_in_place: fn(&mut [i32])) {
+        let mut numbers = vec![1, -2, 3];
+        double_in_place(&mut numbers);
+        assert_eq!(numbers, [2, -4, 6]);
+
+        // Một phần của Vec cũng là slice
+        let mut numbers = vec![1, 2, 3, 4];
+        double_in_place(&mut numbers[2..]);
+        assert_eq!(numbers, [1, 2, 6, 8]);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex01 -- --ignored`"]
+    fn evens() {
+        check_evens(super::evens);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex01 -- --ignored`"]
+    fn sorted_unique() {
+        check_sorted_unique(super::sorted_unique);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex01 -- --ignored`"]
+    fn second_largest() {
+        check_second_largest(super::second_largest);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex01 -- --ignored`"]
+    fn double_in_place() {
+        check_double_in_place(super::double_in_place);
+    }
+}
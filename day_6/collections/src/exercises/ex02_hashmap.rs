@@ -0,0 +1,78 @@
+//! Bài 2: HashMap
+//!
+//! Gợi ý (hint): `*counts.entry(key).or_insert(0) += 1` đếm trong một
+//! bước. `entry(key).or_default()` cho một `Vec` rỗng để `push` vào. Với
+//! `invert`, duyệt `map` bằng `for (key, value) in map`.
+
+use std::collections::HashMap;
+
+/// Đếm số lần mỗi từ xuất hiện, không phân biệt hoa thường:
+/// "Rust rust go" -> {"rust": 2, "go": 1}
+pub fn word_count(text: &str) -> HashMap<String, usize> {
+    todo!()
+}
+
+/// Nhóm email theo role, giữ thứ tự xuất hiện trong mỗi nhóm:
+/// [("a@x", "admin"), ("b@x", "user"), ("c@x", "admin")]
+/// -> {"admin": ["a@x", "c@x"], "user": ["b@x"]}
+pub fn group_by_role(users: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+    todo!()
+}
+
+/// Đảo key và value. Hai key cùng value thì giữ key nhỏ hơn.
+pub fn invert(map: &HashMap<String, u32>) -> HashMap<u32, String> {
+    todo!()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    type Groups = HashMap<String, Vec<String>>;
+
+    pub fn check_word_count(word_count: fn(&str) -> HashMap<String, usize>) {
+        let counts = word_count("Rust rust  go\nRUST");
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["rust"], 3);
+        assert_eq!(counts["go"], 1);
+        assert!(word_count("").is_empty());
+    }
+
+    pub fn check_group_by_role(group_by_role: fn(&[(&str, &str)]) -> Groups) {
+        let groups = group_by_role(&[("a@x", "admin"), ("b@x", "user"), ("c@x", "admin")]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups["admin"], ["a@x", "c@x"]);
+        assert_eq!(groups["user"], ["b@x"]);
+        assert!(group_by_role(&[]).is_empty());
+    }
+
+    pub fn check_invert(invert: fn(&HashMap<String, u32>) -> HashMap<u32, String>) {
+        let map = HashMap::from([
+            ("one".to_string(), 1),
+            ("uno".to_string(), 1),
+            ("two".to_string(), 2),
+        ]);
+        let inverted = invert(&map);
+        assert_eq!(inverted.len(), 2);
+        assert_eq!(inverted[&1], "one");
+        assert_eq!(inverted[&2], "two");
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex02 -- --ignored`"]
+    fn word_count() {
+        check_word_count(super::word_count);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex02 -- --ignored`"]
+    fn group_by_role() {
+        check_group_by_role(super::group_by_role);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex02 -- --ignored`"]
+    fn invert() {
+        check_invert(super::invert);
+    }
+}
@@ -0,0 +1,67 @@
+//! Bài 3: HashSet
+//!
+//! Gợi ý (hint): `set.insert(x)` trả về `false` khi `x` đã có, rất tiện để
+//! tìm phần tử trùng. `a.intersection(&b)` cho phần chung của hai set;
+//! kết quả không có thứ tự, nên sort trước khi trả về `Vec`.
+
+use std::collections::HashSet;
+
+/// Các email khác nhau, không phân biệt hoa thường, đã viết thường
+pub fn unique_emails(emails: &[&str]) -> HashSet<String> {
+    todo!()
+}
+
+/// Phần tử đầu tiên xuất hiện lần thứ hai: [3, 1, 4, 1, 3] -> Some(1)
+pub fn first_repeat(numbers: &[i32]) -> Option<i32> {
+    todo!()
+}
+
+/// Các số có mặt trong cả hai slice, không trùng, tăng dần
+pub fn common(a: &[i32], b: &[i32]) -> Vec<i32> {
+    todo!()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashSet;
+
+    pub fn check_unique_emails(unique_emails: fn(&[&str]) -> HashSet<String>) {
+        let emails = unique_emails(&["An@Example.com", "an@example.com", "binh@example.com"]);
+        assert_eq!(
+            emails,
+            HashSet::from(["an@example.com".to_string(), "binh@example.com".to_string()])
+        );
+        assert!(unique_emails(&[]).is_empty());
+    }
+
+    pub fn check_first_repeat(first_repeat: fn(&[i32]) -> Option<i32>) {
+        assert_eq!(first_repeat(&[3, 1, 4, 1, 3]), Some(1));
+        assert_eq!(first_repeat(&[7, 7]), Some(7));
+        assert_eq!(first_repeat(&[1, 2, 3]), None);
+        assert_eq!(first_repeat(&[]), None);
+    }
+
+    pub fn check_common(common: fn(&[i32], &[i32]) -> Vec<i32>) {
+        assert_eq!(common(&[5, 1, 3, 3], &[3, 5, 7]), [3, 5]);
+        assert!(common(&[1, 2], &[3, 4]).is_empty());
+        assert!(common(&[], &[1]).is_empty());
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex03 -- --ignored`"]
+    fn unique_emails() {
+        check_unique_emails(super::unique_emails);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex03 -- --ignored`"]
+    fn first_repeat() {
+        check_first_repeat(super::first_repeat);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex03 -- --ignored`"]
+    fn common() {
+        check_common(super::common);
+    }
+}
@@ -0,0 +1,122 @@
+//! Bài 4: Iterator adapters
+//!
+//! Viết mỗi hàm bằng một chuỗi iterator, không dùng `for` hay `mut`.
+//!
+//! Gợi ý (hint): `filter` giữ phần tử, `map` biến đổi, `fold(init, |acc, x|
+//! ...)` gộp lại thành một giá trị, `collect()` tạo `Vec` / `HashMap` /
+//! `String`. `collect::<Result<Vec<_>, _>>()` dừng ở lỗi đầu tiên.
+
+use std::collections::HashMap;
+use std::num::ParseIntError;
+
+pub struct Item {
+    pub name: String,
+    pub price: u32,
+    pub quantity: u32,
+}
+
+/// Tổng tiền: price * quantity của mọi item, dùng `map` + `sum` hoặc `fold`
+pub fn total(items: &[Item]) -> u32 {
+    todo!()
+}
+
+/// Tên (viết hoa) của các item còn hàng (quantity > 0)
+pub fn in_stock_names(items: &[Item]) -> Vec<String> {
+    todo!()
+}
+
+/// Parse mọi chuỗi thành số; chuỗi sai đầu tiên làm cả hàm trả về `Err`
+pub fn parse_all(values: &[&str]) -> Result<Vec<i32>, ParseIntError> {
+    todo!()
+}
+
+/// Tên -> price, chỉ các item giá từ `min_price` trở lên
+pub fn price_list(items: &[Item], min_price: u32) -> HashMap<String, u32> {
+    todo!()
+}
+
+/// Nối các từ bằng "-", bỏ từ rỗng: ["a", "", "b"] -> "a-b".
+/// Dùng `fold`, không dùng `join`.
+pub fn slug(words: &[&str]) -> String {
+    todo!()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::Item;
+    use std::collections::HashMap;
+    use std::num::ParseIntError;
+
+    fn items() -> Vec<Item> {
+        let item = |name: &str, price, quantity| Item {
+            name: name.to_string(),
+            price,
+            quantity,
+        };
+        vec![
+            item("book", 120, 2),
+            item("pen", 10, 0),
+            item("bag", 300, 1),
+        ]
+    }
+
+    pub fn check_total(total: fn(&[Item]) -> u32) {
+        assert_eq!(total(&items()), 540);
+        assert_eq!(total(&[]), 0);
+    }
+
+    pub fn check_in_stock_names(in_stock_names: fn(&[Item]) -> Vec<String>) {
+        assert_eq!(in_stock_names(&items()), ["BOOK", "BAG"]);
+    }
+
+    pub fn check_parse_all(parse_all: fn(&[&str]) -> Result<Vec<i32>, ParseIntError>) {
+        assert_eq!(parse_all(&["1", "-2", "30"]), Ok(vec![1, -2, 30]));
+        assert!(parse_all(&["1", "two", "3"]).is_err());
+        assert_eq!(parse_all(&[]), Ok(vec![]));
+    }
+
+    pub fn check_price_list(price_list: fn(&[Item], u32) -> HashMap<String, u32>) {
+        let prices = price_list(&items(), 100);
+        assert_eq!(
+            prices,
+            HashMap::from([("book".to_string(), 120), ("bag".to_string(), 300)])
+        );
+        assert!(price_list(&items(), 1000).is_empty());
+    }
+
+    pub fn check_slug(slug: fn(&[&str]) -> String) {
+        assert_eq!(slug(&["hello", "", "rust", "world"]), "hello-rust-world");
+        assert_eq!(slug(&["one"]), "one");
+        assert_eq!(slug(&["", ""]), "");
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex04 -- --ignored`"]
+    fn total() {
+        check_total(super::total);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex04 -- --ignored`"]
+    fn in_stock_names() {
+        check_in_stock_names(super::in_stock_names);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex04 -- --ignored`"]
+    fn parse_all() {
+        check_parse_all(super::parse_all);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex04 -- --ignored`"]
+    fn price_list() {
+        check_price_list(super::price_list);
+    }
+
+    #[test]
+    #[ignore = "bài tập: chạy với `cargo test ex04 -- --ignored`"]
+    fn slug() {
+        check_slug(super::slug);
+    }
+}
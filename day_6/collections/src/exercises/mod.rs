@@ -0,0 +1,10 @@
+// Mỗi file là một bài. Làm theo thứ tự: bài sau dùng những gì bài trước
+// đã luyện.
+
+// Tham số chưa được dùng cho tới khi bạn thay todo!() bằng lời giải
+#![allow(unused_variables)]
+
+pub mod ex01_vec;
+pub mod ex02_hashmap;
+pub mod ex03_hashset;
+pub mod ex04_iterators;
@@ -0,0 +1,8 @@
+//! Day 6: Collections và iterators
+//!
+//! Bài tập nằm trong `exercises/`, lời giải mẫu trong `solutions.rs`.
+//! Cùng một bộ test chạy trên cả hai: trên bài của bạn khi chạy
+//! `cargo test -- --ignored`, trên lời giải mẫu khi chạy `cargo test`.
+
+pub mod exercises;
+pub mod solutions;
@@ -0,0 +1,193 @@
+// Lời giải mẫu. Chỉ xem sau khi đã tự làm!
+//
+// Mỗi module chạy đúng bộ test của bài tương ứng trong `exercises/`.
+
+pub mod ex01_vec {
+    pub fn evens(numbers: &[i32]) -> Vec<i32> {
+        let mut result = Vec::new();
+        for n in numbers {
+            if n % 2 == 0 {
+                result.push(*n);
+            }
+        }
+        result
+    }
+
+    pub fn sorted_unique(mut numbers: Vec<i32>) -> Vec<i32> {
+        numbers.sort();
+        numbers.dedup();
+        numbers
+    }
+
+    pub fn second_largest(numbers: &[i32]) -> Option<i32> {
+        let unique = sorted_unique(numbers.to_vec());
+        // len() < 2 thì checked_sub trả về None, không panic
+        let index = unique.len().checked_sub(2)?;
+        unique.get(index).copied()
+    }
+
+    pub fn double_in_place(numbers: &mut [i32]) {
+        for n in numbers {
+            *n *= 2;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::exercises::ex01_vec::tests::*;
+
+        #[test]
+        fn solutions() {
+            check_evens(super::evens);
+            check_sorted_unique(super::sorted_unique);
+            check_second_largest(super::second_largest);
+            check_double_in_place(super::double_in_place);
+        }
+    }
+}
+
+pub mod ex02_hashmap {
+    use std::collections::HashMap;
+
+    pub fn word_count(text: &str) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for word in text.split_whitespace() {
+            *counts.entry(word.to_lowercase()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn group_by_role(users: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+        for (email, role) in users {
+            groups
+                .entry(role.to_string())
+                .or_default()
+                .push(email.to_string());
+        }
+        groups
+    }
+
+    pub fn invert(map: &HashMap<String, u32>) -> HashMap<u32, String> {
+        let mut inverted: HashMap<u32, String> = HashMap::new();
+        for (key, value) in map {
+            // HashMap không có thứ tự: phải so sánh, không phải "ai tới trước"
+            let keep = inverted.entry(*value).or_insert_with(|| key.clone());
+            if key < keep {
+                *keep = key.clone();
+            }
+        }
+        inverted
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::exercises::ex02_hashmap::tests::*;
+
+        #[test]
+        fn solutions() {
+            check_word_count(super::word_count);
+            check_group_by_role(super::group_by_role);
+            check_invert(super::invert);
+        }
+    }
+}
+
+pub mod ex03_hashset {
+    use std::collections::HashSet;
+
+    pub fn unique_emails(emails: &[&str]) -> HashSet<String> {
+        let mut unique = HashSet::new();
+        for email in emails {
+            unique.insert(email.to_lowercase());
+        }
+        unique
+    }
+
+    pub fn first_repeat(numbers: &[i32]) -> Option<i32> {
+        let mut seen = HashSet::new();
+        for n in numbers {
+            if !seen.insert(*n) {
+                return Some(*n);
+            }
+        }
+        None
+    }
+
+    pub fn common(a: &[i32], b: &[i32]) -> Vec<i32> {
+        let a: HashSet<i32> = a.iter().copied().collect();
+        let b: HashSet<i32> = b.iter().copied().collect();
+        let mut both: Vec<i32> = a.intersection(&b).copied().collect();
+        both.sort();
+        both
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::exercises::ex03_hashset::tests::*;
+
+        #[test]
+        fn solutions() {
+            check_unique_emails(super::unique_emails);
+            check_first_repeat(super::first_repeat);
+            check_common(super::common);
+        }
+    }
+}
+
+pub mod ex04_iterators {
+    use crate::exercises::ex04_iterators::Item;
+    use std::collections::HashMap;
+    use std::num::ParseIntError;
+
+    pub fn total(items: &[Item]) -> u32 {
+        items.iter().map(|item| item.price * item.quantity).sum()
+    }
+
+    pub fn in_stock_names(items: &[Item]) -> Vec<String> {
+        items
+            .iter()
+            .filter(|item| item.quantity > 0)
+            .map(|item| item.name.to_uppercase())
+            .collect()
+    }
+
+    pub fn parse_all(values: &[&str]) -> Result<Vec<i32>, ParseIntError> {
+        values.iter().map(|value| value.parse()).collect()
+    }
+
+    pub fn price_list(items: &[Item], min_price: u32) -> HashMap<String, u32> {
+        items
+            .iter()
+            .filter(|item| item.price >= min_price)
+            .map(|item| (item.name.clone(), item.price))
+            .collect()
+    }
+
+    pub fn slug(words: &[&str]) -> String {
+        words
+            .iter()
+            .filter(|word| !word.is_empty())
+            .fold(String::new(), |acc, word| {
+                if acc.is_empty() {
+                    word.to_string()
+                } else {
+                    acc + "-" + word
+                }
+            })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::exercises::ex04_iterators::tests::*;
+
+        #[test]
+        fn solutions() {
+            check_total(super::total);
+            check_in_stock_names(super::in_stock_names);
+            check_parse_all(super::parse_all);
+            check_price_list(super::price_list);
+            check_slug(super::slug);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Day 7: Traits và generics
+//!
+//! Cùng một bài toán (tính diện tích, xuất ra text) viết theo ba cách:
+//! generic bound (static dispatch), trait object (dynamic dispatch), và
+//! blanket impl - pattern mà `UserRepositoryExt` trong
+//! material/dependency_inversion dùng.
+
+use std::f64::consts::PI;
+
+// ===================================================================
+// 1. Trait với default method
+// ===================================================================
+
+pub trait Shape {
+    /// Mỗi kiểu phải tự viết
+    fn area(&self) -> f64;
+
+    fn name(&self) -> &'static str;
+
+    /// Default method: có sẵn, dùng `area` và `name` của kiểu implement
+    fn describe(&self) -> String {
+        format!("{} with area {:.2}", self.name(), self.area())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub radius: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rectangle {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Square {
+    pub side: f64,
+}
+
+impl Shape for Circle {
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn name(&self) -> &'static str {
+        "circle"
+    }
+}
+
+impl Shape for Rectangle {
+    fn area(&self) -> f64 {
+        self.width * self.height
+    }
+
+    fn name(&self) -> &'static str {
+        "rectangle"
+    }
+}
+
+impl Shape for Square {
+    fn area(&self) -> f64 {
+        self.side * self.side
+    }
+
+    fn name(&self) -> &'static str {
+        "square"
+    }
+
+    // Override default method
+    fn describe(&self) -> String {
+        format!("square {}x{}", self.side, self.side)
+    }
+}
+
+// ===================================================================
+// 2. Generic bound vs trait object
+// ===================================================================
+
+/// Generic: `S` là MỘT kiểu cụ thể, biết lúc compile. Compiler sinh riêng
+/// `total_area::<Circle>`, `total_area::<Square>`... (static dispatch).
+/// Nhưng `&[S]` không chứa được Circle lẫn Square.
+pub fn total_area<S: Shape>(shapes: &[S]) -> f64 {
+    shapes.iter().map(|shape| shape.area()).sum()
+}
+
+/// Trait object: mỗi phần tử có thể là kiểu khác nhau, `area()` được gọi
+/// qua vtable lúc chạy (dynamic dispatch)
+pub fn total_area_dyn(shapes: &[Box<dyn Shape>]) -> f64 {
+    shapes.iter().map(|shape| shape.area()).sum()
+}
+
+/// `impl Shape` ở tham số: cách viết ngắn của `fn larger<A: Shape, B:
+/// Shape>(a: &A, b: &B)`
+pub fn larger_name(a: &impl Shape, b: &impl Shape) -> &'static str {
+    if a.area() >= b.area() {
+        a.name()
+    } else {
+        b.name()
+    }
+}
+
+// ===================================================================
+// 3. Serializer - generic và trait object cùng lúc
+// ===================================================================
+
+pub trait Serializer {
+    fn shape(&self, shape: &dyn Shape) -> String;
+
+    /// Đầu và cuối output; mặc định không có gì
+    fn begin(&self) -> String {
+        String::new()
+    }
+
+    fn end(&self) -> String {
+        String::new()
+    }
+
+    fn separator(&self) -> &'static str {
+        "\n"
+    }
+}
+
+pub struct Json;
+
+pub struct Csv;
+
+impl Serializer for Json {
+    fn shape(&self, shape: &dyn Shape) -> String {
+        format!(
+            r#"{{"name":"{}","area":{:.2}}}"#,
+            shape.name(),
+            shape.area()
+        )
+    }
+
+    fn begin(&self) -> String {
+        "[".to_string()
+    }
+
+    fn end(&self) -> String {
+        "]".to_string()
+    }
+
+    fn separator(&self) -> &'static str {
+        ","
+    }
+}
+
+impl Serializer for Csv {
+    fn shape(&self, shape: &dyn Shape) -> String {
+        format!("{},{:.2}", shape.name(), shape.area())
+    }
+
+    fn begin(&self) -> String {
+        "name,area\n".to_string()
+    }
+}
+
+/// Serializer là generic (chọn lúc compile), shapes là trait object (lẫn
+/// nhiều kiểu). Giống `UserService<R: UserRepository>` giữ một
+/// `Arc<dyn CacheService>` trong material.
+pub fn export<S: Serializer>(serializer: &S, shapes: &[Box<dyn Shape>]) -> String {
+    let body: Vec<String> = shapes
+        .iter()
+        .map(|shape| serializer.shape(shape.as_ref()))
+        .collect();
+    format!(
+        "{}{}{}",
+        serializer.begin(),
+        body.join(serializer.separator()),
+        serializer.end()
+    )
+}
+
+// ===================================================================
+// 4. Blanket impl - extension trait
+// ===================================================================
+
+/// Thêm method cho MỌI `Shape` mà không sửa trait `Shape`, không sửa từng
+/// kiểu. Giống hệt `UserRepositoryExt: UserRepository` trong material.
+pub trait ShapeExt: Shape {
+    fn is_larger_than(&self, other: &dyn Shape) -> bool {
+        self.area() > other.area()
+    }
+
+    fn to_json(&self) -> String {
+        Json.shape(&ShapeRef(self))
+    }
+}
+
+/// Blanket impl: mọi `T: Shape` đều có `ShapeExt`. `?Sized` để `dyn Shape`
+/// cũng có (trong material: `impl<T: UserRepository + ?Sized>`, vì repo
+/// thường nằm sau `Arc<dyn UserRepository>`).
+impl<T: Shape + ?Sized> ShapeExt for T {}
+
+// `&T` với `T: ?Sized` không đổi thẳng thành `&dyn Shape` được (T có thể
+// đã là `dyn Shape`), nên bọc lại một lớp
+struct ShapeRef<'a, T: ?Sized>(&'a T);
+
+impl<T: Shape + ?Sized> Shape for ShapeRef<'_, T> {
+    fn area(&self) -> f64 {
+        self.0.area()
+    }
+
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed() -> Vec<Box<dyn Shape>> {
+        vec![
+            Box::new(Circle { radius: 1.0 }),
+            Box::new(Rectangle {
+                width: 2.0,
+                height: 3.0,
+            }),
+            Box::new(Square { side: 2.0 }),
+        ]
+    }
+
+    #[test]
+    fn test_default_and_overridden_describe() {
+        assert_eq!(Circle { radius: 1.0 }.describe(), "circle with area 3.14");
+        assert_eq!(Square { side: 2.0 }.describe(), "square 2x2");
+    }
+
+    #[test]
+    fn test_generic_needs_one_type() {
+        let squares = [Square { side: 1.0 }, Square { side: 3.0 }];
+        assert_eq!(total_area(&squares), 10.0);
+        // total_area(&[Square { side: 1.0 }, Circle { radius: 1.0 }]) // lỗi: mismatched types
+    }
+
+    #[test]
+    fn test_trait_objects_mix_types() {
+        assert!((total_area_dyn(&mixed()) - (PI + 6.0 + 4.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_impl_trait_arguments() {
+        let circle = Circle { radius: 1.0 };
+        let square = Square { side: 2.0 };
+        assert_eq!(larger_name(&circle, &square), "square");
+    }
+
+    #[test]
+    fn test_export_json() {
+        assert_eq!(
+            export(&Json, &mixed()),
+            r#"[{"name":"circle","area":3.14},{"name":"rectangle","area":6.00},{"name":"square","area":4.00}]"#
+        );
+    }
+
+    #[test]
+    fn test_export_csv() {
+        assert_eq!(
+            export(&Csv, &mixed()),
+            "name,area\ncircle,3.14\nrectangle,6.00\nsquare,4.00"
+        );
+        assert_eq!(export(&Csv, &[]), "name,area\n");
+    }
+
+    #[test]
+    fn test_blanket_impl_covers_every_shape() {
+        let square = Square { side: 2.0 };
+        // Kiểu cụ thể
+        assert!(square.is_larger_than(&Circle { radius: 1.0 }));
+        assert_eq!(square.to_json(), r#"{"name":"square","area":4.00}"#);
+        // Và cả trait object, nhờ `?Sized`
+        let shapes = mixed();
+        assert!(shapes[1].is_larger_than(shapes[2].as_ref()));
+        assert_eq!(shapes[0].to_json(), r#"{"name":"circle","area":3.14}"#);
+    }
+}
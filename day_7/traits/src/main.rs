@@ -0,0 +1,33 @@
+use day_7_traits::{
+    Circle, Csv, Json, Rectangle, Shape, ShapeExt, Square, export, total_area, total_area_dyn,
+};
+
+fn main() {
+    // Generic: một kiểu
+    let squares = [Square { side: 1.0 }, Square { side: 2.0 }];
+    println!("squares: {:.2}", total_area(&squares));
+
+    // Trait object: nhiều kiểu trong một Vec
+    let shapes: Vec<Box<dyn Shape>> = vec![
+        Box::new(Circle { radius: 1.5 }),
+        Box::new(Rectangle {
+            width: 2.0,
+            height: 4.0,
+        }),
+        Box::new(Square { side: 3.0 }),
+    ];
+    for shape in &shapes {
+        println!("{}", shape.describe());
+    }
+    println!("total: {:.2}", total_area_dyn(&shapes));
+
+    // Cùng shapes, hai serializer
+    println!("{}", export(&Json, &shapes));
+    println!("{}", export(&Csv, &shapes));
+
+    // Method từ blanket impl, có sẵn trên mọi Shape
+    println!(
+        "square larger than circle: {}",
+        shapes[2].is_larger_than(shapes[0].as_ref())
+    );
+}
@@ -0,0 +1,87 @@
+//! Bài tập: sửa memory leak
+//!
+//! Hai `Employee` trỏ tới nhau: nhân viên biết manager, manager biết danh
+//! sách nhân viên. Cả hai hướng đều là `Rc`, nên sau khi drop mọi biến,
+//! strong count vẫn là 1 ở mỗi bên: không ai được drop. Rust không coi
+//! đây là lỗi (leak là "safe"), compiler không báo gì.
+//!
+//! Nhiệm vụ: sửa `Employee` để hết leak, rồi chạy
+//! `cargo test cycle -- --ignored`.
+//!
+//! Gợi ý (hint): chỉ một hướng được phép sở hữu. Manager sở hữu nhân viên
+//! (`reports`), còn `manager` nên là `RefCell<Weak<Employee>>`. `manager()`
+//! khi đó dùng `upgrade()`. Xem `tree.rs`: `parent` làm đúng việc này.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct Employee {
+    pub name: String,
+    manager: RefCell<Option<Rc<Employee>>>, // <- đây là chỗ gây leak
+    reports: RefCell<Vec<Rc<Employee>>>,
+}
+
+impl Employee {
+    pub fn new(name: &str) -> Rc<Employee> {
+        Rc::new(Employee {
+            name: name.to_string(),
+            manager: RefCell::new(None),
+            reports: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn assign(manager: &Rc<Employee>, employee: &Rc<Employee>) {
+        *employee.manager.borrow_mut() = Some(Rc::clone(manager));
+        manager.reports.borrow_mut().push(Rc::clone(employee));
+    }
+
+    pub fn manager(&self) -> Option<Rc<Employee>> {
+        self.manager.borrow().clone()
+    }
+
+    pub fn reports(&self) -> Vec<String> {
+        self.reports
+            .borrow()
+            .iter()
+            .map(|employee| employee.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hành vi không đổi sau khi sửa
+    #[test]
+    fn test_assign_links_both_ways() {
+        let boss = Employee::new("An");
+        let dev = Employee::new("Binh");
+        Employee::assign(&boss, &dev);
+
+        assert_eq!(dev.manager().unwrap().name, "An");
+        assert_eq!(boss.reports(), ["Binh"]);
+        assert!(boss.manager().is_none());
+    }
+
+    #[test]
+    #[ignore = "bài tập: sửa leak rồi chạy `cargo test cycle -- --ignored`"]
+    fn test_no_leak_after_drop() {
+        let boss = Employee::new("An");
+        let dev = Employee::new("Binh");
+        Employee::assign(&boss, &dev);
+
+        // Chỉ manager sở hữu nhân viên, nhân viên không sở hữu manager
+        assert_eq!(Rc::strong_count(&boss), 1);
+        assert_eq!(Rc::strong_count(&dev), 2);
+
+        // Weak không giữ value sống, nên dùng được để kiểm tra đã drop chưa
+        let boss_alive = Rc::downgrade(&boss);
+        let dev_alive = Rc::downgrade(&dev);
+        drop(boss);
+        drop(dev);
+        assert!(boss_alive.upgrade().is_none(), "An is leaked");
+        assert!(dev_alive.upgrade().is_none(), "Binh is leaked");
+    }
+}
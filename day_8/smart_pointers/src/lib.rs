@@ -0,0 +1,11 @@
+//! Day 8: Smart pointers
+//!
+//! - `stack`: linked list với `Box` - một owner
+//! - `tree`: cây với `Rc` (nhiều owner), `RefCell` (sửa qua `&`), `Weak`
+//!   (trỏ ngược lên cha mà không giữ cha sống)
+//! - `cycle`: bài tập, một vòng `Rc` bị rò rỉ bộ nhớ (memory leak), sửa
+//!   bằng `Weak`
+
+pub mod cycle;
+pub mod stack;
+pub mod tree;
@@ -0,0 +1,37 @@
+use std::rc::Rc;
+
+use day_8_smart_pointers::cycle::Employee;
+use day_8_smart_pointers::stack::Stack;
+use day_8_smart_pointers::tree::Node;
+
+fn main() {
+    // Box: một owner
+    let mut stack = Stack::new();
+    for i in 1..=3 {
+        stack.push(i);
+    }
+    println!("stack: {:?}", stack.iter().collect::<Vec<_>>());
+
+    // Rc + Weak: đếm tham chiếu
+    let leaf = Node::new(3);
+    {
+        let branch = Node::new(5);
+        Node::add_child(&branch, Rc::clone(&leaf));
+        println!(
+            "branch: strong = {}, weak = {}",
+            Rc::strong_count(&branch),
+            Rc::weak_count(&branch)
+        );
+        println!("leaf path: {:?}", leaf.path());
+    }
+    println!("after branch is dropped, leaf.parent = {:?}", leaf.parent());
+
+    // Vòng Rc: sau khi drop hết biến, cả hai vẫn còn
+    let boss = Employee::new("An");
+    let dev = Employee::new("Binh");
+    Employee::assign(&boss, &dev);
+    let boss_alive = Rc::downgrade(&boss);
+    drop(boss);
+    drop(dev);
+    println!("An leaked: {}", boss_alive.upgrade().is_some());
+}
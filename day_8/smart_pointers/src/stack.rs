@@ -0,0 +1,129 @@
+//! Stack là một singly linked list: mỗi node sở hữu (own) node sau nó.
+//!
+//! `struct Node { next: Node }` không compile được (E0072: kích thước vô
+//! hạn). `Box<Node>` chỉ là một pointer, kích thước cố định, node thật nằm
+//! trên heap.
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    value: T,
+    next: Link<T>,
+}
+
+pub struct Stack<T> {
+    head: Link<T>,
+    len: usize,
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack { head: None, len: 0 }
+    }
+
+    pub fn push(&mut self, value: T) {
+        // take(): lấy head ra, để lại None, vì không thể move ra khỏi &mut
+        let next = self.head.take();
+        self.head = Some(Box::new(Node { value, next }));
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.head.take().map(|node| {
+            self.head = node.next;
+            self.len -= 1;
+            node.value
+        })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// Duyệt từ đỉnh xuống, chỉ mượn (borrow)
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Stack::new()
+    }
+}
+
+/// Drop mặc định là đệ quy: node 1 drop node 2 drop node 3... list dài
+/// thì tràn stack (stack overflow). Gỡ từng node bằng vòng lặp.
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        let mut link = self.head.take();
+        while let Some(mut node) = link {
+            link = node.next.take();
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            &node.value
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop_is_last_in_first_out() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.pop(), None);
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.peek(), Some(&3));
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.len(), 1);
+        assert!(!stack.is_empty());
+    }
+
+    #[test]
+    fn test_iter_borrows() {
+        let mut stack = Stack::new();
+        for word in ["a", "b", "c"] {
+            stack.push(word.to_string());
+        }
+        let seen: Vec<&String> = stack.iter().collect();
+        assert_eq!(seen, ["c", "b", "a"]);
+        // Vẫn dùng được stack sau khi iter
+        assert_eq!(stack.len(), 3);
+    }
+
+    #[test]
+    fn test_long_list_drops_without_overflow() {
+        let mut stack = Stack::new();
+        for i in 0..1_000_000 {
+            stack.push(i);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Cây mà con biết cha (parent pointer).
+//!
+//! - Cha sở hữu con: `children: RefCell<Vec<Rc<Node>>>`
+//! - Con chỉ trỏ tới cha: `parent: RefCell<Weak<Node>>`. Nếu là `Rc` thì
+//!   cha giữ con, con giữ cha: strong count không bao giờ về 0, không node
+//!   nào được drop.
+//! - `RefCell`: node được chia sẻ qua `Rc` (chỉ có `&Node`) nhưng vẫn cần
+//!   sửa `children` / `parent`. Luật borrow được kiểm tra lúc chạy
+//!   (`borrow()` / `borrow_mut()`), sai thì panic.
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+#[derive(Debug)]
+pub struct Node {
+    pub value: i32,
+    parent: RefCell<Weak<Node>>,
+    children: RefCell<Vec<Rc<Node>>>,
+}
+
+impl Node {
+    pub fn new(value: i32) -> Rc<Node> {
+        Rc::new(Node {
+            value,
+            parent: RefCell::new(Weak::new()),
+            children: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Gắn `child` vào `parent`: cha giữ một `Rc` (strong), con giữ một
+    /// `Weak`
+    pub fn add_child(parent: &Rc<Node>, child: Rc<Node>) {
+        *child.parent.borrow_mut() = Rc::downgrade(parent);
+        parent.children.borrow_mut().push(child);
+    }
+
+    /// `upgrade()` trả về `None` nếu cha đã bị drop
+    pub fn parent(&self) -> Option<Rc<Node>> {
+        self.parent.borrow().upgrade()
+    }
+
+    pub fn children(&self) -> Vec<Rc<Node>> {
+        self.children.borrow().clone()
+    }
+
+    /// Tổng value của node và mọi node con cháu
+    pub fn sum(&self) -> i32 {
+        self.value
+            + self
+                .children
+                .borrow()
+                .iter()
+                .map(|child| child.sum())
+                .sum::<i32>()
+    }
+
+    /// Đường đi từ gốc tới node này, đi ngược lên bằng `parent`
+    pub fn path(&self) -> Vec<i32> {
+        let mut path = vec![self.value];
+        let mut current = self.parent();
+        while let Some(node) = current {
+            path.push(node.value);
+            current = node.parent();
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_while_building() {
+        let leaf = Node::new(3);
+        assert_eq!(Rc::strong_count(&leaf), 1);
+        assert_eq!(Rc::weak_count(&leaf), 0);
+
+        let branch = Node::new(5);
+        Node::add_child(&branch, Rc::clone(&leaf));
+
+        // leaf: biến `leaf` + Vec children của branch
+        assert_eq!(Rc::strong_count(&leaf), 2);
+        // branch: chỉ biến `branch`; leaf trỏ lên bằng Weak
+        assert_eq!(Rc::strong_count(&branch), 1);
+        assert_eq!(Rc::weak_count(&branch), 1);
+
+        assert_eq!(leaf.parent().unwrap().value, 5);
+        // parent() tạo ra một Rc tạm, drop ngay sau dòng trên
+        assert_eq!(Rc::strong_count(&branch), 1);
+    }
+
+    #[test]
+    fn test_parent_is_gone_after_drop() {
+        let leaf = Node::new(3);
+        {
+            let branch = Node::new(5);
+            Node::add_child(&branch, Rc::clone(&leaf));
+            assert!(leaf.parent().is_some());
+        }
+        // Weak không giữ branch sống
+        assert!(leaf.parent().is_none());
+        assert_eq!(Rc::strong_count(&leaf), 1);
+    }
+
+    #[test]
+    fn test_whole_tree_is_freed() {
+        let root = Node::new(1);
+        let child = Node::new(2);
+        Node::add_child(&root, Rc::clone(&child));
+        Node::add_child(&child, Node::new(3));
+
+        let root_alive = Rc::downgrade(&root);
+        let child_alive = Rc::downgrade(&child);
+        drop(child);
+        // root vẫn giữ child
+        assert!(child_alive.upgrade().is_some());
+
+        drop(root);
+        assert!(root_alive.upgrade().is_none());
+        assert!(child_alive.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_walk_down_and_up() {
+        let root = Node::new(1);
+        let child = Node::new(2);
+        let grandchild = Node::new(3);
+        Node::add_child(&root, Rc::clone(&child));
+        Node::add_child(&root, Node::new(10));
+        Node::add_child(&child, Rc::clone(&grandchild));
+
+        assert_eq!(root.sum(), 16);
+        assert_eq!(root.children().len(), 2);
+        assert_eq!(grandchild.path(), [1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn test_refcell_checks_borrows_at_runtime() {
+        let node = Node::new(1);
+        let _reading = node.children.borrow();
+        // Compile được, nhưng panic: đang có một borrow() chưa trả
+        node.children.borrow_mut().push(Node::new(2));
+    }
+}
@@ -0,0 +1,276 @@
+//! Day 9: Threads và channels qua một thread pool
+//!
+//! N worker thread cùng đọc job từ một `mpsc` channel. Đóng channel (drop
+//! `Sender`) là tín hiệu dừng: worker làm nốt các job còn trong hàng đợi
+//! rồi thoát, pool `join` từng thread (graceful shutdown). Đây cũng là hình
+//! dạng của `BackgroundTask` trong material/dependency_inversion, chỉ khác
+//! là material dùng tokio task thay cho OS thread.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Một job: chạy một lần (`FnOnce`), gửi được sang thread khác (`Send`),
+/// không mượn gì của caller (`'static`)
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Kết quả sau khi pool dừng
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub completed: usize,
+    pub panicked: usize,
+}
+
+pub struct ThreadPool {
+    // Option để `stop` lấy ra và drop được, đóng channel
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<Report>>,
+}
+
+impl ThreadPool {
+    /// Tạo pool với `size` worker.
+    ///
+    /// # Panics
+    ///
+    /// Nếu `size` là 0: pool không có worker thì job không bao giờ chạy.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0, "a thread pool needs at least one worker");
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        // Receiver chỉ có một owner: chia sẻ bằng Arc, và chỉ một worker
+        // được nhận tại một thời điểm nhờ Mutex
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("worker-{}", id))
+                    .spawn(move || work(receiver))
+                    .expect("failed to spawn a worker thread")
+            })
+            .collect();
+
+        ThreadPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Đưa job vào hàng đợi, trả về ngay, không chờ job chạy
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let sender = self.sender.as_ref().expect("the pool is running");
+        // Chỉ lỗi khi mọi receiver đã drop, tức mọi worker đã chết: bug
+        sender.send(Box::new(job)).expect("workers are alive");
+    }
+
+    /// Không nhận job mới, chờ làm xong mọi job đã nhận, rồi dừng.
+    ///
+    /// Nhận `self` (by value): sau khi gọi thì không còn pool để
+    /// `execute`, compiler chặn luôn lỗi "gửi job cho pool đã dừng".
+    pub fn shutdown(mut self) -> Report {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Report {
+        // Drop Sender: channel đóng, `recv()` trả về Err khi hàng đợi rỗng
+        drop(self.sender.take());
+
+        let mut total = Report::default();
+        for worker in self.workers.drain(..) {
+            // Job panic đã được bắt trong worker, nên join chỉ lỗi nếu
+            // chính vòng lặp của worker panic
+            let report = worker.join().expect("worker loop panicked");
+            total.completed += report.completed;
+            total.panicked += report.panicked;
+        }
+        total
+    }
+}
+
+/// Quên gọi `shutdown` thì pool vẫn dừng gọn khi ra khỏi scope
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn work(receiver: Arc<Mutex<Receiver<Job>>>) -> Report {
+    let mut report = Report::default();
+    loop {
+        // Lock chỉ giữ trong câu lệnh này: guard tạm bị drop ở dấu `;`.
+        // `while let Ok(job) = receiver.lock().unwrap().recv()` thì khác:
+        // guard sống tới hết thân vòng lặp, và chỉ một worker chạy job tại
+        // một thời điểm.
+        let message = receiver.lock().unwrap().recv();
+        let Ok(job) = message else {
+            // Channel đã đóng và rỗng
+            break;
+        };
+        // Một job panic không được làm chết worker
+        match panic::catch_unwind(AssertUnwindSafe(job)) {
+            Ok(()) => report.completed += 1,
+            Err(_) => report.panicked += 1,
+        }
+    }
+    report
+}
+
+/// Đếm từ của mỗi text song song, trả về theo đúng thứ tự input.
+///
+/// Kết quả đi về qua một channel thứ hai: mỗi job giữ một bản clone của
+/// `Sender`, main thread là `Receiver`. Job xong theo thứ tự bất kỳ, nên
+/// gửi kèm index.
+pub fn word_counts(pool: &ThreadPool, texts: Vec<String>) -> Vec<usize> {
+    let (sender, receiver) = mpsc::channel();
+    let total = texts.len();
+
+    for (index, text) in texts.into_iter().enumerate() {
+        let sender = sender.clone();
+        pool.execute(move || {
+            let count = text.split_whitespace().count();
+            // Receiver đã drop thì không ai cần kết quả nữa: bỏ qua
+            let _ = sender.send((index, count));
+        });
+    }
+    // Còn giữ bản gốc thì `receiver.iter()` không bao giờ kết thúc
+    drop(sender);
+
+    let mut counts = vec![0; total];
+    for (index, count) in receiver {
+        counts[index] = count;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_every_job_runs_before_shutdown_returns() {
+        let pool = ThreadPool::new(4);
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..100 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        let report = pool.shutdown();
+        assert_eq!(done.load(Ordering::SeqCst), 100);
+        assert_eq!(
+            report,
+            Report {
+                completed: 100,
+                panicked: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_queued_slow_jobs_are_not_dropped() {
+        let pool = ThreadPool::new(2);
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(20));
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        // execute không chờ: lúc này hầu hết job còn trong hàng đợi
+        assert!(done.load(Ordering::SeqCst) < 6);
+
+        pool.shutdown();
+        assert_eq!(done.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_jobs_run_in_parallel() {
+        let pool = ThreadPool::new(4);
+        // Barrier chỉ mở khi đủ 4 thread cùng chờ: nếu job chạy lần lượt
+        // thì test này treo
+        let barrier = Arc::new(Barrier::new(4));
+        let names = Arc::new(Mutex::new(HashSet::new()));
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            let names = Arc::clone(&names);
+            pool.execute(move || {
+                barrier.wait();
+                let name = thread::current().name().unwrap().to_string();
+                names.lock().unwrap().insert(name);
+            });
+        }
+
+        pool.shutdown();
+        assert_eq!(names.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_a_panicking_job_does_not_kill_the_pool() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| panic!("bad job"));
+        let done = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&done);
+        pool.execute(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let report = pool.shutdown();
+        // Worker duy nhất vẫn sống để chạy job thứ hai
+        assert_eq!(done.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            report,
+            Report {
+                completed: 1,
+                panicked: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_drop_also_waits_for_jobs() {
+        let done = Arc::new(AtomicUsize::new(0));
+        {
+            let pool = ThreadPool::new(2);
+            for _ in 0..10 {
+                let done = Arc::clone(&done);
+                pool.execute(move || {
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        }
+        assert_eq!(done.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_word_counts_keep_input_order() {
+        let pool = ThreadPool::new(3);
+        let texts = ["one", "two words", "", "a b c d"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(word_counts(&pool, texts), [1, 2, 0, 4]);
+        // Pool dùng lại được sau đó
+        assert_eq!(word_counts(&pool, vec!["x y".to_string()]), [2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one worker")]
+    fn test_empty_pool_is_rejected() {
+        ThreadPool::new(0);
+    }
+}
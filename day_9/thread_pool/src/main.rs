@@ -0,0 +1,30 @@
+use std::thread;
+use std::time::Duration;
+
+use day_9_thread_pool::{ThreadPool, word_counts};
+
+fn main() {
+    let pool = ThreadPool::new(3);
+    println!("pool with {} workers", pool.size());
+
+    for id in 0..6 {
+        pool.execute(move || {
+            let worker = thread::current().name().unwrap_or("?").to_string();
+            thread::sleep(Duration::from_millis(100));
+            println!("job {} done on {}", id, worker);
+        });
+    }
+    // Job panic: worker in lỗi ra stderr nhưng vẫn sống
+    pool.execute(|| panic!("this job fails"));
+
+    let texts = vec![
+        "Rust an toàn".to_string(),
+        "và nhanh".to_string(),
+        "".to_string(),
+    ];
+    println!("word counts: {:?}", word_counts(&pool, texts));
+
+    // Chờ mọi job xong rồi mới thoát
+    let report = pool.shutdown();
+    println!("{:?}", report);
+}
@@ -0,0 +1,315 @@
+//! Runner cho bài tập trong các crate day_*
+//!
+//! Một file bài tập là một file `.rs` trong `day_*/<crate>/src/` có dòng
+//! `//! Gợi ý (hint):` trong phần doc ở đầu file. Bài được coi là xong khi
+//! mọi test trong module của nó pass, kể cả test `#[ignore]`.
+//!
+//! Không cần đăng ký bài mới ở đâu cả: thêm file có hint là runner thấy.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const HINT_MARKER: &str = "Gợi ý (hint):";
+const PROGRESS_FILE: &str = ".exercises-progress";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exercise {
+    /// `day_6/ex01_vec`: dùng trên command line và trong file progress
+    pub key: String,
+    /// Dòng doc đầu tiên của file, ví dụ "Bài 1: Vec"
+    pub title: String,
+    pub day: u32,
+    /// Thư mục chứa Cargo.toml của crate
+    pub crate_dir: PathBuf,
+    pub file: PathBuf,
+    /// Filter cho `cargo test`, ví dụ `exercises::ex01_vec::`
+    pub filter: String,
+}
+
+/// Kết quả một lần chạy `cargo test`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// stdout + stderr của cargo: lỗi compile hoặc test fail
+    Failed(String),
+}
+
+impl Exercise {
+    /// `--include-ignored`: chạy cả test `#[ignore]` (bài tập) lẫn test
+    /// thường trong module (hành vi không được đổi khi sửa bài)
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()));
+        command
+            .arg("test")
+            .arg("--quiet")
+            .arg("--manifest-path")
+            .arg(self.crate_dir.join("Cargo.toml"))
+            .arg(&self.filter)
+            .arg("--")
+            .arg("--include-ignored");
+        command
+    }
+
+    pub fn run(&self) -> io::Result<Outcome> {
+        let output = self.command().output()?;
+        if output.status.success() {
+            return Ok(Outcome::Passed);
+        }
+        let mut log = String::from_utf8_lossy(&output.stderr).into_owned();
+        log.push_str(&String::from_utf8_lossy(&output.stdout));
+        Ok(Outcome::Failed(log))
+    }
+
+    pub fn hint(&self) -> io::Result<Option<String>> {
+        Ok(hint_of(&fs::read_to_string(&self.file)?))
+    }
+
+    /// So khớp tên gõ trên command line: cả key, hoặc chỉ tên file
+    pub fn matches(&self, name: &str) -> bool {
+        self.key == name || self.key.rsplit('/').next() == Some(name)
+    }
+}
+
+/// Mọi bài tập dưới `root`, theo thứ tự học: theo day (day_10 sau day_9),
+/// rồi theo đường dẫn file
+pub fn discover(root: &Path) -> io::Result<Vec<Exercise>> {
+    let mut exercises = Vec::new();
+    for day_entry in fs::read_dir(root)? {
+        let day_dir = day_entry?.path();
+        let Some(day) = day_number(&day_dir) else {
+            continue;
+        };
+        for crate_entry in fs::read_dir(&day_dir)? {
+            let crate_dir = crate_entry?.path();
+            let src = crate_dir.join("src");
+            if !crate_dir.join("Cargo.toml").is_file() || !src.is_dir() {
+                continue;
+            }
+            for file in rust_files(&src)? {
+                let source = fs::read_to_string(&file)?;
+                if hint_of(&source).is_none() {
+                    continue;
+                }
+                let relative = file.strip_prefix(&src).expect("file is under src");
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                exercises.push(Exercise {
+                    key: format!("day_{}/{}", day, stem),
+                    title: title_of(&source).unwrap_or_else(|| stem.to_string()),
+                    day,
+                    crate_dir: crate_dir.clone(),
+                    filter: test_filter(relative),
+                    file,
+                });
+            }
+        }
+    }
+    exercises.sort_by(|a, b| (a.day, &a.file).cmp(&(b.day, &b.file)));
+    Ok(exercises)
+}
+
+fn rust_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(rust_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// `day_10` -> 10; thư mục khác -> None
+pub fn day_number(dir: &Path) -> Option<u32> {
+    dir.file_name()?
+        .to_str()?
+        .strip_prefix("day_")?
+        .parse()
+        .ok()
+}
+
+/// Đường dẫn trong `src/` -> module path: `exercises/ex01_vec.rs` ->
+/// `exercises::ex01_vec::`. `mod.rs` là chính module cha.
+pub fn test_filter(relative: &Path) -> String {
+    let mut parts: Vec<String> = relative
+        .with_extension("")
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if parts.last().is_some_and(|last| last == "mod") {
+        parts.pop();
+    }
+    let mut filter = parts.join("::");
+    filter.push_str("::");
+    filter
+}
+
+// Các dòng `//!` liên tiếp ở đầu file, đã bỏ prefix
+fn doc_lines(source: &str) -> impl Iterator<Item = &str> {
+    source
+        .lines()
+        .map_while(|line| line.strip_prefix("//!"))
+        .map(str::trim)
+}
+
+fn title_of(source: &str) -> Option<String> {
+    doc_lines(source)
+        .next()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+/// Đoạn doc bắt đầu bằng `Gợi ý (hint):`, tới dòng doc trống đầu tiên
+pub fn hint_of(source: &str) -> Option<String> {
+    let mut lines = doc_lines(source).skip_while(|line| !line.starts_with(HINT_MARKER));
+    let first = lines.next()?.strip_prefix(HINT_MARKER)?.trim();
+    let mut hint = first.to_string();
+    for line in lines.take_while(|line| !line.is_empty()) {
+        hint.push(' ');
+        hint.push_str(line);
+    }
+    Some(hint)
+}
+
+/// Những bài đã xong, lưu trong `.exercises-progress` ở root, mỗi dòng một
+/// key. File không có nghĩa là chưa làm bài nào.
+#[derive(Debug)]
+pub struct Progress {
+    path: PathBuf,
+    done: BTreeSet<String>,
+}
+
+impl Progress {
+    pub fn load(root: &Path) -> io::Result<Progress> {
+        let path = root.join(PROGRESS_FILE);
+        let done = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(Progress { path, done })
+    }
+
+    pub fn is_done(&self, exercise: &Exercise) -> bool {
+        self.done.contains(&exercise.key)
+    }
+
+    /// Ghi kết quả một lần chạy, trả về `true` nếu có thay đổi
+    pub fn record(&mut self, exercise: &Exercise, passed: bool) -> bool {
+        if passed {
+            self.done.insert(exercise.key.clone())
+        } else {
+            self.done.remove(&exercise.key)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for key in &self.done {
+            text.push_str(key);
+            text.push('\n');
+        }
+        fs::write(&self.path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root() -> &'static Path {
+        Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap()
+    }
+
+    #[test]
+    fn test_hint_spans_lines_until_a_blank_doc_line() {
+        let source = "//! Bài 1: Vec\n//!\n//! Gợi ý (hint): dùng `iter`\n//! rồi `collect`.\n//!\n//! Không phải hint.\n";
+        assert_eq!(
+            hint_of(source).as_deref(),
+            Some("dùng `iter` rồi `collect`.")
+        );
+        assert_eq!(title_of(source).as_deref(), Some("Bài 1: Vec"));
+    }
+
+    #[test]
+    fn test_hint_only_counts_in_the_header() {
+        // Hint trong comment thường hoặc sau code thì không tính
+        assert_eq!(hint_of("//! Bài\n\n//! Gợi ý (hint): x\n"), None);
+        assert_eq!(hint_of("// Gợi ý (hint): x\n"), None);
+        assert_eq!(hint_of("//! Gợi ý (hint): x").as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn test_filter_follows_the_module_path() {
+        assert_eq!(test_filter(Path::new("cycle.rs")), "cycle::");
+        assert_eq!(
+            test_filter(Path::new("exercises/ex01_vec.rs")),
+            "exercises::ex01_vec::"
+        );
+        assert_eq!(test_filter(Path::new("exercises/mod.rs")), "exercises::");
+    }
+
+    #[test]
+    fn test_day_number() {
+        assert_eq!(day_number(Path::new("/repo/day_10")), Some(10));
+        assert_eq!(day_number(Path::new("day_6")), Some(6));
+        assert_eq!(day_number(Path::new("material")), None);
+        assert_eq!(day_number(Path::new("day_x")), None);
+    }
+
+    #[test]
+    fn test_discovers_the_course_in_order() {
+        let exercises = discover(root()).unwrap();
+        let keys: Vec<&str> = exercises.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "day_6/ex01_vec",
+                "day_6/ex02_hashmap",
+                "day_6/ex03_hashset",
+                "day_6/ex04_iterators",
+                "day_8/cycle",
+            ]
+        );
+        assert_eq!(exercises[0].filter, "exercises::ex01_vec::");
+        assert_eq!(exercises[0].title, "Bài 1: Vec và slice");
+        assert!(exercises[4].matches("cycle"));
+        assert!(exercises[4].hint().unwrap().unwrap().contains("Weak"));
+    }
+
+    #[test]
+    fn test_progress_round_trips() {
+        let dir = std::env::temp_dir().join(format!("exercises-progress-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let exercises = discover(root()).unwrap();
+
+        let mut progress = Progress::load(&dir).unwrap();
+        assert!(!progress.is_done(&exercises[0]));
+        assert!(progress.record(&exercises[0], true));
+        assert!(!progress.record(&exercises[0], true));
+        progress.record(&exercises[1], true);
+        progress.save().unwrap();
+
+        let mut progress = Progress::load(&dir).unwrap();
+        assert!(progress.is_done(&exercises[0]));
+        assert!(progress.is_done(&exercises[1]));
+        // Bài đã xong mà chạy lại fail thì không còn xong nữa
+        assert!(progress.record(&exercises[1], false));
+        assert!(!progress.is_done(&exercises[1]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
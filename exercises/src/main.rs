@@ -0,0 +1,131 @@
+//! `cargo run -- <command>` trong thư mục `exercises/`
+//!
+//! - (không có command) / `next`: chạy lần lượt các bài chưa xong, dừng ở
+//!   bài đầu tiên còn fail và in lỗi cùng gợi ý
+//! - `list`: danh sách bài và trạng thái
+//! - `run <name>`: chạy một bài, ví dụ `run ex01_vec` hoặc `run day_8/cycle`
+//! - `hint <name>`: chỉ in gợi ý
+//! - `verify`: chạy lại mọi bài, cập nhật trạng thái
+//! - `reset`: xóa trạng thái
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use exercises::{Exercise, Outcome, Progress, discover};
+
+const USAGE: &str = "usage: exercises [next | list | run <name> | hint <name> | verify | reset]";
+
+fn main() -> ExitCode {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("exercises/ lives in the repository root");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match run(root, &args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `Ok(false)`: còn bài fail; `Err`: bản thân runner lỗi
+fn run(root: &Path, args: &[&str]) -> Result<bool, String> {
+    let exercises = discover(root).map_err(|e| format!("cannot read {}: {}", root.display(), e))?;
+    let mut progress = Progress::load(root).map_err(|e| e.to_string())?;
+
+    let passed = match args {
+        [] | ["next"] => next(&exercises, &mut progress)?,
+        ["list"] => {
+            list(&exercises, &progress);
+            true
+        }
+        ["run", name] => {
+            let exercise = find(&exercises, name)?;
+            check(exercise, &mut progress)?
+        }
+        ["hint", name] => {
+            print_hint(find(&exercises, name)?);
+            true
+        }
+        ["verify"] => {
+            let mut all = true;
+            for exercise in &exercises {
+                all &= check(exercise, &mut progress)?;
+            }
+            all
+        }
+        ["reset"] => {
+            progress.clear();
+            println!("progress cleared");
+            true
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+
+    progress
+        .save()
+        .map_err(|e| format!("cannot save progress: {}", e))?;
+    Ok(passed)
+}
+
+fn find<'a>(exercises: &'a [Exercise], name: &str) -> Result<&'a Exercise, String> {
+    exercises
+        .iter()
+        .find(|exercise| exercise.matches(name))
+        .ok_or_else(|| format!("no exercise named {:?}, see `exercises list`", name))
+}
+
+fn next(exercises: &[Exercise], progress: &mut Progress) -> Result<bool, String> {
+    for exercise in exercises {
+        if progress.is_done(exercise) {
+            continue;
+        }
+        if !check(exercise, progress)? {
+            return Ok(false);
+        }
+    }
+    println!("all {} exercises done", exercises.len());
+    Ok(true)
+}
+
+/// Chạy một bài, ghi kết quả, in lỗi và gợi ý nếu fail
+fn check(exercise: &Exercise, progress: &mut Progress) -> Result<bool, String> {
+    println!("{} ({})", exercise.key, exercise.title);
+    let outcome = exercise
+        .run()
+        .map_err(|e| format!("cannot run cargo: {}", e))?;
+    let passed = outcome == Outcome::Passed;
+    progress.record(exercise, passed);
+
+    match outcome {
+        Outcome::Passed => println!("  ok"),
+        Outcome::Failed(log) => {
+            println!("{}", log.trim_end());
+            println!();
+            println!("  not done yet: {}", exercise.file.display());
+            print_hint(exercise);
+        }
+    }
+    Ok(passed)
+}
+
+fn print_hint(exercise: &Exercise) {
+    match exercise.hint() {
+        Ok(Some(hint)) => println!("  hint: {}", hint),
+        Ok(None) => println!("  no hint for {}", exercise.key),
+        Err(error) => println!("  cannot read {}: {}", exercise.file.display(), error),
+    }
+}
+
+fn list(exercises: &[Exercise], progress: &Progress) {
+    let done = exercises.iter().filter(|e| progress.is_done(e)).count();
+    for exercise in exercises {
+        let mark = if progress.is_done(exercise) { "x" } else { " " };
+        println!("[{}] {:<22} {}", mark, exercise.key, exercise.title);
+    }
+    println!("{}/{} done", done, exercises.len());
+}
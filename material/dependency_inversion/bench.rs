@@ -0,0 +1,90 @@
+// Login Benchmarks
+// ================
+//
+// AuthService::login, the hot path of every client, on in-memory backends:
+// with the user in the cache and without, and with each PasswordHasher the
+// factory can pick. Run one before a refactor that touches the path and
+// one after; criterion keeps the last run under target/criterion and
+// reports each benchmark's change against it. Declared by
+// refactoring_with_di.rs with `#[cfg(all(test, feature = "bench"))] mod bench;`.
+//
+// Criterion drives the benchmarks from an #[ignore]d test, since an example
+// has no benches/ of its own. Build them optimized, or the numbers say
+// nothing:
+//
+//     cargo test --release --example refactoring_with_di --features bench -- --ignored bench_
+//
+// with, under [dev-dependencies]:
+//
+//     criterion = { version = "0.5", features = ["async_tokio"] }
+//
+// BcryptHasher and Argon2Hasher are stand-ins here, so the two compare
+// the same for now. Once they hash for real, the hash dominates login and
+// these show by how much.
+
+use crate::after::*;
+use criterion::{BenchmarkId, Criterion};
+use std::sync::Arc;
+use std::time::Duration;
+use test_support::{DownCache, UserBuilder};
+
+// Enough samples to see a 5% change, short enough to run before a commit
+fn criterion() -> Criterion {
+    Criterion::default()
+        .warm_up_time(Duration::from_secs(1))
+        .measurement_time(Duration::from_secs(3))
+}
+
+fn hashers() -> [(&'static str, Arc<dyn PasswordHasher>); 2] {
+    [
+        ("bcrypt", Arc::new(BcryptHasher)),
+        ("argon2", Arc::new(Argon2Hasher)),
+    ]
+}
+
+// test@example.com with password123, hashed by `hasher`
+fn auth_service(hasher: Arc<dyn PasswordHasher>, cache: Arc<dyn CacheService>) -> AuthService {
+    let user = UserBuilder::new()
+        .password_hash(hasher.hash("password123"))
+        .build();
+    AuthService::new(
+        Arc::new(MockUserRepository::with_user(user)),
+        hasher,
+        Arc::new(JwtTokenService::new(
+            "secret".into(),
+            JwtTokenService::DEFAULT_TTL_SECONDS,
+            Arc::new(MockClock::new()),
+        )),
+        cache,
+    )
+}
+
+#[test]
+#[ignore = "benchmark; needs --release and a few seconds per case"]
+fn bench_login() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut c = criterion();
+    let mut group = c.benchmark_group("login");
+
+    for (name, hasher) in hashers() {
+        // Warmed by one login, so every measured one reads the cache
+        let service = auth_service(hasher.clone(), Arc::new(MockCache::new()));
+        runtime
+            .block_on(service.login("test@example.com", "password123"))
+            .unwrap();
+        group.bench_function(BenchmarkId::new("cache_hit", name), |b| {
+            b.to_async(&runtime)
+                .iter(|| service.login("test@example.com", "password123"))
+        });
+
+        // Every read misses, so every login goes to the repository
+        let service = auth_service(hasher, Arc::new(DownCache));
+        group.bench_function(BenchmarkId::new("cache_miss", name), |b| {
+            b.to_async(&runtime)
+                .iter(|| service.login("test@example.com", "password123"))
+        });
+    }
+
+    group.finish();
+    c.final_summary();
+}
@@ -0,0 +1,11 @@
+// Generates the `pb` module of grpc.rs from proto/auth.proto. Uses the
+// protoc that ships with protoc-bin-vendored unless PROTOC points at one,
+// so a fresh checkout builds without installing protobuf.
+fn main() {
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+        // SAFETY: build scripts are single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc) };
+    }
+    tonic_build::compile_protos("proto/auth.proto").unwrap();
+}
@@ -0,0 +1,160 @@
+// Token Claims
+// ============
+//
+// What JwtTokenService puts in a token and reads back out of it: who it is
+// for (`sub`) and when it stops being valid (`exp`, Unix seconds). Declared
+// by refactoring_with_di.rs with `mod claims;`.
+//
+// A token is an HS256 JWT of these claims, signed with the service's
+// secret. `Claims::verify` checks the signature before it believes any
+// claim, so a token the secret didn't sign is Malformed whatever it says.
+// The expiry it checks itself, against the caller's `now`, rather than
+// letting jsonwebtoken read the system clock: a MockClock can then move it.
+//
+// The token is whatever a client sent, so `Claims::verify` sees arbitrary
+// input and must answer every string with either claims or a ClaimsError,
+// never a panic. This file uses nothing else from the example, so the fuzz
+// target in fuzz/ includes it as is and checks exactly that:
+//
+//     cd fuzz && cargo +nightly fuzz run claims
+
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimsError {
+    // Not a token this service issued: not a JWT, or not signed with the
+    // secret
+    Malformed,
+    // Was one, and `now` is past its exp
+    Expired,
+}
+
+impl fmt::Display for ClaimsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClaimsError::Malformed => write!(f, "malformed token"),
+            ClaimsError::Expired => write!(f, "expired token"),
+        }
+    }
+}
+
+impl std::error::Error for ClaimsError {}
+
+impl Claims {
+    pub fn encode(&self, secret: &[u8]) -> Result<String, jsonwebtoken::errors::Error> {
+        jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            self,
+            &EncodingKey::from_secret(secret),
+        )
+    }
+
+    // The signature, then the expiry; a token is dead from its exp second
+    // on. Only HS256: a token can't pick a weaker algorithm, or none.
+    pub fn verify(token: &str, secret: &[u8], now: u64) -> Result<Self, ClaimsError> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.validate_exp = false;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        let claims =
+            jsonwebtoken::decode::<Self>(token, &DecodingKey::from_secret(secret), &validation)
+                .map_err(|_| ClaimsError::Malformed)?
+                .claims;
+        if claims.sub.is_empty() {
+            return Err(ClaimsError::Malformed);
+        }
+        if claims.exp <= now {
+            return Err(ClaimsError::Expired);
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"secret";
+
+    #[test]
+    fn test_claims_round_trip() {
+        let claims = Claims {
+            sub: "user.with.dots".to_string(),
+            exp: 1_767_225_600,
+        };
+        let token = claims.encode(SECRET).unwrap();
+        assert_eq!(Claims::verify(&token, SECRET, 0), Ok(claims));
+    }
+
+    #[test]
+    fn test_garbage_is_malformed_not_a_panic() {
+        for token in [
+            "",
+            ".",
+            "..",
+            "a.b.c",
+            "jwt_token_for_1.5",
+            "mock_token_1",
+            "eyJhbGciOiJIUzI1NiJ9..",
+            "eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxIn0.",
+        ] {
+            assert_eq!(
+                Claims::verify(token, SECRET, 0),
+                Err(ClaimsError::Malformed),
+                "{:?}",
+                token
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_the_secret_signs() {
+        let admin = Claims {
+            sub: "admin".to_string(),
+            exp: u64::MAX,
+        };
+        let forged = admin.encode(b"not the secret").unwrap();
+        assert_eq!(
+            Claims::verify(&forged, SECRET, 0),
+            Err(ClaimsError::Malformed)
+        );
+
+        // Nor does dropping the signature, or asking for none
+        let token = admin.encode(SECRET).unwrap();
+        let (unsigned, _) = token.rsplit_once('.').unwrap();
+        assert_eq!(
+            Claims::verify(&format!("{}.", unsigned), SECRET, 0),
+            Err(ClaimsError::Malformed)
+        );
+        let (_, rest) = token.split_once('.').unwrap();
+        let (payload, _) = rest.split_once('.').unwrap();
+        // {"alg":"none","typ":"JWT"}
+        let none = format!("eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.{}.", payload);
+        assert_eq!(
+            Claims::verify(&none, SECRET, 0),
+            Err(ClaimsError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_from_the_exp_second_on() {
+        let token = Claims {
+            sub: "1".to_string(),
+            exp: 100,
+        }
+        .encode(SECRET)
+        .unwrap();
+        assert_eq!(Claims::verify(&token, SECRET, 99).unwrap().sub, "1");
+        assert_eq!(
+            Claims::verify(&token, SECRET, 100),
+            Err(ClaimsError::Expired)
+        );
+    }
+}
@@ -0,0 +1,485 @@
+// A Lightweight DI Container
+// ==========================
+//
+// Shared by the examples with `mod container;`.
+//
+// The factories so far wire everything by hand: build the repository, build
+// the cache, pass both to the service. That's fine for four dependencies; with
+// twenty, every factory repeats the same wiring. A container lets each
+// implementation be registered ONCE and then resolved by its trait:
+//
+//     let mut container = Container::new();
+//     container.singleton::<dyn UserRepository, _>(|_| Arc::new(PostgresUserRepository::new(url)));
+//     container.scoped::<UserService, _>(|scope| {
+//         Arc::new(UserService::new(scope.resolve().unwrap(), scope.resolve().unwrap()))
+//     });
+//
+//     let scope = Arc::new(container).create_scope(); // e.g. one per HTTP request
+//     let service: Arc<UserService> = scope.resolve().unwrap();
+//
+// Lifetimes:
+// - Singleton: built on first use, then shared by everyone (pools, clients)
+// - Scoped:    built once per Scope (per-request state, unit of work)
+//
+// Keep singletons from depending on scoped services: the singleton would hold
+// on to the first scope's instance forever ("captive dependency").
+
+use arc_swap::ArcSwap;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lifetime {
+    Singleton,
+    Scoped,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ContainerError {
+    NotRegistered(&'static str),
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerError::NotRegistered(name) => write!(f, "{} is not registered", name),
+        }
+    }
+}
+
+// `Arc<T>` stored type-erased; T may be a trait object like `dyn CacheService`
+type AnyArc = Box<dyn Any + Send + Sync>;
+type Factory<T> = Arc<dyn Fn(&Scope) -> Arc<T> + Send + Sync>;
+
+struct Registration {
+    lifetime: Lifetime,
+    // Holds a `Factory<T>`
+    factory: AnyArc,
+    singleton: OnceLock<AnyArc>,
+}
+
+#[derive(Default)]
+pub struct Container {
+    registrations: HashMap<TypeId, Registration>,
+}
+
+impl Container {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registering the same type again replaces the previous binding
+    pub fn register<T, F>(&mut self, lifetime: Lifetime, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Scope) -> Arc<T> + Send + Sync + 'static,
+    {
+        let factory: Factory<T> = Arc::new(factory);
+        self.registrations.insert(
+            TypeId::of::<Arc<T>>(),
+            Registration {
+                lifetime,
+                factory: Box::new(factory),
+                singleton: OnceLock::new(),
+            },
+        );
+        self
+    }
+
+    pub fn singleton<T, F>(&mut self, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Scope) -> Arc<T> + Send + Sync + 'static,
+    {
+        self.register(Lifetime::Singleton, factory)
+    }
+
+    pub fn scoped<T, F>(&mut self, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Scope) -> Arc<T> + Send + Sync + 'static,
+    {
+        self.register(Lifetime::Scoped, factory)
+    }
+
+    // Registers a `Swappable<T>` singleton built by `factory`. Bind the
+    // trait itself to that handle (via a proxy impl) so services resolve the
+    // proxy and follow every swap:
+    //
+    //     container
+    //         .swappable::<dyn CacheService, _>(|_| Arc::new(RedisCache::new(url)))
+    //         .singleton::<dyn CacheService, _>(|scope| {
+    //             scope.resolve::<Swappable<dyn CacheService>>().unwrap()
+    //         });
+    pub fn swappable<T, F>(&mut self, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Scope) -> Arc<T> + Send + Sync + 'static,
+    {
+        self.singleton::<Swappable<T>, _>(move |scope| Arc::new(Swappable::new(factory(scope))))
+    }
+
+    pub fn create_scope(self: &Arc<Self>) -> Scope {
+        Scope {
+            container: Arc::clone(self),
+            instances: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+pub struct Scope {
+    container: Arc<Container>,
+    instances: Mutex<HashMap<TypeId, AnyArc>>,
+}
+
+impl Scope {
+    pub fn resolve<T>(&self) -> Result<Arc<T>, ContainerError>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let type_id = TypeId::of::<Arc<T>>();
+        let registration = self
+            .container
+            .registrations
+            .get(&type_id)
+            .ok_or(ContainerError::NotRegistered(std::any::type_name::<T>()))?;
+        let factory = registration
+            .factory
+            .downcast_ref::<Factory<T>>()
+            .expect("registration stored under the wrong type");
+
+        match registration.lifetime {
+            Lifetime::Singleton => {
+                let instance = registration
+                    .singleton
+                    .get_or_init(|| Box::new(factory(self)));
+                Ok(Self::downcast::<T>(instance))
+            }
+            Lifetime::Scoped => {
+                if let Some(instance) = self.instances.lock().unwrap().get(&type_id) {
+                    return Ok(Self::downcast::<T>(instance));
+                }
+
+                // Build without holding the lock: the factory resolves its
+                // own dependencies from this same scope
+                let instance = factory(self);
+                let mut instances = self.instances.lock().unwrap();
+                let stored = instances
+                    .entry(type_id)
+                    .or_insert_with(|| Box::new(instance));
+                Ok(Self::downcast::<T>(stored))
+            }
+        }
+    }
+
+    fn downcast<T: ?Sized + 'static>(instance: &AnyArc) -> Arc<T> {
+        instance
+            .downcast_ref::<Arc<T>>()
+            .expect("instance stored under the wrong type")
+            .clone()
+    }
+}
+
+// Lazy async initialization
+// -------------------------
+// Container factories are synchronous, but opening a database pool or a
+// Redis connection is async and can fail. Lazy<T, E> defers that work to the
+// first call that actually needs the dependency:
+// - the app starts even when Postgres/Redis are down (e.g. `cargo run`)
+// - a failed init is retried on the next call instead of being cached
+//
+// Register a small proxy that implements the trait by awaiting `get()`
+// first (see LazyUserRepository in refactoring_with_di.rs).
+
+type InitFuture<T, E> = Pin<Box<dyn Future<Output = Result<Arc<T>, E>> + Send>>;
+
+// `E` is whatever init fails with, handed back as is: the caller decides
+// what a failed connect means, and the cause isn't flattened to a string
+pub struct Lazy<T: ?Sized, E> {
+    cell: tokio::sync::OnceCell<Arc<T>>,
+    init: Box<dyn Fn() -> InitFuture<T, E> + Send + Sync>,
+}
+
+// Only the networked backends are lazy
+#[cfg_attr(
+    not(any(feature = "postgres", feature = "sqlite", feature = "redis")),
+    allow(dead_code)
+)]
+impl<T: ?Sized + Send + Sync, E> Lazy<T, E> {
+    pub fn new<F, Fut>(init: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Arc<T>, E>> + Send + 'static,
+    {
+        Self {
+            cell: tokio::sync::OnceCell::new(),
+            init: Box::new(move || Box::pin(init())),
+        }
+    }
+
+    // Concurrent first calls share one init; errors are not cached
+    pub async fn get(&self) -> Result<Arc<T>, E> {
+        self.cell
+            .get_or_try_init(|| (self.init)())
+            .await
+            .map(Arc::clone)
+    }
+
+    #[cfg(test)]
+    pub fn is_initialized(&self) -> bool {
+        self.cell.initialized()
+    }
+
+    // The instance if init already ran; never triggers it
+    pub fn get_initialized(&self) -> Option<Arc<T>> {
+        self.cell.get().cloned()
+    }
+}
+
+// Hot-swappable bindings
+// ----------------------
+// A resolved `Arc<dyn CacheService>` is fixed forever: if Redis goes down,
+// every service keeps talking to it. Swappable<T> is a slot whose content
+// can be replaced while the app runs; services hold a proxy that loads the
+// current implementation on every call (see Container::swappable).
+//
+// ArcSwap makes load() lock-free, so the extra indirection costs an atomic
+// read rather than a Mutex on the hot path.
+
+pub struct Swappable<T: ?Sized> {
+    // ArcSwap needs a sized pointee, hence Arc<Arc<T>>
+    current: ArcSwap<Arc<T>>,
+}
+
+impl<T: ?Sized + Send + Sync + 'static> Swappable<T> {
+    pub fn new(initial: Arc<T>) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(initial),
+        }
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        Arc::clone(&self.current.load())
+    }
+
+    // In-flight calls finish on the old implementation; returns it
+    #[cfg(test)]
+    pub fn swap(&self, next: Arc<T>) -> Arc<T> {
+        Arc::clone(&self.current.swap(Arc::new(next)))
+    }
+}
+
+// Runtime service registry
+// ------------------------
+// The Container is configured once at startup and everything in it is
+// required. Plugin-style extensions are different: they may or may not be
+// there, and may be added while the app runs. ServiceRegistry is just a map
+// from a trait to an instance, and callers decide what to do when it's absent:
+//
+//     let registry = ServiceRegistry::new();
+//     registry.insert::<dyn EmailSender>(Arc::new(SmtpEmailSender::new(url)));
+//
+//     if let Some(sender) = registry.get::<dyn EmailSender>() {
+//         sender.send(...).await?;
+//     }
+
+// real_world_di's; refactoring_with_di has no optional extensions
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct ServiceRegistry {
+    // Holds an `Arc<T>` per entry: `Arc<dyn Trait>` can't become
+    // `Arc<dyn Any>` directly, so it's wrapped once more
+    services: RwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+#[allow(dead_code)]
+impl ServiceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns the service it replaced, if any
+    pub fn insert<T>(&self, service: Arc<T>) -> Option<Arc<T>>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let previous = self
+            .services
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<Arc<T>>(), Arc::new(service));
+        previous.map(|entry| Self::downcast::<T>(&entry))
+    }
+
+    pub fn get<T>(&self) -> Option<Arc<T>>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let services = self.services.read().unwrap();
+        services
+            .get(&TypeId::of::<Arc<T>>())
+            .map(|entry| Self::downcast::<T>(entry))
+    }
+
+    pub fn contains<T: ?Sized + 'static>(&self) -> bool {
+        self.services
+            .read()
+            .unwrap()
+            .contains_key(&TypeId::of::<Arc<T>>())
+    }
+
+    pub fn remove<T>(&self) -> Option<Arc<T>>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let removed = self
+            .services
+            .write()
+            .unwrap()
+            .remove(&TypeId::of::<Arc<T>>());
+        removed.map(|entry| Self::downcast::<T>(&entry))
+    }
+
+    fn downcast<T: ?Sized + 'static>(entry: &Arc<dyn Any + Send + Sync>) -> Arc<T> {
+        entry
+            .downcast_ref::<Arc<T>>()
+            .expect("service stored under the wrong type")
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    trait Greeter: Send + Sync {
+        fn greet(&self) -> String;
+    }
+
+    struct English;
+
+    impl Greeter for English {
+        fn greet(&self) -> String {
+            "hello".to_string()
+        }
+    }
+
+    #[test]
+    fn test_resolve_by_trait() {
+        let mut container = Container::new();
+        container.singleton::<dyn Greeter, _>(|_| Arc::new(English));
+
+        let scope = Arc::new(container).create_scope();
+        let greeter: Arc<dyn Greeter> = scope.resolve().unwrap();
+
+        assert_eq!(greeter.greet(), "hello");
+    }
+
+    #[test]
+    fn test_unregistered_type_is_an_error() {
+        let scope = Arc::new(Container::new()).create_scope();
+
+        let result = scope.resolve::<dyn Greeter>();
+
+        assert!(matches!(result, Err(ContainerError::NotRegistered(_))));
+    }
+
+    #[tokio::test]
+    async fn test_lazy_initializes_once_on_first_use() {
+        let inits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = inits.clone();
+        let lazy: Lazy<dyn Greeter, std::io::Error> = Lazy::new(move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { Ok(Arc::new(English) as Arc<dyn Greeter>) }
+        });
+
+        assert!(!lazy.is_initialized());
+        assert_eq!(lazy.get().await.unwrap().greet(), "hello");
+        assert_eq!(lazy.get().await.unwrap().greet(), "hello");
+        assert_eq!(inits.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lazy_retries_after_failed_init() {
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let lazy: Lazy<dyn Greeter, std::io::Error> = Lazy::new(move || {
+            let attempt = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                if attempt == 0 {
+                    Err(std::io::ErrorKind::ConnectionRefused.into())
+                } else {
+                    Ok(Arc::new(English) as Arc<dyn Greeter>)
+                }
+            }
+        });
+
+        let Err(error) = lazy.get().await else {
+            panic!("the first init fails");
+        };
+        assert_eq!(error.kind(), std::io::ErrorKind::ConnectionRefused);
+        assert!(lazy.get().await.is_ok());
+        assert!(lazy.is_initialized());
+    }
+
+    #[test]
+    fn test_registry_looks_up_by_trait() {
+        let registry = ServiceRegistry::new();
+        assert!(registry.get::<dyn Greeter>().is_none());
+
+        registry.insert::<dyn Greeter>(Arc::new(English));
+
+        assert!(registry.contains::<dyn Greeter>());
+        assert_eq!(registry.get::<dyn Greeter>().unwrap().greet(), "hello");
+        // Keyed by the exact type: the concrete type is a different entry
+        assert!(registry.get::<English>().is_none());
+    }
+
+    #[test]
+    fn test_registry_insert_replaces_and_remove_unregisters() {
+        struct Loud;
+
+        impl Greeter for Loud {
+            fn greet(&self) -> String {
+                "HELLO".to_string()
+            }
+        }
+
+        let registry = ServiceRegistry::new();
+        registry.insert::<dyn Greeter>(Arc::new(English));
+
+        let previous = registry.insert::<dyn Greeter>(Arc::new(Loud));
+
+        assert_eq!(previous.unwrap().greet(), "hello");
+        assert_eq!(registry.get::<dyn Greeter>().unwrap().greet(), "HELLO");
+        assert!(registry.remove::<dyn Greeter>().is_some());
+        assert!(!registry.contains::<dyn Greeter>());
+    }
+
+    #[test]
+    fn test_swappable_binding_follows_swaps() {
+        struct Loud;
+
+        impl Greeter for Loud {
+            fn greet(&self) -> String {
+                "HELLO".to_string()
+            }
+        }
+
+        let mut container = Container::new();
+        container.swappable::<dyn Greeter, _>(|_| Arc::new(English));
+        let scope = Arc::new(container).create_scope();
+        let slot: Arc<Swappable<dyn Greeter>> = scope.resolve().unwrap();
+
+        assert_eq!(slot.load().greet(), "hello");
+        let previous = slot.swap(Arc::new(Loud));
+
+        assert_eq!(previous.greet(), "hello");
+        // Same singleton slot, new content
+        let again: Arc<Swappable<dyn Greeter>> = scope.resolve().unwrap();
+        assert_eq!(again.load().greet(), "HELLO");
+    }
+}
@@ -142,19 +142,25 @@ impl ThreadSafeUserService {
 
 // Example 4: Async Trait Pattern
 // ===============================
+//
+// main() is sync, so this one is compiled for the tests only.
 
+#[cfg(test)]
 use async_trait::async_trait;
 
+#[cfg(test)]
 #[async_trait]
 trait AsyncUserRepository: Send + Sync {
     async fn find_by_id(&self, id: u32) -> Option<String>;
     async fn save(&self, id: u32, name: String);
 }
 
+#[cfg(test)]
 struct AsyncInMemoryRepository {
     users: Mutex<HashMap<u32, String>>,
 }
 
+#[cfg(test)]
 impl AsyncInMemoryRepository {
     fn new() -> Self {
         Self {
@@ -163,6 +169,7 @@ impl AsyncInMemoryRepository {
     }
 }
 
+#[cfg(test)]
 #[async_trait]
 impl AsyncUserRepository for AsyncInMemoryRepository {
     async fn find_by_id(&self, id: u32) -> Option<String> {
@@ -178,10 +185,12 @@ impl AsyncUserRepository for AsyncInMemoryRepository {
     }
 }
 
+#[cfg(test)]
 struct AsyncUserService {
     repository: Arc<dyn AsyncUserRepository>,
 }
 
+#[cfg(test)]
 impl AsyncUserService {
     fn new(repository: Arc<dyn AsyncUserRepository>) -> Self {
         Self { repository }
@@ -223,37 +232,135 @@ impl<R: UserRepository> UserServiceBuilder<R> {
 // Example 6: Multiple Dependencies
 // =================================
 
+// Levels, lowest first, so `level >= Level::Warn` reads the way you'd expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Debug,
+    Info,
+    Warn,
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+        };
+        f.write_str(name)
+    }
+}
+
+// Key-value pairs that travel with a message: `&[("id", &id)]`
+type Fields<'a> = &'a [(&'a str, &'a dyn std::fmt::Display)];
+
+// Implementations only write `log_with`; the rest are shorthands.
+// `log` stays so callers written against the old one-method trait still
+// compile, and logs at info.
 trait Logger {
-    fn log(&self, message: &str);
+    fn log_with(&self, level: Level, message: &str, fields: Fields);
+
+    fn log(&self, message: &str) {
+        self.log_with(Level::Info, message, &[]);
+    }
+
+    fn debug(&self, message: &str, fields: Fields) {
+        self.log_with(Level::Debug, message, fields);
+    }
+
+    fn info(&self, message: &str, fields: Fields) {
+        self.log_with(Level::Info, message, fields);
+    }
+
+    fn warn(&self, message: &str, fields: Fields) {
+        self.log_with(Level::Warn, message, fields);
+    }
+}
+
+fn render_fields(fields: Fields) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 struct ConsoleLogger;
 
 impl Logger for ConsoleLogger {
-    fn log(&self, message: &str) {
-        println!("[LOG] {}", message);
+    // [WARN] User not found id=7
+    fn log_with(&self, level: Level, message: &str, fields: Fields) {
+        if fields.is_empty() {
+            println!("[{}] {}", level, message);
+        } else {
+            println!("[{}] {} {}", level, message, render_fields(fields));
+        }
     }
 }
 
+// What MockLogger keeps per call; fields are rendered to strings so tests
+// can compare them without caring about the original types.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+struct LogEntry {
+    level: Level,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+#[cfg(test)]
 struct MockLogger {
-    messages: Mutex<Vec<String>>,
+    entries: Mutex<Vec<LogEntry>>,
 }
 
+#[cfg(test)]
 impl MockLogger {
     fn new() -> Self {
         Self {
-            messages: Mutex::new(Vec::new()),
+            entries: Mutex::new(Vec::new()),
         }
     }
 
     fn get_messages(&self) -> Vec<String> {
-        self.messages.lock().unwrap().clone()
+        self.get_entries()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    fn get_entries(&self) -> Vec<LogEntry> {
+        self.entries.lock().unwrap().clone()
     }
 }
 
+#[cfg(test)]
 impl Logger for MockLogger {
-    fn log(&self, message: &str) {
-        self.messages.lock().unwrap().push(message.to_string());
+    fn log_with(&self, level: Level, message: &str, fields: Fields) {
+        self.entries.lock().unwrap().push(LogEntry {
+            level,
+            message: message.to_string(),
+            fields: fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        });
+    }
+}
+
+// Bridges the trait onto `tracing`, so a service written against Logger
+// shows up in whatever subscriber the binary installs (see logging.rs in
+// refactoring_with_di). tracing wants field names at compile time, so the
+// pairs travel as one rendered `fields` value.
+struct TracingLogger;
+
+impl Logger for TracingLogger {
+    fn log_with(&self, level: Level, message: &str, fields: Fields) {
+        let fields = render_fields(fields);
+        match level {
+            Level::Debug => tracing::debug!(fields = %fields, "{}", message),
+            Level::Info => tracing::info!(fields = %fields, "{}", message),
+            Level::Warn => tracing::warn!(fields = %fields, "{}", message),
+        }
     }
 }
 
@@ -268,13 +375,19 @@ impl<R: UserRepository, L: Logger> AdvancedUserService<R, L> {
         Self { repository, logger }
     }
 
+    fn create_user(&mut self, id: u32, name: String) {
+        self.logger.info("Creating user", &[("id", &id)]);
+        self.repository.save(id, name);
+    }
+
     fn get_user(&self, id: u32) -> Option<String> {
-        self.logger.log(&format!("Fetching user with id: {}", id));
+        self.logger
+            .debug(&format!("Fetching user with id: {}", id), &[("id", &id)]);
         let user = self.repository.find_by_id(id);
         if user.is_some() {
-            self.logger.log("User found");
+            self.logger.info("User found", &[("id", &id)]);
         } else {
-            self.logger.log("User not found");
+            self.logger.warn("User not found", &[("id", &id)]);
         }
         user
     }
@@ -317,9 +430,17 @@ fn main() {
     println!("\n=== Example 6: Multiple Dependencies ===");
     let repo = InMemoryUserRepository::new();
     let logger = ConsoleLogger;
+    logger.log("Wiring AdvancedUserService to a console logger");
     let mut service = AdvancedUserService::new(repo, logger);
     service.create_user(1, "Charlie".to_string());
     service.get_user(1);
+
+    println!("\n=== Example 6b: Same service, tracing underneath ===");
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
+    let service = AdvancedUserService::new(InMemoryUserRepository::new(), TracingLogger);
+    service.get_user(2);
 }
 
 #[cfg(test)]
@@ -348,12 +469,34 @@ mod tests {
         assert!(messages.contains(&"User not found".to_string()));
     }
 
+    #[test]
+    fn test_mock_logger_keeps_levels_and_fields() {
+        let repo = InMemoryUserRepository::new();
+        let logger = MockLogger::new();
+        let service = AdvancedUserService::new(repo, logger);
+
+        service.get_user(7);
+        service.logger.log("plain message");
+
+        let entries = service.logger.get_entries();
+        assert_eq!(
+            entries[1],
+            LogEntry {
+                level: Level::Warn,
+                message: "User not found".to_string(),
+                fields: vec![("id".to_string(), "7".to_string())],
+            }
+        );
+        assert_eq!(entries[2].level, Level::Info);
+        assert!(entries[2].fields.is_empty());
+        assert_eq!(entries.iter().filter(|e| e.level >= Level::Warn).count(), 1);
+    }
+
     #[tokio::test]
     async fn test_async_repository() {
-        let repo = Arc::new(AsyncInMemoryRepository::new());
-        let service = AsyncUserService::new(repo.clone());
+        let service = AsyncUserService::new(Arc::new(AsyncInMemoryRepository::new()));
 
-        repo.save(1, "Test User".to_string()).await;
+        service.create_user(1, "Test User".to_string()).await;
         let user = service.get_user(1).await;
 
         assert_eq!(user, Some("Test User".to_string()));
@@ -0,0 +1,170 @@
+// End-to-End Tests
+// ================
+//
+// The auth flows again, this time against a running server: each test
+// starts a TestServer (the `--serve` router on the in-memory backends,
+// listening on a loopback port) and talks to it with reqwest over TCP.
+// The in-process tests in http.rs cover each route; these check what only
+// a real connection shows: the wire format, Set-Cookie round-tripping
+// through a cookie jar, and a clean shutdown. Declared by
+// refactoring_with_di.rs with `#[cfg(test)] mod e2e;`.
+//
+// No Docker and no fixed port, so they run with the rest of the tests:
+//
+//     cargo test --example refactoring_with_di e2e::
+//
+// with, under [dev-dependencies]:
+//
+//     reqwest = { version = "0.12", default-features = false, features = ["json", "cookies"] }
+
+use crate::http::CSRF_HEADER;
+use crate::test_app::TestServer;
+use reqwest::StatusCode;
+use reqwest::header::WWW_AUTHENTICATE;
+use serde_json::{Value, json};
+
+fn credentials() -> Value {
+    json!({ "email": "new@example.com", "password": "password123" })
+}
+
+#[tokio::test]
+async fn test_bearer_flow_over_the_network() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let registered = client
+        .post(server.url("/api/v1/register"))
+        .json(&credentials())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(registered.status(), StatusCode::CREATED);
+    assert!(registered.headers().contains_key("x-request-id"));
+    let user: Value = registered.json().await.unwrap();
+
+    let login = client
+        .post(server.url("/api/v1/login"))
+        .json(&credentials())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(login.status(), StatusCode::OK);
+    let login: Value = login.json().await.unwrap();
+    let token = login["access_token"].as_str().unwrap();
+
+    let me = client
+        .get(server.url("/api/v1/me"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(me.status(), StatusCode::OK);
+    assert_eq!(me.json::<Value>().await.unwrap()["id"], user["id"]);
+
+    let logout = client
+        .post(server.url("/api/v1/logout"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(logout.status(), StatusCode::NO_CONTENT);
+
+    let me = client
+        .get(server.url("/api/v1/me"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(me.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        me.headers()[WWW_AUTHENTICATE],
+        "Bearer error=\"invalid_token\""
+    );
+
+    // Bearer mode sets no cookies at all
+    assert_eq!(server.cookie("session"), None);
+    server.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cookie_flow_over_the_network() {
+    let server = TestServer::configured(&[("AUTH_MODE", "cookie")], Vec::new()).await;
+    let client = &server.client;
+
+    // Writes need the csrf token, and only a GET hands it out
+    let refused = client
+        .post(server.url("/api/v1/register"))
+        .json(&credentials())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+    client.get(server.url("/healthz")).send().await.unwrap();
+    let csrf = server.cookie("csrf").expect("a GET sets the csrf cookie");
+
+    let registered = client
+        .post(server.url("/api/v1/register"))
+        .header(CSRF_HEADER, &csrf)
+        .json(&credentials())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(registered.status(), StatusCode::CREATED);
+
+    let login = client
+        .post(server.url("/api/v1/login"))
+        .header(CSRF_HEADER, &csrf)
+        .json(&credentials())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(login.status(), StatusCode::NO_CONTENT);
+    assert!(server.cookie("session").is_some());
+
+    // No Authorization header: the jar sends the session cookie
+    let me = client.get(server.url("/api/v1/me")).send().await.unwrap();
+    assert_eq!(me.status(), StatusCode::OK);
+    assert_eq!(
+        me.json::<Value>().await.unwrap()["email"],
+        "new@example.com"
+    );
+
+    let logout = client
+        .post(server.url("/api/v1/logout"))
+        .header(CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(logout.status(), StatusCode::NO_CONTENT);
+    // Max-Age=0 took it out of the jar
+    assert_eq!(server.cookie("session"), None);
+
+    let me = client.get(server.url("/api/v1/me")).send().await.unwrap();
+    assert_eq!(me.status(), StatusCode::UNAUTHORIZED);
+    assert!(!me.headers().contains_key(WWW_AUTHENTICATE));
+    server.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_wrong_password_over_the_network() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    client
+        .post(server.url("/api/v1/register"))
+        .json(&credentials())
+        .send()
+        .await
+        .unwrap();
+
+    let login = client
+        .post(server.url("/api/v1/login"))
+        .json(&json!({ "email": "new@example.com", "password": "wrong-password" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(login.status(), StatusCode::UNAUTHORIZED);
+    // The same error body the in-process tests snapshot
+    let body: Value = login.json().await.unwrap();
+    assert_eq!(body["code"], "AUTH001_INVALID_CREDENTIALS");
+    server.stop().await.unwrap();
+}
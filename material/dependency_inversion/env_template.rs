@@ -0,0 +1,14 @@
+// Writes dev.env.example from the config keys in config.rs:
+//
+//     cargo run --example env_template > dev.env.example
+//
+// A test in config.rs compares the checked-in file with this output, so a
+// new key without a regenerated template fails the build.
+
+// Only env_template() and KEYS; the rest is for refactoring_with_di
+#[allow(dead_code)]
+mod config;
+
+fn main() {
+    print!("{}", config::env_template());
+}
@@ -0,0 +1,57 @@
+// Arbitrary bytes into the token verifier. JwtTokenService::validate is
+// Claims::verify plus From<ClaimsError>, so this covers it too.
+//
+//     cd fuzz && cargo +nightly fuzz run claims
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../claims.rs"]
+#[allow(dead_code)]
+mod claims;
+
+use claims::{Claims, ClaimsError};
+
+const SECRET: &[u8] = b"fuzz";
+
+fuzz_target!(|data: &[u8]| {
+    // A header is bytes, not necessarily UTF-8; those never reach verify
+    let Ok(token) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    // Nothing here was signed with SECRET, so nothing verifies, whatever
+    // the clock says
+    assert_eq!(
+        Claims::verify(token, SECRET, 0),
+        Err(ClaimsError::Malformed)
+    );
+    assert_eq!(
+        Claims::verify(token, SECRET, u64::MAX),
+        Err(ClaimsError::Malformed)
+    );
+
+    // Any sub survives a signed round trip, alive before exp and dead from
+    // it on, and only under the secret that signed it
+    if token.is_empty() {
+        return;
+    }
+    let claims = Claims {
+        sub: token.to_string(),
+        exp: data.len() as u64,
+    };
+    let signed = claims.encode(SECRET).unwrap();
+    assert_eq!(
+        Claims::verify(&signed, SECRET, claims.exp - 1),
+        Ok(claims.clone())
+    );
+    assert_eq!(
+        Claims::verify(&signed, SECRET, claims.exp),
+        Err(ClaimsError::Expired)
+    );
+    assert_eq!(
+        Claims::verify(&signed, b"other", 0),
+        Err(ClaimsError::Malformed)
+    );
+});
@@ -0,0 +1,499 @@
+// gRPC Layer
+// ==========
+//
+// The `after` AuthService again, this time behind tonic instead of axum.
+// Declared by refactoring_with_di.rs with `mod grpc;` and started by
+// `--serve` next to the HTTP server, on SERVER_GRPC_PORT (50051):
+//
+//     service auth.v1.Auth {
+//       Register(RegisterRequest) returns (User)
+//       Login(LoginRequest)       returns (LoginResponse)
+//       Validate(ValidateRequest) returns (User)   // token in metadata
+//     }
+//
+// The messages live in proto/auth.proto. tonic-build turns them into Rust
+// at compile time, in build.rs:
+//
+//     tonic_build::compile_protos("proto/auth.proto").unwrap();
+//
+// with `tonic-build` under [build-dependencies] (protoc comes vendored).
+// `pb` below includes what it generated.
+//
+// Nothing in `after` changed for this. The handlers here do what the HTTP
+// ones do: generated message in, one AuthService call, generated message
+// out. The generated types stay in this file; `From` impls map them to
+// and from the domain `User` and `Error`, the way http.rs maps its DTOs.
+// Two transports, one business layer.
+//
+// Errors become gRPC status codes instead of HTTP statuses:
+//
+//     NotFound           -> NOT_FOUND
+//     AlreadyExists      -> ALREADY_EXISTS
+//     InvalidCredentials -> UNAUTHENTICATED
+//     Internal           -> INTERNAL          // details only in the log (and the ErrorReporter)
+//     bad email/password -> INVALID_ARGUMENT  // same #[validate] rules as HTTP
+//
+// Domain errors also carry their ErrorCode in `error-code` metadata, the
+// same AUTH001_INVALID_CREDENTIALS an HTTP client finds in the body.
+//
+// Tokens travel as `authorization: Bearer <token>` metadata. The
+// `bearer_auth` interceptor pulls the token out for every call and refuses
+// a malformed header; Validate then checks it against AuthService. That
+// second step can't be in the interceptor: interceptors are synchronous,
+// and a token lookup awaits the repository. AUTH_MODE doesn't apply here;
+// cookies are a browser thing, and gRPC clients always get a token.
+//
+// Every call gets the same X-Request-Id handling as an HTTP request, as
+// `x-request-id` metadata, and the same `request` span, so the
+// AuthService and repository lines of one call share its ID.
+//
+// Try it with grpcurl (no reflection, so point it at the .proto):
+//
+//     grpcurl -plaintext -import-path proto -proto auth.proto \
+//         -d '{"email": "me@example.com", "password": "password123"}' \
+//         localhost:50051 auth.v1.Auth/Login
+
+use crate::after::{self, AuthService};
+use crate::config::{AppConfig, Locale};
+use crate::http::{self, AppState};
+use crate::messages;
+use crate::{Error, User};
+use pb::auth_server::{Auth, AuthServer};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use validator::Validate;
+
+pub mod pb {
+    tonic::include_proto!("auth.v1");
+}
+
+pub struct AuthGrpc {
+    state: Arc<AppState>,
+}
+
+impl AuthGrpc {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    // A fresh scope per call, like the HTTP handlers
+    fn auth(&self) -> Arc<AuthService> {
+        self.state.app.auth_service()
+    }
+}
+
+#[tonic::async_trait]
+impl Auth for AuthGrpc {
+    async fn register(
+        &self,
+        request: Request<pb::RegisterRequest>,
+    ) -> Result<Response<pb::User>, Status> {
+        let pb::RegisterRequest { email, password } = request.into_inner();
+        // The HTTP DTO carries the rules; checking it here keeps them in one place
+        http::RegisterRequest {
+            email: email.clone(),
+            password: password.clone(),
+        }
+        .validate()
+        .map_err(|errors| status(Error::Validation(http::validation_errors(&errors))))?;
+
+        let user = self
+            .auth()
+            .register(&email, &password)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(user.into()))
+    }
+
+    async fn login(
+        &self,
+        request: Request<pb::LoginRequest>,
+    ) -> Result<Response<pb::LoginResponse>, Status> {
+        let pb::LoginRequest { email, password } = request.into_inner();
+        http::LoginRequest {
+            email: email.clone(),
+            password: password.clone(),
+        }
+        .validate()
+        .map_err(|errors| status(Error::Validation(http::validation_errors(&errors))))?;
+
+        let token = self.auth().login(&email, &password).await.map_err(status)?;
+        Ok(Response::new(pb::LoginResponse {
+            access_token: token,
+            token_type: "Bearer".to_string(),
+        }))
+    }
+
+    async fn validate(
+        &self,
+        request: Request<pb::ValidateRequest>,
+    ) -> Result<Response<pb::User>, Status> {
+        let BearerToken(token) = request
+            .extensions()
+            .get::<BearerToken>()
+            .cloned()
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+
+        match self.auth().current_user(&token).await {
+            Ok(user) => Ok(Response::new(user.into())),
+            // A database outage is not the client's fault
+            Err(error @ Error::Internal { .. }) => Err(status(error)),
+            Err(_) => Err(Status::unauthenticated("invalid or expired token")),
+        }
+    }
+}
+
+// Set by `bearer_auth` when the call carried a well-formed token
+#[derive(Debug, Clone)]
+pub struct BearerToken(pub String);
+
+// No header is fine here (Register and Login don't need one); a header
+// that isn't `Bearer <token>` is refused before any handler runs.
+// The signature is tonic's, large Status and all.
+#[allow(clippy::result_large_err)]
+pub fn bearer_auth(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let Some(value) = request.metadata().get("authorization") else {
+        return Ok(request);
+    };
+    let token = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| Status::unauthenticated("authorization must be `Bearer <token>`"))?
+        .to_string();
+    request.extensions_mut().insert(BearerToken(token));
+    Ok(request)
+}
+
+impl From<User> for pb::User {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            role: user.role,
+            avatar_url: user.avatar_url,
+        }
+    }
+}
+
+// The ErrorCode rides along as `error-code` metadata. A function, not
+// `From<Error> for Status`: both types are foreign here (Error comes from
+// tutorial-core), so the orphan rule forbids the impl; handlers write
+// `.map_err(status)?` instead of a bare `?`.
+pub fn status(error: Error) -> Status {
+    let code = error.code();
+    let message = messages::message(code, Locale::En);
+    let mut status = match error {
+        Error::NotFound => Status::not_found(message),
+        Error::AlreadyExists => Status::already_exists(message),
+        Error::InvalidCredentials => Status::unauthenticated(message),
+        // `email: must be a valid email address; password: ...`
+        Error::Validation(errors) => Status::invalid_argument(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+        // Connection strings and SQL stay in the log, not in the status
+        error @ Error::Internal { .. } => {
+            after::log_internal(&error);
+            Status::internal(message)
+        }
+    };
+    status
+        .metadata_mut()
+        .insert("error-code", MetadataValue::from_static(code.as_str()));
+    status
+}
+
+pub async fn serve(state: Arc<AppState>, settings: &AppConfig) -> std::io::Result<()> {
+    let server = &settings.server;
+    let listener = TcpListener::bind((server.host.as_str(), server.grpc_port)).await?;
+    tracing::info!(addr = %listener.local_addr()?, "gRPC listening");
+    serve_with(
+        listener,
+        state,
+        http::shutdown_signal(),
+        Duration::from_secs(server.shutdown_timeout_seconds),
+    )
+    .await
+}
+
+// Same contract as http::serve_with: runs until `shutdown` completes, then
+// gives in-flight calls at most `drain_timeout`
+pub async fn serve_with(
+    listener: TcpListener,
+    state: Arc<AppState>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    let (draining_tx, draining) = tokio::sync::oneshot::channel();
+    let reporter = state.app.error_reporter();
+    let server = Server::builder()
+        // As in http.rs: the caller's x-request-id (or a fresh UUID), echoed
+        // in the response metadata and opening the `request` span. Failures
+        // aren't logged here: internal errors already are (log_internal),
+        // and the rest are answers. Inside the span, each call gets the
+        // ErrorReporter.
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(
+                    TraceLayer::new_for_grpc()
+                        .make_span_with(http::request_span)
+                        .on_failure(()),
+                )
+                .map_future(move |call| after::reporting_errors_to(reporter.clone(), call)),
+        )
+        .add_service(AuthServer::with_interceptor(
+            AuthGrpc::new(state),
+            bearer_auth,
+        ))
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), async move {
+            shutdown.await;
+            tracing::info!("shutting down gRPC: draining in-flight calls");
+            let _ = draining_tx.send(());
+        });
+    let mut server = tokio::spawn(server);
+
+    tokio::select! {
+        result = &mut server => {
+            return result
+                .map_err(std::io::Error::other)?
+                .map_err(std::io::Error::other);
+        }
+        _ = draining => {}
+    }
+
+    match tokio::time::timeout(drain_timeout, &mut server).await {
+        Ok(result) => result
+            .map_err(std::io::Error::other)?
+            .map_err(std::io::Error::other),
+        Err(_) => {
+            tracing::warn!(
+                ?drain_timeout,
+                "gRPC drain timed out, abandoning the remaining calls"
+            );
+            server.abort();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::after::{App, AuthServiceFactory, ErrorReporter, FailureConfig, MockErrorReporter};
+    use crate::container::Container;
+    use pb::auth_client::AuthClient;
+    use tonic::Code;
+    use tonic::transport::Channel;
+
+    // A real server on a free port, and a client talking to it over HTTP/2
+    async fn client() -> (AuthClient<Channel>, tokio::sync::oneshot::Sender<()>) {
+        client_for(AuthServiceFactory::test_container(Vec::new())).await
+    }
+
+    async fn client_for(
+        container: Container,
+    ) -> (AuthClient<Channel>, tokio::sync::oneshot::Sender<()>) {
+        let state = Arc::new(AppState::new(App::new(Arc::new(container))));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(serve_with(
+            listener,
+            state,
+            async move {
+                let _ = stopped.await;
+            },
+            Duration::from_secs(1),
+        ));
+
+        let client = AuthClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+        (client, stop)
+    }
+
+    fn with_token<T>(message: T, token: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    #[tokio::test]
+    async fn test_register_login_validate_over_the_wire() {
+        let (mut client, _stop) = client().await;
+
+        let user = client
+            .register(pb::RegisterRequest {
+                email: "grpc@example.com".to_string(),
+                password: "password123".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(user.email, "grpc@example.com");
+        assert_eq!(user.role, "user");
+        assert_eq!(user.avatar_url, None);
+
+        let login = client
+            .login(pb::LoginRequest {
+                email: "grpc@example.com".to_string(),
+                password: "password123".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(login.token_type, "Bearer");
+
+        // The token AuthService issued is the one Validate accepts
+        let me = client
+            .validate(with_token(pb::ValidateRequest {}, &login.access_token))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(me, user);
+    }
+
+    #[tokio::test]
+    async fn test_calls_carry_the_request_id_into_the_service_spans() {
+        let (logs, _guard) = test_support::CapturedLogs::install();
+        let (mut client, _stop) = client().await;
+        let mut request = Request::new(pb::RegisterRequest {
+            email: "traced@example.com".to_string(),
+            password: "password123".to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("x-request-id", "grpc-1".parse().unwrap());
+
+        let response = client.register(request).await.unwrap();
+
+        assert_eq!(response.metadata().get("x-request-id").unwrap(), "grpc-1");
+        let logs = logs.text();
+        assert!(
+            logs.lines().any(|line| line.contains(
+                r#"request{request_id="grpc-1" method=POST path=/auth.v1.Auth/Register}:register{email="traced@example.com"}"#
+            )),
+            "{}",
+            logs
+        );
+    }
+
+    #[tokio::test]
+    async fn test_domain_errors_become_status_codes() {
+        let (mut client, _stop) = client().await;
+        let register = pb::RegisterRequest {
+            email: "taken@example.com".to_string(),
+            password: "password123".to_string(),
+        };
+        client.register(register.clone()).await.unwrap();
+
+        let status = client.register(register).await.unwrap_err();
+        assert_eq!(status.code(), Code::AlreadyExists);
+        assert_eq!(
+            status.metadata().get("error-code").unwrap(),
+            "RES002_ALREADY_EXISTS"
+        );
+
+        let status = client
+            .login(pb::LoginRequest {
+                email: "taken@example.com".to_string(),
+                password: "wrong-password".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "invalid email or password");
+
+        // The HTTP DTO's #[validate] rules, reported field by field
+        let status = client
+            .register(pb::RegisterRequest {
+                email: "not-an-email".to_string(),
+                password: "short".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "email: must be a valid email address; password: must be 8 to 128 characters"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_reach_the_error_reporter() {
+        let reporter = Arc::new(MockErrorReporter::default());
+        let mut container =
+            AuthServiceFactory::flaky_test_container(Vec::new(), FailureConfig::failing(100));
+        let captured = reporter.clone();
+        container.singleton::<dyn ErrorReporter, _>(move |_| captured.clone());
+        let (mut client, _stop) = client_for(container).await;
+
+        let status = client
+            .login(pb::LoginRequest {
+                email: "grpc@example.com".to_string(),
+                password: "password123".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+
+        let events = reporter.events();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].message.contains("injected failure"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_needs_a_good_token() {
+        let (mut client, _stop) = client().await;
+
+        let status = client.validate(pb::ValidateRequest {}).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "missing bearer token");
+
+        let status = client
+            .validate(with_token(pb::ValidateRequest {}, "forged"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.message(), "invalid or expired token");
+
+        // Refused by the interceptor, before Validate runs
+        let mut request = Request::new(pb::ValidateRequest {});
+        request
+            .metadata_mut()
+            .insert("authorization", "Basic dXNlcjpwYXNz".parse().unwrap());
+        let status = client.validate(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "authorization must be `Bearer <token>`");
+    }
+
+    #[test]
+    fn test_bearer_auth_only_reads_the_token() {
+        let request = bearer_auth(Request::new(())).unwrap();
+        assert!(request.extensions().get::<BearerToken>().is_none());
+
+        let request = bearer_auth(with_token((), " abc ")).unwrap();
+        assert_eq!(request.extensions().get::<BearerToken>().unwrap().0, "abc");
+
+        assert!(bearer_auth(with_token((), "")).is_err());
+    }
+}
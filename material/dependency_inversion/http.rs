@@ -0,0 +1,4290 @@
+// HTTP Layer
+// ==========
+//
+// Axum handlers over the `after` AuthService. Declared by
+// refactoring_with_di.rs with `mod http;` and started with:
+//
+//     cargo run --example refactoring_with_di -- --serve
+//
+// | Method | Path                                | Body                | Success                 |
+// |--------|-------------------------------------|---------------------|-------------------------|
+// | POST   | /api/v1/register                    | { email, password } | 201 { id, email, role } |
+// | POST   | /api/v1/login                       | { email, password } | 200 { access_token }    |
+// | GET    | /api/v1/me                          | Bearer token        | 200 { id, email, role } |
+// | GET    | /api/v1/me/events                   | Bearer token        | 200 event stream        |
+// | PUT    | /api/v1/me/avatar                   | multipart `avatar`  | 200 { avatar_url }      |
+// | POST   | /api/v1/logout                      | Bearer token        | 204                     |
+// | GET    | /api/v1/admin/users                 | admin token, ?page= | 200 { users, total }    |
+// | PUT    | /api/v1/admin/users/{id}/role       | admin token, {role} | 204                     |
+// | POST   | /api/v1/admin/users/{id}/deactivate | admin token         | 204                     |
+// | GET    | /api/v1/admin/audit                 | admin token, ?user= | 200 { entries, total }  |
+// | GET    | /api/v1/ws                          | token, WS upgrade   | 101, then user events   |
+// | GET    | /healthz                            |                     | 200 { status: "ok" }    |
+// | GET    | /readyz                             |                     | 200 or 503, per check   |
+// | GET    | /metrics                            |                     | 200 Prometheus text     |
+// | GET    | /docs                               |                     | 200 API reference page  |
+// | GET    | /openapi.json                       |                     | 200 OpenAPI 3.1 spec    |
+//
+// /api/v2 has the same routes. What changed is the user shape: `roles` is
+// a list instead of one `role`, and listings say which page comes next.
+// Handlers whose behaviour didn't change (login, logout) are mounted in
+// both versions as-is; only the ones returning users have a v2 copy, in
+// `mod v2`. v1 keeps working unchanged for existing clients. Probes and
+// docs are unversioned.
+//
+// The spec is generated from the `#[utoipa::path]` attributes on the
+// handlers and the `ToSchema` derives on the DTOs; a route added without
+// them shows up in the router but not in /docs.
+//
+// Handlers only translate: JSON in, one service call, JSON out. Which
+// repository, cache or hasher sits behind AuthService is decided by the
+// factory, so this file doesn't change when the backends do.
+//
+// Handlers return `Result<_, ApiError>` and use `?`; ApiError wraps the
+// domain Error and turns it into a status and a JSON body:
+//
+//     404 { "error": "not_found",           "code": "RES001_NOT_FOUND",            "message": "..." }
+//     409 { "error": "already_exists",      "code": "RES002_ALREADY_EXISTS",       "message": "..." }
+//     401 { "error": "invalid_credentials", "code": "AUTH001_INVALID_CREDENTIALS", "message": "..." }
+//     500 { "error": "internal",            "code": "SYS001_INTERNAL",             "message": "..." }
+//
+// `code` is an ErrorCode, and every error body has one, including the
+// ones the extractors and middleware below produce. Clients and tests
+// should branch on it, not on the message. Internal details only go to
+// the log, and to the ErrorReporter (Sentry, with SENTRY_DSN set).
+//
+// Successful responses are JSON unless the client prefers MessagePack
+// (`Accept: application/msgpack`): handlers take a `WireFormat` and return
+// `Negotiated(format, dto)`, which serializes with serde_json or rmp-serde.
+// The DTOs don't know which. Errors stay JSON, and the OpenAPI spec only
+// lists JSON.
+//
+// Request bodies go through `ValidatedJson<T>`, which checks the DTO's
+// `#[validate]` rules before the handler runs and answers 422 with the
+// failing fields:
+//
+//     422 { "error": "validation_failed", "code": "REQ003_VALIDATION_FAILED", "message": "...",
+//           "errors": [{ "field": "email", "code": "email", "message": "must be a valid email address" }],
+//           "fields": { "email": ["must be a valid email address"] } }
+//
+// Rules the service checks itself (Error::Validation, e.g. the password
+// policy) come back in exactly the same shape.
+//
+// Authentication is an extractor: a handler that takes `CurrentUser` only
+// runs for a valid, unrevoked token, and one that takes `AdminUser` only
+// for admins. Missing or bad tokens get 401, the wrong role gets 403.
+//
+// With AUTH_MODE=cookie the same routes use server-side sessions instead
+// (after::SessionService): /login answers 204 and sets an HttpOnly, Secure
+// `session` cookie, `CurrentUser` reads that cookie instead of the
+// Authorization header, and /logout deletes the session and the cookie.
+// Handlers don't change; only where the credential comes from and what
+// /login hands back does. The OpenAPI spec describes bearer mode.
+//
+// Cookies are sent on requests other sites trigger too, so cookie mode
+// also turns on the `csrf` middleware (double submit): GETs hand out a
+// `csrf` cookie, and POST/PUT/PATCH/DELETE are refused with 403 unless
+// X-CSRF-Token repeats it. Bearer mode doesn't need it; a forged request
+// has no way to add the Authorization header.
+//
+// `router` is just the routes; `app` wraps them in the middleware that
+// depends on configuration (CORS and rate limits, from `AppConfig`), and is
+// what `serve` runs.
+//
+// /healthz only says the process is up (restart me if it doesn't answer).
+// /readyz runs the HealthService checks (take me out of rotation if it
+// says 503): a down cache is "degraded" but still 200, a down database or
+// token service is "unavailable" and 503. /metrics is there with
+// METRICS_BACKEND=prometheus, and 404 otherwise.
+//
+// On SIGTERM or Ctrl-C the server stops accepting connections and gives
+// in-flight requests SERVER_SHUTDOWN_TIMEOUT_SECONDS to finish. After that
+// `serve` returns anyway and main() calls `App::shutdown`, so pools and
+// background tasks outlive every request that finished in time; stragglers
+// die with the process.
+//
+// Every request has an X-Request-Id: the client's, or a fresh UUID. It's
+// echoed on the response and recorded on the request's tracing span. The
+// AuthService, repository and cache spans open inside it, so their log
+// lines for that request carry it too:
+//
+//     DEBUG request{request_id="3f0c..." method=POST path=/api/v1/login}:
+//         login{email="me@example.com"}:find_by_email{email="me@example.com" db="postgres"}:
+//         SELECT * FROM users WHERE email = $1
+//
+// Work that leaves the request's task has to take the span along, or its
+// lines lose the ID: `tokio::spawn(job.in_current_span())`. The /ws socket
+// task does this with its upgrade request's span. gRPC calls open the same
+// `request` span (see grpc.rs).
+//
+// `request_log` adds one `tracing` event per request to that span: method,
+// path, status, latency and, where `RequestLogging` allows it, the JSON
+// bodies with passwords and tokens masked, cut at 1 KiB. Probes aren't
+// logged; avatar uploads are logged without bodies. LOG_HTTP_BODIES turns
+// bodies off everywhere (the default outside dev).
+//
+// Rate limits count per client IP and route in the shared cache (see
+// after::RateLimiter); over the limit is 429 with Retry-After. /login and
+// /register get their own, smaller budget.
+//
+// Error messages follow the request's Accept-Language, English or
+// Vietnamese (see messages.rs), else DEFAULT_LOCALE; `error` and `code`
+// never change with the language. Error responses carry Content-Language.
+//
+// Errors can also come as RFC 7807 problem details instead: put the
+// `problem_details` middleware on a router and every ErrorBody its
+// handlers and extractors produce is rewritten on the way out, status and
+// headers kept. /api/v2 does this; /api/v1 keeps the shape above.
+//
+//     404 application/problem+json
+//         { "type": "/problems/not_found", "title": "Not Found", "status": 404,
+//           "detail": "resource not found", "instance": "/api/v2/me",
+//           "code": "RES001_NOT_FOUND" }
+//
+// Listings take `Pagination`, which reads `?page=&per_page=` and hands the
+// handler a ready `PageRequest` (defaults and caps come from PageRequest).
+// The user listing also takes `Filter` (`?role=admin&active=false&email=`).
+//
+// PUT /me/avatar takes a multipart form with one file field, `avatar`
+// (PNG, JPEG or WebP, 1 MiB at most; the first bytes must agree with the
+// field's content type), stores it through after::StorageService and puts
+// the URL on the user. With file:// storage
+// `app` also serves the files at STORAGE_PUBLIC_URL.
+//
+// /ws pushes the connected user's domain events (after::EventBus) as JSON
+// text frames, e.g. { "type": "profile_updated", "user_id": "..." }. It
+// isn't in the OpenAPI spec, which can't describe a WebSocket.
+//
+// /me/events sends the same events as server-sent events, for clients
+// that only listen: `id` is the bus's event id, `event` the type, `data`
+// the JSON. A comment line every 15 s keeps idle proxies from closing it.
+// A client that reconnects with Last-Event-ID first gets what it missed,
+// as far back as the bus remembers (EventBus::DEFAULT_CAPACITY events).
+//
+// Everything under /admin needs an `AdminUser`. Role changes and
+// deactivations go through after::AdminService, which records them with
+// the admin's IP in the audit trail that GET /admin/audit shows.
+// `?user=<id>` narrows it to one account's history: what they did and
+// what was done to them.
+
+use crate::after::{
+    Actor, AdminService, App, AuditEntry, AuditFilter, AuthService, BusEvent, CacheService, Clock,
+    DomainEvent, ErrorReporter, HealthStatus, Metrics, Page, PageRequest, ProfileService, ROLES,
+    RateLimiter, ReadinessReport, SessionService, UserFilter, log_internal,
+};
+use crate::config::{AppConfig, AuthMode, CorsConfig, Locale, LogConfig, RateLimitConfig};
+use crate::messages;
+use crate::{Error, ErrorCode, User};
+use axum::body::{Body, HttpBody};
+use axum::extract::multipart::{Multipart, MultipartError};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code};
+use axum::extract::{
+    ConnectInfo, DefaultBodyLimit, FromRequest, FromRequestParts, MatchedPath, OriginalUri, Path,
+    Query, Request, State,
+};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tower::ServiceBuilder;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{
+    MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::services::ServeDir;
+use tower_http::trace::TraceLayer;
+use tracing::Instrument;
+use tutorial_core::ValidationError;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+use utoipa_scalar::{Scalar, Servable};
+use validator::{Validate, ValidationErrors};
+
+// What every handler gets through `State<Arc<AppState>>`
+pub struct AppState {
+    pub app: App,
+    pub auth_mode: AuthMode,
+    // CORS_ALLOWED_ORIGINS, for what the CORS layer doesn't see: the
+    // WebSocket handshake
+    pub allowed_origins: Vec<String>,
+}
+
+impl AppState {
+    // Bearer tokens, unless with_auth_mode says otherwise
+    pub fn new(app: App) -> Self {
+        Self {
+            app,
+            auth_mode: AuthMode::Bearer,
+            allowed_origins: Vec::new(),
+        }
+    }
+
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    pub fn with_allowed_origins(mut self, allowed_origins: Vec<String>) -> Self {
+        self.allowed_origins = allowed_origins;
+        self
+    }
+
+    // No Origin isn't a browser; the page's own origin (same host) always
+    // passes, anything else has to be listed
+    fn allows_origin(&self, headers: &HeaderMap) -> bool {
+        let Some(origin) = headers.get(header::ORIGIN) else {
+            return true;
+        };
+        let Ok(origin) = origin.to_str() else {
+            return false;
+        };
+        let host = headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok());
+        let same_host = origin
+            .split_once("://")
+            .is_some_and(|(_, authority)| Some(authority) == host);
+        same_host
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    // A fresh scope per request, so scoped services aren't shared
+    fn auth(&self) -> Arc<AuthService> {
+        self.app.auth_service()
+    }
+
+    fn sessions(&self) -> Arc<SessionService> {
+        self.app.session_service()
+    }
+
+    fn admin(&self) -> Arc<AdminService> {
+        self.app.admin_service()
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct RegisterRequest {
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    #[validate(length(min = 8, max = 128, message = "must be 8 to 128 characters"))]
+    pub password: String,
+}
+
+// No length rule beyond "present": a wrong password is a 401, not a 422
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct LoginRequest {
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    #[validate(length(min = 1, message = "must not be empty"))]
+    pub password: String,
+}
+
+// Never the password hash
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserResponse {
+    pub id: String,
+    pub email: String,
+    pub role: String,
+    pub avatar_url: Option<String>,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            role: user.role,
+            avatar_url: user.avatar_url,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserPage {
+    pub users: Vec<UserResponse>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: u64,
+}
+
+impl From<Page<User>> for UserPage {
+    fn from(page: Page<User>) -> Self {
+        Self {
+            users: page.items.into_iter().map(UserResponse::from).collect(),
+            page: page.page,
+            per_page: page.per_page,
+            total: page.total,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ChangeRoleRequest {
+    #[validate(custom(function = "known_role"))]
+    pub role: String,
+}
+
+fn known_role(role: &str) -> Result<(), validator::ValidationError> {
+    if ROLES.contains(&role) {
+        return Ok(());
+    }
+    Err(validator::ValidationError::new("role")
+        .with_message(format!("must be one of: {}", ROLES.join(", ")).into()))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditEntryResponse {
+    // Unix seconds
+    pub at: u64,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub detail: String,
+    // Where the admin was, when known
+    pub ip: Option<String>,
+}
+
+impl From<AuditEntry> for AuditEntryResponse {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            at: entry.at,
+            actor: entry.actor,
+            action: entry.action,
+            target: entry.target,
+            detail: entry.detail,
+            ip: entry.ip,
+        }
+    }
+}
+
+// Newest first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntryResponse>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: u64,
+}
+
+impl From<Page<AuditEntry>> for AuditPage {
+    fn from(page: Page<AuditEntry>) -> Self {
+        Self {
+            entries: page
+                .items
+                .into_iter()
+                .map(AuditEntryResponse::from)
+                .collect(),
+            page: page.page,
+            per_page: page.per_page,
+            total: page.total,
+        }
+    }
+}
+
+// Only for the spec: the handler reads the multipart form field by field
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct AvatarUpload {
+    // PNG, JPEG or WebP
+    #[schema(value_type = String, format = Binary)]
+    pub avatar: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AvatarResponse {
+    pub avatar_url: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .nest("/api/v1", v1_routes())
+        .nest("/api/v2", v2::routes())
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/openapi.json", get(|| async { Json(ApiDoc::openapi()) }))
+        .merge(Scalar::with_url("/docs", ApiDoc::openapi()))
+        .with_state(state)
+}
+
+fn v1_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/register", post(register))
+        .route("/login", post(login))
+        .route("/me", get(me))
+        .route("/me/events", get(me_events))
+        .route("/me/avatar", put(upload_avatar).layer(avatar_body_limit()))
+        .route("/logout", post(logout))
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/{id}/role", put(change_role))
+        .route("/admin/users/{id}/deactivate", post(deactivate_user))
+        .route("/admin/audit", get(audit_log))
+        .route("/ws", get(ws))
+}
+
+// Outermost first. The request ID comes before everything so even a 429
+// or a preflight answer has one; CORS comes before the rate limit so
+// preflights don't count and 429s still carry the headers a browser needs
+// to read them.
+pub fn app(state: Arc<AppState>, settings: &AppConfig) -> Router {
+    let limits = Arc::new(RateLimits::new(
+        state.app.cache(),
+        state.app.clock(),
+        &settings.rate_limit,
+    ));
+    let logging = Arc::new(RequestLogging::from_config(&settings.log));
+    let reporter = state.app.error_reporter();
+    let metrics = state.app.metrics();
+    let auth_mode = state.auth_mode;
+    let mut router = router(state);
+    // S3 (or a CDN in front of it) serves its own files
+    let storage = &settings.storage;
+    if let Some(root) = storage.url.strip_prefix("file://")
+        && storage.public_url.starts_with('/')
+    {
+        router = router.nest_service(&storage.public_url, ServeDir::new(root));
+    }
+    if auth_mode == AuthMode::Cookie {
+        router = router.layer(middleware::from_fn(csrf));
+    }
+    router.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(TraceLayer::new_for_http().make_span_with(request_span))
+            .layer(middleware::from_fn_with_state(metrics, request_metrics))
+            .layer(middleware::from_fn_with_state(reporter, report_errors))
+            .layer(middleware::from_fn_with_state(
+                settings.locale,
+                scope_locale,
+            ))
+            .layer(middleware::from_fn_with_state(logging, request_log))
+            .layer(cors_layer(&settings.cors))
+            .layer(middleware::from_fn_with_state(limits, rate_limit)),
+    )
+}
+
+pub async fn serve(state: Arc<AppState>, settings: &AppConfig) -> std::io::Result<()> {
+    let server = &settings.server;
+    let listener = TcpListener::bind((server.host.as_str(), server.port)).await?;
+    tracing::info!(addr = %listener.local_addr()?, "HTTP listening");
+    serve_with(
+        listener,
+        app(state, settings),
+        shutdown_signal(),
+        Duration::from_secs(server.shutdown_timeout_seconds),
+    )
+    .await
+}
+
+// Runs until `shutdown` completes, then drains for at most `drain_timeout`
+pub async fn serve_with(
+    listener: TcpListener,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    let (draining_tx, draining) = tokio::sync::oneshot::channel();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown.await;
+        tracing::info!("shutting down: no new connections, draining in-flight requests");
+        let _ = draining_tx.send(());
+    });
+    let mut server = tokio::spawn(server.into_future());
+
+    // Either the server fails on its own, or the signal starts the drain
+    tokio::select! {
+        result = &mut server => return result.map_err(std::io::Error::other)?,
+        _ = draining => {}
+    }
+
+    match tokio::time::timeout(drain_timeout, &mut server).await {
+        Ok(result) => result.map_err(std::io::Error::other)?,
+        Err(_) => {
+            tracing::warn!(
+                ?drain_timeout,
+                "drain timed out, abandoning the remaining requests"
+            );
+            server.abort();
+            Ok(())
+        }
+    }
+}
+
+// SIGTERM is what Kubernetes and systemd send; Ctrl-C is for the terminal
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Ctrl-C handler can be installed");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("SIGTERM handler can be installed")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+// Request IDs
+// -----------
+
+// Generic over the body so grpc.rs can open the same span for its calls
+fn request_id<B>(request: &axum::http::Request<B>) -> &str {
+    request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or("-")
+}
+
+pub fn request_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        request_id = request_id(request),
+        method = %request.method(),
+        path = %request.uri().path(),
+    )
+}
+
+// Error language
+// --------------
+
+tokio::task_local! {
+    // What error bodies in the current request are written in
+    static LOCALE: Locale;
+}
+
+// English outside a request, and for routers built without app()
+fn current_locale() -> Locale {
+    LOCALE.try_with(|locale| *locale).unwrap_or_default()
+}
+
+// The best language Accept-Language names that we have, else the default
+async fn scope_locale(State(default): State<Locale>, request: Request, next: Next) -> Response {
+    let locale = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(messages::from_accept_language)
+        .unwrap_or(default);
+    let mut response = LOCALE.scope(locale, next.run(request)).await;
+    if response.extensions().get::<ErrorBody>().is_some() {
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_LANGUAGE,
+            HeaderValue::from_static(locale.as_str()),
+        );
+        headers.append(header::VARY, HeaderValue::from_static("accept-language"));
+    }
+    response
+}
+
+// Error reporting
+// ---------------
+
+// Internal errors that surface below here go to the app's ErrorReporter
+// as well as the log. Inside the request span, so they carry its ID.
+async fn report_errors(
+    State(reporter): State<Arc<dyn ErrorReporter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    crate::after::reporting_errors_to(reporter, next.run(request)).await
+}
+
+// Request metrics
+// ---------------
+
+// Seconds until the response head, by method, route and status
+pub const REQUEST_DURATION: &str = "http_request_duration_seconds";
+
+// Each label value is a new series, so all three come from small sets: the
+// route pattern (/admin/users/{id}/role, never the id), a standard method,
+// and the status. Paths that match no route share "unmatched", like they
+// share a rate-limit budget, so scanning for URLs can't mint series.
+pub async fn request_metrics(
+    State(metrics): State<Arc<dyn Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = match request.method().as_str() {
+        method @ ("GET" | "HEAD" | "POST" | "PUT" | "PATCH" | "DELETE" | "OPTIONS") => {
+            method.to_string()
+        }
+        _ => "other".to_string(),
+    };
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics.histogram(
+        REQUEST_DURATION,
+        &[
+            ("method", &method),
+            ("route", &route),
+            ("status", response.status().as_str()),
+        ],
+        started.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+// Request logging
+// ---------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RouteLogging {
+    // Not logged (probes every few seconds would drown everything else)
+    Skip,
+    // Method, path, status and latency
+    Summary,
+    // Plus JSON bodies, redacted and truncated
+    WithBodies,
+}
+
+// What `request_log` records, per route. Routes match by suffix, like
+// AUTH_ROUTES, so one entry covers every API version; the first match wins.
+pub struct RequestLogging {
+    default: RouteLogging,
+    routes: Vec<(&'static str, RouteLogging)>,
+    max_body_bytes: usize,
+}
+
+impl RequestLogging {
+    pub const DEFAULT_MAX_BODY_BYTES: usize = 1024;
+
+    pub fn new(default: RouteLogging) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+            max_body_bytes: Self::DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    pub fn route(mut self, suffix: &'static str, logging: RouteLogging) -> Self {
+        self.routes.push((suffix, logging));
+        self
+    }
+
+    // Longer bodies are cut, with a note of how much was left out
+    #[cfg(test)]
+    pub fn max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    pub fn from_config(config: &LogConfig) -> Self {
+        let default = if config.http_bodies {
+            RouteLogging::WithBodies
+        } else {
+            RouteLogging::Summary
+        };
+        Self::new(default)
+            .route("/healthz", RouteLogging::Skip)
+            .route("/readyz", RouteLogging::Skip)
+            .route("/metrics", RouteLogging::Skip)
+            // Images; there's nothing readable to log
+            .route("/me/avatar", RouteLogging::Summary)
+    }
+
+    fn for_route(&self, route: &str) -> RouteLogging {
+        self.routes
+            .iter()
+            .find(|(suffix, _)| route.ends_with(suffix))
+            .map_or(self.default, |(_, logging)| *logging)
+    }
+}
+
+// Bodies are only buffered for the log when their size is known and at
+// most this; anything else streams through untouched
+const MAX_BUFFERED_BODY_BYTES: usize = 64 * 1024;
+
+// A key containing any of these has its value replaced, at any depth
+const SECRET_FIELDS: [&str; 4] = ["password", "token", "secret", "authorization"];
+
+// Inside the span from `request_span`, so the event carries the request id
+pub async fn request_log(
+    State(logging): State<Arc<RequestLogging>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let mode = logging.for_route(&route);
+    if mode == RouteLogging::Skip {
+        return next.run(request).await;
+    }
+
+    let started = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let max = logging.max_body_bytes;
+
+    let (parts, body) = request.into_parts();
+    let (body, request_body) = match mode {
+        RouteLogging::WithBodies => loggable_body(&parts.headers, body, max).await,
+        _ => (body, None),
+    };
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let (parts, body) = response.into_parts();
+    let (body, response_body) = match mode {
+        RouteLogging::WithBodies => loggable_body(&parts.headers, body, max).await,
+        _ => (body, None),
+    };
+    tracing::info!(
+        target: "http",
+        %method,
+        path,
+        status = parts.status.as_u16(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        request_body,
+        response_body,
+        "request finished"
+    );
+    Response::from_parts(parts, body)
+}
+
+// Reads a small JSON body for the log and hands back an equivalent one.
+// Anything else is passed on as it is and not logged.
+async fn loggable_body(
+    headers: &HeaderMap,
+    body: Body,
+    max_bytes: usize,
+) -> (Body, Option<String>) {
+    let json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json") || value.contains("+json"));
+    let small = body
+        .size_hint()
+        .exact()
+        .is_some_and(|size| size <= MAX_BUFFERED_BODY_BYTES as u64);
+    if !json || !small {
+        return (body, None);
+    }
+
+    match axum::body::to_bytes(body, MAX_BUFFERED_BODY_BYTES).await {
+        Ok(bytes) => {
+            let logged = log_body(&bytes, max_bytes);
+            (Body::from(bytes), Some(logged))
+        }
+        // The client went away mid-body; the handler would have failed too
+        Err(e) => (Body::empty(), Some(format!("<unreadable: {}>", e))),
+    }
+}
+
+// Secrets masked, then cut to `max_bytes`
+fn log_body(bytes: &[u8], max_bytes: usize) -> String {
+    let mut value = match serde_json::from_slice::<serde_json::Value>(bytes) {
+        Ok(value) => value,
+        // Can't tell the secrets apart, so none of it is logged
+        Err(_) => return format!("<{} bytes, not valid JSON>", bytes.len()),
+    };
+    redact(&mut value);
+    truncate(value.to_string(), max_bytes)
+}
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                let key = key.to_lowercase();
+                if SECRET_FIELDS.iter().any(|secret| key.contains(secret)) {
+                    *value = serde_json::Value::String("***".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn truncate(mut text: String, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let dropped = text.len() - end;
+    text.truncate(end);
+    format!("{}... ({} more bytes)", text, dropped)
+}
+
+// Values were checked when the config loaded, so nothing is dropped here
+// in practice. No origins means no CORS headers: same-origin only.
+pub fn cors_layer(cors: &CorsConfig) -> CorsLayer {
+    let origins = if cors.allows_any_origin() {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            cors.allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    let methods: Vec<Method> = cors
+        .allowed_methods
+        .iter()
+        .filter_map(|method| method.parse().ok())
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(CSRF_HEADER),
+        ])
+        .allow_credentials(cors.allow_credentials)
+}
+
+#[utoipa::path(
+    post,
+    path = "/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "User created", body = UserResponse),
+        (status = 409, description = "Email already registered", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+async fn register(
+    State(state): State<Arc<AppState>>,
+    format: WireFormat,
+    ValidatedJson(body): ValidatedJson<RegisterRequest>,
+) -> Result<(StatusCode, Negotiated<UserResponse>), ApiError> {
+    let user = state.auth().register(&body.email, &body.password).await?;
+    Ok((StatusCode::CREATED, Negotiated(format, user.into())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Token issued", body = TokenResponse),
+        (status = 204, description = "AUTH_MODE=cookie: session started, see Set-Cookie"),
+        (status = 401, description = "Wrong email or password", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+async fn login(
+    State(state): State<Arc<AppState>>,
+    format: WireFormat,
+    ValidatedJson(body): ValidatedJson<LoginRequest>,
+) -> Result<Response, ApiError> {
+    match state.auth_mode {
+        AuthMode::Bearer => {
+            let token = state.auth().login(&body.email, &body.password).await?;
+            let body = TokenResponse {
+                access_token: token,
+                token_type: "Bearer",
+            };
+            Ok(Negotiated(format, body).into_response())
+        }
+        AuthMode::Cookie => {
+            let sessions = state.sessions();
+            let session_id = sessions.login(&body.email, &body.password).await?;
+            let cookie = session_cookie(&session_id, sessions.ttl_seconds());
+            Ok((StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response())
+        }
+    }
+}
+
+// Counted on the whole multipart body, so the file itself can be a few
+// hundred bytes less. Bigger bodies get 413 before the handler runs.
+pub const MAX_AVATAR_BYTES: usize = 1024 * 1024;
+
+fn avatar_body_limit() -> DefaultBodyLimit {
+    DefaultBodyLimit::max(MAX_AVATAR_BYTES)
+}
+
+#[utoipa::path(
+    put,
+    path = "/me/avatar",
+    tag = "auth",
+    security(("bearer" = [])),
+    request_body(content = AvatarUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Stored; the user now has this avatar", body = AvatarResponse),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+        (status = 413, description = "Larger than 1 MiB", body = ErrorBody),
+        (status = 415, description = "Not PNG, JPEG or WebP, or not what its content type says", body = ErrorBody),
+        (status = 422, description = "No `avatar` field", body = ErrorBody),
+    )
+)]
+async fn upload_avatar(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    format: WireFormat,
+    mut multipart: Multipart,
+) -> Result<Negotiated<AvatarResponse>, Response> {
+    let invalid =
+        |e: MultipartError| error_response(e.status(), ErrorCode::InvalidBody, &e.body_text());
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        if field.name() != Some("avatar") {
+            continue;
+        }
+        let content_type = field.content_type().unwrap_or_default().to_string();
+        if !ProfileService::AVATAR_TYPES
+            .iter()
+            .any(|(known, _)| *known == content_type)
+        {
+            return Err(error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorCode::UnsupportedMediaType,
+                "avatar must be image/png, image/jpeg or image/webp",
+            ));
+        }
+        let bytes = field.bytes().await.map_err(invalid)?;
+        // The header is the client's word; the first bytes are what gets
+        // stored and later served under that type
+        if ProfileService::sniff_avatar_type(&bytes) != Some(content_type.as_str()) {
+            return Err(error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorCode::UnsupportedMediaType,
+                &format!("avatar content is not {}", content_type),
+            ));
+        }
+        let user = state
+            .app
+            .profile_service()
+            .set_avatar(&current.user, &content_type, bytes.to_vec())
+            .await
+            .map_err(|error| ApiError(error).into_response())?;
+        let body = AvatarResponse {
+            avatar_url: user.avatar_url.unwrap_or_default(),
+        };
+        return Ok(Negotiated(format, body));
+    }
+    Err(error_response(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorCode::MissingFile,
+        "expected a file field named \"avatar\"",
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The token's user", body = UserResponse),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+    )
+)]
+async fn me(current: CurrentUser, format: WireFormat) -> Negotiated<UserResponse> {
+    Negotiated(format, current.user.into())
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+    )
+)]
+async fn logout(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+) -> Result<Response, ApiError> {
+    match state.auth_mode {
+        AuthMode::Bearer => {
+            state.auth().logout(&current.token).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        AuthMode::Cookie => {
+            state.sessions().logout(&current.token).await?;
+            let cookie = session_cookie("", 0);
+            Ok((StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    security(("bearer" = [])),
+    params(PaginationQuery, UserFilterQuery),
+    responses(
+        (status = 200, description = "One page of users", body = UserPage),
+        (status = 400, description = "Malformed query", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    )
+)]
+async fn list_users(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Pagination(page): Pagination,
+    Filter(filter): Filter,
+    format: WireFormat,
+) -> Result<Negotiated<UserPage>, ApiError> {
+    let users = state.admin().list_users(&filter, page).await?;
+    Ok(Negotiated(format, users.into()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/role",
+    tag = "admin",
+    security(("bearer" = [])),
+    params(("id" = String, Path, description = "User id")),
+    request_body = ChangeRoleRequest,
+    responses(
+        (status = 204, description = "Role changed"),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+        (status = 422, description = "Unknown role", body = ErrorBody),
+    )
+)]
+async fn change_role(
+    State(state): State<Arc<AppState>>,
+    admin: AdminUser,
+    ip: ClientIp,
+    Path(id): Path<String>,
+    ValidatedJson(body): ValidatedJson<ChangeRoleRequest>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .admin()
+        .change_role(&admin.actor(ip), &id, &body.role)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/deactivate",
+    tag = "admin",
+    security(("bearer" = [])),
+    params(("id" = String, Path, description = "User id")),
+    responses(
+        (status = 204, description = "Deactivated; the user's tokens stop working"),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    )
+)]
+async fn deactivate_user(
+    State(state): State<Arc<AppState>>,
+    admin: AdminUser,
+    ip: ClientIp,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.admin().deactivate(&admin.actor(ip), &id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    security(("bearer" = [])),
+    params(PaginationQuery, AuditFilterQuery),
+    responses(
+        (status = 200, description = "Admin actions, newest first", body = AuditPage),
+        (status = 400, description = "Malformed query", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    )
+)]
+async fn audit_log(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Pagination(page): Pagination,
+    AuditQuery(filter): AuditQuery,
+    format: WireFormat,
+) -> Result<Negotiated<AuditPage>, ApiError> {
+    let entries = state.admin().audit_log(&filter, page).await?;
+    Ok(Negotiated(format, entries.into()))
+}
+
+// API v2
+// ------
+
+pub mod v2 {
+    use super::*;
+
+    pub fn routes() -> Router<Arc<AppState>> {
+        Router::new()
+            .route("/register", post(register))
+            .route("/login", post(super::login))
+            .route("/me", get(me))
+            .route("/me/events", get(super::me_events))
+            .route(
+                "/me/avatar",
+                put(super::upload_avatar).layer(avatar_body_limit()),
+            )
+            .route("/logout", post(super::logout))
+            .route("/admin/users", get(list_users))
+            .route("/admin/users/{id}/role", put(super::change_role))
+            .route("/admin/users/{id}/deactivate", post(super::deactivate_user))
+            .route("/admin/audit", get(super::audit_log))
+            .route("/ws", get(super::ws))
+            .layer(middleware::from_fn(problem_details))
+    }
+
+    // Shared handlers reuse v1's docs, so their error responses are shown
+    // as ErrorBody although v2 sends them as problem details
+    #[derive(OpenApi)]
+    #[openapi(paths(
+        register,
+        super::login,
+        me,
+        super::me_events,
+        super::logout,
+        super::upload_avatar,
+        list_users,
+        super::change_role,
+        super::deactivate_user,
+        super::audit_log,
+    ))]
+    pub struct Api;
+
+    // v1's UserResponse with `role` turned into `roles`
+    #[derive(Debug, Serialize, ToSchema)]
+    #[schema(as = v2::UserResponse)]
+    pub struct UserResponse {
+        pub id: String,
+        pub email: String,
+        pub roles: Vec<String>,
+        pub avatar_url: Option<String>,
+    }
+
+    impl From<User> for UserResponse {
+        fn from(user: User) -> Self {
+            Self {
+                id: user.id,
+                email: user.email,
+                roles: vec![user.role],
+                avatar_url: user.avatar_url,
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize, ToSchema)]
+    #[schema(as = v2::UserPage)]
+    pub struct UserPage {
+        pub items: Vec<UserResponse>,
+        pub page: u32,
+        pub per_page: u32,
+        pub total: u64,
+        // None on the last page
+        pub next_page: Option<u32>,
+    }
+
+    impl From<Page<User>> for UserPage {
+        fn from(page: Page<User>) -> Self {
+            let seen = page.page as u64 * page.per_page as u64;
+            Self {
+                next_page: (seen < page.total).then_some(page.page + 1),
+                items: page.items.into_iter().map(UserResponse::from).collect(),
+                page: page.page,
+                per_page: page.per_page,
+                total: page.total,
+            }
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/register",
+        tag = "auth",
+        request_body = RegisterRequest,
+        responses(
+            (status = 201, description = "User created", body = UserResponse),
+            (status = 409, description = "Email already registered", body = ProblemDetails, content_type = PROBLEM_JSON),
+            (status = 422, description = "Invalid body", body = ProblemDetails, content_type = PROBLEM_JSON),
+        )
+    )]
+    async fn register(
+        State(state): State<Arc<AppState>>,
+        format: WireFormat,
+        ValidatedJson(body): ValidatedJson<RegisterRequest>,
+    ) -> Result<(StatusCode, Negotiated<UserResponse>), ApiError> {
+        let user = state.auth().register(&body.email, &body.password).await?;
+        Ok((StatusCode::CREATED, Negotiated(format, user.into())))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/me",
+        tag = "auth",
+        security(("bearer" = [])),
+        responses(
+            (status = 200, description = "The token's user", body = UserResponse),
+            (status = 401, description = "Missing, invalid or revoked token", body = ProblemDetails, content_type = PROBLEM_JSON),
+        )
+    )]
+    async fn me(current: CurrentUser, format: WireFormat) -> Negotiated<UserResponse> {
+        Negotiated(format, current.user.into())
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/admin/users",
+        tag = "admin",
+        security(("bearer" = [])),
+        params(PaginationQuery, UserFilterQuery),
+        responses(
+            (status = 200, description = "One page of users", body = UserPage),
+            (status = 400, description = "Malformed query", body = ProblemDetails, content_type = PROBLEM_JSON),
+            (status = 401, description = "Missing, invalid or revoked token", body = ProblemDetails, content_type = PROBLEM_JSON),
+            (status = 403, description = "Not an admin", body = ProblemDetails, content_type = PROBLEM_JSON),
+        )
+    )]
+    async fn list_users(
+        State(state): State<Arc<AppState>>,
+        _admin: AdminUser,
+        Pagination(page): Pagination,
+        Filter(filter): Filter,
+        format: WireFormat,
+    ) -> Result<Negotiated<UserPage>, ApiError> {
+        let users = state.admin().list_users(&filter, page).await?;
+        Ok(Negotiated(format, users.into()))
+    }
+}
+
+// Events over WebSocket
+// ----------------------
+
+// Browsers can't set headers on a WebSocket handshake, so the token may
+// also come as `?token=`. That puts it in access logs; in production a
+// short-lived ticket from a normal request is the better trade. Cookies do
+// go with the handshake, so in cookie mode there's nothing extra to pass.
+//
+// Which is also the danger: CORS doesn't apply to a WebSocket handshake,
+// so any page the user visits could open one and the browser would attach
+// the session cookie (cross-site WebSocket hijacking). In cookie mode the
+// handshake's Origin has to be one CORS_ALLOWED_ORIGINS lists.
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    pub token: Option<String>,
+}
+
+async fn ws(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AuthRejection> {
+    let credential = match state.auth_mode {
+        AuthMode::Bearer => bearer_token(&headers)
+            .map(str::to_string)
+            .or(query.token)
+            .ok_or(AuthRejection::MissingToken)?,
+        AuthMode::Cookie => {
+            if !state.allows_origin(&headers) {
+                return Ok(error_response(
+                    StatusCode::FORBIDDEN,
+                    ErrorCode::CsrfFailed,
+                    "websocket origin not allowed",
+                ));
+            }
+            session_id(&headers)
+                .ok_or(AuthRejection::MissingSession)?
+                .to_string()
+        }
+    };
+    let current = CurrentUser::authenticate(&state, credential).await?;
+    // Before the upgrade, so nothing published in between is missed
+    let events = state.app.events().subscribe();
+    // The socket outlives the handler; its task keeps the upgrade's request
+    // span, so what it logs still carries the request ID
+    let span = tracing::Span::current();
+    Ok(upgrade
+        .on_upgrade(move |socket| push_events(socket, state, current, events).instrument(span)))
+}
+
+// Forwards the user's events until the client leaves, the bus closes or
+// this connection's own token is revoked. Client messages are ignored.
+async fn push_events(
+    mut socket: WebSocket,
+    state: Arc<AppState>,
+    current: CurrentUser,
+    mut events: broadcast::Receiver<BusEvent>,
+) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(delivered) if delivered.event.user_id() == current.user.id => {
+                    let Ok(json) = serde_json::to_string(&delivered.event) else {
+                        continue;
+                    };
+                    let send = socket.send(Message::Text(json.into()));
+                    if send.instrument(delivery_span(&delivered)).await.is_err() {
+                        return;
+                    }
+                    let event = delivered.event;
+                    // Another device logging out doesn't end this session
+                    if matches!(event, DomainEvent::SessionRevoked { .. })
+                        && CurrentUser::authenticate(&state, current.token.clone())
+                            .await
+                            .is_err()
+                    {
+                        let close = CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "session revoked".into(),
+                        };
+                        let _ = socket.send(Message::Close(Some(close))).await;
+                        return;
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+// One span per event pushed to a client, inside the span of the /ws or SSE
+// request and linked to the span that published the event
+fn delivery_span(delivered: &BusEvent) -> tracing::Span {
+    let span = tracing::info_span!(
+        "deliver",
+        event.id = delivered.id,
+        event.kind = delivered.event.kind(),
+    );
+    delivered.cause.add_to(&span);
+    span
+}
+
+// Events over SSE
+// ---------------
+
+const SSE_HEARTBEAT: Duration = Duration::from_secs(15);
+
+#[utoipa::path(
+    get,
+    path = "/me/events",
+    tag = "auth",
+    security(("bearer" = [])),
+    params(("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event id")),
+    responses(
+        (status = 200, description = "The user's events as they happen", body = String, content_type = "text/event-stream"),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorBody),
+    )
+)]
+async fn me_events(
+    State(state): State<Arc<AppState>>,
+    current: CurrentUser,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let last_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let bus = state.app.events();
+    let (missed, live) = match last_id {
+        Some(last_id) => bus.subscribe_after(last_id),
+        None => (Vec::new(), bus.subscribe()),
+    };
+
+    let user_id = current.user.id.clone();
+    let missed = missed
+        .into_iter()
+        .filter(move |event| event.event.user_id() == user_id);
+    let live = stream::unfold(Some((live, state, current)), |next| async move {
+        let (mut live, state, current) = next?;
+        loop {
+            match live.recv().await {
+                Ok(event) if event.event.user_id() == current.user.id => {
+                    // Like /ws: ends when this token or session is revoked
+                    let ended = matches!(event.event, DomainEvent::SessionRevoked { .. })
+                        && CurrentUser::authenticate(&state, current.token.clone())
+                            .await
+                            .is_err();
+                    return Some((event, (!ended).then_some((live, state, current))));
+                }
+                Ok(_) => {}
+                // Too far behind: end the stream, and the client's reconnect
+                // with Last-Event-ID picks up from what the bus kept
+                Err(RecvError::Lagged(_) | RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(missed).chain(live).map(|event| {
+        let _delivery = delivery_span(&event).entered();
+        Ok(SseEvent::default()
+            .id(event.id.to_string())
+            .event(event.event.kind())
+            .json_data(&event.event)
+            .unwrap_or_default())
+    });
+    Sse::new(events).keep_alive(KeepAlive::new().interval(SSE_HEARTBEAT).text("heartbeat"))
+}
+
+// Health
+// ------
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: &'static str,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    // ok, degraded or unavailable
+    pub status: &'static str,
+    pub components: Vec<ComponentResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ComponentResponse {
+    pub name: String,
+    // up or down
+    pub status: &'static str,
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<ReadinessReport> for ReadinessResponse {
+    fn from(report: ReadinessReport) -> Self {
+        Self {
+            status: match report.status() {
+                HealthStatus::Healthy => "ok",
+                HealthStatus::Degraded => "degraded",
+                HealthStatus::Unavailable => "unavailable",
+            },
+            components: report
+                .components
+                .into_iter()
+                .map(|component| ComponentResponse {
+                    name: component.name,
+                    status: if component.status.is_ok() {
+                        "up"
+                    } else {
+                        "down"
+                    },
+                    critical: component.critical,
+                    error: component.status.err(),
+                })
+                .collect(),
+        }
+    }
+}
+
+// Liveness: no dependencies, so a database outage doesn't get every
+// instance restarted
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses((status = 200, description = "The process is up", body = HealthResponse))
+)]
+async fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok" })
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready, possibly degraded", body = ReadinessResponse),
+        (status = 503, description = "A critical dependency is down", body = ReadinessResponse),
+    )
+)]
+async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessResponse>) {
+    let report = state.app.health().readiness().await;
+    let status = match report.status() {
+        HealthStatus::Healthy | HealthStatus::Degraded => StatusCode::OK,
+        HealthStatus::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(report.into()))
+}
+
+// What Prometheus scrapes. The other backends push, so there's nothing
+// to serve.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every metric, in Prometheus' text format", body = String, content_type = "text/plain"),
+        (status = 404, description = "METRICS_BACKEND isn't prometheus", body = ErrorBody),
+    )
+)]
+async fn metrics(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    let metrics = state.app.prometheus().ok_or(Error::NotFound)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+        .into_response())
+}
+
+// OpenAPI
+// -------
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Auth API", description = "AuthService over HTTP"),
+    paths(healthz, readyz, metrics),
+    nest(
+        (path = "/api/v1", api = V1Api),
+        (path = "/api/v2", api = v2::Api),
+    ),
+    components(schemas(ErrorBody)),
+    modifiers(&BearerAuth, &VersionedOperationIds),
+)]
+pub struct ApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    register,
+    login,
+    me,
+    me_events,
+    logout,
+    upload_avatar,
+    list_users,
+    change_role,
+    deactivate_user,
+    audit_log,
+))]
+struct V1Api;
+
+// A shared handler appears under both versions with the same operationId,
+// which must be unique: prefix it with the version ("v2_login")
+struct VersionedOperationIds;
+
+impl Modify for VersionedOperationIds {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            let Some(version) = path
+                .strip_prefix("/api/")
+                .and_then(|rest| rest.split('/').next())
+            else {
+                continue;
+            };
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.patch,
+            ];
+            for operation in operations.into_iter().flatten() {
+                if let Some(id) = &operation.operation_id {
+                    operation.operation_id = Some(format!("{}_{}", version, id));
+                }
+            }
+        }
+    }
+}
+
+// Declares the "bearer" scheme the protected paths refer to
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+// Authentication
+// --------------
+
+// The user behind the request's bearer token (or session cookie, in
+// cookie mode), already checked and loaded from the repository
+pub struct CurrentUser {
+    pub user: User,
+    // The token or session id, kept for handlers that act on it (logout)
+    pub token: String,
+}
+
+impl FromRequestParts<Arc<AppState>> for CurrentUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = match state.auth_mode {
+            AuthMode::Bearer => bearer_token(&parts.headers).ok_or(AuthRejection::MissingToken)?,
+            AuthMode::Cookie => session_id(&parts.headers).ok_or(AuthRejection::MissingSession)?,
+        };
+        Self::authenticate(state, token.to_string()).await
+    }
+}
+
+impl CurrentUser {
+    // For handlers that find the credential somewhere other than the
+    // header. A token in bearer mode, a session id in cookie mode.
+    async fn authenticate(state: &AppState, token: String) -> Result<Self, AuthRejection> {
+        let (user, invalid) = match state.auth_mode {
+            AuthMode::Bearer => (
+                state.auth().current_user(&token).await,
+                AuthRejection::InvalidToken,
+            ),
+            AuthMode::Cookie => (
+                state.sessions().current_user(&token).await,
+                AuthRejection::InvalidSession,
+            ),
+        };
+        match user {
+            Ok(user) => Ok(Self { user, token }),
+            // A database outage is not the client's fault
+            Err(error @ Error::Internal { .. }) => {
+                log_internal(&error);
+                Err(AuthRejection::Unavailable)
+            }
+            Err(_) => Err(invalid),
+        }
+    }
+}
+
+// CurrentUser with the admin role
+pub struct AdminUser(pub CurrentUser);
+
+impl AdminUser {
+    // Who the audit trail says did it
+    pub fn actor(&self, ClientIp(ip): ClientIp) -> Actor {
+        Actor {
+            user_id: self.0.user.id.clone(),
+            ip,
+        }
+    }
+}
+
+// The peer address, when the server was started with connect info
+// (`serve` is; a router called directly in a test isn't). Behind a load
+// balancer that's the balancer, and the client IP has to come from
+// X-Forwarded-For set by a trusted proxy.
+pub struct ClientIp(pub Option<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(client_ip(&parts.extensions)))
+    }
+}
+
+fn client_ip(extensions: &axum::http::Extensions) -> Option<String> {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+}
+
+impl FromRequestParts<Arc<AppState>> for AdminUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let current = CurrentUser::from_request_parts(parts, state).await?;
+        if current.user.role != "admin" {
+            return Err(AuthRejection::Forbidden);
+        }
+        Ok(Self(current))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthRejection {
+    // 401: who are you?
+    MissingToken,
+    InvalidToken,
+    MissingSession,
+    InvalidSession,
+    // 403: we know who you are, and the answer is no
+    Forbidden,
+    // 500: couldn't check
+    Unavailable,
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        // RFC 6750: a 401 says which scheme to use, and why the token failed
+        let (challenge, code, message) = match self {
+            AuthRejection::MissingToken => (
+                "Bearer",
+                ErrorCode::MissingCredentials,
+                "missing bearer token",
+            ),
+            AuthRejection::InvalidToken => (
+                "Bearer error=\"invalid_token\"",
+                ErrorCode::InvalidToken,
+                "invalid or expired token",
+            ),
+            // No scheme to name: the cookie comes from /login, not the client
+            AuthRejection::MissingSession => {
+                return error_response(
+                    StatusCode::UNAUTHORIZED,
+                    ErrorCode::MissingCredentials,
+                    "missing session cookie",
+                );
+            }
+            AuthRejection::InvalidSession => {
+                return error_response(
+                    StatusCode::UNAUTHORIZED,
+                    ErrorCode::InvalidToken,
+                    "invalid or expired session",
+                );
+            }
+            AuthRejection::Forbidden => {
+                return error_response(
+                    StatusCode::FORBIDDEN,
+                    ErrorCode::Forbidden,
+                    "admin role required",
+                );
+            }
+            AuthRejection::Unavailable => {
+                return error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::Internal,
+                    "internal server error",
+                );
+            }
+        };
+        let mut response = error_response(StatusCode::UNAUTHORIZED, code, message);
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static(challenge),
+        );
+        response
+    }
+}
+
+// `Authorization: Bearer <token>`
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+pub const SESSION_COOKIE: &str = "session";
+
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    cookie(headers, SESSION_COOKIE)
+}
+
+// `Cookie: a=1; session=<id>`
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+// HttpOnly: page scripts can't read it. Secure: HTTPS only (browsers make
+// an exception for localhost). SameSite=Lax: not sent on cross-site POSTs.
+// Max-Age=0 clears it.
+fn session_cookie(session_id: &str, max_age_seconds: u64) -> String {
+    format!(
+        "{}={}; HttpOnly; Secure; SameSite=Lax; Path=/; Max-Age={}",
+        SESSION_COOKIE, session_id, max_age_seconds
+    )
+}
+
+// CSRF
+// ----
+
+// Double submit: another site can make the browser send our cookies, but
+// can't read them, so it can't copy the token into the header. That's why
+// the `csrf` cookie is deliberately not HttpOnly: the page's own script
+// reads it.
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+// Safe methods pass and get a token if they don't have one yet; everything
+// else needs the header to match the cookie. Login and register are
+// covered too, so a client GETs something (any route) before its first POST.
+pub async fn csrf(request: Request, next: Next) -> Response {
+    let token = cookie(request.headers(), CSRF_COOKIE).map(str::to_string);
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+
+    if safe {
+        let mut response = next.run(request).await;
+        if token.is_none() {
+            let cookie = format!(
+                "{}={}; Secure; SameSite=Lax; Path=/",
+                CSRF_COOKIE,
+                uuid::Uuid::new_v4().simple()
+            );
+            if let Ok(value) = HeaderValue::from_str(&cookie) {
+                response.headers_mut().append(header::SET_COOKIE, value);
+            }
+        }
+        return response;
+    }
+
+    let Some(token) = token else {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::CsrfFailed,
+            "missing csrf cookie; make a GET request first",
+        );
+    };
+    let submitted = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !same_token(&token, submitted) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::CsrfFailed,
+            "X-CSRF-Token must match the csrf cookie",
+        );
+    }
+    next.run(request).await
+}
+
+// Looks at every byte whatever the first difference, so the response time
+// doesn't say how much of a guess was right
+fn same_token(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// Rate limiting
+// -------------
+
+pub struct RateLimits {
+    requests: RateLimiter,
+    auth_requests: RateLimiter,
+}
+
+impl RateLimits {
+    pub fn new(
+        cache: Arc<dyn CacheService>,
+        clock: Arc<dyn Clock>,
+        config: &RateLimitConfig,
+    ) -> Self {
+        Self {
+            requests: RateLimiter::new(cache.clone(), config.requests, config.window_seconds)
+                .with_clock(clock.clone()),
+            auth_requests: RateLimiter::new(cache, config.auth_requests, config.window_seconds)
+                .with_clock(clock),
+        }
+    }
+}
+
+// In every API version
+const AUTH_ROUTES: [&str; 2] = ["/login", "/register"];
+
+pub async fn rate_limit(
+    State(limits): State<Arc<RateLimits>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // The route pattern, not the URI: /users/1 and /users/2 share a budget,
+    // and made-up paths can't mint fresh counters
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    // See ClientIp
+    let ip = client_ip(request.extensions()).unwrap_or_else(|| "unknown".to_string());
+
+    let limiter = if AUTH_ROUTES.iter().any(|auth| route.ends_with(auth)) {
+        &limits.auth_requests
+    } else {
+        &limits.requests
+    };
+    if let Err(limited) = limiter.check(&format!("{}:{}", route, ip)).await {
+        let mut response = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "too many requests, retry later",
+        );
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(limited.retry_after_seconds),
+        );
+        return response;
+    }
+
+    next.run(request).await
+}
+
+// Validation
+// ----------
+
+// `Json<T>` plus `T::validate()`
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ValidationRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state)
+            .await
+            .map_err(ValidationRejection::Body)?;
+        value.validate().map_err(ValidationRejection::Fields)?;
+        Ok(Self(value))
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidationRejection {
+    // Not JSON, or not the right shape: axum's own status (400/415/422)
+    Body(JsonRejection),
+    // Well-formed, but breaks a #[validate] rule
+    Fields(ValidationErrors),
+}
+
+impl IntoResponse for ValidationRejection {
+    fn into_response(self) -> Response {
+        match self {
+            ValidationRejection::Body(rejection) => error_response(
+                rejection.status(),
+                ErrorCode::InvalidBody,
+                &rejection.body_text(),
+            ),
+            ValidationRejection::Fields(errors) => {
+                validation_response("request body failed validation", validation_errors(&errors))
+            }
+        }
+    }
+}
+
+// validator's report as our ValidationErrors, sorted by field. A rule
+// without a message reports its code, e.g. "email".
+pub fn validation_errors(errors: &ValidationErrors) -> Vec<ValidationError> {
+    let mut fields: Vec<_> = errors.field_errors().into_iter().collect();
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+    fields
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |error| {
+                let message = match &error.message {
+                    Some(message) => message.to_string(),
+                    None => error.code.to_string(),
+                };
+                ValidationError::new(field.to_string(), error.code.to_string(), message)
+            })
+        })
+        .collect()
+}
+
+// The one 422, for DTO rules and Error::Validation alike
+fn validation_response(message: &str, errors: Vec<ValidationError>) -> Response {
+    let mut fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for error in &errors {
+        fields
+            .entry(error.field.clone())
+            .or_default()
+            .push(error.message.clone());
+    }
+    let body = ErrorBody {
+        error: error_kind(ErrorCode::ValidationFailed),
+        code: ErrorCode::ValidationFailed,
+        message: localized(ErrorCode::ValidationFailed, message),
+        errors,
+        fields,
+    };
+    error_body_response(StatusCode::UNPROCESSABLE_ENTITY, body)
+}
+
+// Pagination
+// ----------
+
+// `?page=2&per_page=50`; both optional
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaginationQuery {
+    // 1-based, default 1
+    pub page: Option<u32>,
+    // Default 20, capped at 100
+    pub per_page: Option<u32>,
+}
+
+pub struct Pagination(pub PageRequest);
+
+impl<S: Send + Sync> FromRequestParts<S> for Pagination {
+    type Rejection = InvalidQuery;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PaginationQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(InvalidQuery)?;
+        Ok(Self(PageRequest::new(
+            query.page.unwrap_or(1),
+            query.per_page.unwrap_or(PageRequest::DEFAULT_PER_PAGE),
+        )))
+    }
+}
+
+// `?role=admin&active=false&email=example.com`; all optional
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserFilterQuery {
+    pub role: Option<String>,
+    pub active: Option<bool>,
+    // Part of the address, any case
+    pub email: Option<String>,
+}
+
+pub struct Filter(pub UserFilter);
+
+// `?user=<id>` for one account's history; `?actor=`, `?target=` and
+// `?action=role_changed` narrow it further. All optional.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditFilterQuery {
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    // Either side: the actor or the target
+    pub user: Option<String>,
+    pub action: Option<String>,
+}
+
+pub struct AuditQuery(pub AuditFilter);
+
+impl<S: Send + Sync> FromRequestParts<S> for AuditQuery {
+    type Rejection = InvalidQuery;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<AuditFilterQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(InvalidQuery)?;
+        Ok(Self(AuditFilter {
+            actor: query.actor,
+            target: query.target,
+            user: query.user,
+            action: query.action,
+        }))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Filter {
+    type Rejection = InvalidQuery;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<UserFilterQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(InvalidQuery)?;
+        Ok(Self(UserFilter {
+            role: query.role,
+            active: query.active,
+            email: query.email,
+        }))
+    }
+}
+
+// `?page=abc`, `?page=-1` or `?active=maybe`
+#[derive(Debug)]
+pub struct InvalidQuery(QueryRejection);
+
+impl IntoResponse for InvalidQuery {
+    fn into_response(self) -> Response {
+        error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidQuery,
+            &self.0.body_text(),
+        )
+    }
+}
+
+// Errors
+// ------
+
+// The one shape every error response has
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ErrorBody {
+    // Stable, for clients to match on
+    pub error: &'static str,
+    // Stable too, and finer: AUTH002_MISSING_CREDENTIALS vs
+    // AUTH003_INVALID_TOKEN are both "unauthorized"
+    #[schema(value_type = String, example = "AUTH001_INVALID_CREDENTIALS")]
+    pub code: ErrorCode,
+    // For humans; may change
+    pub message: String,
+    // Only for validation errors: every broken rule, with a stable code
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub errors: Vec<ValidationError>,
+    // The same errors as field name -> messages, as /api/v1 always sent them
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+fn error_response(status: StatusCode, code: ErrorCode, message: &str) -> Response {
+    let body = ErrorBody {
+        error: error_kind(code),
+        code,
+        message: localized(code, message),
+        errors: Vec::new(),
+        fields: BTreeMap::new(),
+    };
+    error_body_response(status, body)
+}
+
+// English keeps the call site's message, which is more specific than the
+// catalog's ("missing bearer token"); other languages get the catalog's
+fn localized(code: ErrorCode, english: &str) -> String {
+    match current_locale() {
+        Locale::En => english.to_string(),
+        locale => messages::message(code, locale).to_string(),
+    }
+}
+
+// The coarser `error` each code falls under, as /api/v1 always sent it
+fn error_kind(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::InvalidCredentials => "invalid_credentials",
+        ErrorCode::MissingCredentials | ErrorCode::InvalidToken => "unauthorized",
+        ErrorCode::Forbidden => "forbidden",
+        ErrorCode::CsrfFailed => "csrf_failed",
+        ErrorCode::NotFound => "not_found",
+        ErrorCode::AlreadyExists => "already_exists",
+        ErrorCode::InvalidBody => "invalid_body",
+        ErrorCode::InvalidQuery => "invalid_query",
+        ErrorCode::ValidationFailed => "validation_failed",
+        ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+        ErrorCode::MissingFile => "missing_file",
+        ErrorCode::RateLimited => "rate_limited",
+        ErrorCode::Internal => "internal",
+    }
+}
+
+// A copy of the body rides along in the extensions so `problem_details`
+// can re-render it without parsing JSON back
+fn error_body_response(status: StatusCode, body: ErrorBody) -> Response {
+    let mut response = (status, Json(body.clone())).into_response();
+    response.extensions_mut().insert(body);
+    response
+}
+
+// Content negotiation
+// -------------------
+
+pub const MSGPACK: &str = "application/msgpack";
+
+// How the client wants successful responses encoded, from Accept. JSON
+// wins ties and is what clients that don't say get.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+}
+
+impl WireFormat {
+    // Compares the best q-value each format gets; `*/*` counts for JSON
+    pub fn from_accept(accept: &str) -> Self {
+        let (mut json, mut msgpack) = (0.0, 0.0);
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_lowercase();
+            let q = params
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match media_type.as_str() {
+                "application/msgpack" | "application/x-msgpack" => msgpack = f32::max(msgpack, q),
+                "application/json" | "application/*" | "*/*" => json = f32::max(json, q),
+                _ => {}
+            }
+        }
+        if msgpack > json {
+            WireFormat::MessagePack
+        } else {
+            WireFormat::Json
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for WireFormat {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map_or(WireFormat::Json, WireFormat::from_accept))
+    }
+}
+
+// A DTO in whichever format the request asked for. `Vary: Accept` tells
+// caches that the same URL has more than one representation.
+pub struct Negotiated<T>(pub WireFormat, pub T);
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let Negotiated(format, value) = self;
+        let mut response = match format {
+            WireFormat::Json => Json(value).into_response(),
+            // Named fields, so the maps have the same keys as the JSON
+            WireFormat::MessagePack => match rmp_serde::to_vec_named(&value) {
+                Ok(bytes) => ([(header::CONTENT_TYPE, MSGPACK)], bytes).into_response(),
+                Err(e) => ApiError(Error::with_source("encoding msgpack", e)).into_response(),
+            },
+        };
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+// Problem details
+// ---------------
+
+// RFC 7807. `type` is a relative URI naming the error (clients match on
+// it, nothing has to be served there), `instance` the path that failed.
+// The ErrorCode and, for validation errors, the `errors` and `fields` are
+// extension members.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub instance: String,
+    #[schema(value_type = String, example = "RES001_NOT_FOUND")]
+    pub code: ErrorCode,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub errors: Vec<ValidationError>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode, body: ErrorBody, instance: String) -> Self {
+        Self {
+            problem_type: format!("/problems/{}", body.error),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: body.message,
+            instance,
+            code: body.code,
+            errors: body.errors,
+            fields: body.fields,
+        }
+    }
+}
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+// Per router: `Router::new()...layer(middleware::from_fn(problem_details))`.
+// Responses without an ErrorBody (successes, 404s for unknown routes,
+// errors from middleware outside this router) pass through untouched.
+pub async fn problem_details(request: Request, next: Next) -> Response {
+    // Inside a nest the URI has lost its prefix; OriginalUri still has it
+    let instance = match request.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path().to_string(),
+        None => request.uri().path().to_string(),
+    };
+    let response = next.run(request).await;
+    let Some(body) = response.extensions().get::<ErrorBody>().cloned() else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    let problem = ProblemDetails::new(parts.status, body, instance);
+    let Ok(json) = serde_json::to_vec(&problem) else {
+        return (parts.status, "internal error").into_response();
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    Response::from_parts(parts, Body::from(json))
+}
+
+// What handlers return. Error comes from tutorial-core and IntoResponse
+// from axum, so the orphan rule doesn't let this crate implement one for
+// the other; the wrapper is ours. `?` wraps an Error through the From.
+#[derive(Debug)]
+pub struct ApiError(pub Error);
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let ApiError(error) = self;
+        let code = error.code();
+        let message = messages::message(code, Locale::En);
+        match error {
+            Error::NotFound => error_response(StatusCode::NOT_FOUND, code, message),
+            Error::AlreadyExists => error_response(StatusCode::CONFLICT, code, message),
+            Error::InvalidCredentials => error_response(StatusCode::UNAUTHORIZED, code, message),
+            Error::Validation(errors) => validation_response(message, errors),
+            // Connection strings and SQL stay in the log, not in the response
+            error @ Error::Internal { .. } => {
+                log_internal(&error);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::after::{
+        AuthServiceFactory, FailureConfig, HealthCheck, Metrics, MockErrorReporter,
+        PrometheusMetrics, TokenService,
+    };
+    use crate::container::Scope;
+    use crate::test_app::{TestApp, TestResponse};
+    use async_trait::async_trait;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use serde_json::{Value, json};
+    use test_support::{CapturedLogs, DownCache, TokenBuilder, UserBuilder};
+    use tower::ServiceExt;
+
+    fn test_router() -> Router {
+        let container = AuthServiceFactory::test_container(Vec::new());
+        router(Arc::new(AppState::new(App::new(Arc::new(container)))))
+    }
+
+    // One request through the whole stack, no socket involved
+    async fn send(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let json = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, json)
+    }
+
+    #[tokio::test]
+    async fn test_register_login_me_logout() {
+        let router = test_router();
+        let credentials = json!({ "email": "new@example.com", "password": "password123" });
+
+        let (status, user) = send(
+            &router,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(user["email"], "new@example.com");
+        assert!(user.get("password_hash").is_none());
+
+        let (status, body) = send(&router, "POST", "/api/v1/login", None, Some(credentials)).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = body["access_token"].as_str().unwrap().to_string();
+
+        let (status, me) = send(&router, "GET", "/api/v1/me", Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(me["id"], user["id"]);
+
+        let (status, _) = send(&router, "POST", "/api/v1/logout", Some(&token), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // The token is dead after logout
+        let (status, _) = send(&router, "GET", "/api/v1/me", Some(&token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_error_statuses() {
+        let router = test_router();
+        let credentials = json!({ "email": "taken@example.com", "password": "password123" });
+        send(
+            &router,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+
+        let (status, _) = send(&router, "POST", "/api/v1/register", None, Some(credentials)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let wrong = json!({ "email": "taken@example.com", "password": "wrong" });
+        let (status, _) = send(&router, "POST", "/api/v1/login", None, Some(wrong)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(&router, "GET", "/api/v1/me", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(&router, "GET", "/api/v1/me", Some("forged"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_requests_get_a_bearer_challenge() {
+        let router = test_router();
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/api/v1/me").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let response = router
+            .oneshot(
+                Request::get("/api/v1/me")
+                    .header(
+                        header::AUTHORIZATION,
+                        format!("Bearer {}", TokenBuilder::new().forged().build()),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Bearer error=\"invalid_token\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_user_rejects_other_roles_with_403() {
+        let user = |email: &str, role: &str| {
+            UserBuilder::new()
+                .id(email)
+                .email(email)
+                .password("password")
+                .role(role)
+                .build()
+        };
+        let container = AuthServiceFactory::test_container(vec![
+            user("admin@example.com", "admin"),
+            user("user@example.com", "user"),
+        ]);
+        let state = Arc::new(AppState::new(App::new(Arc::new(container))));
+        let admin_only = Router::new()
+            .route(
+                "/admin",
+                get(|AdminUser(admin): AdminUser| async move { Json(admin.user.email) }),
+            )
+            .with_state(state.clone());
+        let router = router(state).merge(admin_only);
+
+        for (email, expected) in [
+            ("admin@example.com", StatusCode::OK),
+            ("user@example.com", StatusCode::FORBIDDEN),
+        ] {
+            let login = json!({ "email": email, "password": "password" });
+            let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(login)).await;
+            let token = body["access_token"].as_str().unwrap();
+
+            let (status, _) = send(&router, "GET", "/admin", Some(token), None).await;
+            assert_eq!(status, expected, "{}", email);
+        }
+
+        // Still 401 without a token, not 403
+        let (status, _) = send(&router, "GET", "/admin", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_error_bodies_share_one_shape() {
+        let router = test_router();
+        let credentials = json!({ "email": "taken@example.com", "password": "password123" });
+        send(
+            &router,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+
+        let (_, body) = send(&router, "POST", "/api/v1/register", None, Some(credentials)).await;
+        assert_eq!(body["error"], "already_exists");
+        assert_eq!(body["code"], "RES002_ALREADY_EXISTS");
+        assert!(body["message"].is_string());
+
+        let (_, body) = send(&router, "GET", "/api/v1/me", None, None).await;
+        assert_eq!(body["error"], "unauthorized");
+        assert_eq!(body["code"], "AUTH002_MISSING_CREDENTIALS");
+    }
+
+    #[tokio::test]
+    async fn test_codes_tell_apart_what_error_lumps_together() {
+        let router = test_router();
+
+        // Both "unauthorized"; only the code says whether to log in or refresh
+        let (_, missing) = send(&router, "GET", "/api/v1/me", None, None).await;
+        let (_, invalid) = send(&router, "GET", "/api/v1/me", Some("forged"), None).await;
+        assert_eq!(missing["error"], invalid["error"]);
+        assert_eq!(missing["code"], "AUTH002_MISSING_CREDENTIALS");
+        assert_eq!(invalid["code"], "AUTH003_INVALID_TOKEN");
+
+        let login = json!({ "email": "nobody@example.com", "password": "password123" });
+        let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(login)).await;
+        assert_eq!(body["code"], "AUTH001_INVALID_CREDENTIALS");
+
+        let bad = json!({ "email": "nope", "password": "short" });
+        let (_, body) = send(&router, "POST", "/api/v1/register", None, Some(bad)).await;
+        assert_eq!(body["code"], "REQ003_VALIDATION_FAILED");
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_hide_the_details() {
+        let response =
+            ApiError(Error::internal("postgres://admin:hunter2@db failed")).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"], "internal");
+        assert_eq!(body["code"], "SYS001_INTERNAL");
+        assert!(!body.to_string().contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_served_only_for_prometheus() {
+        let (status, _) = send(&test_router(), "GET", "/metrics", None, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let mut container = AuthServiceFactory::test_container(Vec::new());
+        container
+            .singleton::<PrometheusMetrics, _>(|_| Arc::new(PrometheusMetrics::default()))
+            .singleton::<dyn Metrics, _>(|scope: &Scope| {
+                scope.resolve::<PrometheusMetrics>().unwrap()
+            });
+        let router = router(Arc::new(AppState::new(App::new(Arc::new(container)))));
+        let login = json!({ "email": "nobody@example.com", "password": "password123" });
+        send(&router, "POST", "/api/v1/login", None, Some(login)).await;
+
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain")
+        );
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(
+            text.contains("auth_logins_total{outcome=\"invalid_credentials\"} 1"),
+            "{}",
+            text
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_durations_are_labeled_by_route_pattern() {
+        let metrics = Arc::new(PrometheusMetrics::default());
+        let mut container = AuthServiceFactory::test_container(Vec::new());
+        let recorded = metrics.clone();
+        container.singleton::<dyn Metrics, _>(move |_| recorded.clone());
+        let settings = AppConfig::from_lookup(|_| None).unwrap();
+        let app = app(
+            Arc::new(AppState::new(App::new(Arc::new(container)))),
+            &settings,
+        );
+
+        for i in 0..20 {
+            let id = uuid::Uuid::new_v4();
+            let role = json!({ "role": "admin" });
+            let uri = format!("/api/v1/admin/users/{}/role", id);
+            send(&app, "PUT", &uri, None, Some(role)).await;
+            send(&app, "GET", &format!("/wp-admin/{}.php", i), None, None).await;
+        }
+        send(&app, "PURGE", "/healthz", None, None).await;
+        send(&app, "GET", "/healthz", None, None).await;
+
+        let text = metrics.render();
+        let series: Vec<_> = text
+            .lines()
+            .filter(|line| line.starts_with("http_request_duration_seconds_count"))
+            .collect();
+        assert_eq!(
+            series,
+            [
+                r#"http_request_duration_seconds_count{method="GET",route="/healthz",status="200"} 1"#,
+                r#"http_request_duration_seconds_count{method="GET",route="unmatched",status="404"} 20"#,
+                r#"http_request_duration_seconds_count{method="PUT",route="/api/v1/admin/users/{id}/role",status="401"} 20"#,
+                r#"http_request_duration_seconds_count{method="other",route="/healthz",status="405"} 1"#,
+            ],
+            "{}",
+            text
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_reach_the_error_reporter() {
+        let reporter = Arc::new(MockErrorReporter::default());
+        let mut container =
+            AuthServiceFactory::flaky_test_container(Vec::new(), FailureConfig::failing(100));
+        let captured = reporter.clone();
+        container.singleton::<dyn ErrorReporter, _>(move |_| captured.clone());
+        let settings = AppConfig::from_lookup(|_| None).unwrap();
+        let app = app(
+            Arc::new(AppState::new(App::new(Arc::new(container)))),
+            &settings,
+        );
+
+        let login = json!({ "email": "ann@example.com", "password": "password123" });
+        let (status, _) = send(&app, "POST", "/api/v1/login", None, Some(login)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        // Answers aren't errors of ours
+        let bad = json!({ "email": "nope", "password": "short" });
+        send(&app, "POST", "/api/v1/register", None, Some(bad)).await;
+
+        let events = reporter.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].code, "SYS001_INTERNAL");
+        assert!(events[0].message.contains("injected failure"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_bodies_get_422_with_field_errors() {
+        let router = test_router();
+
+        let bad = json!({ "email": "not-an-email", "password": "short" });
+        let (status, body) = send(&router, "POST", "/api/v1/register", None, Some(bad)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "validation_failed");
+        assert_eq!(body["fields"]["email"][0], "must be a valid email address");
+        assert_eq!(body["fields"]["password"][0], "must be 8 to 128 characters");
+        assert_eq!(
+            body["errors"][0],
+            json!({ "field": "email", "code": "email", "message": "must be a valid email address" })
+        );
+        assert_eq!(body["errors"][1]["field"], "password");
+        assert_eq!(body["errors"][1]["code"], "length");
+
+        let (status, body) = send(&router, "POST", "/api/v1/login", None, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "invalid_body");
+        assert!(body.get("fields").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_every_documented_path_is_routed() {
+        let router = test_router();
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+        assert_eq!(paths.len(), 23);
+
+        for (path, operations) in paths {
+            // 404 by design without the Prometheus backend; see
+            // test_metrics_are_served_only_for_prometheus
+            if path == "/metrics" {
+                continue;
+            }
+            for method in operations.as_object().unwrap().keys() {
+                let request = Request::builder()
+                    .method(method.to_uppercase().as_str())
+                    .uri(path)
+                    .body(Body::empty())
+                    .unwrap();
+                let status = router.clone().oneshot(request).await.unwrap().status();
+                assert_ne!(status, StatusCode::NOT_FOUND, "{} {}", method, path);
+                assert_ne!(
+                    status,
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {}",
+                    method,
+                    path
+                );
+            }
+        }
+
+        for schema in [
+            "RegisterRequest",
+            "UserResponse",
+            "TokenResponse",
+            "ErrorBody",
+        ] {
+            assert!(
+                spec["components"]["schemas"].get(schema).is_some(),
+                "{}",
+                schema
+            );
+        }
+        assert_eq!(
+            spec["components"]["securitySchemes"]["bearer"]["scheme"],
+            "bearer"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_docs_are_served() {
+        let router = test_router();
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/docs").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (status, spec) = send(&router, "GET", "/openapi.json", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(spec["info"]["title"], "Auth API");
+    }
+
+    #[tokio::test]
+    async fn test_admin_users_is_paginated() {
+        let users: Vec<User> = (1..=25)
+            .map(|n| {
+                let user = UserBuilder::new()
+                    .id(format!("user-{}", n))
+                    .email(format!("user{}@example.com", n))
+                    .password("password");
+                if n == 1 { user.role_admin() } else { user }.build()
+            })
+            .collect();
+        let container = AuthServiceFactory::test_container(users);
+        let router = router(Arc::new(AppState::new(App::new(Arc::new(container)))));
+
+        let login = json!({ "email": "user1@example.com", "password": "password" });
+        let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(login)).await;
+        let token = body["access_token"].as_str().unwrap().to_string();
+
+        // Defaults: first page of 20
+        let (status, page) = send(&router, "GET", "/api/v1/admin/users", Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["users"].as_array().unwrap().len(), 20);
+        assert_eq!(page["total"], 25);
+
+        let (_, page) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/users?page=3&per_page=10",
+            Some(&token),
+            None,
+        )
+        .await;
+        assert_eq!(page["users"].as_array().unwrap().len(), 5);
+        assert_eq!(page["users"][0]["id"], "user-21");
+
+        // per_page is capped rather than rejected
+        let (_, page) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/users?per_page=1000",
+            Some(&token),
+            None,
+        )
+        .await;
+        assert_eq!(page["per_page"], PageRequest::MAX_PER_PAGE);
+
+        let (status, body) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/users?page=abc",
+            Some(&token),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_query");
+    }
+
+    #[test]
+    fn test_page_request_clamps() {
+        assert_eq!(PageRequest::new(0, 0), PageRequest::new(1, 1));
+        assert_eq!(PageRequest::new(2, 500).per_page, PageRequest::MAX_PER_PAGE);
+        assert_eq!(PageRequest::new(3, 10).offset(), 20);
+    }
+
+    // `app` (all the middleware) over the mocks, configured by `vars`
+    fn test_app(vars: &[(&str, &str)], users: Vec<User>) -> Router {
+        let settings = AppConfig::from_lookup(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        })
+        .unwrap();
+        let container = AuthServiceFactory::test_container(users);
+        let state = AppState::new(App::new(Arc::new(container)))
+            .with_auth_mode(settings.session.auth_mode)
+            .with_allowed_origins(settings.cors.allowed_origins.clone());
+        app(Arc::new(state), &settings)
+    }
+
+    // Preflight for a cross-origin POST /login, through the configured stack
+    async fn preflight(vars: &[(&str, &str)], origin: &str) -> Response {
+        let app = test_app(vars, Vec::new());
+
+        let request = Request::builder()
+            .method("OPTIONS")
+            .uri("/api/v1/login")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_is_permissive_in_dev() {
+        let response = preflight(&[], "http://localhost:5173").await;
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[tokio::test]
+    async fn test_cors_only_allows_listed_origins_in_prod() {
+        let prod = [
+            ("APP_ENV", "prod"),
+            ("DATABASE_URL", "postgres://db/app"),
+            ("REDIS_URL", "redis://cache"),
+            ("JWT_SECRET", "a-production-secret-of-32-chars!!"),
+            ("CORS_ALLOWED_ORIGINS", "https://app.example.com"),
+        ];
+
+        let response = preflight(&prod, "https://app.example.com").await;
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+
+        let response = preflight(&prod, "https://evil.example.com").await;
+        assert!(
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_returns_429_with_retry_after() {
+        let app = test_app(
+            &[
+                ("RATE_LIMIT_WINDOW_SECONDS", "86400"),
+                ("RATE_LIMIT_AUTH_REQUESTS", "2"),
+            ],
+            Vec::new(),
+        );
+        let request = |uri: &str, ip: [u8; 4]| {
+            let mut request = Request::builder()
+                .method(if uri == "/api/v1/login" {
+                    "POST"
+                } else {
+                    "GET"
+                })
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    json!({ "email": "a@example.com", "password": "pw" }).to_string(),
+                ))
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+            request
+        };
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(request("/api/v1/login", [10, 0, 0, 1]))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = app
+            .clone()
+            .oneshot(request("/api/v1/login", [10, 0, 0, 1]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=86400).contains(&retry_after));
+
+        // Per IP, and per route: other clients and other routes still get through
+        let response = app
+            .clone()
+            .oneshot(request("/api/v1/login", [10, 0, 0, 2]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .oneshot(request("/api/v1/me", [10, 0, 0, 1]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_generated_or_echoed() {
+        let app = test_app(&[], Vec::new());
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/api/v1/me").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let generated = response.headers()["x-request-id"].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+        let response = app
+            .oneshot(
+                Request::get("/api/v1/me")
+                    .header("x-request-id", "from-the-client")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-request-id"], "from-the-client");
+    }
+
+    #[tokio::test]
+    async fn test_logs_inside_a_handler_carry_the_request_id() {
+        let (logs, _guard) = CapturedLogs::install();
+        // Stands in for a repository logging its query
+        let probe = Router::new()
+            .route("/probe", get(|| async { tracing::debug!("SELECT 1") }))
+            .layer(
+                ServiceBuilder::new()
+                    .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                    .layer(TraceLayer::new_for_http().make_span_with(request_span)),
+            );
+
+        probe
+            .oneshot(
+                Request::get("/probe")
+                    .header("x-request-id", "abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let line = logs
+            .text()
+            .lines()
+            .find(|line| line.ends_with("SELECT 1"))
+            .map(str::to_string);
+        assert!(
+            line.as_deref().is_some_and(
+                |line| line.contains(r#"request{request_id="abc" method=GET path=/probe}"#)
+            ),
+            "{:?}",
+            line
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_and_readiness() {
+        let container = AuthServiceFactory::test_container(Vec::new());
+        let state = Arc::new(AppState::new(App::new(Arc::new(container))));
+        let router = router(state.clone());
+
+        let (status, body) = send(&router, "GET", "/healthz", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+
+        let (status, body) = send(&router, "GET", "/readyz", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["components"].as_array().unwrap().len(), 3);
+
+        // The cache is optional: degraded, but still taking traffic
+        state.app.swap_cache(Arc::new(DownCache));
+        let (status, body) = send(&router, "GET", "/readyz", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["components"][1]["name"], "cache");
+        assert_eq!(body["components"][1]["status"], "down");
+        assert_eq!(body["components"][1]["error"], "connection refused");
+    }
+
+    #[tokio::test]
+    async fn test_readiness_is_503_when_a_critical_dependency_is_down() {
+        let mut container = AuthServiceFactory::test_container(Vec::new());
+        container.singleton::<dyn TokenService, _>(|_| Arc::new(DownTokens));
+        let router = router(Arc::new(AppState::new(App::new(Arc::new(container)))));
+
+        let (status, body) = send(&router, "GET", "/readyz", None, None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "unavailable");
+    }
+
+    struct DownTokens;
+
+    #[async_trait]
+    impl HealthCheck for DownTokens {
+        async fn check(&self) -> Result<(), Error> {
+            Err(Error::internal("signing key missing"))
+        }
+    }
+
+    #[async_trait]
+    impl TokenService for DownTokens {
+        async fn generate(&self, _user_id: &str) -> Result<String, Error> {
+            Err(Error::internal("signing key missing"))
+        }
+
+        async fn validate(&self, _token: &str) -> Result<String, Error> {
+            Err(Error::InvalidCredentials)
+        }
+    }
+
+    // A route that takes `delay` to answer, served on a random port
+    async fn slow_server(
+        delay: Duration,
+        drain_timeout: Duration,
+    ) -> (
+        SocketAddr,
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/slow",
+            get(move || async move {
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        );
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with(
+            listener,
+            app,
+            async move {
+                let _ = stopped.await;
+            },
+            drain_timeout,
+        ));
+        (addr, stop, server)
+    }
+
+    // Plain HTTP/1.1 over a socket: enough for a status line
+    async fn get_slow(addr: SocketAddr) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_lets_in_flight_requests_finish() {
+        let (addr, stop, server) =
+            slow_server(Duration::from_millis(200), Duration::from_secs(5)).await;
+
+        let request = tokio::spawn(get_slow(addr));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stop.send(()).unwrap();
+
+        assert!(request.await.unwrap().starts_with("HTTP/1.1 200"));
+        server.await.unwrap().unwrap();
+        // ...and nothing new gets in
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_gives_up_after_the_drain_timeout() {
+        let (addr, stop, server) =
+            slow_server(Duration::from_secs(60), Duration::from_millis(100)).await;
+
+        let request = tokio::spawn(get_slow(addr));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stop.send(()).unwrap();
+
+        // Returns long before the request would have finished
+        let started = std::time::Instant::now();
+        server.await.unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!request.is_finished());
+        request.abort();
+    }
+
+    #[tokio::test]
+    async fn test_v2_changes_the_user_shape_only() {
+        let users: Vec<User> = (1..=3)
+            .map(|n| {
+                let user = UserBuilder::new()
+                    .id(format!("user-{}", n))
+                    .email(format!("user{}@example.com", n))
+                    .password("password");
+                if n == 1 { user.role_admin() } else { user }.build()
+            })
+            .collect();
+        let router = router(Arc::new(AppState::new(App::new(Arc::new(
+            AuthServiceFactory::test_container(users),
+        )))));
+
+        // Shared handler: a v1 token works on v2 and vice versa
+        let login = json!({ "email": "user1@example.com", "password": "password" });
+        let (status, body) = send(&router, "POST", "/api/v2/login", None, Some(login)).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = body["access_token"].as_str().unwrap().to_string();
+
+        let (_, v1) = send(&router, "GET", "/api/v1/me", Some(&token), None).await;
+        let (_, v2) = send(&router, "GET", "/api/v2/me", Some(&token), None).await;
+        assert_eq!(v1["role"], "admin");
+        assert_eq!(v2["roles"], json!(["admin"]));
+        assert!(v2.get("role").is_none());
+
+        let (_, page) = send(
+            &router,
+            "GET",
+            "/api/v2/admin/users?per_page=2",
+            Some(&token),
+            None,
+        )
+        .await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+        assert_eq!(page["next_page"], 2);
+        let (_, page) = send(
+            &router,
+            "GET",
+            "/api/v2/admin/users?page=2&per_page=2",
+            Some(&token),
+            None,
+        )
+        .await;
+        assert!(page["next_page"].is_null());
+    }
+
+    #[test]
+    fn test_operation_ids_are_unique_across_versions() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let mut ids: Vec<&str> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .flat_map(|item| item.as_object().unwrap().values())
+            .filter_map(|operation| operation["operationId"].as_str())
+            .collect();
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+        assert!(ids.contains(&"v1_login") && ids.contains(&"v2_login"));
+    }
+
+    #[tokio::test]
+    async fn test_v2_errors_are_problem_details() {
+        let router = test_router();
+        let request = Request::builder()
+            .uri("/api/v2/me")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        // Headers set by the rejection survive the rewrite
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem["type"], "/problems/unauthorized");
+        assert_eq!(problem["title"], "Unauthorized");
+        assert_eq!(problem["status"], 401);
+        assert_eq!(problem["instance"], "/api/v2/me");
+        assert_eq!(problem["code"], "AUTH002_MISSING_CREDENTIALS");
+        assert!(problem["detail"].is_string());
+
+        let bad = json!({ "email": "nope", "password": "short" });
+        let (status, problem) = send(&router, "POST", "/api/v2/register", None, Some(bad)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["type"], "/problems/validation_failed");
+        assert!(problem["fields"]["email"].is_array());
+
+        // Same failure on v1: the plain ErrorBody
+        let (status, body) = send(&router, "GET", "/api/v1/me", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "unauthorized");
+        assert!(body.get("type").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_admin_user_management() {
+        let user = |id: &str, role: &str| {
+            UserBuilder::named(id)
+                .password("password")
+                .role(role)
+                .build()
+        };
+        let router = router(Arc::new(AppState::new(App::new(Arc::new(
+            AuthServiceFactory::test_container(vec![
+                user("admin", "admin"),
+                user("alice", "user"),
+                user("bob", "user"),
+            ]),
+        )))));
+        let login =
+            |id: &str| json!({ "email": format!("{}@example.com", id), "password": "password" });
+        let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(login("admin"))).await;
+        let admin = body["access_token"].as_str().unwrap().to_string();
+        let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(login("bob"))).await;
+        let bob = body["access_token"].as_str().unwrap().to_string();
+
+        // Plain users can't reach any of it
+        let role = json!({ "role": "admin" });
+        let (status, _) = send(
+            &router,
+            "PUT",
+            "/api/v1/admin/users/alice/role",
+            Some(&bob),
+            Some(role.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&router, "GET", "/api/v1/admin/audit", Some(&bob), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send(
+            &router,
+            "PUT",
+            "/api/v1/admin/users/alice/role",
+            Some(&admin),
+            Some(role.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = send(
+            &router,
+            "PUT",
+            "/api/v1/admin/users/alice/role",
+            Some(&admin),
+            Some(json!({ "role": "root" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["fields"]["role"][0], "must be one of: user, admin");
+        let (status, _) = send(
+            &router,
+            "PUT",
+            "/api/v1/admin/users/nobody/role",
+            Some(&admin),
+            Some(role),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/users?role=admin",
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 2);
+
+        // Deactivating kills bob's token at once (v2 shares the handler)
+        let (status, _) = send(
+            &router,
+            "POST",
+            "/api/v2/admin/users/bob/deactivate",
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&router, "GET", "/api/v1/me", Some(&bob), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (_, body) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/users?active=false&email=BOB",
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(body["users"][0]["id"], "bob");
+        let (status, _) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/users?active=maybe",
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = send(&router, "GET", "/api/v1/admin/audit", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["entries"][0]["action"], "deactivated");
+        assert_eq!(body["entries"][0]["target"], "bob");
+        assert_eq!(body["entries"][1]["action"], "role_changed");
+        assert_eq!(body["entries"][1]["actor"], "admin");
+        // Called without connect info, so there's no IP to record
+        assert!(body["entries"][0]["ip"].is_null());
+
+        // One account's history
+        let (status, body) = send(
+            &router,
+            "GET",
+            "/api/v1/admin/audit?user=alice",
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["entries"][0]["action"], "role_changed");
+    }
+
+    #[tokio::test]
+    async fn test_ws_pushes_the_users_own_events() {
+        use futures_util::StreamExt;
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let state = Arc::new(AppState::new(App::new(Arc::new(
+            AuthServiceFactory::test_container(Vec::new()),
+        ))));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router(state.clone())).into_future());
+
+        let auth = state.app.auth_service();
+        let user = auth.register("ws@example.com", "password").await.unwrap();
+        let token = auth.login("ws@example.com", "password").await.unwrap();
+
+        let url = format!("ws://{}/api/v1/ws", addr);
+        let error = tokio_tungstenite::connect_async(&url).await.unwrap_err();
+        assert!(matches!(
+            error,
+            tokio_tungstenite::tungstenite::Error::Http(response)
+                if response.status() == StatusCode::UNAUTHORIZED
+        ));
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("{}?token={}", url, token))
+            .await
+            .unwrap();
+        let mut next = async || match socket.next().await.unwrap().unwrap() {
+            WsMessage::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+            WsMessage::Close(frame) => json!({ "closed": frame.unwrap().reason.as_str() }),
+            other => panic!("unexpected {:?}", other),
+        };
+
+        // Someone else's event is skipped
+        state.app.events().publish(DomainEvent::ProfileUpdated {
+            user_id: "someone-else".to_string(),
+        });
+        auth.change_password("ws@example.com", "password", "password2")
+            .await
+            .unwrap();
+        assert_eq!(
+            next().await,
+            json!({ "type": "profile_updated", "user_id": user.id })
+        );
+
+        auth.logout(&token).await.unwrap();
+        assert_eq!(next().await["type"], "session_revoked");
+        assert_eq!(next().await, json!({ "closed": "session revoked" }));
+    }
+
+    #[tokio::test]
+    async fn test_ws_refuses_foreign_origins_in_cookie_mode() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let state = Arc::new(
+            AppState::new(App::new(Arc::new(AuthServiceFactory::test_container(
+                Vec::new(),
+            ))))
+            .with_auth_mode(AuthMode::Cookie)
+            .with_allowed_origins(vec!["https://app.example.com".to_string()]),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router(state.clone())).into_future());
+
+        let auth = state.app.auth_service();
+        auth.register("ws@example.com", "password").await.unwrap();
+        let session = state
+            .app
+            .session_service()
+            .login("ws@example.com", "password")
+            .await
+            .unwrap();
+
+        // The same cookie a browser would attach, from whatever page
+        let handshake = |origin: Option<&str>| {
+            let mut request = format!("ws://{}/api/v1/ws", addr)
+                .into_client_request()
+                .unwrap();
+            let headers = request.headers_mut();
+            let cookie = format!("{}={}", SESSION_COOKIE, session);
+            headers.insert(header::COOKIE, cookie.parse().unwrap());
+            if let Some(origin) = origin {
+                headers.insert(header::ORIGIN, origin.parse().unwrap());
+            }
+            tokio_tungstenite::connect_async(request)
+        };
+
+        let error = handshake(Some("https://evil.example.com"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            tokio_tungstenite::tungstenite::Error::Http(response)
+                if response.status() == StatusCode::FORBIDDEN
+        ));
+
+        assert!(handshake(Some("https://app.example.com")).await.is_ok());
+        // The page's own origin, and a client that isn't a browser
+        let own = format!("http://{}", addr);
+        assert!(handshake(Some(&own)).await.is_ok());
+        assert!(handshake(None).await.is_ok());
+    }
+
+    // A multipart body with one file field
+    fn multipart(field: &str, content_type: &str, bytes: &[u8]) -> (String, Vec<u8>) {
+        let boundary = "avatar-boundary";
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{field}\"; \
+             filename=\"upload\"\r\nContent-Type: {content_type}\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+        (format!("multipart/form-data; boundary={}", boundary), body)
+    }
+
+    #[tokio::test]
+    async fn test_avatar_upload() {
+        let router = test_router();
+        let credentials = json!({ "email": "pic@example.com", "password": "password123" });
+        let (_, user) = send(
+            &router,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+        let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(credentials)).await;
+        let token = body["access_token"].as_str().unwrap().to_string();
+
+        let upload = |field: &str, content_type: &str, bytes: Vec<u8>| {
+            let (multipart_type, body) = multipart(field, content_type, &bytes);
+            let request = Request::builder()
+                .method("PUT")
+                .uri("/api/v1/me/avatar")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(header::CONTENT_TYPE, multipart_type)
+                .body(Body::from(body))
+                .unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = response.into_body().collect().await.unwrap().to_bytes();
+                (
+                    status,
+                    serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null),
+                )
+            }
+        };
+
+        let png = b"\x89PNG\r\n\x1a\n...".to_vec();
+        let (status, body) = upload("avatar", "image/png", png.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let url = format!("memory://avatars/{}.png", user["id"].as_str().unwrap());
+        assert_eq!(body["avatar_url"], url);
+        let (_, me) = send(&router, "GET", "/api/v1/me", Some(&token), None).await;
+        assert_eq!(me["avatar_url"], url);
+
+        let (status, body) = upload("avatar", "text/plain", b"hello".to_vec()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["error"], "unsupported_media_type");
+        // A spoofed header: an image type, but the bytes are HTML, or
+        // another image type
+        let (status, body) = upload("avatar", "image/png", b"<script>".to_vec()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["error"], "unsupported_media_type");
+        let (status, _) = upload("avatar", "image/jpeg", png).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let (_, me) = send(&router, "GET", "/api/v1/me", Some(&token), None).await;
+        assert_eq!(me["avatar_url"], url);
+        let (status, body) = upload("picture", "image/png", b"png".to_vec()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "missing_file");
+        let (status, _) = upload("avatar", "image/png", vec![0; MAX_AVATAR_BYTES + 1]).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_sessions() {
+        let container = AuthServiceFactory::test_container(Vec::new());
+        let state = AppState::new(App::new(Arc::new(container))).with_auth_mode(AuthMode::Cookie);
+        let router = router(Arc::new(state));
+        let credentials = json!({ "email": "new@example.com", "password": "password123" });
+        send(
+            &router,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+
+        let request = |method: &str, uri: &str, cookie: Option<&str>| {
+            let mut request = Request::builder().method(method).uri(uri);
+            if let Some(cookie) = cookie {
+                request = request.header(header::COOKIE, cookie);
+            }
+            request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(credentials.to_string()))
+                .unwrap()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(request("POST", "/api/v1/login", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.contains("; HttpOnly; Secure;"));
+        assert!(set_cookie.ends_with("; Max-Age=86400"));
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+        assert!(cookie.starts_with("session="));
+
+        let response = router
+            .clone()
+            .oneshot(request(
+                "GET",
+                "/api/v1/me",
+                Some(&format!("theme=dark; {}", cookie)),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Bearer tokens mean nothing in cookie mode
+        let (status, body) = send(
+            &router,
+            "GET",
+            "/api/v1/me",
+            Some(&TokenBuilder::new().build()),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["message"], "missing session cookie");
+
+        let response = router
+            .clone()
+            .oneshot(request("POST", "/api/v1/logout", Some(&cookie)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(
+            response.headers()[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .starts_with("session=; ")
+        );
+
+        // The session is gone from the store, not just from the browser
+        let response = router
+            .clone()
+            .oneshot(request("GET", "/api/v1/me", Some(&cookie)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(!response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[tokio::test]
+    async fn test_csrf_in_cookie_mode() {
+        let app = test_app(&[("AUTH_MODE", "cookie")], Vec::new());
+        let register = |cookie: Option<&str>, header: Option<&str>| {
+            let mut request =
+                Request::post("/api/v1/register").header(header::CONTENT_TYPE, "application/json");
+            if let Some(cookie) = cookie {
+                request = request.header(header::COOKIE, cookie);
+            }
+            if let Some(header) = header {
+                request = request.header(CSRF_HEADER, header);
+            }
+            request
+                .body(Body::from(
+                    json!({ "email": "new@example.com", "password": "password123" }).to_string(),
+                ))
+                .unwrap()
+        };
+        let error = |response: Response| async move {
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<Value>(&bytes).unwrap()["message"].clone()
+        };
+
+        // A GET hands out the token, readable by scripts
+        let response = app
+            .clone()
+            .oneshot(Request::get("/healthz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.starts_with("csrf="));
+        assert!(!set_cookie.contains("HttpOnly"));
+        let cookie = set_cookie.split(';').next().unwrap();
+        let token = cookie.strip_prefix("csrf=").unwrap();
+
+        // ... but only once
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/healthz")
+                    .header(header::COOKIE, cookie)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(header::SET_COOKIE));
+
+        let response = app.clone().oneshot(register(None, Some(token))).await;
+        assert_eq!(
+            error(response.unwrap()).await,
+            "missing csrf cookie; make a GET request first"
+        );
+        let response = app.clone().oneshot(register(Some(cookie), None)).await;
+        assert_eq!(
+            error(response.unwrap()).await,
+            "X-CSRF-Token must match the csrf cookie"
+        );
+        let response = app
+            .clone()
+            .oneshot(register(
+                Some(cookie),
+                Some("0123456789abcdef0123456789abcdef"),
+            ))
+            .await;
+        assert_eq!(
+            error(response.unwrap()).await,
+            "X-CSRF-Token must match the csrf cookie"
+        );
+
+        let response = app
+            .oneshot(register(Some(cookie), Some(token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_no_csrf_in_bearer_mode() {
+        let app = test_app(&[], Vec::new());
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/healthz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(header::SET_COOKIE));
+
+        let request = Request::post("/api/v1/register")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "email": "new@example.com", "password": "password123" }).to_string(),
+            ))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[test]
+    fn test_request_log_masks_secrets_and_truncates() {
+        let body = json!({
+            "email": "me@example.com",
+            "password": "hunter2",
+            "tokens": [{ "access_token": "abc" }],
+            "profile": { "old_password": "hunter1", "name": "Me" },
+        });
+        let logged = log_body(body.to_string().as_bytes(), 1024);
+        assert!(!logged.contains("hunter"));
+        assert!(!logged.contains("abc"));
+        assert!(logged.contains("me@example.com"));
+        assert!(logged.contains("\"name\":\"Me\""));
+
+        assert_eq!(
+            log_body(b"password=hunter2", 1024),
+            "<16 bytes, not valid JSON>"
+        );
+        // Cut on a character boundary, never inside the é
+        assert_eq!(
+            truncate("\"café au lait\"".to_string(), 5),
+            "\"caf... (11 more bytes)"
+        );
+    }
+
+    #[test]
+    fn test_request_log_routes() {
+        let config = AppConfig::from_lookup(|_| None).unwrap();
+        let logging = RequestLogging::from_config(&config.log);
+        assert_eq!(logging.for_route("/healthz"), RouteLogging::Skip);
+        assert_eq!(
+            logging.for_route("/api/v2/me/avatar"),
+            RouteLogging::Summary
+        );
+        assert_eq!(logging.for_route("/api/v1/login"), RouteLogging::WithBodies);
+
+        let config =
+            AppConfig::from_lookup(|key| (key == "LOG_HTTP_BODIES").then(|| "false".to_string()))
+                .unwrap();
+        let logging = RequestLogging::from_config(&config.log);
+        assert_eq!(logging.for_route("/api/v1/login"), RouteLogging::Summary);
+    }
+
+    #[tokio::test]
+    async fn test_request_log_passes_bodies_through() {
+        let logging = Arc::new(RequestLogging::new(RouteLogging::WithBodies).max_body_bytes(4));
+        let echo = Router::new()
+            .route(
+                "/echo",
+                post(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+            .layer(middleware::from_fn_with_state(logging, request_log));
+        let body = json!({ "password": "hunter2", "items": [1, 2, 3] });
+
+        let response = echo
+            .oneshot(
+                Request::post("/echo")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), body);
+    }
+
+    #[test]
+    fn test_wire_format_from_accept() {
+        assert_eq!(
+            WireFormat::from_accept("application/msgpack"),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            WireFormat::from_accept("application/json;q=0.5, application/x-msgpack"),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            WireFormat::from_accept("application/msgpack;q=0.8, */*"),
+            WireFormat::Json
+        );
+        assert_eq!(
+            WireFormat::from_accept("application/msgpack, application/json"),
+            WireFormat::Json
+        );
+        assert_eq!(WireFormat::from_accept("text/html"), WireFormat::Json);
+    }
+
+    #[tokio::test]
+    async fn test_me_as_msgpack() {
+        let router = test_router();
+        let credentials = json!({ "email": "new@example.com", "password": "password123" });
+        send(
+            &router,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+        let (_, body) = send(&router, "POST", "/api/v1/login", None, Some(credentials)).await;
+        let token = body["access_token"].as_str().unwrap();
+
+        let me = |accept: &'static str| {
+            Request::get("/api/v2/me")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(header::ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router.clone().oneshot(me(MSGPACK)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], MSGPACK);
+        assert_eq!(response.headers()[header::VARY], "accept");
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let msgpack: Value = rmp_serde::from_slice(&bytes).unwrap();
+
+        // The same DTO either way
+        let response = router.oneshot(me("application/json")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(msgpack, json);
+        assert_eq!(json["email"], "new@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_me_events_streams_and_resumes() {
+        let state = Arc::new(AppState::new(App::new(Arc::new(
+            AuthServiceFactory::test_container(Vec::new()),
+        ))));
+        let router = router(state.clone());
+        let auth = state.app.auth_service();
+        let user = auth.register("sse@example.com", "password").await.unwrap();
+        // Event 1, logged_in
+        let token = auth.login("sse@example.com", "password").await.unwrap();
+        let events = |last_id: Option<&str>| {
+            let mut request = Request::get("/api/v1/me/events")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token));
+            if let Some(last_id) = last_id {
+                request = request.header("last-event-id", last_id);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        // One event per chunk: its `field: value` lines
+        let next = async |body: &mut axum::body::BodyDataStream| {
+            let chunk = body.next().await.unwrap().unwrap();
+            let text = String::from_utf8(chunk.to_vec()).unwrap();
+            let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+            lines.retain(|line| !line.is_empty());
+            lines.sort();
+            lines
+        };
+
+        let response = router.clone().oneshot(events(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        let mut body = response.into_body().into_data_stream();
+
+        // Event 2 is someone else's, event 3 is ours
+        state.app.events().publish(DomainEvent::ProfileUpdated {
+            user_id: "someone-else".to_string(),
+        });
+        auth.change_password("sse@example.com", "password", "password2")
+            .await
+            .unwrap();
+        let expected = vec![
+            format!(
+                "data: {}",
+                json!({ "type": "profile_updated", "user_id": user.id })
+            ),
+            "event: profile_updated".to_string(),
+            "id: 3".to_string(),
+        ];
+        assert_eq!(next(&mut body).await, expected);
+
+        // Reconnecting after event 1 replays event 3 first
+        let response = router.oneshot(events(Some("1"))).await.unwrap();
+        let mut body = response.into_body().into_data_stream();
+        assert_eq!(next(&mut body).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_password_policy_errors_look_like_body_errors() {
+        let router = test_router();
+
+        // Long enough for the DTO, too simple for the service
+        let weak = json!({ "email": "alice@example.com", "password": "alice-123" });
+        let (status, body) = send(&router, "POST", "/api/v1/register", None, Some(weak)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "validation_failed");
+        assert_eq!(body["code"], "REQ003_VALIDATION_FAILED");
+        assert_eq!(body["errors"][0]["field"], "password");
+        assert_eq!(body["errors"][0]["code"], "contains_email");
+        assert_eq!(
+            body["fields"]["password"][0],
+            "must not contain the email address"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_messages_follow_accept_language() {
+        async fn me(app: &Router, accept_language: Option<&str>) -> (Response, Value) {
+            let mut request = Request::builder().uri("/api/v1/me");
+            if let Some(languages) = accept_language {
+                request = request.header(header::ACCEPT_LANGUAGE, languages);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let (parts, body) = response.into_parts();
+            let bytes = body.collect().await.unwrap().to_bytes();
+            (
+                Response::from_parts(parts, Body::empty()),
+                serde_json::from_slice(&bytes).unwrap(),
+            )
+        }
+
+        let app = test_app(&[], Vec::new());
+        let (response, body) = me(&app, Some("vi-VN,vi;q=0.9,en;q=0.8")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::CONTENT_LANGUAGE], "vi");
+        assert_eq!(body["message"], "thiếu thông tin xác thực");
+        // Only the message is translated
+        assert_eq!(body["code"], "AUTH002_MISSING_CREDENTIALS");
+
+        // English keeps the specific message
+        let (response, body) = me(&app, Some("fr, en;q=0.5")).await;
+        assert_eq!(response.headers()[header::CONTENT_LANGUAGE], "en");
+        assert_eq!(body["message"], "missing bearer token");
+
+        // Nothing we have: DEFAULT_LOCALE
+        let app = test_app(&[("DEFAULT_LOCALE", "vi")], Vec::new());
+        let (_, body) = me(&app, Some("fr")).await;
+        assert_eq!(body["message"], "thiếu thông tin xác thực");
+        let (_, body) = me(&app, None).await;
+        assert_eq!(body["message"], "thiếu thông tin xác thực");
+    }
+
+    // End to end, in process
+    // ----------------------
+    //
+    // Through TestApp: the app `--serve` runs, middleware and all, on the
+    // in-memory backends
+
+    #[tokio::test]
+    async fn test_register_login_me_logout_end_to_end() {
+        let app = TestApp::new();
+        let credentials = json!({ "email": "new@example.com", "password": "password123" });
+
+        let registered = app.post_json("/api/v1/register", credentials).await;
+        assert_eq!(registered.status, StatusCode::CREATED);
+        // The middleware ran too
+        assert!(registered.headers.contains_key("x-request-id"));
+
+        let token = app.login("new@example.com", "password123").await;
+        let me = app.get_auth("/api/v1/me", &token).await;
+        assert_eq!(me.status, StatusCode::OK);
+        assert_eq!(me.body["id"], registered.body["id"]);
+
+        let logout = app.post_auth("/api/v1/logout", &token, None).await;
+        assert_eq!(logout.status, StatusCode::NO_CONTENT);
+        let me = app.get_auth("/api/v1/me", &token).await;
+        assert_eq!(me.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(me.body["code"], "AUTH003_INVALID_TOKEN");
+
+        assert_eq!(app.get("/healthz").await.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_deactivated_users_are_locked_out_end_to_end() {
+        let admin = UserBuilder::named("admin").role_admin().build();
+        let app = TestApp::with_users(vec![admin]);
+        let credentials = json!({ "email": "bob@example.com", "password": "password123" });
+        let bob = app.post_json("/api/v1/register", credentials).await.body;
+        // Leaves bob, still active, in the login cache
+        app.login("bob@example.com", "password123").await;
+
+        let admin_token = app.login("admin@example.com", "password123").await;
+        let uri = format!(
+            "/api/v1/admin/users/{}/deactivate",
+            bob["id"].as_str().unwrap()
+        );
+        let deactivated = app.post_auth(&uri, &admin_token, None).await;
+        assert_eq!(deactivated.status, StatusCode::NO_CONTENT);
+
+        let login = json!({ "email": "bob@example.com", "password": "password123" });
+        let refused = app.post_json("/api/v1/login", login).await;
+        assert_eq!(refused.status, StatusCode::UNAUTHORIZED);
+        // The service behind the route agrees
+        let auth = app.app().auth_service();
+        assert!(matches!(
+            auth.login("bob@example.com", "password123").await,
+            Err(Error::InvalidCredentials)
+        ));
+    }
+
+    // Double-submit CSRF included: a GET for the csrf cookie first, then
+    // the cookie and the header on every POST
+    #[tokio::test]
+    async fn test_cookie_sessions_end_to_end() {
+        let app = TestApp::configured(&[("AUTH_MODE", "cookie")], vec![UserBuilder::new().build()]);
+        let cookie = |response: &TestResponse| {
+            let set_cookie = response.headers[header::SET_COOKIE].to_str().unwrap();
+            set_cookie.split(';').next().unwrap().to_string()
+        };
+        let csrf = cookie(&app.get("/healthz").await);
+        let token = csrf.strip_prefix("csrf=").unwrap();
+
+        let credentials = json!({ "email": "test@example.com", "password": "password123" });
+        let login = Request::post("/api/v1/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::COOKIE, &csrf)
+            .header(CSRF_HEADER, token)
+            .body(Body::from(credentials.to_string()))
+            .unwrap();
+        let login = app.request(login).await;
+        assert_eq!(login.status, StatusCode::NO_CONTENT);
+        let session = cookie(&login);
+
+        let me = Request::get("/api/v1/me")
+            .header(header::COOKIE, format!("{}; {}", csrf, session))
+            .body(Body::empty())
+            .unwrap();
+        let me = app.request(me).await;
+        assert_eq!(me.status, StatusCode::OK);
+        assert_eq!(me.body["email"], "test@example.com");
+    }
+
+    // Response shapes, pinned
+    // -----------------------
+    //
+    // The whole body of each response is compared with a file under
+    // snapshots/, so a renamed DTO field or a changed error mapping fails
+    // here even when no assertion above looks at that field. After an
+    // intended change, `cargo insta review` shows the diff and accepts it.
+    //
+    // Snapshots are named without the module path, so the files are the
+    // same whichever crate the example is built into.
+
+    fn snapshots() -> insta::internals::SettingsBindDropGuard {
+        let mut settings = insta::Settings::clone_current();
+        settings.set_prepend_module_to_snapshot(false);
+        settings.bind_to_scope()
+    }
+
+    // IDs and tokens come from SequentialIdGenerator and MockTokenService,
+    // so nothing needs redacting
+    #[tokio::test]
+    async fn test_success_bodies_match_snapshots() {
+        let _snapshots = snapshots();
+        let admin = UserBuilder::named("admin").role_admin().build();
+        let app = test_app(&[], vec![admin.clone()]);
+        let credentials = json!({ "email": "new@example.com", "password": "password123" });
+
+        let (status, user) = send(
+            &app,
+            "POST",
+            "/api/v1/register",
+            None,
+            Some(credentials.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        insta::assert_json_snapshot!("http_v1_register", user);
+
+        let (_, login) = send(&app, "POST", "/api/v1/login", None, Some(credentials)).await;
+        insta::assert_json_snapshot!("http_v1_login", login);
+        let token = login["access_token"].as_str().unwrap();
+
+        let (_, me) = send(&app, "GET", "/api/v1/me", Some(token), None).await;
+        insta::assert_json_snapshot!("http_v1_me", me);
+        let (_, me) = send(&app, "GET", "/api/v2/me", Some(token), None).await;
+        insta::assert_json_snapshot!("http_v2_me", me);
+
+        let admin_token = TokenBuilder::for_user(&admin).build();
+        let uri = "/api/v1/admin/users?page=1&per_page=10";
+        let (_, users) = send(&app, "GET", uri, Some(&admin_token), None).await;
+        insta::assert_json_snapshot!("http_v1_admin_users", users);
+        let uri = "/api/v2/admin/users?page=1&per_page=1";
+        let (_, users) = send(&app, "GET", uri, Some(&admin_token), None).await;
+        insta::assert_json_snapshot!("http_v2_admin_users", users);
+
+        let (_, health) = send(&app, "GET", "/healthz", None, None).await;
+        insta::assert_json_snapshot!("http_healthz", health);
+    }
+
+    #[tokio::test]
+    async fn test_error_bodies_match_snapshots() {
+        let _snapshots = snapshots();
+        async fn rendered(error: Error) -> Value {
+            let response = ApiError(error).into_response();
+            let status = response.status().as_u16();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            json!({ "status": status, "body": serde_json::from_slice::<Value>(&bytes).unwrap() })
+        }
+
+        // Every variant of the domain Error
+        insta::assert_json_snapshot!("http_error_not_found", rendered(Error::NotFound).await);
+        insta::assert_json_snapshot!(
+            "http_error_already_exists",
+            rendered(Error::AlreadyExists).await
+        );
+        insta::assert_json_snapshot!(
+            "http_error_invalid_credentials",
+            rendered(Error::InvalidCredentials).await
+        );
+        let errors = vec![
+            ValidationError::new("password", "too_short", "must be at least 8 characters"),
+            ValidationError::new(
+                "password",
+                "contains_email",
+                "must not contain the email address",
+            ),
+        ];
+        insta::assert_json_snapshot!(
+            "http_error_validation",
+            rendered(Error::Validation(errors)).await
+        );
+        // The cause stays out of the body
+        insta::assert_json_snapshot!(
+            "http_error_internal",
+            rendered(Error::internal("connection refused")).await
+        );
+
+        // And the ones the extractors answer with before a handler runs
+        let user = UserBuilder::new().build();
+        let app = test_app(&[], vec![user.clone()]);
+        let mut errors = Vec::new();
+        for (name, method, uri, token, body) in [
+            ("missing_token", "GET", "/api/v1/me", None, None),
+            (
+                "invalid_token",
+                "GET",
+                "/api/v1/me",
+                Some(TokenBuilder::new().forged().build()),
+                None,
+            ),
+            (
+                "forbidden",
+                "GET",
+                "/api/v1/admin/users",
+                Some(TokenBuilder::for_user(&user).build()),
+                None,
+            ),
+            (
+                "invalid_body",
+                "POST",
+                "/api/v1/login",
+                None,
+                Some(json!({ "email": 1 })),
+            ),
+            (
+                "validation_failed",
+                "POST",
+                "/api/v1/register",
+                None,
+                Some(json!({ "email": "not-an-email", "password": "short" })),
+            ),
+        ] {
+            let (status, body) = send(&app, method, uri, token.as_deref(), body).await;
+            errors.push((name, json!({ "status": status.as_u16(), "body": body })));
+        }
+        for (name, rendered) in errors {
+            insta::assert_json_snapshot!(format!("http_error_{}", name), rendered);
+        }
+    }
+
+    #[test]
+    fn test_openapi_spec_matches_snapshot() {
+        let _snapshots = snapshots();
+        insta::assert_json_snapshot!("http_openapi", ApiDoc::openapi());
+    }
+}
@@ -0,0 +1,274 @@
+// Integration Tests
+// =================
+//
+// PostgresUserRepository, PostgresAuditRepository and RedisCache against a
+// real Postgres and a real Redis. Everything else in the test suite runs on
+// the mocks; these check what the mocks can't: the SQL, the migrations, the
+// unique index, the TTLs. Declared by refactoring_with_di.rs with
+// `#[cfg(all(test, feature = "integration"))] mod integration;`.
+//
+// PostgresUserRepository also runs run_repository_conformance, the suite
+// MockUserRepository and SqliteUserRepository run in the main tests, and
+// PostgresAuditRepository runs run_audit_conformance, so a backend that
+// differs from the others fails there.
+//
+// Each test starts its own containers with testcontainers, so they need
+// Docker and a few seconds each. They are behind the `integration` feature
+// (which turns on `postgres` and `redis`) and also #[ignore], so even
+// `--all-features` doesn't need Docker:
+//
+//     cargo test --example refactoring_with_di --features integration -- --ignored
+//
+// with, under [dev-dependencies]:
+//
+//     testcontainers = "0.27"
+//     testcontainers-modules = { version = "0.15", features = ["postgres", "redis"] }
+//
+// A container is removed when its handle is dropped, at the end of the
+// test, so keep the handle in a variable (`_postgres`, not `_`).
+
+use crate::Error;
+use crate::after::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use test_support::{UserBuilder, run_audit_conformance, run_repository_conformance};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, ImageExt};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::redis::Redis;
+
+// A server, and its URL without a database name
+async fn postgres_server() -> (ContainerAsync<Postgres>, String) {
+    let container = Postgres::default()
+        .with_tag("16-alpine")
+        .start()
+        .await
+        .expect("starting Postgres; is Docker running?");
+    let server = format!(
+        "postgres://postgres:postgres@{}:{}",
+        container.get_host().await.unwrap(),
+        container.get_host_port_ipv4(5432).await.unwrap()
+    );
+    (container, server)
+}
+
+// A migrated repository on the named database
+async fn postgres_repository(server: &str, database: &str) -> PostgresUserRepository {
+    let url = format!("{}/{}", server, database);
+    let repository = PostgresUserRepository::connect(url.into()).await.unwrap();
+    repository.start().await.unwrap();
+    repository
+}
+
+// A fresh server, and the repository on its default database
+async fn postgres() -> (ContainerAsync<Postgres>, PostgresUserRepository) {
+    let (container, server) = postgres_server().await;
+    (container, postgres_repository(&server, "postgres").await)
+}
+
+// EXPIRE ... NX, which incr uses, needs Redis 7
+async fn redis() -> (ContainerAsync<Redis>, RedisCache) {
+    let container = Redis::default()
+        .with_tag("7-alpine")
+        .start()
+        .await
+        .expect("starting Redis; is Docker running?");
+    let url = format!(
+        "redis://{}:{}",
+        container.get_host().await.unwrap(),
+        container.get_host_port_ipv4(6379).await.unwrap()
+    );
+    let cache = RedisCache::connect(url.into()).await.unwrap();
+    (container, cache)
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_postgres_repository_round_trips_users() {
+    let (_postgres, repository) = postgres().await;
+    repository.check().await.unwrap();
+    // Every instance migrates on startup, so a second run must be harmless
+    repository.start().await.unwrap();
+
+    let ann = UserBuilder::named("ann").build();
+    repository.create(ann.clone()).await.unwrap();
+    let found = repository.find_by_email("ann@example.com").await.unwrap();
+    assert_eq!(found.unwrap().id, "ann");
+    assert!(
+        repository
+            .find_by_email("bob@example.com")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // The unique index, not a SELECT first, is what turns this away
+    let twin = UserBuilder::named("ann2").email("ann@example.com").build();
+    assert!(matches!(
+        repository.create(twin).await,
+        Err(Error::AlreadyExists)
+    ));
+
+    let updated = UserBuilder::named("ann")
+        .role_admin()
+        .inactive()
+        .password("changed")
+        .build();
+    repository.update(updated).await.unwrap();
+    let found = repository.find_by_id("ann").await.unwrap().unwrap();
+    assert_eq!(
+        (found.role.as_str(), found.active, found.avatar_url),
+        ("admin", false, None)
+    );
+    assert_eq!(found.password_hash, "mock_hash_changed");
+
+    let ghost = UserBuilder::named("ghost").build();
+    assert!(matches!(
+        repository.update(ghost).await,
+        Err(Error::NotFound)
+    ));
+    repository.shutdown().await.unwrap();
+}
+
+// One server, and a new database in it for each repository the suite asks for
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_postgres_repository_conforms() {
+    let (_postgres, server) = postgres_server().await;
+    let admin = sqlx::PgPool::connect(&format!("{}/postgres", server))
+        .await
+        .unwrap();
+    let databases = AtomicUsize::new(0);
+    run_repository_conformance(|| async {
+        let database = format!("conformance_{}", databases.fetch_add(1, Ordering::SeqCst));
+        sqlx::raw_sql(&format!("CREATE DATABASE {}", database))
+            .execute(&admin)
+            .await
+            .unwrap();
+        postgres_repository(&server, &database).await
+    })
+    .await;
+}
+
+// The same, with the audit trail on each database's users pool
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_postgres_audit_repository_conforms() {
+    let (_postgres, server) = postgres_server().await;
+    let admin = sqlx::PgPool::connect(&format!("{}/postgres", server))
+        .await
+        .unwrap();
+    let databases = AtomicUsize::new(0);
+    run_audit_conformance(|| async {
+        let database = format!("audit_{}", databases.fetch_add(1, Ordering::SeqCst));
+        sqlx::raw_sql(&format!("CREATE DATABASE {}", database))
+            .execute(&admin)
+            .await
+            .unwrap();
+        postgres_repository(&server, &database).await.audit()
+    })
+    .await;
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_redis_cache_expires_and_counts() {
+    let (_redis, cache) = redis().await;
+    cache.check().await.unwrap();
+
+    cache.set("kept", "a".to_string(), None).await;
+    cache.set("brief", "b".to_string(), Some(1)).await;
+    assert_eq!(cache.get("brief").await.as_deref(), Some("b"));
+    assert!(cache.ttl("brief").await.is_some_and(|ttl| ttl <= 1));
+    assert_eq!(cache.ttl("kept").await, None);
+    assert_eq!(cache.ttl("missing").await, None);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(cache.get("brief").await, None);
+    assert_eq!(cache.get("kept").await.as_deref(), Some("a"));
+
+    cache.delete("kept").await;
+    assert_eq!(cache.get("kept").await, None);
+    // Deleting what isn't there is fine
+    cache.delete("kept").await;
+
+    assert_eq!(cache.incr("hits", 1, Some(1)).await, 1);
+    assert_eq!(cache.incr("hits", 2, Some(60)).await, 3);
+    // NX: the second TTL didn't extend the first
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(cache.incr("hits", 1, Some(1)).await, 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_redis_cache_batches() {
+    let (_redis, cache) = redis().await;
+
+    cache
+        .set_many(&[
+            ("a", "1".to_string(), None),
+            ("brief", "2".to_string(), Some(1)),
+        ])
+        .await;
+    assert_eq!(
+        cache.get_many(&["a", "missing", "brief"]).await,
+        vec![Some("1".to_string()), None, Some("2".to_string())]
+    );
+
+    // Each entry got its own TTL
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        cache.get_many(&["a", "brief"]).await,
+        vec![Some("1".to_string()), None]
+    );
+    assert!(cache.get_many(&[]).await.is_empty());
+    cache.set_many(&[]).await;
+}
+
+// The whole login path on the real backends: AuthService can't tell them
+// from the mocks, and neither should its results
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_auth_service_on_postgres_and_redis() {
+    let (_postgres, repository) = postgres().await;
+    let (_redis, cache) = redis().await;
+    let cache = Arc::new(cache);
+    let service = AuthService::new(
+        Arc::new(repository),
+        Arc::new(MockPasswordHasher),
+        Arc::new(MockTokenService),
+        cache.clone(),
+    );
+
+    let user = service
+        .register("ann@example.com", "password123")
+        .await
+        .unwrap();
+    assert!(matches!(
+        service.register("ann@example.com", "password123").await,
+        Err(Error::AlreadyExists)
+    ));
+
+    let token = service
+        .login("ann@example.com", "password123")
+        .await
+        .unwrap();
+    assert_eq!(service.current_user(&token).await.unwrap().id, user.id);
+    // The login left the user in Redis for the next one
+    assert!(cache.get("user:email:ann@example.com").await.is_some());
+
+    service
+        .change_password("ann@example.com", "password123", "correct horse")
+        .await
+        .unwrap();
+    assert!(matches!(
+        service.login("ann@example.com", "password123").await,
+        Err(Error::InvalidCredentials)
+    ));
+    assert!(
+        service
+            .login("ann@example.com", "correct horse")
+            .await
+            .is_ok()
+    );
+}
@@ -0,0 +1,618 @@
+// Logging
+// =======
+//
+// The one `tracing` subscriber of the process, chosen by LOG_FORMAT and
+// filtered by LOG_LEVEL (RUST_LOG wins when set). Declared by
+// refactoring_with_di.rs with `mod logging;`; main() calls `init` once the
+// config is loaded.
+//
+// pretty (the dev default): one coloured line per event, spans and their
+// fields in front
+//
+//     DEBUG request{request_id="3f0c..." method=POST path=/api/v1/login}:login{email="me@example.com"}: ...
+//
+// json (the default everywhere else): one object per line for a log
+// shipper, with the request's ID at the top level so a query on
+// `request_id` finds every line of one request, whichever span logged it
+//
+//     {"timestamp":"2026-01-05T10:00:00.000000Z","level":"INFO","target":"http",
+//      "request_id":"3f0c...","message":"request finished","status":200,...}
+//
+// Event fields sit next to `message`. Span fields other than request_id
+// aren't repeated on every line; they're in the pretty output when needed.
+//
+// Span export
+// -----------
+//
+// With the `otel` feature and OTEL_EXPORTER_OTLP_ENDPOINT set, the same
+// spans also go over OTLP/gRPC to a collector (Jaeger, Tempo, ...), where
+// one request is one trace: the `request` span, the AuthService call in
+// it, the repository and cache calls in that. Log lines stay as they are.
+// Try it with
+//
+//     docker run -p 16686:16686 -p 4317:4317 jaegertracing/all-in-one
+//     OTEL_EXPORTER_OTLP_ENDPOINT=http://localhost:4317 \
+//         cargo run --example refactoring_with_di --features otel -- --serve
+//
+// and open localhost:16686. The service name is OTEL_SERVICE_NAME, as in
+// any OpenTelemetry SDK. Spans wait in a batch until `Telemetry` is
+// dropped at the end of main(), so a crash can lose the last few seconds.
+//
+// Work a request hands off outlives its trace. An event published on
+// after::EventBus carries a `SpanLink` to the span that published it; the
+// /ws and SSE spans that deliver it link back, so the request that caused
+// a push is one click away without the push joining its trace.
+//
+// Repeats
+// -------
+//
+// A cache that's down fails every request the same way, and a log with
+// the same warning ten thousand times hides everything else. Code that
+// can fail in a tight loop asks a `LogThrottle` first:
+//
+//     if self.failures.allow("cache connect") {
+//         tracing::warn!(error = %e.report(), "cache unavailable");
+//     }
+//
+// The first few per window get through, then (if sampling) one in every
+// N; the first message of the next window is preceded by a "suppressed 9732
+// similar messages" summary for the one before. A storm that stops
+// entirely leaves its last count untold until the key comes up again.
+
+use crate::config::{LogConfig, LogFormat};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[cfg(not(feature = "otel"))]
+    #[error(
+        "OTEL_EXPORTER_OTLP_ENDPOINT is set, but span export is not compiled in (enable the `otel` feature)"
+    )]
+    ExportDisabled,
+    #[cfg(feature = "otel")]
+    #[error("setting up the OTLP exporter")]
+    Exporter(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+
+// LOG_LEVEL is validated by config.rs, so it always parses as a filter.
+// Keep the returned `Telemetry` until the end of main().
+pub fn init(config: &LogConfig) -> Result<Telemetry, InitError> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+    let (export, telemetry) = export(config.otlp_endpoint.as_deref())?;
+    subscriber(config.format, filter, std::io::stdout)
+        .with(export)
+        .init();
+    Ok(telemetry)
+}
+
+// Split from `init` so tests can write somewhere else
+fn subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+) -> impl Subscriber + for<'a> LookupSpan<'a> + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let (pretty, json) = match format {
+        LogFormat::Pretty => (
+            Some(tracing_subscriber::fmt::layer().with_writer(writer)),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .event_format(JsonLines)
+                    .with_writer(writer),
+            ),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(RequestIds)
+        .with(pretty)
+        .with(json)
+}
+
+// Span export
+// -----------
+
+// Flushes the spans still waiting for export when dropped
+#[must_use]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            // Nowhere left to report it but stderr
+            if let Err(e) = provider.shutdown() {
+                eprintln!("flushing spans failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+fn export<S>(endpoint: Option<&str>) -> Result<(Option<impl Layer<S>>, Telemetry), InitError>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let Some(endpoint) = endpoint else {
+        return Ok((None, Telemetry { provider: None }));
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("refactoring_with_di"));
+    Ok((
+        Some(layer),
+        Telemetry {
+            provider: Some(provider),
+        },
+    ))
+}
+
+#[cfg(not(feature = "otel"))]
+fn export(
+    endpoint: Option<&str>,
+) -> Result<(Option<tracing_subscriber::layer::Identity>, Telemetry), InitError> {
+    match endpoint {
+        Some(_) => Err(InitError::ExportDisabled),
+        None => Ok((None, Telemetry {})),
+    }
+}
+
+// The span that handed some work off, for the span that does the work to
+// link to. Holds the exported span's IDs, not the `tracing::Span`: a
+// handle would keep the request's span open for as long as the work is
+// queued. Without the `otel` feature there's nothing to link and it's
+// empty.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpanLink {
+    #[cfg(feature = "otel")]
+    context: Option<opentelemetry::trace::SpanContext>,
+}
+
+impl SpanLink {
+    pub fn current() -> Self {
+        #[cfg(feature = "otel")]
+        {
+            use opentelemetry::trace::TraceContextExt;
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+            let context = tracing::Span::current().context();
+            let span = context.span();
+            let context = span.span_context();
+            Self {
+                context: context.is_valid().then(|| context.clone()),
+            }
+        }
+        #[cfg(not(feature = "otel"))]
+        Self {}
+    }
+
+    pub fn add_to(&self, span: &tracing::Span) {
+        #[cfg(feature = "otel")]
+        if let Some(context) = &self.context {
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+            span.add_link(context.clone());
+        }
+        #[cfg(not(feature = "otel"))]
+        let _ = span;
+    }
+}
+
+// Repeats
+// -------
+
+// Counts per key, and per window of time. Only RedisCache logs through one.
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+pub struct LogThrottle {
+    window: Duration,
+    burst: u32,
+    // 0: nothing past the burst
+    sample_every: u32,
+    windows: Mutex<HashMap<&'static str, Window>>,
+}
+
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+struct Window {
+    started: Instant,
+    seen: u32,
+    suppressed: u64,
+}
+
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+impl LogThrottle {
+    // Up to `burst` messages per key per `window`
+    pub fn new(window: Duration, burst: u32) -> Self {
+        Self {
+            window,
+            burst,
+            sample_every: 0,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Past the burst, still let one in `every` through, so a long storm
+    // keeps showing what it looks like
+    #[cfg(test)]
+    pub fn sampling(mut self, every: u32) -> Self {
+        self.sample_every = every;
+        self
+    }
+
+    // Whether to log this one
+    pub fn allow(&self, key: &'static str) -> bool {
+        self.allow_at(key, Instant::now())
+    }
+
+    fn allow_at(&self, key: &'static str, now: Instant) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(key).or_insert(Window {
+            started: now,
+            seen: 0,
+            suppressed: 0,
+        });
+        if now.duration_since(window.started) >= self.window {
+            if window.suppressed > 0 {
+                tracing::warn!(
+                    key,
+                    suppressed = window.suppressed,
+                    "suppressed {} similar messages",
+                    window.suppressed
+                );
+            }
+            *window = Window {
+                started: now,
+                seen: 0,
+                suppressed: 0,
+            };
+        }
+
+        window.seen = window.seen.saturating_add(1);
+        let past_burst = window.seen.saturating_sub(self.burst);
+        // Nothing is a multiple of 0 but 0 itself, so no sampling is no sampling
+        let allowed = past_burst == 0 || past_burst.is_multiple_of(self.sample_every);
+        if !allowed {
+            window.suppressed += 1;
+        }
+        allowed
+    }
+}
+
+// Request IDs
+// -----------
+// The fmt layer keeps span fields as one preformatted string. This layer
+// keeps the `request_id` of a span as a value too, so JsonLines can lift
+// it out.
+
+struct RequestId(String);
+
+// The request ID JsonLines would print for an event logged here: the
+// innermost span's that has one. None outside a request, and under a
+// subscriber built without this module.
+pub fn current_request_id() -> Option<String> {
+    tracing::Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            let registry = dispatch.downcast_ref::<tracing_subscriber::Registry>()?;
+            registry.span(id)?.scope().find_map(|span| {
+                span.extensions()
+                    .get::<RequestId>()
+                    .map(|request_id| request_id.0.clone())
+            })
+        })
+        .flatten()
+}
+
+struct RequestIds;
+
+impl<S> Layer<S> for RequestIds
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = RequestIdVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(request_id), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(RequestId(request_id));
+        }
+    }
+
+    // `Span::record("request_id", ..)` on a span opened without one
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = RequestIdVisitor(None);
+        values.record(&mut visitor);
+        if let (Some(request_id), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().replace(RequestId(request_id));
+        }
+    }
+}
+
+struct RequestIdVisitor(Option<String>);
+
+impl Visit for RequestIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "request_id" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "request_id" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+// JSON lines
+// ----------
+
+struct JsonLines;
+
+impl<S, N> FormatEvent<S, N> for JsonLines
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut line = Map::new();
+        event.record(&mut JsonVisitor(&mut line));
+        // After the event's fields, so none of them can stand in for these
+        line.insert("timestamp".into(), timestamp.into());
+        line.insert("level".into(), event.metadata().level().as_str().into());
+        line.insert("target".into(), event.metadata().target().into());
+        // The innermost span that has one
+        let request_id = ctx.event_scope().and_then(|scope| {
+            scope
+                .into_iter()
+                .find_map(|span| span.extensions().get::<RequestId>().map(|id| id.0.clone()))
+        });
+        if let Some(request_id) = request_id {
+            line.insert("request_id".into(), request_id.into());
+        }
+
+        let json = serde_json::to_string(&line).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", json)
+    }
+}
+
+// Numbers and booleans stay JSON numbers and booleans; `?value` fields
+// become their Debug string
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::CapturedLogs;
+
+    fn capture(format: LogFormat, level: &str, log: impl FnOnce()) -> String {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = subscriber(format, EnvFilter::new(level), move || writer.clone());
+        tracing::subscriber::with_default(subscriber, log);
+        logs.text()
+    }
+
+    #[test]
+    fn test_json_lines_carry_the_request_id() {
+        let logs = capture(LogFormat::Json, "info", || {
+            let request = tracing::info_span!("request", request_id = "abc", path = "/me");
+            let _request = request.enter();
+            let repository = tracing::info_span!("find_by_id", id = "1");
+            let _repository = repository.enter();
+            // `level` is ours; the event's own doesn't replace it
+            tracing::info!(rows = 1, cached = false, level = "mine", "query done");
+        });
+
+        let line: Value = serde_json::from_str(logs.trim()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], module_path!());
+        assert_eq!(line["request_id"], "abc");
+        assert_eq!(line["message"], "query done");
+        assert_eq!(line["rows"], 1);
+        assert_eq!(line["cached"], false);
+        assert!(line["timestamp"].as_str().is_some_and(|t| t.ends_with('Z')));
+        // Other span fields stay out of the JSON
+        assert!(line.get("path").is_none());
+    }
+
+    #[test]
+    fn test_current_request_id_comes_from_the_enclosing_request() {
+        let subscriber = subscriber(LogFormat::Json, EnvFilter::new("info"), std::io::sink);
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(current_request_id(), None);
+            let _request = tracing::info_span!("request", request_id = "abc").entered();
+            let _login = tracing::info_span!("login").entered();
+            assert_eq!(current_request_id().as_deref(), Some("abc"));
+        });
+    }
+
+    #[test]
+    fn test_throttle_counts_what_it_drops_and_samples_the_rest() {
+        let throttle = LogThrottle::new(Duration::from_secs(10), 2).sampling(5);
+        let start = Instant::now();
+        let logs = capture(LogFormat::Json, "info", || {
+            let allowed: Vec<bool> = (0..12)
+                .map(|_| throttle.allow_at("cache connect", start))
+                .collect();
+            // Two, then every fifth past them
+            assert_eq!(allowed.iter().filter(|allowed| **allowed).count(), 4);
+            assert!(allowed[6] && allowed[11]);
+            // Other keys have their own count
+            assert!(throttle.allow_at("token", start));
+            assert!(throttle.allow_at("cache connect", start + Duration::from_secs(10)));
+        });
+
+        let line: Value = serde_json::from_str(logs.trim()).unwrap();
+        assert_eq!(line["message"], "suppressed 8 similar messages");
+        assert_eq!(line["key"], "cache connect");
+        assert_eq!(line["suppressed"], 8);
+    }
+
+    #[test]
+    fn test_json_lines_outside_a_request_have_no_request_id() {
+        let logs = capture(LogFormat::Json, "info", || {
+            tracing::info!("starting");
+        });
+
+        let line: Value = serde_json::from_str(logs.trim()).unwrap();
+        assert_eq!(line["message"], "starting");
+        assert!(line.get("request_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_spawned_work_keeps_the_request_id_when_it_takes_the_span() {
+        use tracing::Instrument;
+
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = subscriber(LogFormat::Json, EnvFilter::new("info"), move || {
+            writer.clone()
+        });
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = tracing::info_span!("request", request_id = "abc");
+        let (kept, lost) = async {
+            let kept = tokio::spawn(async { tracing::info!("kept") }.in_current_span());
+            let lost = tokio::spawn(async { tracing::info!("lost") });
+            (kept.await, lost.await)
+        }
+        .instrument(request)
+        .await;
+        kept.unwrap();
+        lost.unwrap();
+
+        let lines: Vec<Value> = logs
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let line = |message: &str| {
+            lines
+                .iter()
+                .find(|line| line["message"] == message)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(line("kept")["request_id"], "abc");
+        assert!(line("lost").get("request_id").is_none());
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn test_delivered_events_link_to_the_span_that_published_them() {
+        use crate::after::{DomainEvent, EventBus};
+        use opentelemetry::trace::TracerProvider;
+        use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let bus = EventBus::new(8);
+        let mut events = bus.subscribe();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                bus.publish(DomainEvent::LoggedIn {
+                    user_id: "1".to_string(),
+                })
+            });
+            let delivered = events.try_recv().unwrap();
+            let deliver = tracing::info_span!("deliver");
+            delivered.cause.add_to(&deliver);
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = |name: &str| spans.iter().find(|span| span.name == name).unwrap();
+        let (request, deliver) = (span("request"), span("deliver"));
+        assert_eq!(deliver.links.links.len(), 1);
+        assert_eq!(
+            deliver.links.links[0].span_context.span_id(),
+            request.span_context.span_id()
+        );
+        // Linked, not a child: the delivery stays out of the request's trace
+        assert_ne!(
+            deliver.span_context.trace_id(),
+            request.span_context.trace_id()
+        );
+    }
+
+    #[test]
+    fn test_pretty_is_plain_lines_filtered_by_level() {
+        let logs = capture(LogFormat::Pretty, "info", || {
+            tracing::debug!("hidden");
+            tracing::info!("shown");
+        });
+
+        assert!(logs.contains("shown"));
+        assert!(!logs.contains("hidden"));
+        assert!(serde_json::from_str::<Value>(logs.trim()).is_err());
+    }
+}
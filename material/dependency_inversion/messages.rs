@@ -0,0 +1,118 @@
+// Error Messages
+// ==============
+//
+// What an error says to the person reading it, in English or Vietnamese,
+// one line per ErrorCode. The code stays the same in every language; only
+// `message` changes.
+//
+//     GET /api/v1/me                   Accept-Language: vi-VN,vi;q=0.9,en;q=0.8
+//     401 { "error": "unauthorized", "code": "AUTH002_MISSING_CREDENTIALS",
+//           "message": "thiếu thông tin xác thực" }
+//
+// http.rs picks the locale per request (see `from_accept_language`) and
+// falls back to DEFAULT_LOCALE. English responses keep the more specific
+// message each call site passes ("missing bearer token" rather than
+// "missing credentials"); the English column is what the domain errors
+// say, over HTTP and gRPC alike.
+
+use crate::ErrorCode;
+use crate::config::Locale;
+
+pub fn message(code: ErrorCode, locale: Locale) -> &'static str {
+    let (en, vi) = match code {
+        ErrorCode::InvalidCredentials => (
+            "invalid email or password",
+            "email hoặc mật khẩu không đúng",
+        ),
+        ErrorCode::MissingCredentials => ("missing credentials", "thiếu thông tin xác thực"),
+        ErrorCode::InvalidToken => (
+            "invalid or expired credentials",
+            "thông tin xác thực không hợp lệ hoặc đã hết hạn",
+        ),
+        ErrorCode::Forbidden => (
+            "not allowed to do this",
+            "bạn không có quyền thực hiện thao tác này",
+        ),
+        ErrorCode::CsrfFailed => ("CSRF check failed", "kiểm tra CSRF thất bại"),
+        ErrorCode::NotFound => ("resource not found", "không tìm thấy tài nguyên"),
+        ErrorCode::AlreadyExists => ("resource already exists", "tài nguyên đã tồn tại"),
+        ErrorCode::InvalidBody => (
+            "request body is malformed",
+            "nội dung yêu cầu không đúng định dạng",
+        ),
+        ErrorCode::InvalidQuery => (
+            "query string is malformed",
+            "tham số truy vấn không đúng định dạng",
+        ),
+        ErrorCode::ValidationFailed => ("request failed validation", "dữ liệu không hợp lệ"),
+        ErrorCode::UnsupportedMediaType => {
+            ("unsupported media type", "định dạng tệp không được hỗ trợ")
+        }
+        ErrorCode::MissingFile => ("no file in the request", "yêu cầu không có tệp"),
+        ErrorCode::RateLimited => (
+            "too many requests, try again later",
+            "quá nhiều yêu cầu, vui lòng thử lại sau",
+        ),
+        ErrorCode::Internal => ("internal server error", "lỗi máy chủ nội bộ"),
+    };
+    match locale {
+        Locale::En => en,
+        Locale::Vi => vi,
+    }
+}
+
+// The best supported language in an Accept-Language header, by q-value,
+// ties going to the one listed first. None when nothing listed is
+// supported (or only `*`), so the caller's default applies.
+//
+//     "vi-VN,vi;q=0.9,en;q=0.8" -> Vi      "fr, en;q=0.5" -> En
+pub fn from_accept_language(header: &str) -> Option<Locale> {
+    let mut best: Option<(Locale, f32)> = None;
+    for entry in header.split(',') {
+        let mut parts = entry.split(';').map(str::trim);
+        let tag = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+            .unwrap_or(0.0);
+        let language = tag.split('-').next().unwrap_or_default();
+        let Ok(locale) = language.parse::<Locale>() else {
+            continue;
+        };
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((locale, quality));
+        }
+    }
+    best.map(|(locale, _)| locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_language_picks_the_best_supported_locale() {
+        assert_eq!(
+            from_accept_language("vi-VN,vi;q=0.9,en;q=0.8"),
+            Some(Locale::Vi)
+        );
+        assert_eq!(from_accept_language("fr, en;q=0.5"), Some(Locale::En));
+        assert_eq!(from_accept_language("en;q=0.4, vi;q=0.7"), Some(Locale::Vi));
+        // q=0 means "not this one"
+        assert_eq!(from_accept_language("vi;q=0, en;q=0.1"), Some(Locale::En));
+        assert_eq!(from_accept_language("fr-FR, *"), None);
+        assert_eq!(from_accept_language(""), None);
+    }
+
+    #[test]
+    fn test_messages_follow_the_locale() {
+        assert_eq!(
+            message(ErrorCode::NotFound, Locale::En),
+            "resource not found"
+        );
+        assert_eq!(
+            message(ErrorCode::NotFound, Locale::Vi),
+            "không tìm thấy tài nguyên"
+        );
+    }
+}
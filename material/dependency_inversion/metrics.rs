@@ -0,0 +1,370 @@
+// Metrics Backends
+// ================
+//
+// Shared by the examples with `mod metrics;`.
+//
+// Services record through tutorial-core's Metrics trait and never learn
+// where the numbers go. Which backend gets them is decided where the app
+// is wired up (METRICS_BACKEND in refactoring_with_di):
+//
+//     let metrics: Arc<dyn Metrics> = Arc::new(PrometheusMetrics::default());
+//     let cache = InstrumentedCache::new(cache).with_metrics(metrics.clone());
+//     // ... and GET /metrics serves metrics.render()
+
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tutorial_core::{CacheService, Error, HealthCheck, Lifecycle, Metrics};
+
+// METRICS_BACKEND=none, and what services get unless told otherwise
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {
+    fn counter(&self, _name: &'static str, _labels: &[(&'static str, &str)], _by: u64) {}
+    fn gauge(&self, _name: &'static str, _labels: &[(&'static str, &str)], _value: f64) {}
+    fn histogram(&self, _name: &'static str, _labels: &[(&'static str, &str)], _value: f64) {}
+}
+
+type LabelSet = Vec<(&'static str, String)>;
+
+enum Family {
+    Counter(BTreeMap<LabelSet, u64>),
+    Gauge(BTreeMap<LabelSet, f64>),
+    Histogram(BTreeMap<LabelSet, Histogram>),
+}
+
+// Counts per upper bound (not yet cumulative), plus the +Inf total
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+// Everything in memory, rendered in Prometheus' text format when
+// GET /metrics is scraped:
+//
+//     # TYPE auth_logins_total counter
+//     auth_logins_total{outcome="success"} 3
+//     # TYPE db_query_duration_seconds histogram
+//     db_query_duration_seconds_bucket{method="find_by_email",le="0.005"} 2
+//     ...
+//
+// A name is one kind of metric; recording it as another is ignored.
+#[derive(Default)]
+pub struct PrometheusMetrics {
+    families: std::sync::Mutex<BTreeMap<&'static str, Family>>,
+}
+
+impl PrometheusMetrics {
+    // Seconds, from a fast cache hit to a request that timed out
+    pub const BUCKETS: [f64; 11] = [
+        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+    ];
+
+    fn labels(labels: &[(&'static str, &str)]) -> LabelSet {
+        let mut labels: LabelSet = labels
+            .iter()
+            .map(|(key, value)| (*key, value.to_string()))
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    // `{method="find_by_email",le="0.005"}`, or nothing without labels
+    fn format_labels(labels: &LabelSet, le: Option<&str>) -> String {
+        let escape = |value: &str| {
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        };
+        let mut pairs: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect();
+        if let Some(le) = le {
+            pairs.push(format!("le=\"{}\"", le));
+        }
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", pairs.join(","))
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            match family {
+                Family::Counter(series) => {
+                    out.push_str(&format!("# TYPE {} counter\n", name));
+                    for (labels, value) in series {
+                        let labels = Self::format_labels(labels, None);
+                        out.push_str(&format!("{}{} {}\n", name, labels, value));
+                    }
+                }
+                Family::Gauge(series) => {
+                    out.push_str(&format!("# TYPE {} gauge\n", name));
+                    for (labels, value) in series {
+                        let labels = Self::format_labels(labels, None);
+                        out.push_str(&format!("{}{} {}\n", name, labels, value));
+                    }
+                }
+                Family::Histogram(series) => {
+                    out.push_str(&format!("# TYPE {} histogram\n", name));
+                    for (labels, histogram) in series {
+                        let mut cumulative = 0;
+                        for (bound, count) in Self::BUCKETS.iter().zip(&histogram.buckets) {
+                            cumulative += count;
+                            let le = Self::format_labels(labels, Some(&bound.to_string()));
+                            out.push_str(&format!("{}_bucket{} {}\n", name, le, cumulative));
+                        }
+                        let inf = Self::format_labels(labels, Some("+Inf"));
+                        out.push_str(&format!("{}_bucket{} {}\n", name, inf, histogram.count));
+                        let labels = Self::format_labels(labels, None);
+                        out.push_str(&format!("{}_sum{} {}\n", name, labels, histogram.sum));
+                        out.push_str(&format!("{}_count{} {}\n", name, labels, histogram.count));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64) {
+        let mut families = self.families.lock().unwrap();
+        let family = families
+            .entry(name)
+            .or_insert_with(|| Family::Counter(BTreeMap::new()));
+        if let Family::Counter(series) = family {
+            *series.entry(Self::labels(labels)).or_default() += by;
+        }
+    }
+
+    fn gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut families = self.families.lock().unwrap();
+        let family = families
+            .entry(name)
+            .or_insert_with(|| Family::Gauge(BTreeMap::new()));
+        if let Family::Gauge(series) = family {
+            series.insert(Self::labels(labels), value);
+        }
+    }
+
+    fn histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut families = self.families.lock().unwrap();
+        let family = families
+            .entry(name)
+            .or_insert_with(|| Family::Histogram(BTreeMap::new()));
+        if let Family::Histogram(series) = family {
+            let histogram = series
+                .entry(Self::labels(labels))
+                .or_insert_with(|| Histogram {
+                    buckets: vec![0; Self::BUCKETS.len()],
+                    sum: 0.0,
+                    count: 0,
+                });
+            if let Some(bucket) = Self::BUCKETS.iter().position(|bound| value <= *bound) {
+                histogram.buckets[bucket] += 1;
+            }
+            histogram.sum += value;
+            histogram.count += 1;
+        }
+    }
+}
+
+// One UDP datagram per call to a StatsD agent (Datadog's, Telegraf's),
+// labels as DogStatsD tags: `auth_logins_total:1|c|#outcome:success`.
+// Nothing is kept here, and a lost packet is a lost sample, never an
+// error a request sees.
+pub struct StatsdMetrics {
+    socket: std::net::UdpSocket,
+}
+
+impl StatsdMetrics {
+    // Resolves `addr` once, here
+    pub fn connect(addr: &str) -> std::io::Result<Self> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    pub fn line(
+        name: &str,
+        labels: &[(&'static str, &str)],
+        value: impl std::fmt::Display,
+        kind: &str,
+    ) -> String {
+        let mut line = format!("{}:{}|{}", name, value, kind);
+        if !labels.is_empty() {
+            let tags: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}:{}", key, value))
+                .collect();
+            line.push_str(&format!("|#{}", tags.join(",")));
+        }
+        line
+    }
+
+    fn send(&self, line: String) {
+        let _ = self.socket.send(line.as_bytes());
+    }
+}
+
+impl Metrics for StatsdMetrics {
+    fn counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64) {
+        self.send(Self::line(name, labels, by, "c"));
+    }
+
+    fn gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        self.send(Self::line(name, labels, value, "g"));
+    }
+
+    fn histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        self.send(Self::line(name, labels, value, "h"));
+    }
+}
+
+// Cache statistics
+// ----------------
+// A decorator that counts what happens to the cache it wraps. Any
+// CacheService can be instrumented without touching its implementation.
+// The counts are kept here for stats(), and also recorded as
+// cache_hits_total, cache_misses_total, cache_sets_total and
+// cache_evictions_total so they reach a dashboard.
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+    // Explicit removals (deletes / invalidations)
+    pub evictions: u64,
+}
+
+#[cfg(test)]
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+pub struct InstrumentedCache {
+    inner: Arc<dyn CacheService>,
+    metrics: Arc<dyn Metrics>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    sets: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl InstrumentedCache {
+    pub fn new(inner: Arc<dyn CacheService>) -> Self {
+        Self {
+            inner,
+            metrics: Arc::new(NoopMetrics),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            sets: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            sets: self.sets.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count(&self, counter: &AtomicU64, name: &'static str, by: u64) {
+        counter.fetch_add(by, Ordering::Relaxed);
+        self.metrics.counter(name, &[], by);
+    }
+
+    fn record_lookup(&self, found: bool) {
+        if found {
+            self.count(&self.hits, "cache_hits_total", 1);
+        } else {
+            self.count(&self.misses, "cache_misses_total", 1);
+        }
+    }
+}
+
+#[async_trait]
+impl CacheService for InstrumentedCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        let value = self.inner.get(key).await;
+        self.record_lookup(value.is_some());
+        value
+    }
+
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        self.count(&self.sets, "cache_sets_total", 1);
+        self.inner.set(key, value, ttl_seconds).await;
+    }
+
+    async fn delete(&self, key: &str) {
+        self.count(&self.evictions, "cache_evictions_total", 1);
+        self.inner.delete(key).await;
+    }
+
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        let values = self.inner.get_many(keys).await;
+        for value in &values {
+            self.record_lookup(value.is_some());
+        }
+        values
+    }
+
+    async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+        self.count(&self.sets, "cache_sets_total", entries.len() as u64);
+        self.inner.set_many(entries).await;
+    }
+
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        self.count(&self.sets, "cache_sets_total", 1);
+        self.inner.incr(key, by, ttl_seconds).await
+    }
+
+    async fn ttl(&self, key: &str) -> Option<u64> {
+        self.inner.ttl(key).await
+    }
+}
+
+#[async_trait]
+impl HealthCheck for InstrumentedCache {
+    async fn check(&self) -> Result<(), Error> {
+        self.inner.check().await
+    }
+}
+
+#[async_trait]
+impl Lifecycle for InstrumentedCache {
+    async fn start(&self) -> Result<(), Error> {
+        self.inner.start().await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        self.inner.shutdown().await
+    }
+}
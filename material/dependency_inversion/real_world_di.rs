@@ -2,71 +2,117 @@
 // ===========================================================
 //
 // This example shows how to apply DI patterns similar to your existing codebase
+//
+// main() walks through a few of them; the ones only the tests exercise are
+// #[cfg(test)].
 
-use std::sync::Arc;
 use async_trait::async_trait;
+#[cfg(test)]
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use config::SecretString;
+use container::{Container, Scope, ServiceRegistry};
+#[cfg(test)]
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+#[cfg(test)]
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tutorial_core::{
+    CacheService, CacheServiceExt, Error, HealthCheck, Lifecycle, Page, PageRequest, User,
+    UserFilter, UserRepository,
+};
+
+// Shared with refactoring_with_di, which uses most of them; this example
+// only needs a few pieces of each
+#[allow(dead_code)]
+mod config;
+#[allow(dead_code)]
+mod container;
+#[allow(dead_code)]
+mod metrics;
 
 // Example 1: Repository Pattern (Like your db crate)
 // ==================================================
 
-#[derive(Debug, Clone)]
-struct User {
-    id: String,
-    email: String,
-    name: String,
-}
+// User and Error come from tutorial-core, shared with the other examples.
+// Error is what every layer below main() returns. Causes keep their type
+// (a bad cache value is the serde_json or bincode error itself, not a
+// string of it), and main() adds context with anyhow at the edge.
 
-// Define the abstraction
-#[async_trait]
-trait UserRepository: Send + Sync {
-    async fn find_by_id(&self, id: &str) -> Result<Option<User>, String>;
-    async fn find_by_email(&self, email: &str) -> Result<Option<User>, String>;
-    async fn create(&self, user: User) -> Result<User, String>;
-    async fn update(&self, user: User) -> Result<User, String>;
-    async fn delete(&self, id: &str) -> Result<(), String>;
-}
+// The abstraction is tutorial-core's UserRepository, the same one
+// refactoring_with_di implements. Users are never deleted, only
+// deactivated, so there is no delete().
 
 // Concrete implementation #1: PostgreSQL
 struct PostgresUserRepository {
     // In real code, this would be sqlx::PgPool
-    connection_string: String,
+    connection_string: SecretString,
 }
 
 impl PostgresUserRepository {
-    fn new(connection_string: String) -> Self {
+    fn new(connection_string: SecretString) -> Self {
         Self { connection_string }
     }
 }
 
+// Each call is a span with the lookup key; the query is a debug event in
+// it. The connection string is never a field.
 #[async_trait]
 impl UserRepository for PostgresUserRepository {
-    async fn find_by_id(&self, id: &str) -> Result<Option<User>, String> {
+    #[tracing::instrument(skip(self), fields(db = "postgres"))]
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
         // Simulate database query
-        println!("Querying PostgreSQL for user id: {}", id);
+        tracing::debug!("SELECT * FROM users WHERE id = $1");
         Ok(Some(User {
             id: id.to_string(),
             email: "user@example.com".to_string(),
-            name: "John Doe".to_string(),
+            password_hash: String::new(),
+            role: "user".to_string(),
+            active: true,
+            avatar_url: None,
         }))
     }
 
-    async fn find_by_email(&self, email: &str) -> Result<Option<User>, String> {
-        println!("Querying PostgreSQL for user email: {}", email);
+    #[tracing::instrument(skip(self), fields(db = "postgres"))]
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+        tracing::debug!("SELECT * FROM users WHERE email = $1");
         Ok(None)
     }
 
-    async fn create(&self, user: User) -> Result<User, String> {
-        println!("Creating user in PostgreSQL: {:?}", user);
+    #[tracing::instrument(skip_all, fields(db = "postgres", user.id = %user.id))]
+    async fn create(&self, user: User) -> Result<User, Error> {
+        tracing::debug!("INSERT INTO users VALUES ($1, $2, $3)");
         Ok(user)
     }
 
-    async fn update(&self, user: User) -> Result<User, String> {
-        println!("Updating user in PostgreSQL: {:?}", user);
+    #[tracing::instrument(skip_all, fields(db = "postgres", user.id = %user.id))]
+    async fn update(&self, user: User) -> Result<User, Error> {
+        tracing::debug!("UPDATE users SET email = $2, name = $3 WHERE id = $1");
         Ok(user)
     }
 
-    async fn delete(&self, id: &str) -> Result<(), String> {
-        println!("Deleting user from PostgreSQL: {}", id);
+    #[tracing::instrument(skip(self), fields(db = "postgres"))]
+    async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+        tracing::debug!("SELECT * FROM users WHERE ... LIMIT $1 OFFSET $2");
+        Ok(Page::empty(page))
+    }
+}
+
+#[async_trait]
+impl HealthCheck for PostgresUserRepository {
+    async fn check(&self) -> Result<(), Error> {
+        tracing::debug!(db = "postgres", "SELECT 1");
+        Ok(())
+    }
+}
+
+// In real code, this is where the pool would connect
+#[async_trait]
+impl Lifecycle for PostgresUserRepository {
+    async fn start(&self) -> Result<(), Error> {
+        tracing::info!(db = "postgres", url = %self.connection_string, "connecting");
         Ok(())
     }
 }
@@ -77,6 +123,7 @@ struct MockUserRepository {
 }
 
 impl MockUserRepository {
+    #[cfg(test)]
     fn new() -> Self {
         Self {
             users: std::sync::Mutex::new(Vec::new()),
@@ -92,340 +139,2380 @@ impl MockUserRepository {
 
 #[async_trait]
 impl UserRepository for MockUserRepository {
-    async fn find_by_id(&self, id: &str) -> Result<Option<User>, String> {
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
         let users = self.users.lock().unwrap();
         Ok(users.iter().find(|u| u.id == id).cloned())
     }
 
-    async fn find_by_email(&self, email: &str) -> Result<Option<User>, String> {
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
         let users = self.users.lock().unwrap();
         Ok(users.iter().find(|u| u.email == email).cloned())
     }
 
-    async fn create(&self, user: User) -> Result<User, String> {
+    async fn create(&self, user: User) -> Result<User, Error> {
         let mut users = self.users.lock().unwrap();
         users.push(user.clone());
         Ok(user)
     }
 
-    async fn update(&self, user: User) -> Result<User, String> {
+    async fn update(&self, user: User) -> Result<User, Error> {
         let mut users = self.users.lock().unwrap();
         if let Some(existing) = users.iter_mut().find(|u| u.id == user.id) {
             *existing = user.clone();
             Ok(user)
         } else {
-            Err("User not found".to_string())
+            Err(Error::NotFound)
         }
     }
 
-    async fn delete(&self, id: &str) -> Result<(), String> {
-        let mut users = self.users.lock().unwrap();
-        users.retain(|u| u.id != id);
+    async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+        let users = self.users.lock().unwrap();
+        let matching: Vec<&User> = users.iter().filter(|u| filter.matches(u)).collect();
+        Ok(Page {
+            items: matching
+                .iter()
+                .skip(page.offset())
+                .take(page.per_page as usize)
+                .map(|u| (*u).clone())
+                .collect(),
+            page: page.page,
+            per_page: page.per_page,
+            total: matching.len() as u64,
+        })
+    }
+}
+
+#[async_trait]
+impl HealthCheck for MockUserRepository {
+    async fn check(&self) -> Result<(), Error> {
         Ok(())
     }
 }
 
+impl Lifecycle for MockUserRepository {}
+
 // Example 2: Service Layer with Multiple Dependencies
 // ===================================================
 
-#[async_trait]
-trait CacheService: Send + Sync {
-    async fn get(&self, key: &str) -> Option<String>;
-    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>);
-    async fn delete(&self, key: &str);
-}
+// CacheService is tutorial-core's too. Besides get/set/delete it has
+// batch operations (get_many/set_many, which loop by default; backends
+// that can do better override them) and atomic counters (incr/decr, for
+// rate limits and failed logins).
 
 struct RedisCacheService {
-    redis_url: String,
+    redis_url: SecretString,
 }
 
+// Keys are fields, values never are: they're whatever the caller cached
 #[async_trait]
 impl CacheService for RedisCacheService {
+    #[tracing::instrument(skip(self), fields(cache = "redis"))]
     async fn get(&self, key: &str) -> Option<String> {
-        println!("Getting from Redis: {}", key);
+        tracing::debug!("GET");
         None
     }
 
-    async fn set(&self, key: &str, value: String, _ttl_seconds: Option<u64>) {
-        println!("Setting in Redis: {} = {}", key, value);
+    #[tracing::instrument(skip(self, value, ttl_seconds), fields(cache = "redis"))]
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        tracing::debug!(bytes = value.len(), ?ttl_seconds, "SET");
     }
 
+    #[tracing::instrument(skip(self), fields(cache = "redis"))]
     async fn delete(&self, key: &str) {
-        println!("Deleting from Redis: {}", key);
+        tracing::debug!("DEL");
     }
-}
 
-struct InMemoryCacheService {
-    cache: std::sync::Mutex<std::collections::HashMap<String, String>>,
-}
+    #[tracing::instrument(skip(self), fields(cache = "redis"))]
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        // A real MGET is RedisCache::get_many in refactoring_with_di.rs
+        tracing::debug!("MGET");
+        vec![None; keys.len()]
+    }
 
-impl InMemoryCacheService {
-    fn new() -> Self {
-        Self {
-            cache: std::sync::Mutex::new(std::collections::HashMap::new()),
-        }
+    #[tracing::instrument(skip_all, fields(cache = "redis", count = entries.len()))]
+    async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+        // A real pipeline of SET ... EX is RedisCache::set_many in
+        // refactoring_with_di.rs
+        tracing::debug!("pipeline of SETs");
+    }
+
+    #[tracing::instrument(skip(self, ttl_seconds), fields(cache = "redis"))]
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        // In real code: MULTI / INCRBY key by / EXPIRE key ttl NX / EXEC
+        tracing::debug!(?ttl_seconds, "INCRBY");
+        by
+    }
+
+    #[tracing::instrument(skip(self), fields(cache = "redis"))]
+    async fn ttl(&self, key: &str) -> Option<u64> {
+        tracing::debug!("TTL");
+        None
     }
 }
 
 #[async_trait]
-impl CacheService for InMemoryCacheService {
-    async fn get(&self, key: &str) -> Option<String> {
-        self.cache.lock().unwrap().get(key).cloned()
+impl HealthCheck for RedisCacheService {
+    async fn check(&self) -> Result<(), Error> {
+        tracing::debug!(cache = "redis", "PING");
+        Ok(())
     }
+}
 
-    async fn set(&self, key: &str, value: String, _ttl_seconds: Option<u64>) {
-        self.cache.lock().unwrap().insert(key.to_string(), value);
+#[async_trait]
+impl Lifecycle for RedisCacheService {
+    async fn start(&self) -> Result<(), Error> {
+        tracing::info!(cache = "redis", url = %self.redis_url, "connecting");
+        Ok(())
     }
+}
 
-    async fn delete(&self, key: &str) {
-        self.cache.lock().unwrap().remove(key);
-    }
+// Memcached: a second remote backend behind the same trait
+// ---------------------------------------------------------
+// Memcached is stricter than Redis, and those limits are part of the
+// contract. They are modelled as a typed error instead of being hidden.
+
+#[cfg(test)]
+const MEMCACHED_MAX_KEY_BYTES: usize = 250;
+#[cfg(test)]
+const MEMCACHED_MAX_VALUE_BYTES: usize = 1024 * 1024;
+// Larger TTLs are read by memcached as a unix timestamp, not a duration
+#[cfg(test)]
+const MEMCACHED_MAX_TTL_SECONDS: u64 = 60 * 60 * 24 * 30;
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, thiserror::Error)]
+enum MemcachedError {
+    #[error("key is {len} bytes, memcached allows at most {MEMCACHED_MAX_KEY_BYTES}")]
+    KeyTooLong { len: usize },
+    #[error("key {key:?} contains whitespace or control characters")]
+    InvalidKey { key: String },
+    #[error("value is {size} bytes, memcached allows at most {MEMCACHED_MAX_VALUE_BYTES}")]
+    ValueTooLarge { size: usize },
+    #[error("ttl of {ttl_seconds}s is longer than memcached's {MEMCACHED_MAX_TTL_SECONDS}s limit")]
+    TtlTooLong { ttl_seconds: u64 },
 }
 
-// Business logic service that depends on repository and cache
-struct UserService {
-    repository: Arc<dyn UserRepository>,
-    cache: Arc<dyn CacheService>,
+#[cfg(test)]
+struct MemcachedCacheService {
+    // In real code, this would be memcache::Client
+    servers: Vec<String>,
 }
 
-impl UserService {
-    fn new(repository: Arc<dyn UserRepository>, cache: Arc<dyn CacheService>) -> Self {
-        Self { repository, cache }
+#[cfg(test)]
+impl MemcachedCacheService {
+    fn new(servers: Vec<String>) -> Self {
+        Self { servers }
     }
 
-    async fn get_user(&self, id: &str) -> Result<Option<User>, String> {
-        // Try cache first
-        let cache_key = format!("user:{}", id);
-        if let Some(_cached) = self.cache.get(&cache_key).await {
-            println!("Cache hit!");
-            // In real code, deserialize from cache
+    fn validate_key(key: &str) -> Result<(), MemcachedError> {
+        if key.len() > MEMCACHED_MAX_KEY_BYTES {
+            return Err(MemcachedError::KeyTooLong { len: key.len() });
         }
+        if key.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(MemcachedError::InvalidKey {
+                key: key.to_string(),
+            });
+        }
+        Ok(())
+    }
 
-        // Fetch from repository
-        let user = self.repository.find_by_id(id).await?;
+    // The keys a multi-get may send. A key with a space would be read as
+    // two keys and shift every result after it, so invalid ones are left
+    // out; they can't have been stored anyway.
+    fn valid_keys<'a>(keys: &[&'a str]) -> Vec<&'a str> {
+        keys.iter()
+            .copied()
+            .filter(|key| Self::validate_key(key).is_ok())
+            .collect()
+    }
 
-        // Update cache
-        if let Some(ref user) = user {
-            self.cache.set(&cache_key, user.id.clone(), Some(3600)).await;
+    // Checked version of set(): callers that care get the reason back
+    fn try_set(
+        &self,
+        key: &str,
+        value: &str,
+        ttl_seconds: Option<u64>,
+    ) -> Result<(), MemcachedError> {
+        Self::validate_key(key)?;
+        if value.len() > MEMCACHED_MAX_VALUE_BYTES {
+            return Err(MemcachedError::ValueTooLarge { size: value.len() });
+        }
+        if let Some(ttl_seconds) = ttl_seconds.filter(|ttl| *ttl > MEMCACHED_MAX_TTL_SECONDS) {
+            return Err(MemcachedError::TtlTooLong { ttl_seconds });
         }
 
-        Ok(user)
+        tracing::debug!(
+            cache = "memcached",
+            key,
+            bytes = value.len(),
+            ?ttl_seconds,
+            servers = ?self.servers,
+            "SET"
+        );
+        Ok(())
     }
+}
 
-    async fn create_user(&self, user: User) -> Result<User, String> {
-        // Check if email exists
-        if let Some(_) = self.repository.find_by_email(&user.email).await? {
-            return Err("Email already exists".to_string());
+#[cfg(test)]
+#[async_trait]
+impl CacheService for MemcachedCacheService {
+    #[tracing::instrument(skip(self), fields(cache = "memcached"))]
+    async fn get(&self, key: &str) -> Option<String> {
+        Self::validate_key(key).ok()?;
+        tracing::debug!("GET");
+        None
+    }
+
+    // A cache write that can't be stored is skipped, not fatal
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        if let Err(e) = self.try_set(key, &value, ttl_seconds) {
+            tracing::warn!(cache = "memcached", key, error = %e, "SET skipped");
         }
+    }
 
-        // Create user
-        let created_user = self.repository.create(user).await?;
+    #[tracing::instrument(skip(self), fields(cache = "memcached"))]
+    async fn delete(&self, key: &str) {
+        if Self::validate_key(key).is_ok() {
+            tracing::debug!("DELETE");
+        }
+    }
 
-        // Cache the new user
-        let cache_key = format!("user:{}", created_user.id);
-        self.cache.set(&cache_key, created_user.id.clone(), Some(3600)).await;
+    #[tracing::instrument(skip(self), fields(cache = "memcached"))]
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        // In real code: a single multi-get ("get k1 k2 k3") of the valid
+        // keys, whose hits come back by key
+        let valid = Self::valid_keys(keys);
+        tracing::debug!(sent = valid.len(), "GET");
+        let found: HashMap<&str, String> = HashMap::new();
 
-        Ok(created_user)
+        // Invalid keys are misses, as in get()
+        keys.iter().map(|key| found.get(key).cloned()).collect()
     }
 
-    async fn delete_user(&self, id: &str) -> Result<(), String> {
-        // Delete from repository
-        self.repository.delete(id).await?;
-
-        // Invalidate cache
-        let cache_key = format!("user:{}", id);
-        self.cache.delete(&cache_key).await;
+    // memcached counters are unsigned: "decr" stops at 0 and "incr" fails on
+    // a missing key, so real code does `add key 0` first, then incr/decr.
+    #[tracing::instrument(skip(self, ttl_seconds), fields(cache = "memcached"))]
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        let command = if by < 0 { "DECR" } else { "INCR" };
+        tracing::debug!(
+            ?ttl_seconds,
+            "ADD 0, then {} {}",
+            command,
+            by.unsigned_abs()
+        );
+        by.max(0)
+    }
+}
 
+#[cfg(test)]
+#[async_trait]
+impl HealthCheck for MemcachedCacheService {
+    async fn check(&self) -> Result<(), Error> {
+        tracing::debug!(cache = "memcached", servers = ?self.servers, "VERSION");
         Ok(())
     }
 }
 
-// Example 3: Axum Handler with Dependency Injection
-// =================================================
-
-use std::sync::Mutex;
+#[cfg(test)]
+impl Lifecycle for MemcachedCacheService {}
 
-// Application state that holds dependencies
-struct AppState {
-    user_service: Arc<UserService>,
+// Each entry remembers when it expires (None = never)
+struct InMemoryCacheService {
+    cache: std::sync::Mutex<std::collections::HashMap<String, (String, Option<Instant>)>>,
 }
 
-// In Axum, you would use this like:
-// async fn get_user_handler(
-//     State(state): State<Arc<AppState>>,
-//     Path(id): Path<String>,
-// ) -> Result<Json<User>, StatusCode> {
-//     let user = state.user_service.get_user(&id).await
-//         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-//     user.ok_or(StatusCode::NOT_FOUND).map(Json)
-// }
+impl InMemoryCacheService {
+    fn new() -> Self {
+        Self {
+            cache: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
 
-// Example 4: Factory Pattern for Creating Services
-// ================================================
+    // Returns the value if present and not expired (expired entries are dropped)
+    fn live_value(
+        cache: &mut HashMap<String, (String, Option<Instant>)>,
+        key: &str,
+    ) -> Option<String> {
+        match cache.get(key) {
+            Some((_, Some(expires_at))) if *expires_at <= Instant::now() => {
+                cache.remove(key);
+                None
+            }
+            Some((value, _)) => Some(value.clone()),
+            None => None,
+        }
+    }
 
-struct ServiceFactory {
-    database_url: String,
-    redis_url: Option<String>,
-    use_cache: bool,
+    fn expires_at(ttl_seconds: Option<u64>) -> Option<Instant> {
+        ttl_seconds.map(|ttl| Instant::now() + Duration::from_secs(ttl))
+    }
 }
 
-impl ServiceFactory {
-    fn new(database_url: String) -> Self {
-        Self {
-            database_url,
-            redis_url: None,
-            use_cache: false,
-        }
+#[async_trait]
+impl CacheService for InMemoryCacheService {
+    async fn get(&self, key: &str) -> Option<String> {
+        Self::live_value(&mut self.cache.lock().unwrap(), key)
     }
 
-    fn with_redis(mut self, redis_url: String) -> Self {
-        self.redis_url = Some(redis_url);
-        self.use_cache = true;
-        self
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (value, Self::expires_at(ttl_seconds)));
     }
 
-    fn build_user_service(self) -> UserService {
-        // Create repository
-        let repository: Arc<dyn UserRepository> =
-            Arc::new(PostgresUserRepository::new(self.database_url));
+    async fn delete(&self, key: &str) {
+        self.cache.lock().unwrap().remove(key);
+    }
 
-        // Create cache
-        let cache: Arc<dyn CacheService> = if self.use_cache {
-            if let Some(redis_url) = self.redis_url {
-                Arc::new(RedisCacheService { redis_url })
-            } else {
-                Arc::new(InMemoryCacheService::new())
-            }
-        } else {
-            Arc::new(InMemoryCacheService::new())
+    // Take the lock once for the whole batch
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        let mut cache = self.cache.lock().unwrap();
+        keys.iter()
+            .map(|key| Self::live_value(&mut cache, key))
+            .collect()
+    }
+
+    async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+        let mut cache = self.cache.lock().unwrap();
+        for (key, value, ttl_seconds) in entries {
+            cache.insert(
+                key.to_string(),
+                (value.clone(), Self::expires_at(*ttl_seconds)),
+            );
+        }
+    }
+
+    // Read-modify-write under one lock, so concurrent increments can't race
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        let mut cache = self.cache.lock().unwrap();
+        let current = Self::live_value(&mut cache, key);
+
+        // Redis refuses INCRBY on a value that isn't a number, and
+        // RedisCache reads the error as 0; so does this, and the value is
+        // left as it was
+        let value = match current.as_deref().map(str::parse::<i64>) {
+            Some(Ok(current)) => current + by,
+            Some(Err(_)) => return 0,
+            None => by,
         };
 
-        UserService::new(repository, cache)
+        // Keep the original expiry of an existing counter
+        let expires_at = match (current, cache.get(key)) {
+            (Some(_), Some((_, expires_at))) => *expires_at,
+            _ => Self::expires_at(ttl_seconds),
+        };
+        cache.insert(key.to_string(), (value.to_string(), expires_at));
+        value
     }
 
-    fn build_test_user_service(mock_users: Vec<User>) -> UserService {
-        let repository: Arc<dyn UserRepository> =
-            Arc::new(MockUserRepository::with_users(mock_users));
-        let cache: Arc<dyn CacheService> =
-            Arc::new(InMemoryCacheService::new());
+    // Rounded down, so a copy made with it never outlives the original
+    async fn ttl(&self, key: &str) -> Option<u64> {
+        let mut cache = self.cache.lock().unwrap();
+        Self::live_value(&mut cache, key)?;
+        let expires_at = cache.get(key)?.1?;
+        Some(
+            expires_at
+                .saturating_duration_since(Instant::now())
+                .as_secs(),
+        )
+    }
+}
 
-        UserService::new(repository, cache)
+#[async_trait]
+impl HealthCheck for InMemoryCacheService {
+    async fn check(&self) -> Result<(), Error> {
+        Ok(())
     }
 }
 
-// Example 5: Extension Trait Pattern
-// ===================================
+impl Lifecycle for InMemoryCacheService {}
 
-// This pattern allows you to add methods to existing types
+// Distributed lock
+// ----------------
+// "Only one instance runs the cleanup job": whoever manages to create the
+// lock key first wins. The value is a random token so an instance can only
+// release a lock it still owns, and the TTL frees the lock if its owner
+// crashes.
 
-trait UserRepositoryExt: UserRepository {
-    async fn find_active_users(&self) -> Result<Vec<User>, String> {
-        // Default implementation
-        Ok(vec![])
+#[cfg(test)]
+#[async_trait]
+trait DistributedLock: Send + Sync {
+    // Create `key` only if it doesn't exist yet; true if we got it
+    async fn acquire(&self, key: &str, token: &str, ttl: Duration) -> bool;
+    // Delete `key` only if it still holds our token
+    async fn release(&self, key: &str, token: &str);
+}
+
+// The token is what proves we hold the lock, so it stays out of the spans
+#[cfg(test)]
+#[async_trait]
+impl DistributedLock for RedisCacheService {
+    #[tracing::instrument(skip(self, _token, ttl), fields(cache = "redis"))]
+    async fn acquire(&self, key: &str, _token: &str, ttl: Duration) -> bool {
+        tracing::debug!(ttl_ms = ttl.as_millis() as u64, "SET NX PX");
+        true
     }
 
-    async fn count_users(&self) -> Result<usize, String> {
-        Ok(0)
+    #[tracing::instrument(skip(self, _token), fields(cache = "redis"))]
+    async fn release(&self, key: &str, _token: &str) {
+        // In real code, a Lua script so check-and-delete is atomic:
+        // if redis.call("GET", KEYS[1]) == ARGV[1] then redis.call("DEL", KEYS[1]) end
+        tracing::debug!("EVAL compare-and-delete");
     }
 }
 
-// Any type implementing UserRepository automatically gets these methods
-impl<T: UserRepository + ?Sized> UserRepositoryExt for T {}
+#[cfg(test)]
+#[async_trait]
+impl DistributedLock for InMemoryCacheService {
+    async fn acquire(&self, key: &str, token: &str, ttl: Duration) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        if Self::live_value(&mut cache, key).is_some() {
+            return false;
+        }
+        cache.insert(
+            key.to_string(),
+            (token.to_string(), Some(Instant::now() + ttl)),
+        );
+        true
+    }
 
-// DEMONSTRATION
-// =============
+    async fn release(&self, key: &str, token: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        if Self::live_value(&mut cache, key).as_deref() == Some(token) {
+            cache.remove(key);
+        }
+    }
+}
 
-#[tokio::main]
-async fn main() {
-    println!("=== Production Setup ===");
-    let service = ServiceFactory::new("postgresql://localhost".to_string())
-        .with_redis("redis://localhost".to_string())
-        .build_user_service();
+#[cfg(test)]
+struct LockGuard {
+    locks: Arc<dyn DistributedLock>,
+    key: String,
+    token: String,
+    released: bool,
+}
 
-    let user = service.get_user("123").await.unwrap();
-    println!("User: {:?}", user);
+#[cfg(test)]
+impl LockGuard {
+    async fn release(mut self) {
+        self.released = true;
+        self.locks.release(&self.key, &self.token).await;
+    }
+}
 
-    println!("\n=== Test Setup ===");
-    let test_users = vec![
-        User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            name: "Test User".to_string(),
-        },
-    ];
-    let test_service = ServiceFactory::build_test_user_service(test_users);
+// Drop can't await, so a forgotten guard is released in the background
+#[cfg(test)]
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let locks = self.locks.clone();
+            let key = std::mem::take(&mut self.key);
+            let token = std::mem::take(&mut self.token);
+            runtime.spawn(async move { locks.release(&key, &token).await });
+        }
+    }
+}
 
-    let user = test_service.get_user("1").await.unwrap();
-    println!("Test User: {:?}", user);
+#[cfg(test)]
+impl dyn DistributedLock {
+    async fn try_lock(self: &Arc<Self>, key: &str, ttl: Duration) -> Option<LockGuard> {
+        let token = uuid::Uuid::new_v4().to_string();
+        if !self.acquire(key, &token, ttl).await {
+            return None;
+        }
+        Some(LockGuard {
+            locks: self.clone(),
+            key: key.to_string(),
+            token,
+            released: false,
+        })
+    }
+}
 
-    println!("\n=== Creating new user ===");
-    let new_user = User {
-        id: "2".to_string(),
-        email: "new@example.com".to_string(),
-        name: "New User".to_string(),
+// Every instance calls this on a timer; only one of them does the work
+#[cfg(test)]
+async fn run_cleanup_job(locks: &Arc<dyn DistributedLock>, instance: &str) -> bool {
+    let Some(guard) = locks
+        .try_lock("jobs:cleanup", Duration::from_secs(60))
+        .await
+    else {
+        tracing::info!(instance, "cleanup already running elsewhere, skipping");
+        return false;
     };
-    let created = test_service.create_user(new_user).await.unwrap();
-    println!("Created: {:?}", created);
+
+    tracing::info!(instance, "running cleanup job");
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    guard.release().await;
+    true
 }
 
+// Moka: a concurrent in-memory cache
+// ----------------------------------
+// InMemoryCacheService serialises every call on one Mutex and never evicts,
+// so it grows without bound. moka is built for concurrent access, expires
+// entries per key and evicts by total size once `max_bytes` is reached.
+// Measure before switching: bench_moka_vs_mutex_hashmap in the tests
+// compares the two (for tiny values a plain Mutex can still be faster).
+
+// The TTL travels with the value so the expiry policy can read it
 #[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Clone)]
+struct MokaEntry {
+    value: String,
+    ttl_seconds: Option<u64>,
+    // Counter updates keep the expiry set when the counter was created
+    keep_expiry: bool,
+}
 
-    #[tokio::test]
-    async fn test_user_service_get_user() {
-        let mock_users = vec![User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            name: "Test User".to_string(),
-        }];
+#[cfg(test)]
+struct PerEntryTtl;
 
-        let service = ServiceFactory::build_test_user_service(mock_users);
+#[cfg(test)]
+impl moka::Expiry<String, MokaEntry> for PerEntryTtl {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        entry: &MokaEntry,
+        _now: Instant,
+    ) -> Option<Duration> {
+        entry.ttl_seconds.map(Duration::from_secs)
+    }
 
-        let user = service.get_user("1").await.unwrap();
-        assert!(user.is_some());
-        assert_eq!(user.unwrap().email, "test@example.com");
+    // Overwriting a key restarts its TTL (except for counters)
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &MokaEntry,
+        _now: Instant,
+        remaining: Option<Duration>,
+    ) -> Option<Duration> {
+        if entry.keep_expiry {
+            remaining
+        } else {
+            entry.ttl_seconds.map(Duration::from_secs)
+        }
     }
+}
 
-    #[tokio::test]
-    async fn test_user_service_create_duplicate_email() {
-        let existing_user = User {
-            id: "1".to_string(),
-            email: "existing@example.com".to_string(),
-            name: "Existing".to_string(),
-        };
+#[cfg(test)]
+struct MokaCacheService {
+    cache: moka::future::Cache<String, MokaEntry>,
+}
 
-        let service = ServiceFactory::build_test_user_service(vec![existing_user]);
+#[cfg(test)]
+impl MokaCacheService {
+    fn new(max_bytes: u64) -> Self {
+        let cache = moka::future::Cache::builder()
+            .max_capacity(max_bytes)
+            .weigher(|key: &String, entry: &MokaEntry| {
+                u32::try_from(key.len() + entry.value.len()).unwrap_or(u32::MAX)
+            })
+            .expire_after(PerEntryTtl)
+            .build();
+        Self { cache }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl CacheService for MokaCacheService {
+    async fn get(&self, key: &str) -> Option<String> {
+        self.cache.get(key).await.map(|entry| entry.value)
+    }
 
-        let new_user = User {
-            id: "2".to_string(),
-            email: "existing@example.com".to_string(),
-            name: "New".to_string(),
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        let entry = MokaEntry {
+            value,
+            ttl_seconds,
+            keep_expiry: false,
         };
+        self.cache.insert(key.to_string(), entry).await;
+    }
 
-        let result = service.create_user(new_user).await;
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Email already exists");
+    async fn delete(&self, key: &str) {
+        self.cache.invalidate(key).await;
     }
 
-    #[tokio::test]
-    async fn test_cache_invalidation_on_delete() {
-        let mock_users = vec![User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            name: "Test".to_string(),
-        }];
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        // and_upsert_with runs the closure atomically for this key
+        let entry = self
+            .cache
+            .entry(key.to_string())
+            .and_upsert_with(|existing| {
+                let entry = match existing.map(|entry| entry.into_value()) {
+                    Some(entry) => match entry.value.parse::<i64>() {
+                        Ok(current) => MokaEntry {
+                            value: (current + by).to_string(),
+                            ttl_seconds,
+                            keep_expiry: true,
+                        },
+                        // Not a number: put back as it was, and read as 0
+                        // below, like InMemoryCacheService
+                        Err(_) => MokaEntry {
+                            keep_expiry: true,
+                            ..entry
+                        },
+                    },
+                    None => MokaEntry {
+                        value: by.to_string(),
+                        ttl_seconds,
+                        keep_expiry: true,
+                    },
+                };
+                std::future::ready(entry)
+            })
+            .await;
+        entry.into_value().value.parse().unwrap_or(0)
+    }
+}
 
-        let service = ServiceFactory::build_test_user_service(mock_users);
+#[cfg(test)]
+#[async_trait]
+impl HealthCheck for MokaCacheService {
+    async fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
 
-        // Get user to populate cache
-        let _ = service.get_user("1").await.unwrap();
+#[cfg(test)]
+impl Lifecycle for MokaCacheService {}
+
+// Two-tier cache: a fast local L1 in front of a shared L2 (e.g. Redis)
+// ---------------------------------------------------------------------
+// - get: L1 first, then L2 (and refill L1 on an L2 hit)
+// - set: write-through to both tiers
+// - L1 entries live shorter than L2 so instances don't serve stale data for long
+//
+// A refill lasts no longer than what the L2 copy has left (CacheService::ttl),
+// so L1 never serves a key L2 has already expired. An L2 that can't report
+// its TTLs gets the full L1 TTL instead: the copy may then outlive L2 by up
+// to l1_ttl_seconds, which is one more reason to keep that short.
+//
+// Because it implements CacheService itself, UserService doesn't know
+// whether it talks to one cache or two.
+
+const DEFAULT_L1_TTL_SECONDS: u64 = 60;
+
+struct TieredCache {
+    l1: Arc<dyn CacheService>,
+    l2: Arc<dyn CacheService>,
+    l1_ttl_seconds: u64,
+    // Tells other instances to drop their L1 copy when we write a key
+    invalidation_bus: Option<Arc<dyn InvalidationBus>>,
+    instance_id: String,
+}
 
-        // Delete user
-        service.delete_user("1").await.unwrap();
+impl TieredCache {
+    fn new(l1: Arc<dyn CacheService>, l2: Arc<dyn CacheService>) -> Self {
+        Self {
+            l1,
+            l2,
+            l1_ttl_seconds: DEFAULT_L1_TTL_SECONDS,
+            invalidation_bus: None,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
 
-        // Verify user is deleted
-        let user = service.get_user("1").await.unwrap();
-        assert!(user.is_none());
+    #[cfg(test)]
+    fn with_l1_ttl(mut self, l1_ttl_seconds: u64) -> Self {
+        self.l1_ttl_seconds = l1_ttl_seconds;
+        self
+    }
+
+    fn with_invalidation_bus(mut self, bus: Arc<dyn InvalidationBus>) -> Self {
+        self.invalidation_bus = Some(bus);
+        self
+    }
+
+    async fn publish_invalidation(&self, key: &str) {
+        if let Some(bus) = &self.invalidation_bus {
+            bus.publish(Invalidation {
+                origin: self.instance_id.clone(),
+                key: key.to_string(),
+            })
+            .await;
+        }
+    }
+
+    // Background task: evict keys that *other* instances changed from our L1
+    fn spawn_invalidation_listener(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let mut receiver = self.invalidation_bus.as_ref()?.subscribe();
+        let cache = Arc::clone(self);
+
+        Some(tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(message) if message.origin != cache.instance_id => {
+                        cache.l1.delete(&message.key).await;
+                    }
+                    Ok(_) => {}
+                    // We missed some messages; the short L1 TTL bounds the staleness
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }))
+    }
+
+    // L1 never outlives L2: use the shorter of the two TTLs
+    fn l1_ttl(&self, ttl_seconds: Option<u64>) -> u64 {
+        match ttl_seconds {
+            Some(ttl) => ttl.min(self.l1_ttl_seconds),
+            None => self.l1_ttl_seconds,
+        }
+    }
+
+    // For a value just read from L2: what it has left there, not what it
+    // was set with
+    async fn refill_ttl(&self, key: &str) -> Option<u64> {
+        Some(self.l1_ttl(self.l2.ttl(key).await))
+    }
+}
+
+#[async_trait]
+impl CacheService for TieredCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        if let Some(value) = self.l1.get(key).await {
+            return Some(value);
+        }
+
+        let value = self.l2.get(key).await?;
+        self.l1
+            .set(key, value.clone(), self.refill_ttl(key).await)
+            .await;
+        Some(value)
+    }
+
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        self.l2.set(key, value.clone(), ttl_seconds).await;
+        self.l1
+            .set(key, value, Some(self.l1_ttl(ttl_seconds)))
+            .await;
+        self.publish_invalidation(key).await;
+    }
+
+    async fn delete(&self, key: &str) {
+        self.l2.delete(key).await;
+        self.l1.delete(key).await;
+        self.publish_invalidation(key).await;
+    }
+
+    // L1 for everything, then a single L2 batch for whatever L1 missed
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        let mut values = self.l1.get_many(keys).await;
+
+        let missing: Vec<usize> = (0..keys.len()).filter(|&i| values[i].is_none()).collect();
+        if missing.is_empty() {
+            return values;
+        }
+
+        let missing_keys: Vec<&str> = missing.iter().map(|&i| keys[i]).collect();
+        let l2_values = self.l2.get_many(&missing_keys).await;
+
+        // One TTL lookup per L2 hit: there's no batch version of it
+        let mut refill = Vec::new();
+        for (i, value) in missing.into_iter().zip(l2_values) {
+            if let Some(value) = value {
+                refill.push((keys[i], value.clone(), self.refill_ttl(keys[i]).await));
+                values[i] = Some(value);
+            }
+        }
+        self.l1.set_many(&refill).await;
+
+        values
+    }
+
+    async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+        self.l2.set_many(entries).await;
+
+        let l1_entries: Vec<_> = entries
+            .iter()
+            .map(|(key, value, ttl)| (*key, value.clone(), Some(self.l1_ttl(*ttl))))
+            .collect();
+        self.l1.set_many(&l1_entries).await;
+
+        for (key, _, _) in entries {
+            self.publish_invalidation(key).await;
+        }
+    }
+
+    // Counters must be shared by every instance: they live in L2 only
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        self.l1.delete(key).await;
+        self.l2.incr(key, by, ttl_seconds).await
+    }
+
+    // L2's is the one that counts: L1 copies never outlive it
+    async fn ttl(&self, key: &str) -> Option<u64> {
+        self.l2.ttl(key).await
+    }
+}
+
+// Healthy only if both tiers are
+#[async_trait]
+impl HealthCheck for TieredCache {
+    async fn check(&self) -> Result<(), Error> {
+        self.l1.check().await?;
+        self.l2.check().await
+    }
+}
+
+impl Lifecycle for TieredCache {}
+
+// Cross-instance invalidation (pub/sub)
+// -------------------------------------
+// Every instance has its own L1. When instance A updates user:1, instance B
+// would keep serving its old L1 copy until the L1 TTL runs out. Publishing
+// the changed key lets every other instance evict it right away.
+
+#[derive(Debug, Clone)]
+struct Invalidation {
+    // Which instance made the change (so it can ignore its own messages)
+    origin: String,
+    key: String,
+}
+
+#[async_trait]
+trait InvalidationBus: Send + Sync {
+    async fn publish(&self, message: Invalidation);
+    fn subscribe(&self) -> broadcast::Receiver<Invalidation>;
+}
+
+const INVALIDATION_CHANNEL: &str = "cache:invalidate";
+
+struct RedisInvalidationBus {
+    redis_url: SecretString,
+    // In real code, a task reading the SUBSCRIBE connection feeds this sender
+    local: broadcast::Sender<Invalidation>,
+}
+
+impl RedisInvalidationBus {
+    fn new(redis_url: SecretString) -> Self {
+        let (local, _) = broadcast::channel(1024);
+        Self { redis_url, local }
+    }
+}
+
+#[async_trait]
+impl InvalidationBus for RedisInvalidationBus {
+    async fn publish(&self, message: Invalidation) {
+        tracing::debug!(channel = INVALIDATION_CHANNEL, key = %message.key, "Redis PUBLISH");
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Invalidation> {
+        tracing::debug!(channel = INVALIDATION_CHANNEL, url = %self.redis_url, "Redis SUBSCRIBE");
+        self.local.subscribe()
+    }
+}
+
+// In-process bus: lets tests run several "instances" side by side
+#[cfg(test)]
+struct InMemoryInvalidationBus {
+    sender: broadcast::Sender<Invalidation>,
+}
+
+#[cfg(test)]
+impl InMemoryInvalidationBus {
+    fn new() -> Self {
+        let (sender, _) = broadcast::channel(1024);
+        Self { sender }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl InvalidationBus for InMemoryInvalidationBus {
+    async fn publish(&self, message: Invalidation) {
+        // No subscribers is not an error
+        let _ = self.sender.send(message);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Invalidation> {
+        self.sender.subscribe()
+    }
+}
+
+// Single-flight: protect the repository from cache stampedes
+// ----------------------------------------------------------
+// When a hot key expires, many requests miss at the same time and all of
+// them would hit the database. get_or_compute() takes a per-key async lock so
+// only the first caller runs the loader; the others wait and then read the
+// freshly cached value.
+
+#[cfg(test)]
+struct SingleFlightCache {
+    cache: Arc<dyn CacheService>,
+    in_flight: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+#[cfg(test)]
+impl SingleFlightCache {
+    fn new(cache: Arc<dyn CacheService>) -> Self {
+        Self {
+            cache,
+            in_flight: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_or_compute<E, Fut>(
+        &self,
+        key: &str,
+        ttl_seconds: Option<u64>,
+        fut: Fut,
+    ) -> Result<String, E>
+    where
+        Fut: Future<Output = Result<String, E>>,
+    {
+        if let Some(value) = self.cache.get(key).await {
+            return Ok(value);
+        }
+
+        let key_lock = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+
+        let result = {
+            let _guard = key_lock.lock().await;
+
+            // Another caller may have filled the cache while we were waiting
+            match self.cache.get(key).await {
+                Some(value) => Ok(value),
+                None => match fut.await {
+                    Ok(value) => {
+                        self.cache.set(key, value.clone(), ttl_seconds).await;
+                        Ok(value)
+                    }
+                    Err(e) => Err(e),
+                },
+            }
+        };
+
+        // Drop the per-key lock once nobody else is waiting on it
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if Arc::strong_count(&key_lock) == 2 {
+            in_flight.remove(key);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl CacheService for SingleFlightCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        self.cache.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+        self.cache.set(key, value, ttl_seconds).await;
+    }
+
+    async fn delete(&self, key: &str) {
+        self.cache.delete(key).await;
+    }
+
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        self.cache.get_many(keys).await
+    }
+
+    async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+        self.cache.set_many(entries).await;
+    }
+
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        self.cache.incr(key, by, ttl_seconds).await
+    }
+
+    async fn ttl(&self, key: &str) -> Option<u64> {
+        self.cache.ttl(key).await
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl HealthCheck for SingleFlightCache {
+    async fn check(&self) -> Result<(), Error> {
+        self.cache.check().await
+    }
+}
+
+#[cfg(test)]
+impl Lifecycle for SingleFlightCache {}
+
+// Business logic service that depends on repository and cache
+struct UserService {
+    repository: Arc<dyn UserRepository>,
+    cache: Arc<dyn CacheService>,
+}
+
+impl UserService {
+    fn new(repository: Arc<dyn UserRepository>, cache: Arc<dyn CacheService>) -> Self {
+        Self { repository, cache }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Error> {
+        // Cache first, repository on a miss (tutorial-core's CacheServiceExt)
+        let cache_key = format!("user:{}", id);
+        self.cache
+            .get_or_set_with(&cache_key, Some(3600), || self.repository.find_by_id(id))
+            .await
+    }
+
+    // Hydrate a list of users with one cache batch, going to the
+    // repository only for the ids that weren't cached
+    #[cfg(test)]
+    #[tracing::instrument(skip(self))]
+    async fn get_users(&self, ids: &[&str]) -> Result<Vec<User>, Error> {
+        let cache_keys: Vec<String> = ids.iter().map(|id| format!("user:{}", id)).collect();
+        let key_refs: Vec<&str> = cache_keys.iter().map(String::as_str).collect();
+        let cached = self.cache.get_many(&key_refs).await;
+
+        let mut users = Vec::with_capacity(ids.len());
+        let mut to_cache = Vec::new();
+        for ((id, key), cached) in ids.iter().zip(&key_refs).zip(cached) {
+            if let Some(user) = cached.and_then(|json| serde_json::from_str(&json).ok()) {
+                users.push(user);
+            } else if let Some(user) = self.repository.find_by_id(id).await? {
+                if let Ok(json) = serde_json::to_string(&user) {
+                    to_cache.push((*key, json, Some(3600)));
+                }
+                users.push(user);
+            }
+        }
+
+        if !to_cache.is_empty() {
+            self.cache.set_many(&to_cache).await;
+        }
+
+        Ok(users)
+    }
+
+    #[tracing::instrument(skip_all, fields(user.id = %user.id, user.email = %user.email))]
+    async fn create_user(&self, user: User) -> Result<User, Error> {
+        // Check if email exists
+        if self.repository.find_by_email(&user.email).await?.is_some() {
+            return Err(Error::AlreadyExists);
+        }
+
+        // Create user
+        let created_user = self.repository.create(user).await?;
+
+        // Cache the new user
+        let cache_key = format!("user:{}", created_user.id);
+        self.cache
+            .set_json(&cache_key, &created_user, Some(3600))
+            .await;
+
+        Ok(created_user)
+    }
+
+    #[cfg(test)]
+    #[tracing::instrument(skip(self))]
+    async fn deactivate_user(&self, id: &str) -> Result<(), Error> {
+        let mut user = self
+            .repository
+            .find_by_id(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        user.active = false;
+        self.repository.update(user).await?;
+
+        // Invalidate cache, or get_user keeps returning the active copy
+        let cache_key = format!("user:{}", id);
+        self.cache.delete(&cache_key).await;
+
+        Ok(())
+    }
+}
+
+// Cache warm-up
+// -------------
+// Right after a deploy every cache is cold and the first wave of traffic
+// goes straight to the database. CacheWarmer preloads the keys we know
+// will be hot, a few at a time so the database isn't flooded either.
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct WarmupReport {
+    requested: usize,
+    loaded: usize,
+    // Ids the repository no longer knows about
+    missing: usize,
+    failed: usize,
+}
+
+#[cfg(test)]
+struct CacheWarmer {
+    repository: Arc<dyn UserRepository>,
+    cache: Arc<dyn CacheService>,
+    max_concurrency: usize,
+    ttl_seconds: Option<u64>,
+}
+
+#[cfg(test)]
+impl CacheWarmer {
+    fn new(repository: Arc<dyn UserRepository>, cache: Arc<dyn CacheService>) -> Self {
+        Self {
+            repository,
+            cache,
+            max_concurrency: 8,
+            ttl_seconds: Some(3600),
+        }
+    }
+
+    fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    // Static values (feature flags, settings...) don't need the repository
+    async fn warm_values(&self, entries: &[(&str, String)]) {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(key, value)| (*key, value.clone(), self.ttl_seconds))
+            .collect();
+        self.cache.set_many(&entries).await;
+    }
+
+    // `on_progress(done, total)` is called after every user
+    async fn warm_users(
+        &self,
+        user_ids: Vec<String>,
+        on_progress: impl Fn(usize, usize),
+    ) -> WarmupReport {
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(self.max_concurrency));
+        let mut tasks = tokio::task::JoinSet::new();
+
+        let mut report = WarmupReport {
+            requested: user_ids.len(),
+            ..WarmupReport::default()
+        };
+
+        for id in user_ids {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let repository = self.repository.clone();
+            let cache = self.cache.clone();
+            let ttl_seconds = self.ttl_seconds;
+
+            tasks.spawn(async move {
+                let _permit = permit;
+                let user = repository.find_by_id(&id).await?;
+                if let Some(ref user) = user {
+                    cache
+                        .set_json(&format!("user:{}", id), user, ttl_seconds)
+                        .await;
+                }
+                Ok::<_, Error>(user.is_some())
+            });
+        }
+
+        let mut done = 0;
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Ok(true)) => report.loaded += 1,
+                Ok(Ok(false)) => report.missing += 1,
+                Ok(Err(_)) | Err(_) => report.failed += 1,
+            }
+            done += 1;
+            on_progress(done, report.requested);
+        }
+
+        report
+    }
+}
+
+// Example 3: Axum Handler with Dependency Injection
+// =================================================
+
+// Optional extensions
+// -------------------
+// Sending a welcome email is nice to have, not something UserService should
+// require. The sender is registered in a ServiceRegistry only when SMTP is
+// configured; code that wants it checks whether it's there.
+
+#[async_trait]
+trait EmailSender: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), Error>;
+}
+
+struct SmtpEmailSender {
+    // In real code, this would be lettre::AsyncSmtpTransport
+    smtp_url: SecretString,
+}
+
+#[async_trait]
+impl EmailSender for SmtpEmailSender {
+    async fn send(&self, to: &str, subject: &str, _body: &str) -> Result<(), Error> {
+        tracing::info!(smtp = %self.smtp_url, to, subject, "sending email");
+        Ok(())
+    }
+}
+
+// Returns whether an email went out. A failed email doesn't fail the signup.
+async fn send_welcome_email(extensions: &ServiceRegistry, user: &User) -> bool {
+    let Some(sender) = extensions.get::<dyn EmailSender>() else {
+        return false;
+    };
+
+    let body = format!("Hi {}, thanks for signing up!", user.email);
+    match sender.send(&user.email, "Welcome", &body).await {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!(to = %user.email, error = %e, "welcome email failed");
+            false
+        }
+    }
+}
+
+// Composition root: the ONE place that knows concrete types.
+// config -> repositories/caches -> services -> Arc<AppState>
+//
+// Handlers only ever see AppState, so nothing below main() changes when
+// Postgres is swapped for a mock. A runnable axum server built the same way
+// is in http.rs (`cargo run --example refactoring_with_di -- --serve`);
+// with this AppState it would look like:
+//
+// let state = composition_root::build(&AppConfig::from_lookup(|key| std::env::var(key).ok())?);
+// let app = Router::new()
+//     .route("/users/:id", get(get_user_handler))
+//     .with_state(state);
+//
+// async fn get_user_handler(
+//     State(state): State<Arc<AppState>>,
+//     Path(id): Path<String>,
+// ) -> Result<Json<User>, StatusCode> {
+//     let user = state.user_service.get_user(&id).await
+//         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+//     user.ok_or(StatusCode::NOT_FOUND).map(Json)
+// }
+mod composition_root {
+    use super::*;
+    use crate::config::ConfigErrors;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AppConfig {
+        pub database_url: SecretString,
+        // No Redis: the in-memory cache is used instead
+        pub redis_url: Option<SecretString>,
+        // No SMTP: no EmailSender is registered
+        pub smtp_url: Option<SecretString>,
+    }
+
+    impl AppConfig {
+        // Takes the lookup as a function so tests don't have to touch the
+        // process environment. Every bad key is reported, not just the first.
+        pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigErrors> {
+            let mut reader = config::Reader::new(&lookup);
+
+            let database_url = reader
+                .required("DATABASE_URL")
+                .and_then(|url| reader.url("DATABASE_URL", url, &["postgres", "postgresql"]))
+                .map(SecretString::from)
+                .unwrap_or_default();
+            let redis_url = reader
+                .optional("REDIS_URL")
+                .and_then(|url| reader.url("REDIS_URL", url, &["redis", "rediss"]))
+                .map(SecretString::from);
+            let smtp_url = reader
+                .optional("SMTP_URL")
+                .and_then(|url| reader.url("SMTP_URL", url, &["smtp", "smtps"]))
+                .map(SecretString::from);
+
+            reader.finish(Self {
+                database_url,
+                redis_url,
+                smtp_url,
+            })
+        }
+    }
+
+    // Cheap to clone behind an Arc; this is what axum's State<_> holds
+    pub struct AppState {
+        pub user_service: Arc<UserService>,
+        // Optional components, looked up by trait (e.g. dyn EmailSender)
+        pub extensions: ServiceRegistry,
+    }
+
+    pub fn build(config: &AppConfig) -> Arc<AppState> {
+        let mut factory = ServiceFactory::new(config.database_url.clone());
+        if let Some(redis_url) = &config.redis_url {
+            factory = factory.with_redis(redis_url.clone());
+        }
+
+        let extensions = ServiceRegistry::new();
+        if let Some(smtp_url) = &config.smtp_url {
+            extensions.insert::<dyn EmailSender>(Arc::new(SmtpEmailSender {
+                smtp_url: smtp_url.clone(),
+            }));
+        }
+
+        Arc::new(AppState {
+            user_service: factory.build_user_service(),
+            extensions,
+        })
+    }
+
+    // Same shape, mocks inside
+    #[cfg(test)]
+    pub fn build_for_tests(users: Vec<User>) -> Arc<AppState> {
+        Arc::new(AppState {
+            user_service: ServiceFactory::build_test_user_service(users),
+            extensions: ServiceRegistry::new(),
+        })
+    }
+}
+
+// Example 4: Factory Pattern for Creating Services
+// ================================================
+
+struct ServiceFactory {
+    database_url: SecretString,
+    redis_url: Option<SecretString>,
+    use_cache: bool,
+}
+
+impl ServiceFactory {
+    fn new(database_url: SecretString) -> Self {
+        Self {
+            database_url,
+            redis_url: None,
+            use_cache: false,
+        }
+    }
+
+    fn with_redis(mut self, redis_url: SecretString) -> Self {
+        self.redis_url = Some(redis_url);
+        self.use_cache = true;
+        self
+    }
+
+    // Register every implementation once; the service is resolved from it
+    fn container(self) -> Container {
+        let mut container = Container::new();
+
+        let database_url = self.database_url;
+        container.singleton::<dyn UserRepository, _>(move |_| {
+            Arc::new(PostgresUserRepository::new(database_url.clone()))
+        });
+
+        match (self.use_cache, self.redis_url) {
+            (true, Some(redis_url)) => {
+                container.singleton::<dyn CacheService, _>(move |_| {
+                    // Local L1 in front of the shared Redis L2; what other
+                    // instances write is evicted from L1 as it's published
+                    let cache = Arc::new(
+                        TieredCache::new(
+                            Arc::new(InMemoryCacheService::new()),
+                            Arc::new(RedisCacheService {
+                                redis_url: redis_url.clone(),
+                            }),
+                        )
+                        .with_invalidation_bus(Arc::new(
+                            RedisInvalidationBus::new(redis_url.clone()),
+                        )),
+                    );
+                    cache.spawn_invalidation_listener();
+                    cache
+                })
+            }
+            _ => container
+                .singleton::<dyn CacheService, _>(|_| Arc::new(InMemoryCacheService::new())),
+        };
+
+        Self::register_user_service(&mut container);
+        container
+    }
+
+    fn test_container(mock_users: Vec<User>) -> Container {
+        let mut container = Container::new();
+        container
+            .singleton::<dyn UserRepository, _>(move |_| {
+                Arc::new(MockUserRepository::with_users(mock_users.clone()))
+            })
+            .singleton::<dyn CacheService, _>(|_| Arc::new(InMemoryCacheService::new()));
+
+        Self::register_user_service(&mut container);
+        container
+    }
+
+    // Scoped: one UserService per request, sharing the singleton repository/cache
+    fn register_user_service(container: &mut Container) {
+        container.scoped::<UserService, _>(|scope: &Scope| {
+            Arc::new(UserService::new(
+                scope.resolve().expect("UserRepository is registered"),
+                scope.resolve().expect("CacheService is registered"),
+            ))
+        });
+    }
+
+    fn resolve_user_service(container: Container) -> Arc<UserService> {
+        Arc::new(container)
+            .create_scope()
+            .resolve()
+            .expect("UserService is registered")
+    }
+
+    fn build_user_service(self) -> Arc<UserService> {
+        Self::resolve_user_service(self.container())
+    }
+
+    fn build_test_user_service(mock_users: Vec<User>) -> Arc<UserService> {
+        Self::resolve_user_service(Self::test_container(mock_users))
+    }
+}
+
+// Example 5: Extension Trait Pattern
+// ===================================
+
+// This pattern allows you to add methods to existing types
+
+trait UserRepositoryExt: UserRepository {
+    // First page only: enough for a dashboard, not for an export
+    async fn find_active_users(&self) -> Result<Vec<User>, Error> {
+        let filter = UserFilter {
+            active: Some(true),
+            ..UserFilter::default()
+        };
+        Ok(self.list(&filter, PageRequest::default()).await?.items)
+    }
+
+    async fn count_users(&self) -> Result<u64, Error> {
+        Ok(self
+            .list(&UserFilter::default(), PageRequest::new(1, 1))
+            .await?
+            .total)
+    }
+}
+
+// Any type implementing UserRepository automatically gets these methods
+impl<T: UserRepository + ?Sized> UserRepositoryExt for T {}
+
+// tutorial-core's CacheServiceExt uses the same trick to remove the
+// "check cache -> load -> fill cache" boilerplate from every service:
+// set_json and get_or_set_with work on any CacheService, with any serde
+// type, because values are stored as JSON.
+
+// Pluggable serialization
+// -----------------------
+// CacheServiceExt always stores JSON: readable in redis-cli, but verbose.
+// TypedCache takes the encoding as a dependency too, so switching to a
+// binary format is a one-line change where the cache is built.
+// Binary isn't automatically smaller: for a User made of short strings,
+// bincode's 8-byte length prefixes plus base64 lose to JSON (see
+// bench_cache_codecs). Measure with your real payloads.
+
+#[cfg(test)]
+trait CacheCodec: Send + Sync {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<String, Error>;
+    fn decode<T: DeserializeOwned>(&self, raw: &str) -> Result<T, Error>;
+}
+
+#[cfg(test)]
+struct JsonCodec;
+
+#[cfg(test)]
+impl CacheCodec for JsonCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<String, Error> {
+        serde_json::to_string(value).map_err(|e| Error::with_source("cache codec error", e))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, raw: &str) -> Result<T, Error> {
+        serde_json::from_str(raw).map_err(|e| Error::with_source("cache codec error", e))
+    }
+}
+
+// bincode bytes, base64-encoded because CacheService stores strings
+#[cfg(test)]
+struct BincodeCodec;
+
+#[cfg(test)]
+impl CacheCodec for BincodeCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<String, Error> {
+        let bytes =
+            bincode::serialize(value).map_err(|e| Error::with_source("cache codec error", e))?;
+        Ok(BASE64.encode(bytes))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, raw: &str) -> Result<T, Error> {
+        let bytes = BASE64
+            .decode(raw)
+            .map_err(|e| Error::with_source("cache codec error", e))?;
+        bincode::deserialize(&bytes).map_err(|e| Error::with_source("cache codec error", e))
+    }
+}
+
+#[cfg(test)]
+struct TypedCache<C: CacheCodec = JsonCodec> {
+    cache: Arc<dyn CacheService>,
+    codec: C,
+}
+
+#[cfg(test)]
+impl<C: CacheCodec> TypedCache<C> {
+    fn new(cache: Arc<dyn CacheService>, codec: C) -> Self {
+        Self { cache, codec }
+    }
+
+    // A value we can't decode (e.g. written by the other codec) is a miss
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let raw = self.cache.get(key).await?;
+        self.codec.decode(&raw).ok()
+    }
+
+    async fn set<T: Serialize>(&self, key: &str, value: &T, ttl_seconds: Option<u64>) {
+        if let Ok(raw) = self.codec.encode(value) {
+            self.cache.set(key, raw, ttl_seconds).await;
+        }
+    }
+
+    async fn get_or_set_with<T, E, F, Fut>(
+        &self,
+        key: &str,
+        ttl_seconds: Option<u64>,
+        load: F,
+    ) -> Result<Option<T>, E>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<T>, E>>,
+    {
+        if let Some(value) = self.get(key).await {
+            return Ok(Some(value));
+        }
+
+        let value = load().await?;
+        if let Some(ref value) = value {
+            self.set(key, value, ttl_seconds).await;
+        }
+        Ok(value)
+    }
+}
+
+// DEMONSTRATION
+// =============
+
+// The edge: everything below returns the typed Error above, and main()
+// only adds what it was doing when that failed. `{:#}` on the result
+// prints the whole chain, e.g. "creating the demo user: email already
+// exists".
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    // Debug shows every repository and cache call; RUST_LOG=info hides them
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("debug"));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+
+    println!("=== Production Setup ===");
+    // The environment wins; whatever it leaves unset points at localhost
+    let config = composition_root::AppConfig::from_lookup(|key| {
+        std::env::var(key).ok().or_else(|| match key {
+            "DATABASE_URL" => Some("postgresql://localhost".to_string()),
+            "REDIS_URL" => Some("redis://localhost".to_string()),
+            "SMTP_URL" => Some("smtp://localhost".to_string()),
+            _ => None,
+        })
+    })
+    .context("loading configuration")?;
+    let state = composition_root::build(&config);
+
+    let user = state
+        .user_service
+        .get_user("123")
+        .await
+        .context("loading user 123")?;
+    println!("User: {:?}", user);
+
+    println!("\n=== Test Setup ===");
+    let test_users = vec![User {
+        id: "1".to_string(),
+        email: "test@example.com".to_string(),
+        password_hash: String::new(),
+        role: "user".to_string(),
+        active: true,
+        avatar_url: None,
+    }];
+    let test_service = ServiceFactory::build_test_user_service(test_users);
+
+    let user = test_service
+        .get_user("1")
+        .await
+        .context("loading test user 1")?;
+    println!("Test User: {:?}", user);
+
+    println!("\n=== Creating new user ===");
+    let new_user = User {
+        id: "2".to_string(),
+        email: "new@example.com".to_string(),
+        password_hash: String::new(),
+        role: "user".to_string(),
+        active: true,
+        avatar_url: None,
+    };
+    let created = test_service
+        .create_user(new_user)
+        .await
+        .context("creating the demo user")?;
+    println!("Created: {:?}", created);
+    send_welcome_email(&state.extensions, &created).await;
+
+    println!("\n=== Extension Trait ===");
+    let active = test_service
+        .repository
+        .find_active_users()
+        .await
+        .context("listing active users")?;
+    let total = test_service
+        .repository
+        .count_users()
+        .await
+        .context("counting users")?;
+    println!("{} of {} users are active", active.len(), total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::{CacheStats, InstrumentedCache};
+    use test_support::UserBuilder;
+
+    #[test]
+    fn test_app_config_from_lookup() {
+        let env = HashMap::from([("DATABASE_URL", "postgresql://db"), ("REDIS_URL", "")]);
+        let config = composition_root::AppConfig::from_lookup(|key| {
+            env.get(key).map(|value| value.to_string())
+        })
+        .unwrap();
+
+        assert_eq!(config.database_url.expose(), "postgresql://db");
+        // Empty means "not configured"
+        assert_eq!(config.redis_url, None);
+
+        assert!(composition_root::AppConfig::from_lookup(|_| None).is_err());
+    }
+
+    #[test]
+    fn test_app_config_reports_every_bad_key() {
+        let env = HashMap::from([("REDIS_URL", "localhost:6379"), ("SMTP_URL", "mail")]);
+        let errors = composition_root::AppConfig::from_lookup(|key| {
+            env.get(key).map(|value| value.to_string())
+        })
+        .unwrap_err();
+
+        let keys: Vec<&str> = errors.0.iter().map(|e| e.key).collect();
+        assert_eq!(keys, ["DATABASE_URL", "REDIS_URL", "SMTP_URL"]);
+    }
+
+    struct RecordingEmailSender {
+        sent_to: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl EmailSender for RecordingEmailSender {
+        async fn send(&self, to: &str, _subject: &str, _body: &str) -> Result<(), Error> {
+            self.sent_to.lock().unwrap().push(to.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_welcome_email_only_when_sender_registered() {
+        let state = composition_root::build_for_tests(Vec::new());
+        let user = UserBuilder::new().email("new@example.com").build();
+
+        assert!(!send_welcome_email(&state.extensions, &user).await);
+
+        let sender = Arc::new(RecordingEmailSender {
+            sent_to: std::sync::Mutex::new(Vec::new()),
+        });
+        state.extensions.insert::<dyn EmailSender>(sender.clone());
+
+        assert!(send_welcome_email(&state.extensions, &user).await);
+        assert_eq!(*sender.sent_to.lock().unwrap(), ["new@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_composition_root_builds_state_for_handlers() {
+        let state = composition_root::build_for_tests(vec![UserBuilder::new().build()]);
+
+        // What a handler would do with State(state)
+        let handler_state = Arc::clone(&state);
+        let user = handler_state.user_service.get_user("1").await.unwrap();
+        assert_eq!(user.unwrap().email, "test@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_user_service_get_user() {
+        let mock_users = vec![UserBuilder::new().build()];
+
+        let service = ServiceFactory::build_test_user_service(mock_users);
+
+        let user = service.get_user("1").await.unwrap();
+        assert!(user.is_some());
+        assert_eq!(user.unwrap().email, "test@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_user_service_create_duplicate_email() {
+        let existing_user = UserBuilder::new().email("existing@example.com").build();
+
+        let service = ServiceFactory::build_test_user_service(vec![existing_user]);
+
+        let new_user = UserBuilder::new()
+            .id("2")
+            .email("existing@example.com")
+            .build();
+
+        let result = service.create_user(new_user).await;
+        assert!(matches!(result, Err(Error::AlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn test_errors_reach_main_with_their_types() {
+        let existing = UserBuilder::new().email("existing@example.com").build();
+        let service = ServiceFactory::build_test_user_service(vec![existing.clone()]);
+
+        let error = service
+            .create_user(existing)
+            .await
+            .map_err(|e| anyhow::Error::from(e).context("creating the demo user"))
+            .unwrap_err();
+
+        // Still the domain error under the context, not its message
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::AlreadyExists)
+        ));
+        assert_eq!(
+            format!("{:#}", error),
+            "creating the demo user: resource already exists"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_invalidation_on_deactivate() {
+        let mock_users = vec![UserBuilder::new().build()];
+
+        let service = ServiceFactory::build_test_user_service(mock_users);
+
+        // Get user to populate cache
+        let _ = service.get_user("1").await.unwrap();
+
+        service.deactivate_user("1").await.unwrap();
+
+        // The cached active copy is gone
+        let user = service.get_user("1").await.unwrap().unwrap();
+        assert!(!user.active);
+    }
+
+    #[tokio::test]
+    async fn test_tiered_cache_writes_through_both_tiers() {
+        let l1 = Arc::new(InMemoryCacheService::new());
+        let l2 = Arc::new(InMemoryCacheService::new());
+        let cache = TieredCache::new(l1.clone(), l2.clone());
+
+        cache.set("user:1", "1".to_string(), Some(3600)).await;
+
+        assert_eq!(l1.get("user:1").await, Some("1".to_string()));
+        assert_eq!(l2.get("user:1").await, Some("1".to_string()));
+
+        cache.delete("user:1").await;
+
+        assert!(l1.get("user:1").await.is_none());
+        assert!(l2.get("user:1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tiered_cache_refills_l1_from_l2() {
+        let l1 = Arc::new(InMemoryCacheService::new());
+        let l2 = Arc::new(InMemoryCacheService::new());
+        l2.set("user:1", "1".to_string(), None).await;
+
+        let cache = TieredCache::new(l1.clone(), l2.clone());
+
+        assert_eq!(cache.get("user:1").await, Some("1".to_string()));
+        assert_eq!(l1.get("user:1").await, Some("1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tiered_cache_l1_expires_before_l2() {
+        let l1 = Arc::new(InMemoryCacheService::new());
+        let l2 = Arc::new(InMemoryCacheService::new());
+        let cache = TieredCache::new(l1.clone(), l2.clone()).with_l1_ttl(0);
+
+        cache.set("user:1", "1".to_string(), Some(3600)).await;
+
+        // L1 entry is already expired, L2 still serves the value
+        assert!(l1.get("user:1").await.is_none());
+        assert_eq!(cache.get("user:1").await, Some("1".to_string()));
+    }
+
+    // The refilled L1 copy gets what L2 has left, not the 60s L1 TTL
+    #[tokio::test]
+    async fn test_tiered_cache_refill_expires_with_l2() {
+        let l1 = Arc::new(InMemoryCacheService::new());
+        let l2 = Arc::new(InMemoryCacheService::new());
+        let cache = TieredCache::new(l1.clone(), l2.clone());
+        l2.set("user:1", "1".to_string(), Some(1)).await;
+        l2.set("user:2", "2".to_string(), Some(1)).await;
+        l2.set("user:3", "3".to_string(), Some(3600)).await;
+
+        assert_eq!(cache.get("user:1").await, Some("1".to_string()));
+        assert_eq!(cache.get_many(&["user:2", "user:3"]).await.len(), 2);
+        assert_eq!(l1.ttl("user:3").await, Some(DEFAULT_L1_TTL_SECONDS - 1));
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(l2.get("user:1").await.is_none());
+        assert!(cache.get("user:1").await.is_none());
+        assert_eq!(
+            cache.get_many(&["user:2", "user:3"]).await,
+            vec![None, Some("3".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_coalesces_concurrent_misses() {
+        let cache = Arc::new(SingleFlightCache::new(
+            Arc::new(InMemoryCacheService::new()),
+        ));
+        let loads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for _ in 0..10 {
+            let cache = cache.clone();
+            let loads = loads.clone();
+            handles.push(tokio::spawn(async move {
+                cache
+                    .get_or_compute::<String, _>("user:1", Some(60), async {
+                        loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        Ok("1".to_string())
+                    })
+                    .await
+            }));
+        }
+
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), Ok("1".to_string()));
+        }
+        assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(cache.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_does_not_cache_errors() {
+        let cache = SingleFlightCache::new(Arc::new(InMemoryCacheService::new()));
+
+        let result = cache
+            .get_or_compute("user:1", None, async { Err("db down".to_string()) })
+            .await;
+        assert_eq!(result, Err("db down".to_string()));
+        assert!(cache.in_flight.lock().unwrap().is_empty());
+
+        let result = cache
+            .get_or_compute::<String, _>("user:1", None, async { Ok("1".to_string()) })
+            .await;
+        assert_eq!(result, Ok("1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_or_set_with_loads_once_then_hits_cache() {
+        let cache = InMemoryCacheService::new();
+        let loads = std::sync::atomic::AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok::<_, String>(Some(UserBuilder::new().build()))
+        };
+
+        let first: Option<User> = cache.get_or_set_with("user:1", None, load).await.unwrap();
+        let second: Option<User> = cache.get_or_set_with("user:1", None, load).await.unwrap();
+
+        assert_eq!(first.unwrap().email, "test@example.com");
+        assert_eq!(second.unwrap().email, "test@example.com");
+        assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_set_with_does_not_cache_missing_values() {
+        let cache = InMemoryCacheService::new();
+
+        let user: Option<User> = cache
+            .get_or_set_with("user:404", None, || async { Ok::<_, String>(None) })
+            .await
+            .unwrap();
+
+        assert!(user.is_none());
+        assert!(cache.get("user:404").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_get_many_and_set_many() {
+        let cache = InMemoryCacheService::new();
+
+        cache
+            .set_many(&[
+                ("a", "1".to_string(), None),
+                ("b", "2".to_string(), Some(0)),
+            ])
+            .await;
+
+        // "b" was stored with a zero TTL and is already expired
+        let values = cache.get_many(&["a", "b", "c"]).await;
+        assert_eq!(values, vec![Some("1".to_string()), None, None]);
+    }
+
+    #[tokio::test]
+    async fn test_tiered_get_many_falls_back_to_l2_for_misses() {
+        let l1 = Arc::new(InMemoryCacheService::new());
+        let l2 = Arc::new(InMemoryCacheService::new());
+        l1.set("a", "1".to_string(), None).await;
+        l2.set("b", "2".to_string(), None).await;
+
+        let cache = TieredCache::new(l1.clone(), l2.clone());
+        let values = cache.get_many(&["a", "b", "c"]).await;
+
+        assert_eq!(
+            values,
+            vec![Some("1".to_string()), Some("2".to_string()), None]
+        );
+        assert_eq!(l1.get("b").await, Some("2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_users_hydrates_from_cache_and_repository() {
+        let mock_users = vec![
+            UserBuilder::new().email("one@example.com").build(),
+            UserBuilder::new().id("2").email("two@example.com").build(),
+        ];
+        let service = ServiceFactory::build_test_user_service(mock_users);
+
+        // Warm the cache for user 1 only
+        service.get_user("1").await.unwrap();
+
+        let users = service.get_users(&["1", "2", "404"]).await.unwrap();
+        let ids: Vec<&str> = users.iter().map(|u| u.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+        assert!(service.cache.get("user:2").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_instrumented_cache_counts_operations() {
+        let cache = InstrumentedCache::new(Arc::new(InMemoryCacheService::new()));
+
+        cache.get("user:1").await;
+        cache.set("user:1", "1".to_string(), None).await;
+        cache.get("user:1").await;
+        cache.get_many(&["user:1", "user:2"]).await;
+        cache.delete("user:1").await;
+
+        let stats = cache.stats();
+        assert_eq!(
+            stats,
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                sets: 1,
+                evictions: 1,
+            }
+        );
+        assert_eq!(stats.hit_ratio(), 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_evicts_other_instances_l1() {
+        let bus: Arc<dyn InvalidationBus> = Arc::new(InMemoryInvalidationBus::new());
+        let shared_l2: Arc<dyn CacheService> = Arc::new(InMemoryCacheService::new());
+
+        let instance_a = Arc::new(
+            TieredCache::new(Arc::new(InMemoryCacheService::new()), shared_l2.clone())
+                .with_invalidation_bus(bus.clone()),
+        );
+        let b_l1 = Arc::new(InMemoryCacheService::new());
+        let instance_b = Arc::new(
+            TieredCache::new(b_l1.clone(), shared_l2.clone()).with_invalidation_bus(bus.clone()),
+        );
+        let listener = instance_b.spawn_invalidation_listener().unwrap();
+
+        instance_a.set("user:1", "v1".to_string(), None).await;
+        assert_eq!(instance_b.get("user:1").await, Some("v1".to_string()));
+
+        // A changes the user; B must not keep serving v1 from its L1
+        instance_a.set("user:1", "v2".to_string(), None).await;
+        for _ in 0..100 {
+            if b_l1.get("user:1").await.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(instance_b.get("user:1").await, Some("v2".to_string()));
+
+        listener.abort();
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_ignores_own_messages() {
+        let bus: Arc<dyn InvalidationBus> = Arc::new(InMemoryInvalidationBus::new());
+        let l1 = Arc::new(InMemoryCacheService::new());
+        let cache = Arc::new(
+            TieredCache::new(l1.clone(), Arc::new(InMemoryCacheService::new()))
+                .with_invalidation_bus(bus),
+        );
+        let listener = cache.spawn_invalidation_listener().unwrap();
+
+        cache.set("user:1", "1".to_string(), None).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(l1.get("user:1").await, Some("1".to_string()));
+        listener.abort();
+    }
+
+    #[test]
+    fn test_memcached_rejects_values_outside_its_limits() {
+        let cache = MemcachedCacheService::new(vec!["localhost:11211".to_string()]);
+
+        assert_eq!(cache.try_set("user:1", "1", Some(60)), Ok(()));
+        assert_eq!(
+            cache.try_set(&"k".repeat(251), "1", None),
+            Err(MemcachedError::KeyTooLong { len: 251 })
+        );
+        assert_eq!(
+            cache.try_set("user 1", "1", None),
+            Err(MemcachedError::InvalidKey {
+                key: "user 1".to_string()
+            })
+        );
+        assert_eq!(
+            cache.try_set("user:1", &"x".repeat(MEMCACHED_MAX_VALUE_BYTES + 1), None),
+            Err(MemcachedError::ValueTooLarge {
+                size: MEMCACHED_MAX_VALUE_BYTES + 1
+            })
+        );
+        assert_eq!(
+            cache.try_set("user:1", "1", Some(MEMCACHED_MAX_TTL_SECONDS + 1)),
+            Err(MemcachedError::TtlTooLong {
+                ttl_seconds: MEMCACHED_MAX_TTL_SECONDS + 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memcached_get_many_skips_invalid_keys() {
+        let cache = MemcachedCacheService::new(vec!["localhost:11211".to_string()]);
+        let long_key = "k".repeat(251);
+        let keys = ["user:1", "user 2", &long_key, "user:3"];
+
+        assert_eq!(
+            MemcachedCacheService::valid_keys(&keys),
+            vec!["user:1", "user:3"]
+        );
+        // Still one result per key asked for
+        assert_eq!(cache.get_many(&keys).await, vec![None; 4]);
+
+        // A real error, so it can be a source or go through `?`
+        let error: Box<dyn std::error::Error> = Box::new(MemcachedError::KeyTooLong { len: 251 });
+        assert_eq!(
+            error.to_string(),
+            "key is 251 bytes, memcached allows at most 250"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_moka_cache_expires_per_entry() {
+        let cache = MokaCacheService::new(1024 * 1024);
+
+        cache.set("short", "1".to_string(), Some(0)).await;
+        cache.set("long", "2".to_string(), Some(3600)).await;
+        cache.set("forever", "3".to_string(), None).await;
+
+        assert!(cache.get("short").await.is_none());
+        assert_eq!(cache.get("long").await, Some("2".to_string()));
+        assert_eq!(cache.get("forever").await, Some("3".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_moka_cache_evicts_by_weight() {
+        let cache = MokaCacheService::new(100);
+
+        for i in 0..50 {
+            cache
+                .set(&format!("user:{}", i), "x".repeat(10), None)
+                .await;
+        }
+        cache.cache.run_pending_tasks().await;
+
+        assert!(cache.cache.weighted_size() <= 100);
+    }
+
+    // cargo test --example real_world_di -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn bench_moka_vs_mutex_hashmap() {
+        async fn run(name: &str, cache: Arc<dyn CacheService>) {
+            for i in 0..1_000 {
+                cache.set(&format!("user:{}", i), i.to_string(), None).await;
+            }
+
+            let started = Instant::now();
+            let mut handles = Vec::new();
+            for task in 0..8 {
+                let cache = cache.clone();
+                handles.push(tokio::spawn(async move {
+                    for i in 0..50_000 {
+                        cache.get(&format!("user:{}", (i + task) % 1_000)).await;
+                    }
+                }));
+            }
+            for handle in handles {
+                handle.await.unwrap();
+            }
+            println!("{:<12} 400k concurrent gets: {:?}", name, started.elapsed());
+        }
+
+        run("Mutex<Map>", Arc::new(InMemoryCacheService::new())).await;
+        run("moka", Arc::new(MokaCacheService::new(64 * 1024 * 1024))).await;
+    }
+
+    #[tokio::test]
+    async fn test_cache_warmer_preloads_users_and_reports_progress() {
+        let repository: Arc<dyn UserRepository> = Arc::new(MockUserRepository::with_users(vec![
+            UserBuilder::new().email("one@example.com").build(),
+            UserBuilder::new().id("2").email("two@example.com").build(),
+        ]));
+        let cache = Arc::new(InMemoryCacheService::new());
+        let progress = std::sync::Mutex::new(Vec::new());
+
+        let report = CacheWarmer::new(repository, cache.clone())
+            .with_max_concurrency(2)
+            .warm_users(
+                vec!["1".to_string(), "2".to_string(), "404".to_string()],
+                |done, total| progress.lock().unwrap().push((done, total)),
+            )
+            .await;
+
+        assert_eq!(
+            report,
+            WarmupReport {
+                requested: 3,
+                loaded: 2,
+                missing: 1,
+                failed: 0,
+            }
+        );
+        assert_eq!(*progress.lock().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+        assert!(cache.get("user:1").await.is_some());
+        assert!(cache.get("user:2").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cache_warmer_preloads_config_values() {
+        let cache = Arc::new(InMemoryCacheService::new());
+        let warmer = CacheWarmer::new(Arc::new(MockUserRepository::new()), cache.clone());
+
+        warmer
+            .warm_values(&[("config:signup_enabled", "true".to_string())])
+            .await;
+
+        assert_eq!(
+            cache.get("config:signup_enabled").await,
+            Some("true".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_incr_and_decr() {
+        let cache = InMemoryCacheService::new();
+
+        assert_eq!(cache.incr("login_failures:a@b.c", 1, Some(900)).await, 1);
+        assert_eq!(cache.incr("login_failures:a@b.c", 2, Some(900)).await, 3);
+        assert_eq!(cache.decr("login_failures:a@b.c", 1, Some(900)).await, 2);
+        assert_eq!(
+            cache.get("login_failures:a@b.c").await,
+            Some("2".to_string())
+        );
+
+        // An expired counter starts over
+        cache.incr("window", 5, Some(0)).await;
+        assert_eq!(cache.incr("window", 1, Some(60)).await, 1);
+    }
+
+    // Redis answers INCRBY on a string with an error, which RedisCache
+    // reads as 0. The in-memory backends do the same, and leave the value.
+    #[tokio::test]
+    async fn test_incr_leaves_a_non_numeric_value_alone() {
+        let caches: Vec<Arc<dyn CacheService>> = vec![
+            Arc::new(InMemoryCacheService::new()),
+            Arc::new(MokaCacheService::new(1024 * 1024)),
+        ];
+
+        for cache in caches {
+            cache.set("flag", "on".to_string(), Some(60)).await;
+            assert_eq!(cache.incr("flag", 1, Some(900)).await, 0);
+            assert_eq!(cache.get("flag").await, Some("on".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_incr_does_not_lose_updates() {
+        let caches: Vec<Arc<dyn CacheService>> = vec![
+            Arc::new(InMemoryCacheService::new()),
+            Arc::new(MokaCacheService::new(1024 * 1024)),
+        ];
+
+        for cache in caches {
+            let mut handles = Vec::new();
+            for _ in 0..20 {
+                let cache = cache.clone();
+                handles.push(tokio::spawn(async move {
+                    for _ in 0..50 {
+                        cache.incr("hits", 1, None).await;
+                    }
+                }));
+            }
+            for handle in handles {
+                handle.await.unwrap();
+            }
+
+            assert_eq!(cache.get("hits").await, Some("1000".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_one_instance_runs_the_cleanup_job() {
+        let locks: Arc<dyn DistributedLock> = Arc::new(InMemoryCacheService::new());
+
+        let (a, b) = tokio::join!(
+            run_cleanup_job(&locks, "instance-a"),
+            run_cleanup_job(&locks, "instance-b")
+        );
+        assert!(a ^ b);
+
+        // The lock was released, so the next run can take it again
+        assert!(run_cleanup_job(&locks, "instance-b").await);
+    }
+
+    #[tokio::test]
+    async fn test_lock_expires_and_stale_owner_cannot_release() {
+        let cache = Arc::new(InMemoryCacheService::new());
+        let locks: Arc<dyn DistributedLock> = cache.clone();
+
+        let stale = locks
+            .try_lock("jobs:cleanup", Duration::ZERO)
+            .await
+            .unwrap();
+
+        // The TTL ran out, someone else takes over
+        let current = locks
+            .try_lock("jobs:cleanup", Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        // Releasing the stale guard must not free the new owner's lock
+        stale.release().await;
+        assert!(
+            locks
+                .try_lock("jobs:cleanup", Duration::from_secs(60))
+                .await
+                .is_none()
+        );
+
+        current.release().await;
+        assert!(cache.get("jobs:cleanup").await.is_none());
+    }
+
+    fn sample_user() -> User {
+        UserBuilder::new()
+            .id("0193b6f2-7c1e-7d2a-9f3b-1a2b3c4d5e6f")
+            .email("nguyen.van.a@example.com")
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_typed_cache_round_trips_with_each_codec() {
+        let json = TypedCache::new(Arc::new(InMemoryCacheService::new()), JsonCodec);
+        json.set("user:1", &sample_user(), None).await;
+        let user: User = json.get("user:1").await.unwrap();
+        assert_eq!(user, sample_user());
+
+        let bincode = TypedCache::new(Arc::new(InMemoryCacheService::new()), BincodeCodec);
+        bincode.set("user:1", &sample_user(), None).await;
+        let user: User = bincode.get("user:1").await.unwrap();
+        assert_eq!(user.email, "nguyen.van.a@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_typed_cache_treats_foreign_encoding_as_miss() {
+        let cache: Arc<dyn CacheService> = Arc::new(InMemoryCacheService::new());
+        TypedCache::new(cache.clone(), JsonCodec)
+            .set("user:1", &sample_user(), None)
+            .await;
+
+        let bincode = TypedCache::new(cache, BincodeCodec);
+        let user: Option<User> = bincode
+            .get_or_set_with("user:1", None, || async {
+                Ok::<_, String>(Some(sample_user()))
+            })
+            .await
+            .unwrap();
+
+        // Reloaded and re-cached in the new format
+        assert!(user.is_some());
+        assert!(bincode.get::<User>("user:1").await.is_some());
+    }
+
+    #[test]
+    fn test_codec_errors_keep_their_cause() {
+        let error = JsonCodec.decode::<User>("not json").unwrap_err();
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(source.is::<serde_json::Error>());
+
+        let error = BincodeCodec.decode::<User>("%%%").unwrap_err();
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(source.is::<base64::DecodeError>());
+    }
+
+    // cargo test --example real_world_di -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_cache_codecs() {
+        fn run<C: CacheCodec>(name: &str, codec: C) {
+            let user = sample_user();
+            let encoded = codec.encode(&user).unwrap();
+
+            let started = Instant::now();
+            for _ in 0..100_000 {
+                codec.encode(&user).unwrap();
+            }
+            let encode_time = started.elapsed();
+
+            let started = Instant::now();
+            for _ in 0..100_000 {
+                codec.decode::<User>(&encoded).unwrap();
+            }
+            let decode_time = started.elapsed();
+
+            println!(
+                "{:<8} {:>4} bytes   100k encodes: {:?}   100k decodes: {:?}",
+                name,
+                encoded.len(),
+                encode_time,
+                decode_time
+            );
+        }
+
+        run("json", JsonCodec);
+        run("bincode", BincodeCodec);
     }
 }
@@ -4,36 +4,54 @@
 // This shows how to refactor code similar to your biz crate to use DI
 
 use async_trait::async_trait;
+use clap::Parser;
+use config::SecretString;
+use container::{Container, Lazy, Scope, Swappable};
+use serde::Serialize;
+use std::future::Future;
 use std::sync::Arc;
+use tutorial_core::{Error, ErrorCode, User, ValidationError};
 
-// Models
-#[derive(Debug, Clone)]
-struct User {
-    id: String,
-    email: String,
-    password_hash: String,
-    role: String,
-}
+#[cfg(all(test, feature = "bench"))]
+mod bench;
+mod claims;
+mod config;
+mod container;
+#[cfg(test)]
+mod e2e;
+mod grpc;
+mod http;
+#[cfg(all(test, feature = "integration"))]
+mod integration;
+mod logging;
+mod messages;
+mod metrics;
+#[cfg(test)]
+mod test_app;
+
+// The model, Error and the traits below come from tutorial-core, shared
+// with the other examples. What's here is what only this example needs.
 
-#[derive(Debug)]
-enum Error {
-    NotFound,
-    AlreadyExists,
-    InvalidCredentials,
-    Internal(String),
+// Malformed or expired, it's the client's token that's wrong
+impl From<claims::ClaimsError> for Error {
+    fn from(_: claims::ClaimsError) -> Self {
+        Error::InvalidCredentials
+    }
 }
 
 // ===================================================================
 // BEFORE: Concrete dependencies (hard to test)
 // ===================================================================
 
+// The code as it was, to compare against; nothing runs it
+#[allow(dead_code)]
 mod before {
     use super::*;
 
     // Service directly depends on concrete types
     pub struct AuthService {
-        database_pool: String,  // Pretend this is sqlx::PgPool
-        redis_url: String,      // Pretend this is redis::Client
+        database_pool: String, // Pretend this is sqlx::PgPool
+        redis_url: String,     // Pretend this is redis::Client
     }
 
     impl AuthService {
@@ -45,12 +63,12 @@ mod before {
         }
 
         // ❌ Problem: Can't test without real database
-        pub async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+        pub async fn login(&self, email: &str, _password: &str) -> Result<String, Error> {
             // Direct database query - hard to mock
-            println!("Querying database: {}", self.database_pool);
+            tracing::info!(email, "Querying database: {}", self.database_pool);
 
             // Direct Redis access - hard to mock
-            println!("Checking Redis: {}", self.redis_url);
+            tracing::info!("Checking Redis: {}", self.redis_url);
 
             // Business logic mixed with data access
             Ok("fake_token".to_string())
@@ -58,12 +76,14 @@ mod before {
 
         // ❌ Problem: Can't test without real database
         pub async fn register(&self, email: &str, password: &str) -> Result<User, Error> {
-            println!("Inserting into database: {}", self.database_pool);
+            tracing::info!("Inserting into database: {}", self.database_pool);
             Ok(User {
                 id: "1".to_string(),
                 email: email.to_string(),
                 password_hash: password.to_string(),
                 role: "user".to_string(),
+                active: true,
+                avatar_url: None,
             })
         }
     }
@@ -78,41 +98,156 @@ mod after {
 
     // 1. Define abstractions (traits)
     // ================================
+    //
+    // They live in tutorial-core; re-exported so `after::*` still brings in
+    // everything a test or a transport needs.
+    pub use tutorial_core::{
+        AuditEntry, AuditFilter, AuditRepository, CacheService, CacheServiceExt, Clock,
+        ErrorContext, HealthCheck, IdGenerator, Lifecycle, Metrics, Page, PageRequest,
+        PasswordHasher, SessionStore, StorageService, TokenService, UserFilter, UserRepository,
+    };
+    // The Metrics backends, shared with real_world_di
+    pub use crate::metrics::{InstrumentedCache, NoopMetrics, PrometheusMetrics, StatsdMetrics};
 
-    #[async_trait]
-    pub trait UserRepository: Send + Sync {
-        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error>;
-        async fn create(&self, user: User) -> Result<User, Error>;
-        async fn update(&self, user: User) -> Result<User, Error>;
+    // An Internal error as an error tracker sees it: the whole chain of
+    // causes, the request it broke, and where it was made when a backtrace
+    // was captured
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ErrorEvent {
+        pub message: String,
+        pub code: &'static str,
+        pub transient: bool,
+        pub request_id: Option<String>,
+        pub backtrace: Option<String>,
     }
 
-    #[async_trait]
-    pub trait PasswordHasher: Send + Sync {
-        fn hash(&self, password: &str) -> String;
-        fn verify(&self, password: &str, hash: &str) -> bool;
+    impl ErrorEvent {
+        pub fn new(error: &Error) -> Self {
+            Self {
+                message: error.report(),
+                code: error.code().as_str(),
+                transient: error.is_retryable(),
+                request_id: crate::logging::current_request_id(),
+                backtrace: error.backtrace().map(ToString::to_string),
+            }
+        }
     }
 
-    #[async_trait]
-    pub trait TokenService: Send + Sync {
-        async fn generate(&self, user_id: &str) -> Result<String, Error>;
-        async fn validate(&self, token: &str) -> Result<String, Error>;
+    // Somewhere Internal errors go besides the log (Sentry, say). Called
+    // from the transports, where an Internal error becomes a 500, so `report`
+    // doesn't wait: an implementation that sends something spawns it.
+    pub trait ErrorReporter: Send + Sync {
+        fn report(&self, event: ErrorEvent);
     }
 
-    #[async_trait]
-    pub trait CacheService: Send + Sync {
-        async fn get(&self, key: &str) -> Option<String>;
-        async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>);
-        async fn delete(&self, key: &str);
+    tokio::task_local! {
+        // Who hears about Internal errors surfacing in the current request
+        static ERROR_REPORTER: Arc<dyn ErrorReporter>;
+    }
+
+    // What http.rs and grpc.rs wrap each request in. `IntoResponse` and
+    // `From<Error> for Status` can't reach the container, so the reporter
+    // rides along with the request instead, the way the error language does.
+    pub async fn reporting_errors_to<F: Future>(
+        reporter: Arc<dyn ErrorReporter>,
+        request: F,
+    ) -> F::Output {
+        ERROR_REPORTER.scope(reporter, request).await
+    }
+
+    // Nothing happens outside `reporting_errors_to`; the error is still logged
+    pub fn report_error(error: &Error) {
+        let _ = ERROR_REPORTER.try_with(|reporter| reporter.report(ErrorEvent::new(error)));
+    }
+
+    // What the transports log, and hand to the ErrorReporter, before
+    // answering with a bare 500
+    pub fn log_internal(error: &Error) {
+        tracing::error!(
+            error = %error.report(),
+            backtrace = error.backtrace().map(tracing::field::display),
+            "internal error"
+        );
+        report_error(error);
     }
 
     // 2. Service depends on abstractions
     // ===================================
 
+    // What AuthService accepts as a new password. The HTTP DTO only checks
+    // the length; rules that need the email too, or that every transport
+    // must apply, live here.
+    #[derive(Debug, Clone, Copy)]
+    pub struct PasswordPolicy {
+        pub min_length: usize,
+        pub max_length: usize,
+        // "aaaaaaaa" and "abababab" are long enough and still guessable
+        pub min_distinct_chars: usize,
+    }
+
+    impl Default for PasswordPolicy {
+        fn default() -> Self {
+            Self {
+                min_length: 8,
+                max_length: 128,
+                min_distinct_chars: 4,
+            }
+        }
+    }
+
+    impl PasswordPolicy {
+        // Every rule the password breaks, not just the first
+        pub fn check(&self, email: &str, password: &str) -> Result<(), Error> {
+            let mut errors = Vec::new();
+            let length = password.chars().count();
+            if length < self.min_length || length > self.max_length {
+                errors.push(ValidationError::new(
+                    "password",
+                    "length",
+                    format!(
+                        "must be {} to {} characters",
+                        self.min_length, self.max_length
+                    ),
+                ));
+            }
+            let distinct: std::collections::HashSet<char> = password.chars().collect();
+            if distinct.len() < self.min_distinct_chars {
+                errors.push(ValidationError::new(
+                    "password",
+                    "too_simple",
+                    format!(
+                        "must use at least {} different characters",
+                        self.min_distinct_chars
+                    ),
+                ));
+            }
+            let local_part = email.split('@').next().unwrap_or_default();
+            let lowercase = password.to_lowercase();
+            if !local_part.is_empty() && lowercase.contains(&local_part.to_lowercase()) {
+                errors.push(ValidationError::new(
+                    "password",
+                    "contains_email",
+                    "must not contain the email address",
+                ));
+            }
+
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::Validation(errors))
+            }
+        }
+    }
+
     pub struct AuthService {
         repository: Arc<dyn UserRepository>,
         hasher: Arc<dyn PasswordHasher>,
         token_service: Arc<dyn TokenService>,
         cache: Arc<dyn CacheService>,
+        events: Arc<EventBus>,
+        metrics: Arc<dyn Metrics>,
+        ids: Arc<dyn IdGenerator>,
+        password_policy: PasswordPolicy,
     }
 
     impl AuthService {
@@ -127,42 +262,97 @@ mod after {
                 hasher,
                 token_service,
                 cache,
+                events: Arc::new(EventBus::new(EventBus::DEFAULT_CAPACITY)),
+                metrics: Arc::new(NoopMetrics),
+                ids: Arc::new(UuidV7Generator),
+                password_policy: PasswordPolicy::default(),
             }
         }
 
-        // ✅ Easy to test with mocks
-        pub async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
-            // Check cache first
-            let cache_key = format!("user:email:{}", email);
-            if let Some(_cached_user) = self.cache.get(&cache_key).await {
-                // In real code, deserialize and use cached user
-            }
+        // Without this, events go to a bus nobody listens to
+        pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+            self.events = events;
+            self
+        }
 
-            // Find user
-            let user = self
-                .repository
-                .find_by_email(email)
-                .await?
-                .ok_or(Error::InvalidCredentials)?;
+        // auth_logins_total{outcome} and auth_registrations_total
+        pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+            self.metrics = metrics;
+            self
+        }
 
-            // Verify password
-            if !self.hasher.verify(password, &user.password_hash) {
-                return Err(Error::InvalidCredentials);
-            }
+        // IDs for registered users
+        pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+            self.ids = ids;
+            self
+        }
+
+        #[cfg(test)]
+        pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+            self.password_policy = password_policy;
+            self
+        }
+
+        // ✅ Easy to test with mocks
+        #[tracing::instrument(skip(self, password))]
+        pub async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+            let user = self.authenticate(email, password).await?;
 
             // Generate token
             let token = self.token_service.generate(&user.id).await?;
 
-            // Cache the user
-            self.cache.set(&cache_key, user.id.clone(), Some(3600)).await;
-
             Ok(token)
         }
 
+        // The password check behind login, for callers that hand out
+        // something other than a token (SessionService)
+        #[tracing::instrument(skip(self, password))]
+        pub async fn authenticate(&self, email: &str, password: &str) -> Result<User, Error> {
+            let checked = async {
+                // Find user (cache first, repository on a miss). The cached
+                // copy has the password hash, since that's what is checked:
+                // change_password and AdminService::save delete it, or the
+                // old password and a deactivated account would work until
+                // it expired.
+                let cache_key = format!("user:email:{}", email);
+                let user: User = self
+                    .cache
+                    .get_or_set_with(&cache_key, Some(3600), || {
+                        self.repository.find_by_email(email)
+                    })
+                    .await?
+                    .ok_or(Error::InvalidCredentials)?;
+
+                // Verify password
+                if !user.active || !self.hasher.verify(password, &user.password_hash) {
+                    return Err(Error::InvalidCredentials);
+                }
+                Ok(user)
+            }
+            .await;
+
+            let outcome = match &checked {
+                Ok(_) => "success",
+                Err(Error::InvalidCredentials) => "invalid_credentials",
+                Err(_) => "error",
+            };
+            self.metrics
+                .counter("auth_logins_total", &[("outcome", outcome)], 1);
+
+            let user = checked?;
+            self.events.publish(DomainEvent::LoggedIn {
+                user_id: user.id.clone(),
+            });
+            Ok(user)
+        }
+
         // ✅ Easy to test with mocks
+        #[tracing::instrument(skip(self, password))]
         pub async fn register(&self, email: &str, password: &str) -> Result<User, Error> {
+            self.password_policy.check(email, password)?;
+
             // Check if user exists
-            if let Some(_) = self.repository.find_by_email(email).await? {
+            if self.repository.find_by_email(email).await?.is_some() {
                 return Err(Error::AlreadyExists);
             }
 
@@ -171,19 +361,27 @@ mod after {
 
             // Create user
             let user = User {
-                id: uuid::Uuid::new_v4().to_string(),
+                id: self.ids.next_id(),
                 email: email.to_string(),
                 password_hash,
                 role: "user".to_string(),
+                active: true,
+                avatar_url: None,
             };
 
             // Save to database
-            let created_user = self.repository.create(user).await?;
+            let created_user = self
+                .repository
+                .create(user)
+                .await
+                .context("creating user")?;
 
+            self.metrics.counter("auth_registrations_total", &[], 1);
             Ok(created_user)
         }
 
         // ✅ New feature: Change password (easy to add)
+        #[tracing::instrument(skip(self, old_password, new_password))]
         pub async fn change_password(
             &self,
             user_id: &str,
@@ -202,6 +400,7 @@ mod after {
             if !self.hasher.verify(old_password, &user.password_hash) {
                 return Err(Error::InvalidCredentials);
             }
+            self.password_policy.check(&user.email, new_password)?;
 
             let new_hash = self.hasher.hash(new_password);
             let updated_user = User {
@@ -209,359 +408,7102 @@ mod after {
                 ..user
             };
 
-            self.repository.update(updated_user).await?;
+            let updated_user = self
+                .repository
+                .update(updated_user)
+                .await
+                .context("saving new password")?;
+
+            // Invalidate the copy authenticate() cached, under the email
+            // it was cached by
+            self.cache
+                .delete(&format!("user:email:{}", updated_user.email))
+                .await;
+
+            self.events.publish(DomainEvent::ProfileUpdated {
+                user_id: updated_user.id,
+            });
+            Ok(())
+        }
+
+        // Who a bearer token belongs to. Revoked, malformed and orphaned
+        // tokens, and tokens of deactivated users, all come back as
+        // InvalidCredentials.
+        #[tracing::instrument(skip_all)]
+        pub async fn current_user(&self, token: &str) -> Result<User, Error> {
+            if self.cache.get(&revoked_token_key(token)).await.is_some() {
+                return Err(Error::InvalidCredentials);
+            }
 
-            // Invalidate cache
-            self.cache.delete(&format!("user:email:{}", user_id)).await;
+            let user_id = self.token_service.validate(token).await?;
+            self.repository
+                .find_by_id(&user_id)
+                .await?
+                .filter(|user| user.active)
+                .ok_or(Error::InvalidCredentials)
+        }
 
+        // Tokens are stateless, so logging out means remembering the token
+        // as revoked until it would have expired anyway
+        #[tracing::instrument(skip_all)]
+        pub async fn logout(&self, token: &str) -> Result<(), Error> {
+            let user = self.current_user(token).await?;
+            self.cache
+                .set(
+                    &revoked_token_key(token),
+                    "revoked".to_string(),
+                    Some(JwtTokenService::DEFAULT_TTL_SECONDS),
+                )
+                .await;
+            self.events
+                .publish(DomainEvent::SessionRevoked { user_id: user.id });
             Ok(())
         }
     }
 
-    // 3. Production implementations
-    // ==============================
+    fn revoked_token_key(token: &str) -> String {
+        format!("token:revoked:{}", token)
+    }
 
-    pub struct PostgresUserRepository {
-        pool_url: String, // In real code: sqlx::PgPool
+    // What callers (HTTP handlers, CLIs, tests) actually need from an auth
+    // service. Both AuthService and AuthServiceGeneric implement it, so code
+    // written against `&dyn Authenticator` or `impl Authenticator` doesn't
+    // care which dispatch style is behind it.
+    #[async_trait]
+    pub trait Authenticator: Send + Sync {
+        async fn login(&self, email: &str, password: &str) -> Result<String, Error>;
+        async fn register(&self, email: &str, password: &str) -> Result<User, Error>;
+        async fn change_password(
+            &self,
+            user_id: &str,
+            old_password: &str,
+            new_password: &str,
+        ) -> Result<(), Error>;
+        async fn current_user(&self, token: &str) -> Result<User, Error>;
+        async fn logout(&self, token: &str) -> Result<(), Error>;
     }
 
+    // Inherent methods win over trait methods, so these delegate rather than recurse
     #[async_trait]
-    impl UserRepository for PostgresUserRepository {
-        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
-            println!("PostgreSQL: Finding user by email: {}", email);
-            // Real implementation would use sqlx
-            Ok(None)
+    impl Authenticator for AuthService {
+        async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+            self.login(email, password).await
         }
 
-        async fn create(&self, user: User) -> Result<User, Error> {
-            println!("PostgreSQL: Creating user: {:?}", user);
-            Ok(user)
+        async fn register(&self, email: &str, password: &str) -> Result<User, Error> {
+            self.register(email, password).await
         }
 
-        async fn update(&self, user: User) -> Result<User, Error> {
-            println!("PostgreSQL: Updating user: {:?}", user);
-            Ok(user)
+        async fn change_password(
+            &self,
+            user_id: &str,
+            old_password: &str,
+            new_password: &str,
+        ) -> Result<(), Error> {
+            self.change_password(user_id, old_password, new_password)
+                .await
         }
-    }
-
-    pub struct BcryptHasher;
 
-    #[async_trait]
-    impl PasswordHasher for BcryptHasher {
-        fn hash(&self, password: &str) -> String {
-            format!("hashed_{}", password)
+        async fn current_user(&self, token: &str) -> Result<User, Error> {
+            self.current_user(token).await
         }
 
-        fn verify(&self, password: &str, hash: &str) -> bool {
-            hash == &format!("hashed_{}", password)
+        async fn logout(&self, token: &str) -> Result<(), Error> {
+            self.logout(token).await
         }
     }
 
-    pub struct JwtTokenService {
-        secret: String,
-    }
-
+    // Factories hand out Arc<AuthService>; that should count too
     #[async_trait]
-    impl TokenService for JwtTokenService {
-        async fn generate(&self, user_id: &str) -> Result<String, Error> {
-            Ok(format!("jwt_token_for_{}", user_id))
+    impl<A: Authenticator + ?Sized> Authenticator for Arc<A> {
+        async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+            (**self).login(email, password).await
         }
 
-        async fn validate(&self, token: &str) -> Result<String, Error> {
-            Ok("user_id".to_string())
+        async fn register(&self, email: &str, password: &str) -> Result<User, Error> {
+            (**self).register(email, password).await
         }
-    }
-
-    pub struct RedisCache {
-        url: String,
-    }
 
-    #[async_trait]
-    impl CacheService for RedisCache {
-        async fn get(&self, key: &str) -> Option<String> {
-            println!("Redis GET: {}", key);
-            None
+        async fn change_password(
+            &self,
+            user_id: &str,
+            old_password: &str,
+            new_password: &str,
+        ) -> Result<(), Error> {
+            (**self)
+                .change_password(user_id, old_password, new_password)
+                .await
         }
 
-        async fn set(&self, key: &str, value: String, _ttl: Option<u64>) {
-            println!("Redis SET: {} = {}", key, value);
+        async fn current_user(&self, token: &str) -> Result<User, Error> {
+            (**self).current_user(token).await
         }
 
-        async fn delete(&self, key: &str) {
-            println!("Redis DEL: {}", key);
+        async fn logout(&self, token: &str) -> Result<(), Error> {
+            (**self).logout(token).await
         }
     }
 
-    // 4. Mock implementations for testing
-    // ====================================
+    // 2b. The same service with static dispatch
+    // ==========================================
+    //
+    // Same logic as AuthService, but every dependency is a type parameter:
+    // calls are monomorphized and can be inlined, and there's no Arc or
+    // vtable. The price: the concrete types are fixed at compile time, so a
+    // config file can't pick the backend, and each combination of R/H/T/C/I
+    // is compiled separately.
 
-    pub struct MockUserRepository {
-        users: std::sync::Mutex<Vec<User>>,
+    pub struct AuthServiceGeneric<R, H, T, C, I = UuidV7Generator> {
+        repository: R,
+        hasher: H,
+        token_service: T,
+        cache: C,
+        ids: I,
     }
 
-    impl MockUserRepository {
-        pub fn new() -> Self {
+    impl<R, H, T, C> AuthServiceGeneric<R, H, T, C> {
+        pub fn new(repository: R, hasher: H, token_service: T, cache: C) -> Self {
             Self {
-                users: std::sync::Mutex::new(Vec::new()),
+                repository,
+                hasher,
+                token_service,
+                cache,
+                ids: UuidV7Generator,
             }
         }
+    }
 
-        pub fn with_user(user: User) -> Self {
-            Self {
-                users: std::sync::Mutex::new(vec![user]),
+    impl<R, H, T, C, I> AuthServiceGeneric<R, H, T, C, I>
+    where
+        R: UserRepository,
+        H: PasswordHasher,
+        T: TokenService,
+        C: CacheService,
+        I: IdGenerator,
+    {
+        // IDs for registered users. Like every dependency here, it's part
+        // of the type, so this returns a different one.
+        pub fn with_ids<J: IdGenerator>(self, ids: J) -> AuthServiceGeneric<R, H, T, C, J> {
+            AuthServiceGeneric {
+                repository: self.repository,
+                hasher: self.hasher,
+                token_service: self.token_service,
+                cache: self.cache,
+                ids,
             }
         }
-    }
 
-    #[async_trait]
-    impl UserRepository for MockUserRepository {
-        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
-            let users = self.users.lock().unwrap();
-            Ok(users.iter().find(|u| u.email == email).cloned())
-        }
+        pub async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+            let cache_key = format!("user:email:{}", email);
+            let user: User = self
+                .cache
+                .get_or_set_with(&cache_key, Some(3600), || {
+                    self.repository.find_by_email(email)
+                })
+                .await?
+                .ok_or(Error::InvalidCredentials)?;
 
-        async fn create(&self, user: User) -> Result<User, Error> {
-            let mut users = self.users.lock().unwrap();
-            users.push(user.clone());
-            Ok(user)
+            if !user.active || !self.hasher.verify(password, &user.password_hash) {
+                return Err(Error::InvalidCredentials);
+            }
+
+            self.token_service.generate(&user.id).await
         }
 
-        async fn update(&self, user: User) -> Result<User, Error> {
-            let mut users = self.users.lock().unwrap();
-            if let Some(existing) = users.iter_mut().find(|u| u.id == user.id) {
-                *existing = user.clone();
-                Ok(user)
-            } else {
-                Err(Error::NotFound)
+        pub async fn register(&self, email: &str, password: &str) -> Result<User, Error> {
+            PasswordPolicy::default().check(email, password)?;
+            if self.repository.find_by_email(email).await?.is_some() {
+                return Err(Error::AlreadyExists);
             }
-        }
-    }
 
-    pub struct MockPasswordHasher;
+            let user = User {
+                id: self.ids.next_id(),
+                email: email.to_string(),
+                password_hash: self.hasher.hash(password),
+                role: "user".to_string(),
+                active: true,
+                avatar_url: None,
+            };
 
-    #[async_trait]
-    impl PasswordHasher for MockPasswordHasher {
-        fn hash(&self, password: &str) -> String {
-            format!("mock_hash_{}", password)
+            self.repository.create(user).await
         }
 
-        fn verify(&self, password: &str, hash: &str) -> bool {
-            hash == &format!("mock_hash_{}", password)
+        pub async fn change_password(
+            &self,
+            user_id: &str,
+            old_password: &str,
+            new_password: &str,
+        ) -> Result<(), Error> {
+            let user = self
+                .repository
+                .find_by_email(user_id)
+                .await?
+                .ok_or(Error::NotFound)?;
+
+            if !self.hasher.verify(old_password, &user.password_hash) {
+                return Err(Error::InvalidCredentials);
+            }
+            PasswordPolicy::default().check(&user.email, new_password)?;
+
+            let updated_user = User {
+                password_hash: self.hasher.hash(new_password),
+                ..user
+            };
+            let updated_user = self.repository.update(updated_user).await?;
+            self.cache
+                .delete(&format!("user:email:{}", updated_user.email))
+                .await;
+
+            Ok(())
         }
-    }
 
-    pub struct MockTokenService;
+        pub async fn current_user(&self, token: &str) -> Result<User, Error> {
+            if self.cache.get(&revoked_token_key(token)).await.is_some() {
+                return Err(Error::InvalidCredentials);
+            }
 
-    #[async_trait]
-    impl TokenService for MockTokenService {
-        async fn generate(&self, user_id: &str) -> Result<String, Error> {
-            Ok(format!("mock_token_{}", user_id))
+            let user_id = self.token_service.validate(token).await?;
+            self.repository
+                .find_by_id(&user_id)
+                .await?
+                .filter(|user| user.active)
+                .ok_or(Error::InvalidCredentials)
         }
 
-        async fn validate(&self, _token: &str) -> Result<String, Error> {
-            Ok("mock_user_id".to_string())
+        pub async fn logout(&self, token: &str) -> Result<(), Error> {
+            self.current_user(token).await?;
+            self.cache
+                .set(
+                    &revoked_token_key(token),
+                    "revoked".to_string(),
+                    Some(JwtTokenService::DEFAULT_TTL_SECONDS),
+                )
+                .await;
+            Ok(())
         }
     }
 
-    pub struct MockCache {
-        cache: std::sync::Mutex<std::collections::HashMap<String, String>>,
-    }
+    #[async_trait]
+    impl<R, H, T, C, I> Authenticator for AuthServiceGeneric<R, H, T, C, I>
+    where
+        R: UserRepository,
+        H: PasswordHasher,
+        T: TokenService,
+        C: CacheService,
+        I: IdGenerator,
+    {
+        async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+            self.login(email, password).await
+        }
 
-    impl MockCache {
-        pub fn new() -> Self {
-            Self {
-                cache: std::sync::Mutex::new(std::collections::HashMap::new()),
-            }
+        async fn register(&self, email: &str, password: &str) -> Result<User, Error> {
+            self.register(email, password).await
         }
-    }
 
-    #[async_trait]
-    impl CacheService for MockCache {
-        async fn get(&self, key: &str) -> Option<String> {
-            self.cache.lock().unwrap().get(key).cloned()
+        async fn change_password(
+            &self,
+            user_id: &str,
+            old_password: &str,
+            new_password: &str,
+        ) -> Result<(), Error> {
+            self.change_password(user_id, old_password, new_password)
+                .await
         }
 
-        async fn set(&self, key: &str, value: String, _ttl: Option<u64>) {
-            self.cache.lock().unwrap().insert(key.to_string(), value);
+        async fn current_user(&self, token: &str) -> Result<User, Error> {
+            self.current_user(token).await
         }
 
-        async fn delete(&self, key: &str) {
-            self.cache.lock().unwrap().remove(key);
+        async fn logout(&self, token: &str) -> Result<(), Error> {
+            self.logout(token).await
         }
     }
 
-    // 5. Factory for easy setup
-    // ==========================
+    // 3. Production implementations
+    // ==============================
+    //
+    // Each backend sits behind a cargo feature (`postgres`, `sqlite`, `redis`)
+    // so the default build only compiles the in-memory implementations.
+    //
+    // Every call is a span named after the method, with the backend and the
+    // lookup key as fields and the query as a debug event inside it. Inside
+    // a request it nests under the HTTP request span, so its log lines carry
+    // the request_id. A whole User is never a field: it has password_hash.
 
-    pub struct AuthServiceFactory;
+    // Applied in order by PostgresUserRepository::start. Each one is safe
+    // to run again, so every instance can run them all on startup. Past a
+    // tutorial, sqlx::migrate!() keeps track of which ones already ran.
+    #[cfg(feature = "postgres")]
+    pub const POSTGRES_MIGRATIONS: [&str; 4] = [
+        // created_at only orders list(): oldest first, like the mock
+        "CREATE TABLE IF NOT EXISTS users (
+            id TEXT PRIMARY KEY,
+            email TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL,
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            avatar_url TEXT,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )",
+        // id only breaks ties between entries from the same second
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id BIGSERIAL PRIMARY KEY,
+            at BIGINT NOT NULL,
+            actor TEXT NOT NULL,
+            action TEXT NOT NULL,
+            target TEXT NOT NULL,
+            detail TEXT NOT NULL,
+            ip TEXT
+        )",
+        "CREATE INDEX IF NOT EXISTS audit_log_actor_at ON audit_log (actor, at)",
+        "CREATE INDEX IF NOT EXISTS audit_log_target_at ON audit_log (target, at)",
+    ];
 
-    impl AuthServiceFactory {
-        pub fn create_production(
-            db_url: String,
-            redis_url: String,
-            jwt_secret: String,
-        ) -> AuthService {
-            let repository: Arc<dyn UserRepository> =
-                Arc::new(PostgresUserRepository { pool_url: db_url });
-            let hasher: Arc<dyn PasswordHasher> = Arc::new(BcryptHasher);
-            let token_service: Arc<dyn TokenService> =
-                Arc::new(JwtTokenService { secret: jwt_secret });
-            let cache: Arc<dyn CacheService> = Arc::new(RedisCache { url: redis_url });
+    #[cfg(feature = "postgres")]
+    pub struct PostgresUserRepository {
+        pool: sqlx::PgPool,
+        pool_url: SecretString,
+    }
+
+    // Columns in User's field order, for user_from_row and user_from_sqlite_row
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    const USER_COLUMNS: &str = "id, email, password_hash, role, active, avatar_url";
+
+    // Every filter field is optional: a NULL parameter matches everything.
+    // The email match is a plain substring, so % and _ mean themselves.
+    #[cfg(feature = "postgres")]
+    const USER_FILTER: &str = "($1::text IS NULL OR role = $1)
+        AND ($2::boolean IS NULL OR active = $2)
+        AND ($3::text IS NULL OR strpos(lower(email), lower($3)) > 0)";
+
+    #[cfg(feature = "postgres")]
+    fn user_from_row(row: sqlx::postgres::PgRow) -> Result<User, Error> {
+        use sqlx::Row;
+        Ok(User {
+            id: row.try_get("id")?,
+            email: row.try_get("email")?,
+            password_hash: row.try_get("password_hash")?,
+            role: row.try_get("role")?,
+            active: row.try_get("active")?,
+            avatar_url: row.try_get("avatar_url")?,
+        })
+    }
 
-            AuthService::new(repository, hasher, token_service, cache)
+    #[cfg(feature = "postgres")]
+    #[async_trait]
+    impl UserRepository for PostgresUserRepository {
+        #[tracing::instrument(skip(self), fields(db = "postgres"))]
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            let sql = format!("SELECT {} FROM users WHERE email = $1", USER_COLUMNS);
+            tracing::debug!("{}", sql);
+            let row = sqlx::query(&sql)
+                .bind(email)
+                .fetch_optional(&self.pool)
+                .await?;
+            row.map(user_from_row).transpose()
         }
 
-        pub fn create_test() -> AuthService {
-            let repository: Arc<dyn UserRepository> = Arc::new(MockUserRepository::new());
-            let hasher: Arc<dyn PasswordHasher> = Arc::new(MockPasswordHasher);
-            let token_service: Arc<dyn TokenService> = Arc::new(MockTokenService);
-            let cache: Arc<dyn CacheService> = Arc::new(MockCache::new());
+        #[tracing::instrument(skip(self), fields(db = "postgres"))]
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            let sql = format!("SELECT {} FROM users WHERE id = $1", USER_COLUMNS);
+            tracing::debug!("{}", sql);
+            let row = sqlx::query(&sql)
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+            row.map(user_from_row).transpose()
+        }
 
-            AuthService::new(repository, hasher, token_service, cache)
+        #[tracing::instrument(
+            skip(self, page),
+            fields(db = "postgres", limit = page.per_page, offset = page.offset())
+        )]
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            let sql = format!(
+                "SELECT {} FROM users WHERE {} ORDER BY created_at, id LIMIT $4 OFFSET $5",
+                USER_COLUMNS, USER_FILTER
+            );
+            tracing::debug!("{}", sql);
+            let rows = sqlx::query(&sql)
+                .bind(&filter.role)
+                .bind(filter.active)
+                .bind(&filter.email)
+                .bind(i64::from(page.per_page))
+                .bind(page.offset() as i64)
+                .fetch_all(&self.pool)
+                .await?;
+            // A page past the end is empty but still knows the total
+            let total: i64 =
+                sqlx::query_scalar(&format!("SELECT count(*) FROM users WHERE {}", USER_FILTER))
+                    .bind(&filter.role)
+                    .bind(filter.active)
+                    .bind(&filter.email)
+                    .fetch_one(&self.pool)
+                    .await?;
+            Ok(Page {
+                items: rows
+                    .into_iter()
+                    .map(user_from_row)
+                    .collect::<Result<_, _>>()?,
+                page: page.page,
+                per_page: page.per_page,
+                total: total as u64,
+            })
         }
 
-        pub fn create_test_with_user(user: User) -> AuthService {
-            let repository: Arc<dyn UserRepository> =
-                Arc::new(MockUserRepository::with_user(user));
-            let hasher: Arc<dyn PasswordHasher> = Arc::new(MockPasswordHasher);
-            let token_service: Arc<dyn TokenService> = Arc::new(MockTokenService);
-            let cache: Arc<dyn CacheService> = Arc::new(MockCache::new());
+        // A taken email is a unique violation, which From<sqlx::Error>
+        // turns into AlreadyExists
+        #[tracing::instrument(skip_all, fields(db = "postgres", user.id = %user.id))]
+        async fn create(&self, user: User) -> Result<User, Error> {
+            let sql = format!(
+                "INSERT INTO users ({}) VALUES ($1, $2, $3, $4, $5, $6)",
+                USER_COLUMNS
+            );
+            tracing::debug!("{}", sql);
+            sqlx::query(&sql)
+                .bind(&user.id)
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(&user.role)
+                .bind(user.active)
+                .bind(&user.avatar_url)
+                .execute(&self.pool)
+                .await?;
+            Ok(user)
+        }
 
-            AuthService::new(repository, hasher, token_service, cache)
+        #[tracing::instrument(skip_all, fields(db = "postgres", user.id = %user.id))]
+        async fn update(&self, user: User) -> Result<User, Error> {
+            let sql = "UPDATE users SET email = $2, password_hash = $3, role = $4, active = $5, \
+                       avatar_url = $6 WHERE id = $1";
+            tracing::debug!("{}", sql);
+            let result = sqlx::query(sql)
+                .bind(&user.id)
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(&user.role)
+                .bind(user.active)
+                .bind(&user.avatar_url)
+                .execute(&self.pool)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound);
+            }
+            Ok(user)
         }
     }
-}
 
+    #[cfg(feature = "postgres")]
+    #[async_trait]
+    impl HealthCheck for PostgresUserRepository {
+        async fn check(&self) -> Result<(), Error> {
+            tracing::debug!(db = "postgres", url = %self.pool_url, "SELECT 1");
+            sqlx::query("SELECT 1").execute(&self.pool).await?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    #[async_trait]
+    impl Lifecycle for PostgresUserRepository {
+        async fn start(&self) -> Result<(), Error> {
+            tracing::info!(db = "postgres", url = %self.pool_url, "running migrations");
+            for migration in POSTGRES_MIGRATIONS {
+                sqlx::raw_sql(migration)
+                    .execute(&self.pool)
+                    .await
+                    .map_err(Error::from)
+                    .context("running migrations")?;
+            }
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            tracing::info!(db = "postgres", url = %self.pool_url, "closing pool");
+            self.pool.close().await;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    impl PostgresUserRepository {
+        // Opens the pool; start() brings the schema up to date
+        pub async fn connect(pool_url: SecretString) -> Result<Self, Error> {
+            tracing::info!(db = "postgres", url = %pool_url, "connecting");
+            let pool = sqlx::PgPool::connect(pool_url.expose()).await?;
+            Ok(Self { pool, pool_url })
+        }
+    }
+
+    // Applied in order by SqliteUserRepository::start, like
+    // POSTGRES_MIGRATIONS. No created_at, and no id on audit_log: list()
+    // and query() order by rowid, which SQLite already assigns in
+    // insertion order.
+    #[cfg(feature = "sqlite")]
+    pub const SQLITE_MIGRATIONS: [&str; 4] = [
+        "CREATE TABLE IF NOT EXISTS users (
+            id TEXT PRIMARY KEY,
+            email TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL,
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            avatar_url TEXT
+        )",
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            at INTEGER NOT NULL,
+            actor TEXT NOT NULL,
+            action TEXT NOT NULL,
+            target TEXT NOT NULL,
+            detail TEXT NOT NULL,
+            ip TEXT
+        )",
+        "CREATE INDEX IF NOT EXISTS audit_log_actor_at ON audit_log (actor, at)",
+        "CREATE INDEX IF NOT EXISTS audit_log_target_at ON audit_log (target, at)",
+    ];
+
+    #[cfg(feature = "sqlite")]
+    pub struct SqliteUserRepository {
+        pool: sqlx::SqlitePool,
+        path: String,
+    }
+
+    // USER_FILTER in SQLite: instr for strpos, and a NULL check needs no cast
+    #[cfg(feature = "sqlite")]
+    const SQLITE_USER_FILTER: &str = "(?1 IS NULL OR role = ?1)
+        AND (?2 IS NULL OR active = ?2)
+        AND (?3 IS NULL OR instr(lower(email), lower(?3)) > 0)";
+
+    #[cfg(feature = "sqlite")]
+    fn user_from_sqlite_row(row: sqlx::sqlite::SqliteRow) -> Result<User, Error> {
+        use sqlx::Row;
+        Ok(User {
+            id: row.try_get("id")?,
+            email: row.try_get("email")?,
+            password_hash: row.try_get("password_hash")?,
+            role: row.try_get("role")?,
+            active: row.try_get("active")?,
+            avatar_url: row.try_get("avatar_url")?,
+        })
+    }
+
+    // The same queries as PostgresUserRepository, with SQLite's ?N for $N
+    #[cfg(feature = "sqlite")]
+    #[async_trait]
+    impl UserRepository for SqliteUserRepository {
+        #[tracing::instrument(skip(self), fields(db = "sqlite", path = %self.path))]
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            let sql = format!("SELECT {} FROM users WHERE email = ?1", USER_COLUMNS);
+            tracing::debug!("{}", sql);
+            let row = sqlx::query(&sql)
+                .bind(email)
+                .fetch_optional(&self.pool)
+                .await?;
+            row.map(user_from_sqlite_row).transpose()
+        }
+
+        #[tracing::instrument(skip(self), fields(db = "sqlite", path = %self.path))]
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            let sql = format!("SELECT {} FROM users WHERE id = ?1", USER_COLUMNS);
+            tracing::debug!("{}", sql);
+            let row = sqlx::query(&sql)
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+            row.map(user_from_sqlite_row).transpose()
+        }
+
+        #[tracing::instrument(
+            skip(self, page),
+            fields(db = "sqlite", path = %self.path, limit = page.per_page, offset = page.offset())
+        )]
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            let sql = format!(
+                "SELECT {} FROM users WHERE {} ORDER BY rowid LIMIT ?4 OFFSET ?5",
+                USER_COLUMNS, SQLITE_USER_FILTER
+            );
+            tracing::debug!("{}", sql);
+            let rows = sqlx::query(&sql)
+                .bind(&filter.role)
+                .bind(filter.active)
+                .bind(&filter.email)
+                .bind(i64::from(page.per_page))
+                .bind(page.offset() as i64)
+                .fetch_all(&self.pool)
+                .await?;
+            let total: i64 = sqlx::query_scalar(&format!(
+                "SELECT count(*) FROM users WHERE {}",
+                SQLITE_USER_FILTER
+            ))
+            .bind(&filter.role)
+            .bind(filter.active)
+            .bind(&filter.email)
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(Page {
+                items: rows
+                    .into_iter()
+                    .map(user_from_sqlite_row)
+                    .collect::<Result<_, _>>()?,
+                page: page.page,
+                per_page: page.per_page,
+                total: total as u64,
+            })
+        }
+
+        #[tracing::instrument(
+            skip_all,
+            fields(db = "sqlite", path = %self.path, user.id = %user.id)
+        )]
+        async fn create(&self, user: User) -> Result<User, Error> {
+            let sql = format!(
+                "INSERT INTO users ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                USER_COLUMNS
+            );
+            tracing::debug!("{}", sql);
+            sqlx::query(&sql)
+                .bind(&user.id)
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(&user.role)
+                .bind(user.active)
+                .bind(&user.avatar_url)
+                .execute(&self.pool)
+                .await?;
+            Ok(user)
+        }
+
+        #[tracing::instrument(
+            skip_all,
+            fields(db = "sqlite", path = %self.path, user.id = %user.id)
+        )]
+        async fn update(&self, user: User) -> Result<User, Error> {
+            let sql = "UPDATE users SET email = ?2, password_hash = ?3, role = ?4, active = ?5, \
+                       avatar_url = ?6 WHERE id = ?1";
+            tracing::debug!("{}", sql);
+            let result = sqlx::query(sql)
+                .bind(&user.id)
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(&user.role)
+                .bind(user.active)
+                .bind(&user.avatar_url)
+                .execute(&self.pool)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound);
+            }
+            Ok(user)
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_trait]
+    impl HealthCheck for SqliteUserRepository {
+        async fn check(&self) -> Result<(), Error> {
+            tracing::debug!(db = "sqlite", path = %self.path, "SELECT 1");
+            sqlx::query("SELECT 1").execute(&self.pool).await?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_trait]
+    impl Lifecycle for SqliteUserRepository {
+        async fn start(&self) -> Result<(), Error> {
+            tracing::info!(db = "sqlite", path = %self.path, "running migrations");
+            for migration in SQLITE_MIGRATIONS {
+                sqlx::raw_sql(migration)
+                    .execute(&self.pool)
+                    .await
+                    .map_err(Error::from)
+                    .context("running migrations")?;
+            }
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            tracing::info!(db = "sqlite", path = %self.path, "closing pool");
+            self.pool.close().await;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    impl SqliteUserRepository {
+        // `sqlite://users.db`; the file is created if it isn't there. Not
+        // `sqlite::memory:`: each pooled connection would get its own
+        // empty database.
+        pub async fn connect(path: &str) -> Result<Self, Error> {
+            tracing::info!(db = "sqlite", path, "connecting");
+            let options = path
+                .parse::<sqlx::sqlite::SqliteConnectOptions>()?
+                .create_if_missing(true);
+            let pool = sqlx::SqlitePool::connect_with(options).await?;
+            Ok(Self {
+                pool,
+                path: path.to_string(),
+            })
+        }
+    }
+
+    // The audit trail goes in the same database as the users, one row per
+    // entry in `audit_log`, indexed on (actor, at) and (target, at) so a
+    // user's history is an index scan. Built from the users' repository
+    // with audit(), so both share one pool.
+    #[cfg(feature = "postgres")]
+    pub struct PostgresAuditRepository {
+        pool: sqlx::PgPool,
+    }
+
+    // Columns in AuditEntry's field order, for audit_from_row and
+    // audit_from_sqlite_row
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    const AUDIT_COLUMNS: &str = "at, actor, action, target, detail, ip";
+
+    // Like USER_FILTER; `user` matches either side of the entry
+    #[cfg(feature = "postgres")]
+    const AUDIT_FILTER: &str = "($1::text IS NULL OR actor = $1)
+        AND ($2::text IS NULL OR target = $2)
+        AND ($3::text IS NULL OR actor = $3 OR target = $3)
+        AND ($4::text IS NULL OR action = $4)";
+
+    // BIGINT is signed; Unix seconds fit either way
+    #[cfg(feature = "postgres")]
+    fn audit_from_row(row: sqlx::postgres::PgRow) -> Result<AuditEntry, Error> {
+        use sqlx::Row;
+        Ok(AuditEntry {
+            at: row.try_get::<i64, _>("at")? as u64,
+            actor: row.try_get("actor")?,
+            action: row.try_get("action")?,
+            target: row.try_get("target")?,
+            detail: row.try_get("detail")?,
+            ip: row.try_get("ip")?,
+        })
+    }
+
+    #[cfg(feature = "postgres")]
+    #[async_trait]
+    impl AuditRepository for PostgresAuditRepository {
+        #[tracing::instrument(
+            skip_all,
+            fields(db = "postgres", action = %entry.action, target = %entry.target)
+        )]
+        async fn append(&self, entry: AuditEntry) -> Result<(), Error> {
+            let sql = format!(
+                "INSERT INTO audit_log ({}) VALUES ($1, $2, $3, $4, $5, $6)",
+                AUDIT_COLUMNS
+            );
+            tracing::debug!("{}", sql);
+            sqlx::query(&sql)
+                .bind(entry.at as i64)
+                .bind(&entry.actor)
+                .bind(&entry.action)
+                .bind(&entry.target)
+                .bind(&entry.detail)
+                .bind(&entry.ip)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        }
+
+        // Entries appended in the same second come back newest first by id
+        #[tracing::instrument(
+            skip(self, page),
+            fields(db = "postgres", limit = page.per_page, offset = page.offset())
+        )]
+        async fn query(
+            &self,
+            filter: &AuditFilter,
+            page: PageRequest,
+        ) -> Result<Page<AuditEntry>, Error> {
+            let sql = format!(
+                "SELECT {} FROM audit_log WHERE {} ORDER BY at DESC, id DESC LIMIT $5 OFFSET $6",
+                AUDIT_COLUMNS, AUDIT_FILTER
+            );
+            tracing::debug!("{}", sql);
+            let rows = sqlx::query(&sql)
+                .bind(&filter.actor)
+                .bind(&filter.target)
+                .bind(&filter.user)
+                .bind(&filter.action)
+                .bind(i64::from(page.per_page))
+                .bind(page.offset() as i64)
+                .fetch_all(&self.pool)
+                .await?;
+            let total: i64 = sqlx::query_scalar(&format!(
+                "SELECT count(*) FROM audit_log WHERE {}",
+                AUDIT_FILTER
+            ))
+            .bind(&filter.actor)
+            .bind(&filter.target)
+            .bind(&filter.user)
+            .bind(&filter.action)
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(Page {
+                items: rows
+                    .into_iter()
+                    .map(audit_from_row)
+                    .collect::<Result<_, _>>()?,
+                page: page.page,
+                per_page: page.per_page,
+                total: total as u64,
+            })
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    impl PostgresUserRepository {
+        // start() has already created audit_log along with users
+        pub fn audit(&self) -> PostgresAuditRepository {
+            PostgresAuditRepository {
+                pool: self.pool.clone(),
+            }
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub struct SqliteAuditRepository {
+        pool: sqlx::SqlitePool,
+        path: String,
+    }
+
+    // AUDIT_FILTER in SQLite
+    #[cfg(feature = "sqlite")]
+    const SQLITE_AUDIT_FILTER: &str = "(?1 IS NULL OR actor = ?1)
+        AND (?2 IS NULL OR target = ?2)
+        AND (?3 IS NULL OR actor = ?3 OR target = ?3)
+        AND (?4 IS NULL OR action = ?4)";
+
+    #[cfg(feature = "sqlite")]
+    fn audit_from_sqlite_row(row: sqlx::sqlite::SqliteRow) -> Result<AuditEntry, Error> {
+        use sqlx::Row;
+        Ok(AuditEntry {
+            at: row.try_get::<i64, _>("at")? as u64,
+            actor: row.try_get("actor")?,
+            action: row.try_get("action")?,
+            target: row.try_get("target")?,
+            detail: row.try_get("detail")?,
+            ip: row.try_get("ip")?,
+        })
+    }
+
+    // The same queries as PostgresAuditRepository, with rowid for id
+    #[cfg(feature = "sqlite")]
+    #[async_trait]
+    impl AuditRepository for SqliteAuditRepository {
+        #[tracing::instrument(
+            skip_all,
+            fields(db = "sqlite", path = %self.path, action = %entry.action, target = %entry.target)
+        )]
+        async fn append(&self, entry: AuditEntry) -> Result<(), Error> {
+            let sql = format!(
+                "INSERT INTO audit_log ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                AUDIT_COLUMNS
+            );
+            tracing::debug!("{}", sql);
+            sqlx::query(&sql)
+                .bind(entry.at as i64)
+                .bind(&entry.actor)
+                .bind(&entry.action)
+                .bind(&entry.target)
+                .bind(&entry.detail)
+                .bind(&entry.ip)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        }
+
+        #[tracing::instrument(
+            skip(self, page),
+            fields(db = "sqlite", path = %self.path, limit = page.per_page, offset = page.offset())
+        )]
+        async fn query(
+            &self,
+            filter: &AuditFilter,
+            page: PageRequest,
+        ) -> Result<Page<AuditEntry>, Error> {
+            let sql = format!(
+                "SELECT {} FROM audit_log WHERE {} ORDER BY at DESC, rowid DESC LIMIT ?5 OFFSET ?6",
+                AUDIT_COLUMNS, SQLITE_AUDIT_FILTER
+            );
+            tracing::debug!("{}", sql);
+            let rows = sqlx::query(&sql)
+                .bind(&filter.actor)
+                .bind(&filter.target)
+                .bind(&filter.user)
+                .bind(&filter.action)
+                .bind(i64::from(page.per_page))
+                .bind(page.offset() as i64)
+                .fetch_all(&self.pool)
+                .await?;
+            let total: i64 = sqlx::query_scalar(&format!(
+                "SELECT count(*) FROM audit_log WHERE {}",
+                SQLITE_AUDIT_FILTER
+            ))
+            .bind(&filter.actor)
+            .bind(&filter.target)
+            .bind(&filter.user)
+            .bind(&filter.action)
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(Page {
+                items: rows
+                    .into_iter()
+                    .map(audit_from_sqlite_row)
+                    .collect::<Result<_, _>>()?,
+                page: page.page,
+                per_page: page.per_page,
+                total: total as u64,
+            })
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    impl SqliteUserRepository {
+        pub fn audit(&self) -> SqliteAuditRepository {
+            SqliteAuditRepository {
+                pool: self.pool.clone(),
+                path: self.path.clone(),
+            }
+        }
+    }
+
+    pub struct BcryptHasher;
+
+    #[async_trait]
+    impl PasswordHasher for BcryptHasher {
+        fn hash(&self, password: &str) -> String {
+            format!("hashed_{}", password)
+        }
+
+        fn verify(&self, password: &str, hash: &str) -> bool {
+            hash == format!("hashed_{}", password)
+        }
+    }
+
+    pub struct Argon2Hasher;
+
+    #[async_trait]
+    impl PasswordHasher for Argon2Hasher {
+        fn hash(&self, password: &str) -> String {
+            format!("argon2_{}", password)
+        }
+
+        fn verify(&self, password: &str, hash: &str) -> bool {
+            hash == format!("argon2_{}", password)
+        }
+    }
+
+    pub struct JwtTokenService {
+        secret: SecretString,
+        ttl_seconds: u64,
+        clock: Arc<dyn Clock>,
+    }
+
+    impl JwtTokenService {
+        // Matches the JWT_TTL_SECONDS default
+        pub const DEFAULT_TTL_SECONDS: u64 = 3600;
+
+        pub fn new(secret: SecretString, ttl_seconds: u64, clock: Arc<dyn Clock>) -> Self {
+            Self {
+                secret,
+                ttl_seconds,
+                clock,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TokenService for JwtTokenService {
+        // `?` on a jsonwebtoken error: one while signing is ours, Internal
+        // (see From<jsonwebtoken::errors::Error>)
+        async fn generate(&self, user_id: &str) -> Result<String, Error> {
+            let claims = claims::Claims {
+                sub: user_id.to_string(),
+                exp: self.clock.unix_seconds() + self.ttl_seconds,
+            };
+            Ok(claims.encode(self.secret.expose().as_bytes())?)
+        }
+
+        // Expired, forged or not a JWT at all, it's InvalidCredentials
+        async fn validate(&self, token: &str) -> Result<String, Error> {
+            let claims = claims::Claims::verify(
+                token,
+                self.secret.expose().as_bytes(),
+                self.clock.unix_seconds(),
+            )?;
+            Ok(claims.sub)
+        }
+    }
+
+    // Nothing remote to ping, but an empty secret is a key anyone can
+    // sign tokens with
+    #[async_trait]
+    impl HealthCheck for JwtTokenService {
+        async fn check(&self) -> Result<(), Error> {
+            if self.secret.expose().is_empty() {
+                return Err(Error::internal("JWT secret is empty"));
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    pub struct RedisCache {
+        // Cheap to clone, and every clone shares the one connection
+        connection: redis::aio::MultiplexedConnection,
+        url: SecretString,
+    }
+
+    // Callers get no errors from a cache: a failed command is logged and
+    // then treated like a miss, or for incr like an empty counter, which
+    // lets the request through
+    #[cfg(feature = "redis")]
+    fn or_miss<T: Default>(result: redis::RedisResult<T>) -> T {
+        result.unwrap_or_else(|e| {
+            tracing::warn!(error = %Error::from(e).report(), "cache command failed");
+            T::default()
+        })
+    }
+
+    #[cfg(feature = "redis")]
+    #[async_trait]
+    impl CacheService for RedisCache {
+        #[tracing::instrument(skip_all, fields(cache = "redis", key = loggable_key(key)))]
+        async fn get(&self, key: &str) -> Option<String> {
+            tracing::debug!("GET");
+            or_miss(
+                redis::cmd("GET")
+                    .arg(key)
+                    .query_async(&mut self.connection.clone())
+                    .await,
+            )
+        }
+
+        // Values are never fields: a session maps to a user, a cached User
+        // has its password hash
+        #[tracing::instrument(skip_all, fields(cache = "redis", key = loggable_key(key)))]
+        async fn set(&self, key: &str, value: String, ttl: Option<u64>) {
+            tracing::debug!(bytes = value.len(), ?ttl, "SET");
+            let mut command = redis::cmd("SET");
+            command.arg(key).arg(value);
+            if let Some(ttl) = ttl {
+                command.arg("EX").arg(ttl);
+            }
+            or_miss(
+                command
+                    .query_async::<()>(&mut self.connection.clone())
+                    .await,
+            )
+        }
+
+        #[tracing::instrument(skip_all, fields(cache = "redis", key = loggable_key(key)))]
+        async fn delete(&self, key: &str) {
+            tracing::debug!("DEL");
+            or_miss(
+                redis::cmd("DEL")
+                    .arg(key)
+                    .query_async::<()>(&mut self.connection.clone())
+                    .await,
+            )
+        }
+
+        // One MGET for the whole batch. Keys can be session IDs, so only
+        // the count is a field. A failed MGET is a miss for every key.
+        #[tracing::instrument(skip_all, fields(cache = "redis", count = keys.len()))]
+        async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+            if keys.is_empty() {
+                // MGET with no keys is a syntax error
+                return Vec::new();
+            }
+            tracing::debug!("MGET");
+            redis::cmd("MGET")
+                .arg(keys)
+                .query_async(&mut self.connection.clone())
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!(error = %Error::from(e).report(), "cache command failed");
+                    vec![None; keys.len()]
+                })
+        }
+
+        // MSET can't set a TTL, so one SET ... EX per entry, sent as a
+        // single pipeline: one round-trip, not one transaction
+        #[tracing::instrument(skip_all, fields(cache = "redis", count = entries.len()))]
+        async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+            if entries.is_empty() {
+                return;
+            }
+            tracing::debug!("pipeline of SETs");
+            let mut pipeline = redis::pipe();
+            for (key, value, ttl) in entries {
+                pipeline.cmd("SET").arg(*key).arg(value);
+                if let Some(ttl) = ttl {
+                    pipeline.arg("EX").arg(*ttl);
+                }
+                pipeline.ignore();
+            }
+            or_miss(
+                pipeline
+                    .query_async::<()>(&mut self.connection.clone())
+                    .await,
+            )
+        }
+
+        // One transaction, so a counter can't be left without its expiry.
+        // NX keeps the first TTL: the window starts at the first hit.
+        #[tracing::instrument(skip_all, fields(cache = "redis", key = loggable_key(key)))]
+        async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+            tracing::debug!(by, ?ttl_seconds, "INCRBY");
+            let mut pipeline = redis::pipe();
+            pipeline.atomic().cmd("INCRBY").arg(key).arg(by);
+            if let Some(ttl) = ttl_seconds {
+                pipeline.cmd("EXPIRE").arg(key).arg(ttl).arg("NX").ignore();
+            }
+            let (count,): (i64,) =
+                or_miss(pipeline.query_async(&mut self.connection.clone()).await);
+            count
+        }
+
+        // TTL answers -2 for a missing key and -1 for one that never expires
+        #[tracing::instrument(skip_all, fields(cache = "redis", key = loggable_key(key)))]
+        async fn ttl(&self, key: &str) -> Option<u64> {
+            tracing::debug!("TTL");
+            let seconds: Option<i64> = or_miss(
+                redis::cmd("TTL")
+                    .arg(key)
+                    .query_async(&mut self.connection.clone())
+                    .await,
+            );
+            seconds.and_then(|seconds| u64::try_from(seconds).ok())
+        }
+    }
+
+    // A cache key as it may appear in a log. Session IDs and revoked tokens
+    // are credentials, so those keys keep only their prefix.
+    #[cfg(feature = "redis")]
+    pub fn loggable_key(key: &str) -> &str {
+        ["session:", "token:revoked:"]
+            .into_iter()
+            .find(|prefix| key.starts_with(prefix))
+            .unwrap_or(key)
+    }
+
+    #[cfg(feature = "redis")]
+    #[async_trait]
+    impl HealthCheck for RedisCache {
+        async fn check(&self) -> Result<(), Error> {
+            tracing::debug!(cache = "redis", url = %self.url, "PING");
+            redis::cmd("PING")
+                .query_async::<()>(&mut self.connection.clone())
+                .await?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    #[async_trait]
+    impl Lifecycle for RedisCache {
+        async fn shutdown(&self) -> Result<(), Error> {
+            tracing::info!(cache = "redis", url = %self.url, "closing connection");
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    impl RedisCache {
+        pub async fn connect(url: SecretString) -> Result<Self, Error> {
+            tracing::info!(cache = "redis", url = %url, "connecting");
+            let connection = redis::Client::open(url.expose())?
+                .get_multiplexed_async_connection()
+                .await?;
+            Ok(Self { connection, url })
+        }
+    }
+
+    // Lazy proxies: connect on first use instead of at startup.
+    // They implement the same traits, so AuthService can't tell the difference.
+
+    #[cfg_attr(not(any(feature = "postgres", feature = "sqlite")), allow(dead_code))]
+    pub struct LazyUserRepository(pub Lazy<dyn UserRepository, Error>);
+
+    #[cfg_attr(not(any(feature = "postgres", feature = "sqlite")), allow(dead_code))]
+    impl LazyUserRepository {
+        // A failed connect is worth retrying: the database may be back
+        async fn connect(&self) -> Result<Arc<dyn UserRepository>, Error> {
+            self.0
+                .get()
+                .await
+                .map_err(|error| error.context("connecting to the database").transient())
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for LazyUserRepository {
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            self.connect().await?.find_by_email(email).await
+        }
+
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            self.connect().await?.find_by_id(id).await
+        }
+
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            self.connect().await?.list(filter, page).await
+        }
+
+        async fn create(&self, user: User) -> Result<User, Error> {
+            self.connect().await?.create(user).await
+        }
+
+        async fn update(&self, user: User) -> Result<User, Error> {
+            self.connect().await?.update(user).await
+        }
+    }
+
+    // Checking readiness is a "first use": it connects if nothing has yet
+    #[async_trait]
+    impl HealthCheck for LazyUserRepository {
+        async fn check(&self) -> Result<(), Error> {
+            self.connect().await?.check().await
+        }
+    }
+
+    // start() stays lazy; shutdown() only closes what was actually opened
+    #[async_trait]
+    impl Lifecycle for LazyUserRepository {
+        async fn shutdown(&self) -> Result<(), Error> {
+            match self.0.get_initialized() {
+                Some(repository) => repository.shutdown().await,
+                None => Ok(()),
+            }
+        }
+    }
+
+    // Shares the connect with LazyUserRepository, so an entry written
+    // before any user query still opens the pool only once
+    #[cfg_attr(not(any(feature = "postgres", feature = "sqlite")), allow(dead_code))]
+    pub struct LazyAuditRepository(pub Lazy<dyn AuditRepository, Error>);
+
+    #[cfg_attr(not(any(feature = "postgres", feature = "sqlite")), allow(dead_code))]
+    impl LazyAuditRepository {
+        async fn connect(&self) -> Result<Arc<dyn AuditRepository>, Error> {
+            self.0
+                .get()
+                .await
+                .map_err(|error| error.context("connecting to the database").transient())
+        }
+    }
+
+    #[async_trait]
+    impl AuditRepository for LazyAuditRepository {
+        async fn append(&self, entry: AuditEntry) -> Result<(), Error> {
+            self.connect().await?.append(entry).await
+        }
+
+        async fn query(
+            &self,
+            filter: &AuditFilter,
+            page: PageRequest,
+        ) -> Result<Page<AuditEntry>, Error> {
+            self.connect().await?.query(filter, page).await
+        }
+    }
+
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    pub struct LazyCache {
+        cache: Lazy<dyn CacheService, Error>,
+        // Every request tries to connect while Redis is down; the log gets
+        // a few of the failures and a count of the rest
+        failures: crate::logging::LogThrottle,
+    }
+
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    impl LazyCache {
+        pub fn new(cache: Lazy<dyn CacheService, Error>) -> Self {
+            Self {
+                cache,
+                failures: crate::logging::LogThrottle::new(std::time::Duration::from_secs(10), 3),
+            }
+        }
+
+        async fn connect(&self) -> Option<Arc<dyn CacheService>> {
+            match self.cache.get().await {
+                Ok(cache) => Some(cache),
+                Err(e) => {
+                    if self.failures.allow("cache connect") {
+                        tracing::warn!(error = %e.report(), "cache unavailable");
+                    }
+                    None
+                }
+            }
+        }
+    }
+
+    // A cache that can't connect behaves like an empty cache
+    #[async_trait]
+    impl CacheService for LazyCache {
+        async fn get(&self, key: &str) -> Option<String> {
+            self.connect().await?.get(key).await
+        }
+
+        async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+            if let Some(cache) = self.connect().await {
+                cache.set(key, value, ttl_seconds).await;
+            }
+        }
+
+        async fn delete(&self, key: &str) {
+            if let Some(cache) = self.connect().await {
+                cache.delete(key).await;
+            }
+        }
+
+        // Nothing to count with: 0 lets the request through (limits fail open)
+        async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+            match self.connect().await {
+                Some(cache) => cache.incr(key, by, ttl_seconds).await,
+                None => 0,
+            }
+        }
+
+        // The batches are passed on whole, so RedisCache's MGET and
+        // pipelined SET EX aren't lost behind the proxy
+        async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+            match self.connect().await {
+                Some(cache) => cache.get_many(keys).await,
+                None => vec![None; keys.len()],
+            }
+        }
+
+        async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+            if let Some(cache) = self.connect().await {
+                cache.set_many(entries).await;
+            }
+        }
+
+        async fn ttl(&self, key: &str) -> Option<u64> {
+            self.connect().await?.ttl(key).await
+        }
+    }
+
+    // Requests tolerate a missing cache; readiness still reports it
+    #[async_trait]
+    impl HealthCheck for LazyCache {
+        async fn check(&self) -> Result<(), Error> {
+            self.cache.get().await?.check().await
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for LazyCache {
+        async fn shutdown(&self) -> Result<(), Error> {
+            match self.cache.get_initialized() {
+                Some(cache) => cache.shutdown().await,
+                None => Ok(()),
+            }
+        }
+    }
+
+    // The proxy services hold: every call goes to whatever cache is current
+    #[async_trait]
+    impl CacheService for Swappable<dyn CacheService> {
+        async fn get(&self, key: &str) -> Option<String> {
+            self.load().get(key).await
+        }
+
+        async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+            self.load().set(key, value, ttl_seconds).await
+        }
+
+        async fn delete(&self, key: &str) {
+            self.load().delete(key).await
+        }
+
+        async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+            self.load().incr(key, by, ttl_seconds).await
+        }
+
+        async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+            self.load().get_many(keys).await
+        }
+
+        async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+            self.load().set_many(entries).await
+        }
+
+        async fn ttl(&self, key: &str) -> Option<u64> {
+            self.load().ttl(key).await
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for Swappable<dyn CacheService> {
+        async fn check(&self) -> Result<(), Error> {
+            self.load().check().await
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for Swappable<dyn CacheService> {
+        async fn start(&self) -> Result<(), Error> {
+            self.load().start().await
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            self.load().shutdown().await
+        }
+    }
+
+    // Background work (cleanup jobs, metrics flush) as a Lifecycle component:
+    // spawned on start(), stopped and awaited on shutdown()
+    pub struct BackgroundTask {
+        name: String,
+        interval: std::time::Duration,
+        clock: Arc<dyn Clock>,
+        job: Arc<dyn Fn() + Send + Sync>,
+        stop: Arc<tokio::sync::Notify>,
+        handle: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    }
+
+    impl BackgroundTask {
+        pub fn new(
+            name: &str,
+            interval: std::time::Duration,
+            job: impl Fn() + Send + Sync + 'static,
+        ) -> Self {
+            Self {
+                name: name.to_string(),
+                interval,
+                clock: Arc::new(SystemClock),
+                job: Arc::new(job),
+                stop: Arc::new(tokio::sync::Notify::new()),
+                handle: std::sync::Mutex::new(None),
+            }
+        }
+
+        // Runs every `interval` of this clock's time instead of the OS's
+        pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for BackgroundTask {
+        async fn start(&self) -> Result<(), Error> {
+            let name = self.name.clone();
+            let interval = self.interval;
+            let clock = self.clock.clone();
+            let job = self.job.clone();
+            let stop = self.stop.clone();
+            let handle = tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        // No request behind a run, so each gets an ID of its
+                        // own and its lines group like a request's
+                        _ = clock.sleep(interval) => {
+                            let run = tracing::info_span!(
+                                "task",
+                                name = %name,
+                                request_id = %uuid::Uuid::new_v4(),
+                            );
+                            run.in_scope(|| job())
+                        }
+                        _ = stop.notified() => break,
+                    }
+                }
+            });
+            *self.handle.lock().unwrap() = Some(handle);
+            Ok(())
+        }
+
+        // Waits for the current run to finish instead of aborting mid-job
+        async fn shutdown(&self) -> Result<(), Error> {
+            let handle = self.handle.lock().unwrap().take();
+            if let Some(handle) = handle {
+                self.stop.notify_one();
+                handle
+                    .await
+                    .map_err(|e| Error::with_source(format!("{} panicked", self.name), e))?;
+            }
+            Ok(())
+        }
+    }
+
+    // Circuit breaker
+    // ---------------
+    // When the database is down, every request still waits for its own
+    // timeout before failing, piling up connections and threads. A breaker
+    // fails fast instead:
+    //
+    //   Closed    --N failures in a row-->     Open (reject everything)
+    //   Open      --after open_duration-->     HalfOpen (let one probe through)
+    //   HalfOpen  --probe succeeds/fails-->    Closed / Open
+    //
+    // Only transient failures count (Error::is_retryable): a NotFound is a
+    // healthy database saying "no", and a bad query fails the same way
+    // however healthy the database is. Neither is a reason to stop asking.
+    //
+    // Only the Postgres backend is wired through one (and through
+    // WithRetry below); without it, only the tests use them.
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CircuitState {
+        Closed,
+        Open,
+        HalfOpen,
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    #[derive(Debug, Clone, Copy)]
+    pub struct CircuitBreakerConfig {
+        pub failure_threshold: u32,
+        pub open_duration: std::time::Duration,
+        // Slower than this counts as a failure (caches can't return errors)
+        pub call_timeout: std::time::Duration,
+    }
+
+    impl Default for CircuitBreakerConfig {
+        fn default() -> Self {
+            Self {
+                failure_threshold: 5,
+                open_duration: std::time::Duration::from_secs(30),
+                call_timeout: std::time::Duration::from_secs(2),
+            }
+        }
+    }
+
+    // How often each transition happened, plus calls turned away while open
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct CircuitMetrics {
+        pub opened: u64,
+        pub half_opened: u64,
+        pub closed: u64,
+        pub rejected: u64,
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    struct CircuitInner {
+        state: CircuitState,
+        consecutive_failures: u32,
+        opened_at: Option<std::time::Instant>,
+        probe_in_flight: bool,
+        metrics: CircuitMetrics,
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub struct CircuitBreaker {
+        name: String,
+        config: CircuitBreakerConfig,
+        inner: std::sync::Mutex<CircuitInner>,
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    impl CircuitBreaker {
+        pub fn new(name: &str, config: CircuitBreakerConfig) -> Self {
+            Self {
+                name: name.to_string(),
+                config,
+                inner: std::sync::Mutex::new(CircuitInner {
+                    state: CircuitState::Closed,
+                    consecutive_failures: 0,
+                    opened_at: None,
+                    probe_in_flight: false,
+                    metrics: CircuitMetrics::default(),
+                }),
+            }
+        }
+
+        pub fn state(&self) -> CircuitState {
+            self.inner.lock().unwrap().state
+        }
+
+        #[cfg(test)]
+        pub fn metrics(&self) -> CircuitMetrics {
+            self.inner.lock().unwrap().metrics
+        }
+
+        // Runs `call` unless the circuit is open. Transient errors and
+        // timeouts count as failures; a permanent one says nothing about
+        // whether the backend is up.
+        pub async fn call<T, Fut>(&self, call: impl FnOnce() -> Fut) -> Result<T, Error>
+        where
+            Fut: Future<Output = Result<T, Error>>,
+        {
+            self.acquire()?;
+
+            let result = match tokio::time::timeout(self.config.call_timeout, call()).await {
+                Ok(result) => result,
+                Err(_) => Err(Error::internal(format!("{}: timed out", self.name)).transient()),
+            };
+            self.record(!matches!(&result, Err(error) if error.is_retryable()));
+            result
+        }
+
+        fn acquire(&self) -> Result<(), Error> {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.state {
+                CircuitState::Closed => return Ok(()),
+                CircuitState::Open => {
+                    let cooled_down = inner
+                        .opened_at
+                        .is_some_and(|at| at.elapsed() >= self.config.open_duration);
+                    if cooled_down {
+                        self.transition(&mut inner, CircuitState::HalfOpen);
+                    }
+                }
+                CircuitState::HalfOpen => {}
+            }
+
+            if inner.state == CircuitState::HalfOpen && !inner.probe_in_flight {
+                inner.probe_in_flight = true;
+                return Ok(());
+            }
+
+            inner.metrics.rejected += 1;
+            Err(Error::internal(format!("{}: circuit open", self.name)).transient())
+        }
+
+        fn record(&self, success: bool) {
+            let mut inner = self.inner.lock().unwrap();
+            inner.probe_in_flight = false;
+
+            if success {
+                inner.consecutive_failures = 0;
+                if inner.state != CircuitState::Closed {
+                    self.transition(&mut inner, CircuitState::Closed);
+                }
+                return;
+            }
+
+            inner.consecutive_failures += 1;
+            let trips = inner.state == CircuitState::HalfOpen
+                || inner.consecutive_failures >= self.config.failure_threshold;
+            if trips && inner.state != CircuitState::Open {
+                self.transition(&mut inner, CircuitState::Open);
+            }
+        }
+
+        fn transition(&self, inner: &mut CircuitInner, to: CircuitState) {
+            tracing::warn!(circuit = %self.name, from = ?inner.state, ?to, "circuit state changed");
+            match to {
+                CircuitState::Open => {
+                    inner.opened_at = Some(std::time::Instant::now());
+                    inner.metrics.opened += 1;
+                }
+                CircuitState::HalfOpen => inner.metrics.half_opened += 1,
+                CircuitState::Closed => inner.metrics.closed += 1,
+            }
+            inner.state = to;
+        }
+    }
+
+    // Wraps any Arc<dyn UserRepository> or Arc<dyn CacheService>
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub struct WithCircuitBreaker<T: ?Sized> {
+        inner: Arc<T>,
+        breaker: CircuitBreaker,
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    impl<T: ?Sized> WithCircuitBreaker<T> {
+        pub fn new(inner: Arc<T>, breaker: CircuitBreaker) -> Self {
+            Self { inner, breaker }
+        }
+
+        #[cfg(test)]
+        pub fn breaker(&self) -> &CircuitBreaker {
+            &self.breaker
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for WithCircuitBreaker<dyn UserRepository> {
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            self.breaker.call(|| self.inner.find_by_email(email)).await
+        }
+
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            self.breaker.call(|| self.inner.find_by_id(id)).await
+        }
+
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            self.breaker.call(|| self.inner.list(filter, page)).await
+        }
+
+        async fn create(&self, user: User) -> Result<User, Error> {
+            self.breaker.call(|| self.inner.create(user)).await
+        }
+
+        async fn update(&self, user: User) -> Result<User, Error> {
+            self.breaker.call(|| self.inner.update(user)).await
+        }
+    }
+
+    // An open circuit shows up in readiness without calling the backend
+    #[async_trait]
+    impl HealthCheck for WithCircuitBreaker<dyn UserRepository> {
+        async fn check(&self) -> Result<(), Error> {
+            if self.breaker.state() == CircuitState::Open {
+                return Err(
+                    Error::internal(format!("{}: circuit open", self.breaker.name)).transient(),
+                );
+            }
+            self.inner.check().await
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for WithCircuitBreaker<dyn UserRepository> {
+        async fn start(&self) -> Result<(), Error> {
+            self.inner.start().await
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            self.inner.shutdown().await
+        }
+    }
+
+    // Open circuit = cache miss / dropped write, same as LazyCache
+    #[async_trait]
+    impl CacheService for WithCircuitBreaker<dyn CacheService> {
+        async fn get(&self, key: &str) -> Option<String> {
+            self.breaker
+                .call(|| async { Ok(self.inner.get(key).await) })
+                .await
+                .ok()
+                .flatten()
+        }
+
+        async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+            let _ = self
+                .breaker
+                .call(|| async {
+                    self.inner.set(key, value, ttl_seconds).await;
+                    Ok(())
+                })
+                .await;
+        }
+
+        async fn delete(&self, key: &str) {
+            let _ = self
+                .breaker
+                .call(|| async {
+                    self.inner.delete(key).await;
+                    Ok(())
+                })
+                .await;
+        }
+
+        async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+            self.breaker
+                .call(|| async { Ok(self.inner.incr(key, by, ttl_seconds).await) })
+                .await
+                .unwrap_or(0)
+        }
+
+        // One breaker call per batch, as it is one round trip
+        async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+            self.breaker
+                .call(|| async { Ok(self.inner.get_many(keys).await) })
+                .await
+                .unwrap_or_else(|_| vec![None; keys.len()])
+        }
+
+        async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+            let _ = self
+                .breaker
+                .call(|| async {
+                    self.inner.set_many(entries).await;
+                    Ok(())
+                })
+                .await;
+        }
+
+        async fn ttl(&self, key: &str) -> Option<u64> {
+            self.breaker
+                .call(|| async { Ok(self.inner.ttl(key).await) })
+                .await
+                .ok()
+                .flatten()
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for WithCircuitBreaker<dyn CacheService> {
+        async fn check(&self) -> Result<(), Error> {
+            if self.breaker.state() == CircuitState::Open {
+                return Err(
+                    Error::internal(format!("{}: circuit open", self.breaker.name)).transient(),
+                );
+            }
+            self.inner.check().await
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for WithCircuitBreaker<dyn CacheService> {
+        async fn start(&self) -> Result<(), Error> {
+            self.inner.start().await
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            self.inner.shutdown().await
+        }
+    }
+
+    // Retries
+    // -------
+    // A dropped connection or a deadlock often works on the second try; a
+    // constraint violation never does. RetryPolicy only repeats what
+    // Error::is_retryable says might succeed, waiting twice as long each
+    // time. It goes inside the circuit breaker, so the breaker counts one
+    // failure per call, not one per attempt, and an open circuit is never
+    // retried in a tight loop.
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    #[derive(Debug, Clone, Copy)]
+    pub struct RetryPolicy {
+        // Including the first one
+        pub max_attempts: u32,
+        pub base_delay: std::time::Duration,
+    }
+
+    impl Default for RetryPolicy {
+        fn default() -> Self {
+            Self {
+                max_attempts: 3,
+                base_delay: std::time::Duration::from_millis(50),
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    impl RetryPolicy {
+        pub async fn call<T, Fut>(&self, mut call: impl FnMut() -> Fut) -> Result<T, Error>
+        where
+            Fut: Future<Output = Result<T, Error>>,
+        {
+            let mut delay = self.base_delay;
+            let mut attempt = 1;
+            loop {
+                match call().await {
+                    Err(error) if error.is_retryable() && attempt < self.max_attempts => {
+                        tracing::warn!(
+                            attempt,
+                            ?delay,
+                            error = %error.report(),
+                            "attempt failed, retrying"
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                        attempt += 1;
+                    }
+                    result => return result,
+                }
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub struct WithRetry<T: ?Sized> {
+        inner: Arc<T>,
+        policy: RetryPolicy,
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    impl<T: ?Sized> WithRetry<T> {
+        pub fn new(inner: Arc<T>, policy: RetryPolicy) -> Self {
+            Self { inner, policy }
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for WithRetry<dyn UserRepository> {
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            self.policy.call(|| self.inner.find_by_email(email)).await
+        }
+
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            self.policy.call(|| self.inner.find_by_id(id)).await
+        }
+
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            self.policy.call(|| self.inner.list(filter, page)).await
+        }
+
+        // A transient failure means the write didn't happen (or the unique
+        // index turns the repeat into AlreadyExists), so these are safe too
+        async fn create(&self, user: User) -> Result<User, Error> {
+            self.policy.call(|| self.inner.create(user.clone())).await
+        }
+
+        async fn update(&self, user: User) -> Result<User, Error> {
+            self.policy.call(|| self.inner.update(user.clone())).await
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for WithRetry<dyn UserRepository> {
+        async fn check(&self) -> Result<(), Error> {
+            self.inner.check().await
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for WithRetry<dyn UserRepository> {
+        async fn start(&self) -> Result<(), Error> {
+            self.inner.start().await
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            self.inner.shutdown().await
+        }
+    }
+
+    // Slow queries
+    // ------------
+    // Every repository call is timed; one that takes longer than the
+    // threshold (DATABASE_SLOW_QUERY_MS) becomes a warning with the method,
+    // what it was asked and how long it took:
+    //
+    //     WARN request{...}:login{...}: slow query method="find_by_email"
+    //         args=email=me@example.com elapsed_ms=412 threshold_ms=200
+    //
+    // Outermost, so the time is what the service waited, retries and all.
+    // The args summary is only built for slow calls, and never includes a
+    // whole User (password_hash).
+
+    #[cfg(all(test, feature = "memory"))]
+    pub const DEFAULT_SLOW_QUERY_MS: u64 = 200;
+
+    #[cfg_attr(
+        not(any(feature = "postgres", feature = "sqlite", feature = "memory")),
+        allow(dead_code)
+    )]
+    pub struct WithSlowQueryLog<T: ?Sized> {
+        inner: Arc<T>,
+        threshold: std::time::Duration,
+        metrics: Arc<dyn Metrics>,
+    }
+
+    #[cfg_attr(
+        not(any(feature = "postgres", feature = "sqlite", feature = "memory")),
+        allow(dead_code)
+    )]
+    impl<T: ?Sized> WithSlowQueryLog<T> {
+        pub fn new(inner: Arc<T>, threshold: std::time::Duration) -> Self {
+            Self {
+                inner,
+                threshold,
+                metrics: Arc::new(NoopMetrics),
+            }
+        }
+
+        // Every call, slow or not, also goes into
+        // db_query_duration_seconds{method}
+        pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+            self.metrics = metrics;
+            self
+        }
+
+        async fn timed<R>(
+            &self,
+            method: &'static str,
+            args: impl FnOnce() -> String,
+            call: impl Future<Output = R>,
+        ) -> R {
+            let started = std::time::Instant::now();
+            let result = call.await;
+            let elapsed = started.elapsed();
+            self.metrics.histogram(
+                "db_query_duration_seconds",
+                &[("method", method)],
+                elapsed.as_secs_f64(),
+            );
+            if elapsed > self.threshold {
+                tracing::warn!(
+                    method,
+                    args = %args(),
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    threshold_ms = self.threshold.as_millis() as u64,
+                    "slow query"
+                );
+            }
+            result
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for WithSlowQueryLog<dyn UserRepository> {
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            self.timed(
+                "find_by_email",
+                || format!("email={}", email),
+                self.inner.find_by_email(email),
+            )
+            .await
+        }
+
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            self.timed(
+                "find_by_id",
+                || format!("id={}", id),
+                self.inner.find_by_id(id),
+            )
+            .await
+        }
+
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            self.timed(
+                "list",
+                || format!("{:?} {:?}", filter, page),
+                self.inner.list(filter, page),
+            )
+            .await
+        }
+
+        async fn create(&self, user: User) -> Result<User, Error> {
+            let id = user.id.clone();
+            self.timed(
+                "create",
+                || format!("user.id={}", id),
+                self.inner.create(user),
+            )
+            .await
+        }
+
+        async fn update(&self, user: User) -> Result<User, Error> {
+            let id = user.id.clone();
+            self.timed(
+                "update",
+                || format!("user.id={}", id),
+                self.inner.update(user),
+            )
+            .await
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for WithSlowQueryLog<dyn UserRepository> {
+        async fn check(&self) -> Result<(), Error> {
+            self.inner.check().await
+        }
+    }
+
+    #[async_trait]
+    impl Lifecycle for WithSlowQueryLog<dyn UserRepository> {
+        async fn start(&self) -> Result<(), Error> {
+            self.inner.start().await
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            self.inner.shutdown().await
+        }
+    }
+
+    // Sessions as cache entries that expire on their own. With Redis behind
+    // the cache, every instance sees every session.
+    pub struct CacheSessionStore {
+        cache: Arc<dyn CacheService>,
+    }
+
+    impl CacheSessionStore {
+        pub fn new(cache: Arc<dyn CacheService>) -> Self {
+            Self { cache }
+        }
+
+        fn key(session_id: &str) -> String {
+            format!("session:{}", session_id)
+        }
+    }
+
+    #[async_trait]
+    impl SessionStore for CacheSessionStore {
+        // Two v4 UUIDs: 244 random bits, nothing to guess from
+        async fn create(&self, user_id: &str, ttl_seconds: u64) -> Result<String, Error> {
+            let session_id = format!(
+                "{}{}",
+                uuid::Uuid::new_v4().simple(),
+                uuid::Uuid::new_v4().simple()
+            );
+            self.cache
+                .set(
+                    &Self::key(&session_id),
+                    user_id.to_string(),
+                    Some(ttl_seconds),
+                )
+                .await;
+            Ok(session_id)
+        }
+
+        async fn get(&self, session_id: &str) -> Result<Option<String>, Error> {
+            Ok(self.cache.get(&Self::key(session_id)).await)
+        }
+
+        async fn delete(&self, session_id: &str) -> Result<(), Error> {
+            self.cache.delete(&Self::key(session_id)).await;
+            Ok(())
+        }
+    }
+
+    // Files on the local disk, under `root`. Fine for dev and one instance;
+    // with several, each has its own disk and only one of them has the file.
+    pub struct LocalStorage {
+        root: std::path::PathBuf,
+        public_url: String,
+    }
+
+    impl LocalStorage {
+        pub fn new(root: impl Into<std::path::PathBuf>, public_url: &str) -> Self {
+            Self {
+                root: root.into(),
+                public_url: public_url.trim_end_matches('/').to_string(),
+            }
+        }
+
+        // Keys are ours (see ProfileService), but a `..` would still escape
+        // the root, so refuse anything that isn't plain path segments
+        fn path(&self, key: &str) -> Result<std::path::PathBuf, Error> {
+            let plain = key
+                .split('/')
+                .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+            if !plain {
+                return Err(Error::internal(format!("invalid storage key: {:?}", key)));
+            }
+            Ok(self.root.join(key))
+        }
+    }
+
+    #[async_trait]
+    impl StorageService for LocalStorage {
+        async fn put(
+            &self,
+            key: &str,
+            _content_type: &str,
+            bytes: Vec<u8>,
+        ) -> Result<String, Error> {
+            let path = self.path(key)?;
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .map_err(|e| Error::with_source(format!("creating {}", dir.display()), e))?;
+            }
+            tokio::fs::write(&path, bytes)
+                .await
+                .map_err(|e| Error::with_source(format!("writing {}", path.display()), e))?;
+            Ok(format!("{}/{}", self.public_url, key))
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), Error> {
+            let path = self.path(key)?;
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::with_source(
+                    format!("deleting {}", path.display()),
+                    e,
+                )),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    // s3://bucket/prefix. Pretend, like the Postgres and Redis ones.
+    #[cfg(feature = "s3")]
+    pub struct S3Storage {
+        bucket: String,
+        prefix: String,
+        public_url: String,
+    }
+
+    #[cfg(feature = "s3")]
+    impl S3Storage {
+        pub fn new(url: &str, public_url: &str) -> Self {
+            let location = url.strip_prefix("s3://").unwrap_or(url);
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            Self {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_matches('/').to_string(),
+                public_url: public_url.trim_end_matches('/').to_string(),
+            }
+        }
+
+        fn object_key(&self, key: &str) -> String {
+            if self.prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}/{}", self.prefix, key)
+            }
+        }
+    }
+
+    #[cfg(feature = "s3")]
+    #[async_trait]
+    impl StorageService for S3Storage {
+        // In real code: client.put_object().bucket(..).key(..).content_type(..).body(..)
+        async fn put(
+            &self,
+            key: &str,
+            content_type: &str,
+            bytes: Vec<u8>,
+        ) -> Result<String, Error> {
+            tracing::debug!(
+                bucket = %self.bucket,
+                key = %self.object_key(key),
+                content_type,
+                bytes = bytes.len(),
+                "S3 PUT"
+            );
+            Ok(format!("{}/{}", self.public_url, self.object_key(key)))
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), Error> {
+            tracing::debug!(bucket = %self.bucket, key = %self.object_key(key), "S3 DELETE");
+            Ok(())
+        }
+    }
+
+    // Error reporting
+    // ---------------
+
+    // Without SENTRY_DSN: the log already has the error
+    pub struct NoErrorReporter;
+
+    impl ErrorReporter for NoErrorReporter {
+        fn report(&self, _event: ErrorEvent) {}
+    }
+
+    // Posts each event to the store endpoint of the Sentry project a DSN
+    // like https://<key>@o0.ingest.sentry.io/<project> names. Fire and
+    // forget: Sentry being down costs a warning in the log, not the request.
+    #[cfg(feature = "sentry")]
+    pub struct SentryErrorReporter {
+        client: reqwest::Client,
+        pub store_url: String,
+        // The X-Sentry-Auth header
+        pub auth: String,
+    }
+
+    #[cfg(feature = "sentry")]
+    impl SentryErrorReporter {
+        // config.rs has already checked the DSN's shape
+        pub fn new(dsn: &str) -> Self {
+            let (scheme, rest) = dsn.split_once("://").expect("SENTRY_DSN is validated");
+            let (key, rest) = rest.split_once('@').expect("SENTRY_DSN is validated");
+            let (host, project) = rest.rsplit_once('/').expect("SENTRY_DSN is validated");
+            // Old DSNs carry `key:secret`; the secret isn't needed any more
+            let key = key.split(':').next().unwrap_or(key);
+            Self {
+                client: reqwest::Client::new(),
+                store_url: format!("{}://{}/api/{}/store/", scheme, host, project),
+                auth: format!(
+                    "Sentry sentry_version=7, sentry_client=refactoring_with_di/0.1, sentry_key={}",
+                    key
+                ),
+            }
+        }
+
+        // Sentry's event format; tag values are strings
+        pub fn payload(event: &ErrorEvent) -> serde_json::Value {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            let mut tags = serde_json::json!({
+                "code": event.code,
+                "transient": event.transient.to_string(),
+            });
+            if let Some(request_id) = &event.request_id {
+                tags["request_id"] = request_id.as_str().into();
+            }
+            serde_json::json!({
+                "event_id": uuid::Uuid::new_v4().simple().to_string(),
+                "timestamp": timestamp,
+                "platform": "rust",
+                "level": "error",
+                "logger": "refactoring_with_di",
+                "message": { "formatted": event.message },
+                "tags": tags,
+                "extra": { "backtrace": event.backtrace },
+            })
+        }
+    }
+
+    #[cfg(feature = "sentry")]
+    impl ErrorReporter for SentryErrorReporter {
+        fn report(&self, event: ErrorEvent) {
+            let request = self
+                .client
+                .post(&self.store_url)
+                .header("x-sentry-auth", &self.auth)
+                .json(&Self::payload(&event));
+            tokio::spawn(async move {
+                let sent = request
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status);
+                // Not log_internal, which would report the failure to report
+                if let Err(e) = sent {
+                    tracing::warn!(error = %e, "reporting to Sentry failed");
+                }
+            });
+        }
+    }
+
+    // Time
+    // ----
+
+    // The OS clock and tokio's timer
+    pub struct SystemClock;
+
+    #[async_trait]
+    impl Clock for SystemClock {
+        fn now(&self) -> std::time::SystemTime {
+            std::time::SystemTime::now()
+        }
+
+        async fn sleep(&self, duration: std::time::Duration) {
+            tokio::time::sleep(duration).await
+        }
+    }
+
+    // IDs
+    // ---
+
+    // UUIDv7 starts with the creation time, so IDs sort in the order users
+    // signed up and new rows go at the end of the primary key index instead
+    // of at random places in it
+    pub struct UuidV7Generator;
+
+    impl IdGenerator for UuidV7Generator {
+        fn next_id(&self) -> String {
+            uuid::Uuid::now_v7().to_string()
+        }
+    }
+
+    // 4. Mock implementations for testing
+    // ====================================
+
+    pub struct MockUserRepository {
+        users: std::sync::Mutex<Vec<User>>,
+    }
+
+    impl MockUserRepository {
+        // The memory backend; otherwise only the tests build one
+        #[cfg_attr(not(feature = "memory"), allow(dead_code))]
+        pub fn new() -> Self {
+            Self {
+                users: std::sync::Mutex::new(Vec::new()),
+            }
+        }
+
+        #[cfg(test)]
+        pub fn with_user(user: User) -> Self {
+            Self {
+                users: std::sync::Mutex::new(vec![user]),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for MockUserRepository {
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().find(|u| u.email == email).cloned())
+        }
+
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().find(|u| u.id == id).cloned())
+        }
+
+        // Insertion order, like an auto-increment primary key
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            let users = self.users.lock().unwrap();
+            let matching: Vec<&User> = users.iter().filter(|user| filter.matches(user)).collect();
+            Ok(Page {
+                items: matching
+                    .iter()
+                    .skip(page.offset())
+                    .take(page.per_page as usize)
+                    .map(|user| (*user).clone())
+                    .collect(),
+                page: page.page,
+                per_page: page.per_page,
+                total: matching.len() as u64,
+            })
+        }
+
+        // Unique id and email, like the primary key and the index on email
+        async fn create(&self, user: User) -> Result<User, Error> {
+            let mut users = self.users.lock().unwrap();
+            if users
+                .iter()
+                .any(|u| u.id == user.id || u.email == user.email)
+            {
+                return Err(Error::AlreadyExists);
+            }
+            users.push(user.clone());
+            Ok(user)
+        }
+
+        async fn update(&self, user: User) -> Result<User, Error> {
+            let mut users = self.users.lock().unwrap();
+            if users
+                .iter()
+                .any(|u| u.id != user.id && u.email == user.email)
+            {
+                return Err(Error::AlreadyExists);
+            }
+            if let Some(existing) = users.iter_mut().find(|u| u.id == user.id) {
+                *existing = user.clone();
+                Ok(user)
+            } else {
+                Err(Error::NotFound)
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for MockUserRepository {
+        async fn check(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Lifecycle for MockUserRepository {}
+
+    // Kept for as long as the process runs
+    #[derive(Default)]
+    pub struct MockAuditRepository {
+        entries: std::sync::Mutex<Vec<AuditEntry>>,
+    }
+
+    #[async_trait]
+    impl AuditRepository for MockAuditRepository {
+        async fn append(&self, entry: AuditEntry) -> Result<(), Error> {
+            self.entries.lock().unwrap().push(entry);
+            Ok(())
+        }
+
+        async fn query(
+            &self,
+            filter: &AuditFilter,
+            page: PageRequest,
+        ) -> Result<Page<AuditEntry>, Error> {
+            let entries = self.entries.lock().unwrap();
+            let matching: Vec<&AuditEntry> = entries
+                .iter()
+                .rev()
+                .filter(|entry| filter.matches(entry))
+                .collect();
+            Ok(Page {
+                items: matching
+                    .iter()
+                    .skip(page.offset())
+                    .take(page.per_page as usize)
+                    .map(|entry| (*entry).clone())
+                    .collect(),
+                page: page.page,
+                per_page: page.per_page,
+                total: matching.len() as u64,
+            })
+        }
+    }
+
+    pub struct MockPasswordHasher;
+
+    #[async_trait]
+    impl PasswordHasher for MockPasswordHasher {
+        fn hash(&self, password: &str) -> String {
+            format!("mock_hash_{}", password)
+        }
+
+        fn verify(&self, password: &str, hash: &str) -> bool {
+            hash == format!("mock_hash_{}", password)
+        }
+    }
+
+    pub struct MockTokenService;
+
+    #[async_trait]
+    impl TokenService for MockTokenService {
+        async fn generate(&self, user_id: &str) -> Result<String, Error> {
+            Ok(format!("mock_token_{}", user_id))
+        }
+
+        async fn validate(&self, token: &str) -> Result<String, Error> {
+            token
+                .strip_prefix("mock_token_")
+                .map(str::to_string)
+                .ok_or(Error::InvalidCredentials)
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for MockTokenService {
+        async fn check(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    // Keeps files in a map; URLs are memory://<key>
+    pub struct MockStorage {
+        files: std::sync::Mutex<std::collections::HashMap<String, (String, Vec<u8>)>>,
+    }
+
+    impl MockStorage {
+        pub fn new() -> Self {
+            Self {
+                files: std::sync::Mutex::new(std::collections::HashMap::new()),
+            }
+        }
+
+        #[cfg(test)]
+        // Content type and bytes, for tests
+        pub fn get(&self, key: &str) -> Option<(String, Vec<u8>)> {
+            self.files.lock().unwrap().get(key).cloned()
+        }
+    }
+
+    #[async_trait]
+    impl StorageService for MockStorage {
+        async fn put(
+            &self,
+            key: &str,
+            content_type: &str,
+            bytes: Vec<u8>,
+        ) -> Result<String, Error> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (content_type.to_string(), bytes));
+            Ok(format!("memory://{}", key))
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), Error> {
+            self.files.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    // Values with the second they expire at, if they do
+    type CacheEntries = std::collections::HashMap<String, (String, Option<u64>)>;
+
+    pub struct MockCache {
+        cache: std::sync::Mutex<CacheEntries>,
+        clock: Arc<dyn Clock>,
+    }
+
+    impl MockCache {
+        pub fn new() -> Self {
+            Self {
+                cache: std::sync::Mutex::new(std::collections::HashMap::new()),
+                clock: Arc::new(SystemClock),
+            }
+        }
+
+        // TTLs run on this clock; with a MockClock, entries expire when the
+        // test advances it
+        pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        // Expired entries are dropped on the next look, like Redis does lazily
+        fn live<'a>(
+            &self,
+            cache: &'a mut CacheEntries,
+            key: &str,
+        ) -> Option<&'a mut (String, Option<u64>)> {
+            let now = self.clock.unix_seconds();
+            if cache
+                .get(key)
+                .is_some_and(|(_, expires)| expires.is_some_and(|at| at <= now))
+            {
+                cache.remove(key);
+            }
+            cache.get_mut(key)
+        }
+
+        fn expiry(&self, ttl_seconds: Option<u64>) -> Option<u64> {
+            ttl_seconds.map(|ttl| self.clock.unix_seconds() + ttl)
+        }
+    }
+
+    #[async_trait]
+    impl CacheService for MockCache {
+        async fn get(&self, key: &str) -> Option<String> {
+            let mut cache = self.cache.lock().unwrap();
+            self.live(&mut cache, key).map(|(value, _)| value.clone())
+        }
+
+        // Like SET with EX: a set without a TTL clears the old one
+        async fn set(&self, key: &str, value: String, ttl: Option<u64>) {
+            let expires = self.expiry(ttl);
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (value, expires));
+        }
+
+        async fn delete(&self, key: &str) {
+            self.cache.lock().unwrap().remove(key);
+        }
+
+        // Under one lock, like Redis. Like EXPIRE NX, only a counter without
+        // a TTL gets this one, so a window keeps its first expiry.
+        async fn incr(&self, key: &str, by: i64, ttl: Option<u64>) -> i64 {
+            let expires = self.expiry(ttl);
+            let mut cache = self.cache.lock().unwrap();
+            let (value, current_expiry) = match self.live(&mut cache, key) {
+                // INCRBY on a string fails, which RedisCache reads as 0
+                Some((current, current_expiry)) => match current.parse::<i64>() {
+                    Ok(current) => (current + by, current_expiry.or(expires)),
+                    Err(_) => return 0,
+                },
+                None => (by, expires),
+            };
+            cache.insert(key.to_string(), (value.to_string(), current_expiry));
+            value
+        }
+
+        async fn ttl(&self, key: &str) -> Option<u64> {
+            let mut cache = self.cache.lock().unwrap();
+            let expires = self.live(&mut cache, key)?.1?;
+            Some(expires.saturating_sub(self.clock.unix_seconds()))
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for MockCache {
+        async fn check(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Lifecycle for MockCache {}
+
+    // Time that only moves when a test says so. It starts at a fixed
+    // instant, so timestamps in assertions don't depend on when the test
+    // ran, and sleep() returns once advance() has passed its deadline.
+    #[cfg(test)]
+    pub struct MockClock {
+        now: tokio::sync::watch::Sender<std::time::SystemTime>,
+    }
+
+    #[cfg(test)]
+    impl MockClock {
+        // 2026-01-01T00:00:00Z
+        pub const START: u64 = 1_767_225_600;
+
+        pub fn new() -> Self {
+            let start = std::time::UNIX_EPOCH + std::time::Duration::from_secs(Self::START);
+            Self {
+                now: tokio::sync::watch::Sender::new(start),
+            }
+        }
+
+        // Wakes every sleep whose deadline this reaches
+        pub fn advance(&self, by: std::time::Duration) {
+            self.now.send_modify(|now| *now += by);
+        }
+    }
+
+    #[cfg(test)]
+    impl Default for MockClock {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[cfg(test)]
+    #[async_trait]
+    impl Clock for MockClock {
+        fn now(&self) -> std::time::SystemTime {
+            *self.now.borrow()
+        }
+
+        async fn sleep(&self, duration: std::time::Duration) {
+            let deadline = self.now() + duration;
+            // The sender lives as long as self, so this only ends one way
+            let _ = self.now.subscribe().wait_for(|now| *now >= deadline).await;
+        }
+    }
+
+    // "id-1", "id-2", ... in the order they're asked for. Not UUIDs, so
+    // they can't collide with the IDs UserBuilder gives test users.
+    #[derive(Default)]
+    pub struct SequentialIdGenerator {
+        issued: std::sync::atomic::AtomicU64,
+    }
+
+    impl SequentialIdGenerator {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl IdGenerator for SequentialIdGenerator {
+        fn next_id(&self) -> String {
+            let n = self
+                .issued
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                + 1;
+            format!("id-{}", n)
+        }
+    }
+
+    // Keeps every event in memory for a test to look at
+    #[derive(Default)]
+    pub struct MockErrorReporter {
+        events: std::sync::Mutex<Vec<ErrorEvent>>,
+    }
+
+    impl MockErrorReporter {
+        #[cfg(test)]
+        pub fn events(&self) -> Vec<ErrorEvent> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    impl ErrorReporter for MockErrorReporter {
+        fn report(&self, event: ErrorEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    // Generated mocks (mockall)
+    // -------------------------
+    // The hand-written mocks above are fakes: they behave like a tiny
+    // in-memory backend, which is easy to read but can't answer "was
+    // create() called?" or "with which email?". mockall generates mocks that
+    // record calls and check expectations when dropped:
+    //
+    //     let mut repository = generated::MockUserRepository::new();
+    //     repository
+    //         .expect_find_by_email()
+    //         .with(eq("test@example.com"))
+    //         .times(1)
+    //         .returning(|_| Ok(None));
+    //
+    // Same struct names as the fakes, so they live in their own module.
+    // mockall is a dev-dependency, hence cfg(test).
+    #[cfg(test)]
+    pub mod generated {
+        use super::{Error, Page, PageRequest, User, UserFilter};
+        use async_trait::async_trait;
+
+        mockall::mock! {
+            pub UserRepository {}
+
+            #[async_trait]
+            impl super::HealthCheck for UserRepository {
+                async fn check(&self) -> Result<(), Error>;
+            }
+
+            impl super::Lifecycle for UserRepository {}
+
+            #[async_trait]
+            impl super::UserRepository for UserRepository {
+                async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error>;
+                async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error>;
+                async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error>;
+                async fn create(&self, user: User) -> Result<User, Error>;
+                async fn update(&self, user: User) -> Result<User, Error>;
+            }
+        }
+
+        mockall::mock! {
+            pub CacheService {}
+
+            #[async_trait]
+            impl super::HealthCheck for CacheService {
+                async fn check(&self) -> Result<(), Error>;
+            }
+
+            impl super::Lifecycle for CacheService {}
+
+            #[async_trait]
+            impl super::CacheService for CacheService {
+                async fn get(&self, key: &str) -> Option<String>;
+                async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>);
+                async fn delete(&self, key: &str);
+                async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64;
+            }
+        }
+
+        mockall::mock! {
+            pub TokenService {}
+
+            #[async_trait]
+            impl super::HealthCheck for TokenService {
+                async fn check(&self) -> Result<(), Error>;
+            }
+
+            #[async_trait]
+            impl super::TokenService for TokenService {
+                async fn generate(&self, user_id: &str) -> Result<String, Error>;
+                async fn validate(&self, token: &str) -> Result<String, Error>;
+            }
+        }
+
+        mockall::mock! {
+            pub PasswordHasher {}
+
+            impl super::PasswordHasher for PasswordHasher {
+                fn hash(&self, password: &str) -> String;
+                fn verify(&self, password: &str, hash: &str) -> bool;
+            }
+        }
+    }
+
+    // Failure injection
+    // -----------------
+    // Wraps the mocks so a test can make dependencies slow or unreliable.
+    // Failures are spread evenly by call count instead of drawn at random:
+    // 25% means calls 4, 8, 12, ... fail, so a test sees the same sequence
+    // on every run.
+
+    #[cfg(test)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct FailureConfig {
+        pub failure_percent: u8,
+        pub latency: std::time::Duration,
+    }
+
+    #[cfg(test)]
+    impl FailureConfig {
+        pub fn failing(failure_percent: u8) -> Self {
+            Self {
+                failure_percent: failure_percent.min(100),
+                ..Self::default()
+            }
+        }
+
+        pub fn with_latency(mut self, latency: std::time::Duration) -> Self {
+            self.latency = latency;
+            self
+        }
+    }
+
+    #[cfg(test)]
+    pub struct FaultInjector {
+        config: FailureConfig,
+        calls: std::sync::atomic::AtomicU64,
+    }
+
+    #[cfg(test)]
+    impl FaultInjector {
+        pub fn new(config: FailureConfig) -> Self {
+            Self {
+                config,
+                calls: std::sync::atomic::AtomicU64::new(0),
+            }
+        }
+
+        // Call n fails when n * percent crosses the next multiple of 100
+        pub async fn before_call(&self, operation: &str) -> Result<(), Error> {
+            if !self.config.latency.is_zero() {
+                tokio::time::sleep(self.config.latency).await;
+            }
+
+            let n = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            let percent = u64::from(self.config.failure_percent);
+            if n * percent / 100 > (n - 1) * percent / 100 {
+                return Err(Error::internal(format!("injected failure: {}", operation)).transient());
+            }
+            Ok(())
+        }
+
+        pub fn calls(&self) -> u64 {
+            self.calls.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[cfg(test)]
+    pub struct FlakyUserRepository {
+        inner: Arc<dyn UserRepository>,
+        faults: Arc<FaultInjector>,
+    }
+
+    #[cfg(test)]
+    impl FlakyUserRepository {
+        pub fn new(inner: Arc<dyn UserRepository>, faults: Arc<FaultInjector>) -> Self {
+            Self { inner, faults }
+        }
+    }
+
+    #[cfg(test)]
+    #[async_trait]
+    impl UserRepository for FlakyUserRepository {
+        async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+            self.faults.before_call("find_by_email").await?;
+            self.inner.find_by_email(email).await
+        }
+
+        async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error> {
+            self.faults.before_call("find_by_id").await?;
+            self.inner.find_by_id(id).await
+        }
+
+        async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error> {
+            self.faults.before_call("list").await?;
+            self.inner.list(filter, page).await
+        }
+
+        async fn create(&self, user: User) -> Result<User, Error> {
+            self.faults.before_call("create").await?;
+            self.inner.create(user).await
+        }
+
+        async fn update(&self, user: User) -> Result<User, Error> {
+            self.faults.before_call("update").await?;
+            self.inner.update(user).await
+        }
+    }
+
+    #[cfg(test)]
+    #[async_trait]
+    impl HealthCheck for FlakyUserRepository {
+        async fn check(&self) -> Result<(), Error> {
+            self.inner.check().await
+        }
+    }
+
+    #[cfg(test)]
+    impl Lifecycle for FlakyUserRepository {}
+
+    #[cfg(test)]
+    pub struct FlakyTokenService {
+        inner: Arc<dyn TokenService>,
+        faults: Arc<FaultInjector>,
+    }
+
+    #[cfg(test)]
+    impl FlakyTokenService {
+        pub fn new(inner: Arc<dyn TokenService>, faults: Arc<FaultInjector>) -> Self {
+            Self { inner, faults }
+        }
+    }
+
+    #[cfg(test)]
+    #[async_trait]
+    impl TokenService for FlakyTokenService {
+        async fn generate(&self, user_id: &str) -> Result<String, Error> {
+            self.faults.before_call("generate").await?;
+            self.inner.generate(user_id).await
+        }
+
+        async fn validate(&self, token: &str) -> Result<String, Error> {
+            self.faults.before_call("validate").await?;
+            self.inner.validate(token).await
+        }
+    }
+
+    #[cfg(test)]
+    #[async_trait]
+    impl HealthCheck for FlakyTokenService {
+        async fn check(&self) -> Result<(), Error> {
+            self.inner.check().await
+        }
+    }
+
+    // 5. Factory for easy setup
+    // ==========================
+
+    // Which implementation to use is a configuration value,
+    // not something hard-coded in the factory
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum RepositoryBackend {
+        #[cfg(feature = "postgres")]
+        Postgres,
+        #[cfg(feature = "sqlite")]
+        Sqlite,
+        #[cfg(feature = "memory")]
+        Memory,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CacheBackend {
+        #[cfg(feature = "redis")]
+        Redis,
+        #[cfg(feature = "memory")]
+        Memory,
+    }
+
+    // A backend that exists but wasn't compiled in deserves a better
+    // message than "unknown"
+    // Unused only when every backend is compiled in
+    #[cfg_attr(
+        all(
+            feature = "memory",
+            feature = "postgres",
+            feature = "sqlite",
+            feature = "redis",
+            feature = "s3",
+            feature = "sentry"
+        ),
+        allow(dead_code)
+    )]
+    fn feature_disabled(backend: &str) -> String {
+        format!(
+            "{} backend is not compiled in (enable the `{}` feature)",
+            backend, backend
+        )
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum StorageBackend {
+        Local,
+        #[cfg(feature = "s3")]
+        S3,
+    }
+
+    // Chosen by PASSWORD_HASHER
+    pub use config::HasherKind;
+
+    impl std::str::FromStr for RepositoryBackend {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                #[cfg(feature = "postgres")]
+                "postgres" => Ok(RepositoryBackend::Postgres),
+                #[cfg(feature = "sqlite")]
+                "sqlite" => Ok(RepositoryBackend::Sqlite),
+                #[cfg(feature = "memory")]
+                "memory" => Ok(RepositoryBackend::Memory),
+                #[cfg(not(feature = "postgres"))]
+                "postgres" => Err(feature_disabled(s)),
+                #[cfg(not(feature = "sqlite"))]
+                "sqlite" => Err(feature_disabled(s)),
+                #[cfg(not(feature = "memory"))]
+                "memory" => Err(feature_disabled(s)),
+                other => Err(format!("unknown repository backend: {}", other)),
+            }
+        }
+    }
+
+    impl std::str::FromStr for CacheBackend {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                #[cfg(feature = "redis")]
+                "redis" => Ok(CacheBackend::Redis),
+                #[cfg(feature = "memory")]
+                "memory" => Ok(CacheBackend::Memory),
+                #[cfg(not(feature = "redis"))]
+                "redis" => Err(feature_disabled(s)),
+                #[cfg(not(feature = "memory"))]
+                "memory" => Err(feature_disabled(s)),
+                other => Err(format!("unknown cache backend: {}", other)),
+            }
+        }
+    }
+
+    impl std::str::FromStr for StorageBackend {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "file" => Ok(StorageBackend::Local),
+                #[cfg(feature = "s3")]
+                "s3" => Ok(StorageBackend::S3),
+                #[cfg(not(feature = "s3"))]
+                "s3" => Err(feature_disabled(s)),
+                other => Err(format!("unknown storage backend: {}", other)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct AppConfig {
+        pub repository: RepositoryBackend,
+        // Read only by the backends that use them
+        #[cfg_attr(not(any(feature = "postgres", feature = "sqlite")), allow(dead_code))]
+        pub database_url: SecretString,
+        #[cfg_attr(
+            not(any(feature = "postgres", feature = "sqlite", feature = "memory")),
+            allow(dead_code)
+        )]
+        pub slow_query_ms: u64,
+        pub cache: CacheBackend,
+        #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+        pub redis_url: SecretString,
+        pub hasher: HasherKind,
+        pub jwt_secret: SecretString,
+        pub jwt_ttl_seconds: u64,
+        pub storage: StorageBackend,
+        pub storage_url: String,
+        pub storage_public_url: String,
+        pub session_ttl_seconds: u64,
+        // Where Internal errors are reported; None reports nowhere
+        pub sentry_dsn: Option<String>,
+        pub metrics: config::MetricsBackend,
+        pub statsd_addr: String,
+    }
+
+    impl AppConfig {
+        // Backends follow from the validated settings: the database URL's
+        // scheme picks the repository, a Redis URL turns on the Redis cache,
+        // the storage URL's scheme picks where uploads go
+        // A backend that isn't compiled in is reported like any other
+        // invalid setting
+        pub fn from_settings(settings: &config::AppConfig) -> Result<Self, config::ConfigErrors> {
+            let scheme = settings
+                .database
+                .url
+                .expose()
+                .split_once("://")
+                .map_or("", |(scheme, _)| scheme);
+            let repository = match scheme {
+                "postgresql" => "postgres",
+                other => other,
+            }
+            .parse::<RepositoryBackend>()
+            .map_err(|message| config::ConfigError::new("DATABASE_URL", message));
+
+            let cache = match settings.redis.url {
+                Some(_) => "redis",
+                None => "memory",
+            }
+            .parse::<CacheBackend>()
+            .map_err(|message| config::ConfigError::new("REDIS_URL", message));
+
+            let storage = settings
+                .storage
+                .url
+                .split_once("://")
+                .map_or("", |(scheme, _)| scheme)
+                .parse::<StorageBackend>()
+                .map_err(|message| config::ConfigError::new("STORAGE_URL", message));
+
+            let sentry_dsn = match &settings.log.sentry_dsn {
+                #[cfg(not(feature = "sentry"))]
+                Some(_) => Err(config::ConfigError::new(
+                    "SENTRY_DSN",
+                    feature_disabled("sentry"),
+                )),
+                dsn => Ok(dsn.clone()),
+            };
+
+            match (repository, cache, storage, sentry_dsn) {
+                (Ok(repository), Ok(cache), Ok(storage), Ok(sentry_dsn)) => Ok(Self {
+                    repository,
+                    database_url: settings.database.url.clone(),
+                    slow_query_ms: settings.database.slow_query_ms,
+                    cache,
+                    redis_url: settings.redis.url.clone().unwrap_or_default(),
+                    hasher: settings.password_hasher,
+                    jwt_secret: settings.jwt.secret.clone(),
+                    jwt_ttl_seconds: settings.jwt.ttl_seconds,
+                    storage,
+                    storage_url: settings.storage.url.clone(),
+                    storage_public_url: settings.storage.public_url.clone(),
+                    session_ttl_seconds: settings.session.ttl_seconds,
+                    sentry_dsn,
+                    metrics: settings.metrics.backend,
+                    statsd_addr: settings.metrics.statsd_addr.clone(),
+                }),
+                (repository, cache, storage, sentry_dsn) => Err(config::ConfigErrors(
+                    [
+                        repository.err(),
+                        cache.err(),
+                        storage.err(),
+                        sentry_dsn.err(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                )),
+            }
+        }
+
+        // What `profile` would wire, with placeholder URLs and secret: for
+        // tests, never for a real deployment
+        #[cfg(test)]
+        pub fn for_profile(profile: config::Profile) -> Result<Self, config::ConfigErrors> {
+            let settings = config::AppConfig::from_lookup(|key| match key {
+                "APP_ENV" => Some(profile.to_string()),
+                _ if profile.is_dev() => None,
+                "DATABASE_URL" => Some("postgres://localhost/app".to_string()),
+                "REDIS_URL" => Some("redis://localhost".to_string()),
+                "JWT_SECRET" => Some("placeholder-secret-for-presets-only".to_string()),
+                _ => None,
+            })?;
+            Self::from_settings(&settings)
+        }
+    }
+
+    pub struct AuthServiceFactory;
+
+    impl AuthServiceFactory {
+        // Every binding the auth stack needs, chosen from configuration.
+        // Dependencies are singletons; AuthService itself is scoped so each
+        // request (scope) gets its own instance.
+        //
+        // Built without any repository (or any cache) backend, that enum has
+        // no variants: from_settings rejects every URL, no AppConfig can
+        // exist, and the match on it is where this function stops. Allowed
+        // for those builds only, so `--features redis` alone stays clean.
+        #[cfg_attr(
+            any(
+                not(any(feature = "postgres", feature = "sqlite", feature = "memory")),
+                not(any(feature = "redis", feature = "memory"))
+            ),
+            allow(unreachable_code)
+        )]
+        pub fn container(config: &AppConfig) -> Container {
+            let mut container = Container::new();
+            container
+                .singleton::<dyn Clock, _>(|_| Arc::new(SystemClock))
+                .singleton::<dyn IdGenerator, _>(|_| Arc::new(UuidV7Generator));
+            #[cfg(any(feature = "postgres", feature = "sqlite", feature = "memory"))]
+            let slow_query = std::time::Duration::from_millis(config.slow_query_ms);
+            #[cfg(any(feature = "postgres", feature = "sqlite", feature = "memory"))]
+            let timed = move |scope: &Scope, repository: Arc<dyn UserRepository>| {
+                Arc::new(
+                    WithSlowQueryLog::<dyn UserRepository>::new(repository, slow_query)
+                        .with_metrics(scope.resolve().expect("Metrics is registered")),
+                )
+            };
+
+            // The audit trail lives in the users' database, on the same pool
+            match config.repository {
+                // Connected on first query, not here
+                #[cfg(feature = "postgres")]
+                RepositoryBackend::Postgres => {
+                    let database_url = config.database_url.clone();
+                    let database: Arc<Lazy<PostgresUserRepository, Error>> =
+                        Arc::new(Lazy::new(move || {
+                            let database_url = database_url.clone();
+                            async move {
+                                let repository =
+                                    PostgresUserRepository::connect(database_url).await?;
+                                // The lazy wrappers' start() doesn't reach this one
+                                repository.start().await?;
+                                Ok(Arc::new(repository))
+                            }
+                        }));
+                    let users = database.clone();
+                    container.singleton::<dyn UserRepository, _>(move |scope: &Scope| {
+                        let users = users.clone();
+                        let lazy = LazyUserRepository(Lazy::new(move || {
+                            let users = users.clone();
+                            async move { Ok(users.get().await? as Arc<dyn UserRepository>) }
+                        }));
+                        // Ride out blips, fail fast while the database is down
+                        let retried = WithRetry::<dyn UserRepository>::new(
+                            Arc::new(lazy),
+                            RetryPolicy::default(),
+                        );
+                        timed(
+                            scope,
+                            Arc::new(WithCircuitBreaker::<dyn UserRepository>::new(
+                                Arc::new(retried),
+                                CircuitBreaker::new("postgres", CircuitBreakerConfig::default()),
+                            )),
+                        )
+                    });
+                    container.singleton::<dyn AuditRepository, _>(move |_| {
+                        let database = database.clone();
+                        Arc::new(LazyAuditRepository(Lazy::new(move || {
+                            let database = database.clone();
+                            async move {
+                                let audit = database.get().await?.audit();
+                                Ok(Arc::new(audit) as Arc<dyn AuditRepository>)
+                            }
+                        })))
+                    });
+                }
+                // A local file: no retries or circuit breaker to get through
+                #[cfg(feature = "sqlite")]
+                RepositoryBackend::Sqlite => {
+                    let database_url = config.database_url.clone();
+                    let database: Arc<Lazy<SqliteUserRepository, Error>> =
+                        Arc::new(Lazy::new(move || {
+                            let database_url = database_url.clone();
+                            async move {
+                                let repository =
+                                    SqliteUserRepository::connect(database_url.expose()).await?;
+                                repository.start().await?;
+                                Ok(Arc::new(repository))
+                            }
+                        }));
+                    let users = database.clone();
+                    container.singleton::<dyn UserRepository, _>(move |scope: &Scope| {
+                        let users = users.clone();
+                        let lazy = LazyUserRepository(Lazy::new(move || {
+                            let users = users.clone();
+                            async move { Ok(users.get().await? as Arc<dyn UserRepository>) }
+                        }));
+                        timed(scope, Arc::new(lazy))
+                    });
+                    container.singleton::<dyn AuditRepository, _>(move |_| {
+                        let database = database.clone();
+                        Arc::new(LazyAuditRepository(Lazy::new(move || {
+                            let database = database.clone();
+                            async move {
+                                let audit = database.get().await?.audit();
+                                Ok(Arc::new(audit) as Arc<dyn AuditRepository>)
+                            }
+                        })))
+                    });
+                }
+                // The in-memory mocks double as a zero-setup dev backend
+                #[cfg(feature = "memory")]
+                RepositoryBackend::Memory => {
+                    container
+                        .singleton::<dyn UserRepository, _>(move |scope: &Scope| {
+                            timed(scope, Arc::new(MockUserRepository::new()))
+                        })
+                        .singleton::<dyn AuditRepository, _>(|_| {
+                            Arc::new(MockAuditRepository::default())
+                        });
+                }
+            };
+
+            match config.cache {
+                #[cfg(feature = "redis")]
+                CacheBackend::Redis => {
+                    let redis_url = config.redis_url.clone();
+                    container.swappable::<dyn CacheService, _>(move |_| {
+                        let redis_url = redis_url.clone();
+                        Arc::new(LazyCache::new(Lazy::new(move || {
+                            let redis_url = redis_url.clone();
+                            async move {
+                                let cache = RedisCache::connect(redis_url).await?;
+                                Ok(Arc::new(cache) as Arc<dyn CacheService>)
+                            }
+                        })))
+                    })
+                }
+                #[cfg(feature = "memory")]
+                CacheBackend::Memory => {
+                    container.swappable::<dyn CacheService, _>(|scope: &Scope| {
+                        Arc::new(
+                            MockCache::new()
+                                .with_clock(scope.resolve().expect("Clock is registered")),
+                        )
+                    })
+                }
+            };
+            Self::bind_swappable_cache(&mut container);
+
+            match config.hasher {
+                HasherKind::Bcrypt => {
+                    container.singleton::<dyn PasswordHasher, _>(|_| Arc::new(BcryptHasher))
+                }
+                HasherKind::Argon2 => {
+                    container.singleton::<dyn PasswordHasher, _>(|_| Arc::new(Argon2Hasher))
+                }
+            };
+
+            let (jwt_secret, jwt_ttl_seconds) = (config.jwt_secret.clone(), config.jwt_ttl_seconds);
+            container.singleton::<dyn TokenService, _>(move |scope: &Scope| {
+                Arc::new(JwtTokenService::new(
+                    jwt_secret.clone(),
+                    jwt_ttl_seconds,
+                    scope.resolve().expect("Clock is registered"),
+                ))
+            });
+
+            let (storage_url, public_url) = (
+                config.storage_url.clone(),
+                config.storage_public_url.clone(),
+            );
+            match config.storage {
+                StorageBackend::Local => container.singleton::<dyn StorageService, _>(move |_| {
+                    let root = storage_url.strip_prefix("file://").unwrap_or(&storage_url);
+                    Arc::new(LocalStorage::new(root, &public_url))
+                }),
+                #[cfg(feature = "s3")]
+                StorageBackend::S3 => container.singleton::<dyn StorageService, _>(move |_| {
+                    Arc::new(S3Storage::new(&storage_url, &public_url))
+                }),
+            };
+
+            // Prometheus is also bound as itself, for GET /metrics to render
+            match config.metrics {
+                config::MetricsBackend::None => {
+                    container.singleton::<dyn Metrics, _>(|_| Arc::new(NoopMetrics))
+                }
+                config::MetricsBackend::Prometheus => container
+                    .singleton::<PrometheusMetrics, _>(|_| Arc::new(PrometheusMetrics::default()))
+                    .singleton::<dyn Metrics, _>(|scope: &Scope| {
+                        scope
+                            .resolve::<PrometheusMetrics>()
+                            .expect("PrometheusMetrics is registered")
+                    }),
+                config::MetricsBackend::Statsd => {
+                    let statsd_addr = config.statsd_addr.clone();
+                    container.singleton::<dyn Metrics, _>(move |_| {
+                        match StatsdMetrics::connect(&statsd_addr) {
+                            Ok(statsd) => Arc::new(statsd),
+                            // Metrics aren't worth failing a request over
+                            Err(e) => {
+                                tracing::warn!(addr = %statsd_addr, error = %e, "StatsD unreachable, metrics off");
+                                Arc::new(NoopMetrics)
+                            }
+                        }
+                    })
+                }
+            };
+
+            let sentry_dsn = config.sentry_dsn.clone();
+            container.singleton::<dyn ErrorReporter, _>(move |_| match &sentry_dsn {
+                #[cfg(feature = "sentry")]
+                Some(dsn) => Arc::new(SentryErrorReporter::new(dsn)),
+                _ => Arc::new(NoErrorReporter),
+            });
+
+            Self::register_auth_service(&mut container);
+            Self::register_session_service(&mut container, config.session_ttl_seconds);
+            Self::register_admin_service(&mut container);
+            Self::register_profile_service(&mut container);
+            Self::register_health_service(&mut container);
+            container
+        }
+
+        // "Give me a staging-like stack": the profile's backends without
+        // spelling them out. Re-registering a binding on the result replaces
+        // just that dependency:
+        //
+        //     let mut container = AuthServiceFactory::for_profile(Profile::Staging)?;
+        //     container.singleton::<dyn UserRepository, _>(|_| Arc::new(MockUserRepository::new()));
+        #[cfg(test)]
+        pub fn for_profile(profile: config::Profile) -> Result<Container, config::ConfigErrors> {
+            Ok(Self::container(&AppConfig::for_profile(profile)?))
+        }
+
+        // Same shape, all mocks
+        pub fn test_container(users: Vec<User>) -> Container {
+            let mut container = Container::new();
+            container
+                .singleton::<dyn UserRepository, _>(move |_| {
+                    Arc::new(MockUserRepository {
+                        users: std::sync::Mutex::new(users.clone()),
+                    })
+                })
+                .singleton::<dyn PasswordHasher, _>(|_| Arc::new(MockPasswordHasher))
+                .singleton::<dyn TokenService, _>(|_| Arc::new(MockTokenService))
+                .singleton::<dyn StorageService, _>(|_| Arc::new(MockStorage::new()))
+                .singleton::<dyn AuditRepository, _>(|_| Arc::new(MockAuditRepository::default()))
+                .singleton::<dyn ErrorReporter, _>(|_| Arc::new(MockErrorReporter::default()))
+                .singleton::<dyn Metrics, _>(|_| Arc::new(NoopMetrics))
+                // Real time, so background tasks tick; a test that wants
+                // to control it registers a MockClock over this
+                .singleton::<dyn Clock, _>(|_| Arc::new(SystemClock))
+                // Predictable, so tests can assert on registered users' IDs
+                .singleton::<dyn IdGenerator, _>(|_| Arc::new(SequentialIdGenerator::new()))
+                .swappable::<dyn CacheService, _>(|scope: &Scope| {
+                    Arc::new(
+                        MockCache::new().with_clock(scope.resolve().expect("Clock is registered")),
+                    )
+                });
+
+            Self::bind_swappable_cache(&mut container);
+            Self::register_auth_service(&mut container);
+            Self::register_session_service(&mut container, SessionService::DEFAULT_TTL_SECONDS);
+            Self::register_admin_service(&mut container);
+            Self::register_profile_service(&mut container);
+            Self::register_health_service(&mut container);
+            container
+        }
+
+        // Mocks behind fault injectors. The repository and the token service
+        // each get their own injector so their failures don't interleave.
+        #[cfg(test)]
+        pub fn flaky_test_container(users: Vec<User>, config: FailureConfig) -> Container {
+            let mut container = Self::test_container(Vec::new());
+            let repository: Arc<dyn UserRepository> = Arc::new(MockUserRepository {
+                users: std::sync::Mutex::new(users),
+            });
+
+            // Re-registering replaces the plain mocks
+            container
+                .singleton::<dyn UserRepository, _>(move |_| {
+                    Arc::new(FlakyUserRepository::new(
+                        repository.clone(),
+                        Arc::new(FaultInjector::new(config)),
+                    ))
+                })
+                .singleton::<dyn TokenService, _>(move |_| {
+                    Arc::new(FlakyTokenService::new(
+                        Arc::new(MockTokenService),
+                        Arc::new(FaultInjector::new(config)),
+                    ))
+                });
+            container
+        }
+
+        // Services get the proxy, counted into cache_hits_total and friends;
+        // ops code resolves Swappable<dyn CacheService> to change what's
+        // behind it
+        fn bind_swappable_cache(container: &mut Container) {
+            container.singleton::<dyn CacheService, _>(|scope: &Scope| {
+                let slot: Arc<dyn CacheService> = scope
+                    .resolve::<Swappable<dyn CacheService>>()
+                    .expect("cache slot is registered");
+                Arc::new(
+                    InstrumentedCache::new(slot)
+                        .with_metrics(scope.resolve().expect("Metrics is registered")),
+                )
+            });
+        }
+
+        fn register_auth_service(container: &mut Container) {
+            container
+                .singleton::<EventBus, _>(|_| Arc::new(EventBus::new(EventBus::DEFAULT_CAPACITY)))
+                .scoped::<AuthService, _>(|scope: &Scope| {
+                    Arc::new(
+                        AuthService::new(
+                            scope.resolve().expect("UserRepository is registered"),
+                            scope.resolve().expect("PasswordHasher is registered"),
+                            scope.resolve().expect("TokenService is registered"),
+                            scope.resolve().expect("CacheService is registered"),
+                        )
+                        .with_events(scope.resolve().expect("EventBus is registered"))
+                        .with_metrics(scope.resolve().expect("Metrics is registered"))
+                        .with_ids(scope.resolve().expect("IdGenerator is registered")),
+                    )
+                });
+        }
+
+        // Sessions live in the cache, so they follow whatever backs it.
+        // Goes after register_auth_service, which registers the EventBus.
+        fn register_session_service(container: &mut Container, ttl_seconds: u64) {
+            container
+                .singleton::<dyn SessionStore, _>(|scope: &Scope| {
+                    Arc::new(CacheSessionStore::new(
+                        scope.resolve().expect("CacheService is registered"),
+                    ))
+                })
+                .scoped::<SessionService, _>(move |scope: &Scope| {
+                    Arc::new(SessionService::new(
+                        scope.resolve().expect("AuthService is registered"),
+                        scope.resolve().expect("UserRepository is registered"),
+                        scope.resolve().expect("SessionStore is registered"),
+                        scope.resolve().expect("EventBus is registered"),
+                        ttl_seconds,
+                    ))
+                });
+        }
+
+        // The AuditRepository is registered with the UserRepository, by
+        // backend. Goes after register_auth_service, which registers the
+        // EventBus.
+        fn register_admin_service(container: &mut Container) {
+            container
+                .singleton::<AuditService, _>(|scope: &Scope| {
+                    Arc::new(AuditService::new(
+                        scope.resolve().expect("AuditRepository is registered"),
+                    ))
+                })
+                .scoped::<AdminService, _>(|scope: &Scope| {
+                    Arc::new(AdminService::new(
+                        scope.resolve().expect("UserRepository is registered"),
+                        scope.resolve().expect("CacheService is registered"),
+                        scope.resolve().expect("AuditService is registered"),
+                        scope.resolve().expect("EventBus is registered"),
+                    ))
+                });
+        }
+
+        fn register_profile_service(container: &mut Container) {
+            container.scoped::<ProfileService, _>(|scope: &Scope| {
+                Arc::new(ProfileService::new(
+                    scope.resolve().expect("UserRepository is registered"),
+                    scope.resolve().expect("CacheService is registered"),
+                    scope.resolve().expect("StorageService is registered"),
+                    scope.resolve().expect("EventBus is registered"),
+                ))
+            });
+        }
+
+        // Checks the exact instances AuthService uses, not fresh copies
+        fn register_health_service(container: &mut Container) {
+            container.singleton::<HealthService, _>(|scope: &Scope| {
+                let repository: Arc<dyn UserRepository> =
+                    scope.resolve().expect("UserRepository is registered");
+                let cache: Arc<dyn CacheService> =
+                    scope.resolve().expect("CacheService is registered");
+                let token_service: Arc<dyn TokenService> =
+                    scope.resolve().expect("TokenService is registered");
+
+                Arc::new(
+                    HealthService::new()
+                        .with_check("database", repository)
+                        .with_optional_check("cache", cache)
+                        .with_check("token_service", token_service),
+                )
+            });
+        }
+
+        fn resolve(container: Container) -> Arc<AuthService> {
+            Arc::new(container)
+                .create_scope()
+                .resolve()
+                .expect("AuthService is registered")
+        }
+
+        #[cfg(all(test, feature = "memory"))]
+        pub fn from_config(config: &AppConfig) -> Arc<AuthService> {
+            Self::resolve(Self::container(config))
+        }
+
+        // The production entry point: everything from_config builds, plus
+        // the handle that starts and stops it
+        pub fn app(config: &AppConfig) -> App {
+            let container = Arc::new(Self::container(config));
+            let scope = container.create_scope();
+            let repository: Arc<dyn UserRepository> =
+                scope.resolve().expect("UserRepository is registered");
+            let cache: Arc<dyn CacheService> = scope.resolve().expect("CacheService is registered");
+
+            App::new(container)
+                .with_component("database", Arc::new(repository))
+                .with_component("cache", Arc::new(cache))
+        }
+
+        pub fn create_test() -> Arc<AuthService> {
+            Self::resolve(Self::test_container(Vec::new()))
+        }
+
+        #[cfg(test)]
+        pub fn create_test_with_user(user: User) -> Arc<AuthService> {
+            Self::resolve(Self::test_container(vec![user]))
+        }
+
+        #[cfg(test)]
+        pub fn create_flaky_test(config: FailureConfig) -> Arc<AuthService> {
+            Self::resolve(Self::flaky_test_container(Vec::new(), config))
+        }
+
+        // No container: with generics the wiring is the type itself
+        pub fn create_test_generic(
+            users: Vec<User>,
+        ) -> AuthServiceGeneric<
+            MockUserRepository,
+            MockPasswordHasher,
+            MockTokenService,
+            MockCache,
+            SequentialIdGenerator,
+        > {
+            AuthServiceGeneric::new(
+                MockUserRepository {
+                    users: std::sync::Mutex::new(users),
+                },
+                MockPasswordHasher,
+                MockTokenService,
+                MockCache::new(),
+            )
+            .with_ids(SequentialIdGenerator::new())
+        }
+    }
+
+    // 6. Application lifecycle
+    // ========================
+
+    pub struct App {
+        container: Arc<Container>,
+        // In start order; shut down in reverse
+        components: Vec<(String, Arc<dyn Lifecycle>)>,
+    }
+
+    impl App {
+        pub fn new(container: Arc<Container>) -> Self {
+            Self {
+                container,
+                components: Vec::new(),
+            }
+        }
+
+        // Register dependencies before whatever uses them
+        pub fn with_component(mut self, name: &str, component: Arc<dyn Lifecycle>) -> Self {
+            self.components.push((name.to_string(), component));
+            self
+        }
+
+        // Tasks start after the resources they use, so they stop before them
+        pub fn with_task(self, task: BackgroundTask) -> Self {
+            let name = task.name().to_string();
+            self.with_component(&name, Arc::new(task))
+        }
+
+        // Stops at the first failure and shuts down whatever already started
+        pub async fn start(&self) -> Result<(), Error> {
+            for (index, (name, component)) in self.components.iter().enumerate() {
+                if let Err(e) = component.start().await {
+                    tracing::error!(component = %name, error = %e.report(), "failed to start");
+                    self.shutdown_components(&self.components[..index]).await;
+                    return Err(e);
+                }
+            }
+            Ok(())
+        }
+
+        // One per request
+        pub fn auth_service(&self) -> Arc<AuthService> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("AuthService is registered")
+        }
+
+        pub fn session_service(&self) -> Arc<SessionService> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("SessionService is registered")
+        }
+
+        pub fn admin_service(&self) -> Arc<AdminService> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("AdminService is registered")
+        }
+
+        pub fn profile_service(&self) -> Arc<ProfileService> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("ProfileService is registered")
+        }
+
+        // What the app's services tell the time by
+        pub fn clock(&self) -> Arc<dyn Clock> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("Clock is registered")
+        }
+
+        // Whichever backend METRICS_BACKEND chose
+        pub fn metrics(&self) -> Arc<dyn Metrics> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("Metrics is registered")
+        }
+
+        // Only with METRICS_BACKEND=prometheus; GET /metrics renders it
+        pub fn prometheus(&self) -> Option<Arc<PrometheusMetrics>> {
+            self.container.create_scope().resolve().ok()
+        }
+
+        // What the transports hand Internal errors to
+        pub fn error_reporter(&self) -> Arc<dyn ErrorReporter> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("ErrorReporter is registered")
+        }
+
+        // Subscribe here to hear what every request's services publish
+        pub fn events(&self) -> Arc<EventBus> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("EventBus is registered")
+        }
+
+        pub fn health(&self) -> Arc<HealthService> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("HealthService is registered")
+        }
+
+        // The proxy services hold, so it keeps working across swap_cache
+        pub fn cache(&self) -> Arc<dyn CacheService> {
+            self.container
+                .create_scope()
+                .resolve()
+                .expect("CacheService is registered")
+        }
+
+        // Replace the cache for every service at once; returns the old one
+        #[cfg(test)]
+        pub fn swap_cache(&self, cache: Arc<dyn CacheService>) -> Arc<dyn CacheService> {
+            let slot: Arc<Swappable<dyn CacheService>> = self
+                .container
+                .create_scope()
+                .resolve()
+                .expect("cache slot is registered");
+            slot.swap(cache)
+        }
+
+        // Meant to run on a timer (see BackgroundTask). A down cache is
+        // replaced by a local in-memory one: slower for the fleet (no
+        // sharing), but requests keep working. Returns true if it swapped.
+        #[cfg(test)]
+        pub async fn fail_over_cache_if_down(&self) -> bool {
+            let slot: Arc<Swappable<dyn CacheService>> = self
+                .container
+                .create_scope()
+                .resolve()
+                .expect("cache slot is registered");
+            match slot.check().await {
+                Ok(()) => false,
+                Err(e) => {
+                    tracing::warn!(error = %e.report(), "cache is down, switching to in-memory");
+                    slot.swap(Arc::new(MockCache::new()));
+                    true
+                }
+            }
+        }
+
+        // Every component gets its shutdown() even if an earlier one failed;
+        // the first error is returned
+        pub async fn shutdown(&self) -> Result<(), Error> {
+            match self.shutdown_components(&self.components).await {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        }
+
+        async fn shutdown_components(
+            &self,
+            components: &[(String, Arc<dyn Lifecycle>)],
+        ) -> Option<Error> {
+            let mut first_error = None;
+            for (name, component) in components.iter().rev() {
+                if let Err(e) = component.shutdown().await {
+                    tracing::error!(component = %name, error = %e.report(), "failed to shut down");
+                    first_error.get_or_insert(e);
+                }
+            }
+            first_error
+        }
+    }
+
+    // 7. Readiness
+    // ============
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ComponentHealth {
+        pub name: String,
+        pub status: Result<(), String>,
+        // false for dependencies requests can live without (the cache)
+        pub critical: bool,
+    }
+
+    // Healthy: everything up. Degraded: only optional dependencies are down,
+    // so traffic is still served, just slower. Unavailable: a critical one is
+    // down and requests will fail.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum HealthStatus {
+        Healthy,
+        Degraded,
+        Unavailable,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ReadinessReport {
+        pub components: Vec<ComponentHealth>,
+    }
+
+    impl ReadinessReport {
+        // Ready only if every dependency is; status() tells degraded apart
+        pub fn is_ready(&self) -> bool {
+            self.components.iter().all(|c| c.status.is_ok())
+        }
+
+        pub fn status(&self) -> HealthStatus {
+            if self.is_ready() {
+                HealthStatus::Healthy
+            } else if self.failures().any(|c| c.critical) {
+                HealthStatus::Unavailable
+            } else {
+                HealthStatus::Degraded
+            }
+        }
+
+        pub fn failures(&self) -> impl Iterator<Item = &ComponentHealth> {
+            self.components.iter().filter(|c| c.status.is_err())
+        }
+    }
+
+    pub struct HealthService {
+        // (name, check, critical)
+        checks: Vec<(String, Box<dyn HealthCheck>, bool)>,
+        timeout: std::time::Duration,
+    }
+
+    impl HealthService {
+        pub fn new() -> Self {
+            Self {
+                checks: Vec::new(),
+                timeout: std::time::Duration::from_secs(2),
+            }
+        }
+
+        pub fn with_check(mut self, name: &str, check: impl HealthCheck + 'static) -> Self {
+            self.checks.push((name.to_string(), Box::new(check), true));
+            self
+        }
+
+        // Reported, but its failure only degrades the service
+        pub fn with_optional_check(
+            mut self,
+            name: &str,
+            check: impl HealthCheck + 'static,
+        ) -> Self {
+            self.checks.push((name.to_string(), Box::new(check), false));
+            self
+        }
+
+        #[cfg(test)]
+        pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+            self.timeout = timeout;
+            self
+        }
+
+        // A dependency that hangs counts as down; one slow check must not
+        // stall the whole probe
+        pub async fn readiness(&self) -> ReadinessReport {
+            let mut components = Vec::with_capacity(self.checks.len());
+            for (name, check, critical) in &self.checks {
+                // The report is what /readyz shows, so this is where the
+                // error becomes text
+                let status = match tokio::time::timeout(self.timeout, check.check()).await {
+                    Ok(status) => status.map_err(|error| error.report()),
+                    Err(_) => Err(format!("timed out after {:?}", self.timeout)),
+                };
+                components.push(ComponentHealth {
+                    name: name.clone(),
+                    status,
+                    critical: *critical,
+                });
+            }
+            ReadinessReport { components }
+        }
+    }
+
+    // 8. Rate limiting
+    // ================
+    //
+    // A fixed-window counter on CacheService::incr. The window number is
+    // part of the key, so each window starts from zero without a reset and
+    // the TTL only cleans up. With Redis behind the cache, every instance
+    // shares the same counts.
+
+    pub struct RateLimiter {
+        cache: Arc<dyn CacheService>,
+        clock: Arc<dyn Clock>,
+        limit: u32,
+        window_seconds: u64,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct RateLimited {
+        // Until the current window ends
+        pub retry_after_seconds: u64,
+    }
+
+    impl RateLimiter {
+        pub fn new(cache: Arc<dyn CacheService>, limit: u32, window_seconds: u64) -> Self {
+            Self {
+                cache,
+                clock: Arc::new(SystemClock),
+                limit,
+                window_seconds: window_seconds.max(1),
+            }
+        }
+
+        // Windows follow this clock; it should be the cache's too, or
+        // counters expire on a different time than windows end
+        pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        // Counts one hit for `key` (an IP, a user id, "route:ip"...)
+        pub async fn check(&self, key: &str) -> Result<(), RateLimited> {
+            let now = self.clock.unix_seconds();
+            let window = now / self.window_seconds;
+
+            let hits = self
+                .cache
+                .incr(
+                    &format!("ratelimit:{}:{}", key, window),
+                    1,
+                    Some(self.window_seconds),
+                )
+                .await;
+            if hits > self.limit as i64 {
+                return Err(RateLimited {
+                    retry_after_seconds: self.window_seconds - now % self.window_seconds,
+                });
+            }
+            Ok(())
+        }
+    }
+
+    // 9. Administration
+    // =================
+    //
+    // What admins do to other accounts. Every change goes into the audit
+    // trail (AuditService) with who made it and from where. Checking that
+    // the caller is an admin is the caller's job (see http::AdminUser).
+
+    pub const ROLES: [&str; 2] = ["user", "admin"];
+
+    // Who is acting, and from where: the client IP when the transport
+    // knows it
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Actor {
+        pub user_id: String,
+        pub ip: Option<String>,
+    }
+
+    // No transport, no IP: a script or a test acting as this user
+    impl From<&User> for Actor {
+        fn from(user: &User) -> Self {
+            Self {
+                user_id: user.id.clone(),
+                ip: None,
+            }
+        }
+    }
+
+    // The audit trail, kept by an AuditRepository so it outlives the
+    // process and every instance writes to the same one. Entries are
+    // recorded after the change they describe; if recording fails the
+    // change stands, but the caller gets the error instead of a silent gap.
+    pub struct AuditService {
+        repository: Arc<dyn AuditRepository>,
+    }
+
+    impl AuditService {
+        pub fn new(repository: Arc<dyn AuditRepository>) -> Self {
+            Self { repository }
+        }
+
+        pub async fn record(
+            &self,
+            actor: &Actor,
+            action: &str,
+            target: &User,
+            detail: String,
+        ) -> Result<(), Error> {
+            let at = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            self.repository
+                .append(AuditEntry {
+                    at,
+                    actor: actor.user_id.clone(),
+                    action: action.to_string(),
+                    target: target.id.clone(),
+                    detail,
+                    ip: actor.ip.clone(),
+                })
+                .await
+        }
+
+        // Newest first
+        pub async fn query(
+            &self,
+            filter: &AuditFilter,
+            page: PageRequest,
+        ) -> Result<Page<AuditEntry>, Error> {
+            self.repository.query(filter, page).await
+        }
+    }
+
+    pub struct AdminService {
+        repository: Arc<dyn UserRepository>,
+        cache: Arc<dyn CacheService>,
+        audit: Arc<AuditService>,
+        events: Arc<EventBus>,
+    }
+
+    impl AdminService {
+        pub fn new(
+            repository: Arc<dyn UserRepository>,
+            cache: Arc<dyn CacheService>,
+            audit: Arc<AuditService>,
+            events: Arc<EventBus>,
+        ) -> Self {
+            Self {
+                repository,
+                cache,
+                audit,
+                events,
+            }
+        }
+
+        pub async fn list_users(
+            &self,
+            filter: &UserFilter,
+            page: PageRequest,
+        ) -> Result<Page<User>, Error> {
+            self.repository.list(filter, page).await
+        }
+
+        // `role` is one of ROLES; the HTTP layer rejects anything else
+        pub async fn change_role(
+            &self,
+            actor: &Actor,
+            user_id: &str,
+            role: &str,
+        ) -> Result<User, Error> {
+            let user = self.find(user_id).await?;
+            if user.role == role {
+                return Ok(user);
+            }
+            let detail = format!("{} -> {}", user.role, role);
+            let user = self
+                .save(User {
+                    role: role.to_string(),
+                    ..user
+                })
+                .await?;
+            self.audit
+                .record(actor, "role_changed", &user, detail)
+                .await?;
+            self.events.publish(DomainEvent::ProfileUpdated {
+                user_id: user.id.clone(),
+            });
+            Ok(user)
+        }
+
+        // The user's tokens stop working on their next request
+        pub async fn deactivate(&self, actor: &Actor, user_id: &str) -> Result<User, Error> {
+            let user = self.find(user_id).await?;
+            if !user.active {
+                return Ok(user);
+            }
+            let user = self
+                .save(User {
+                    active: false,
+                    ..user
+                })
+                .await?;
+            self.audit
+                .record(actor, "deactivated", &user, user.email.clone())
+                .await?;
+            self.events.publish(DomainEvent::SessionRevoked {
+                user_id: user.id.clone(),
+            });
+            Ok(user)
+        }
+
+        pub async fn audit_log(
+            &self,
+            filter: &AuditFilter,
+            page: PageRequest,
+        ) -> Result<Page<AuditEntry>, Error> {
+            self.audit.query(filter, page).await
+        }
+
+        async fn find(&self, user_id: &str) -> Result<User, Error> {
+            self.repository
+                .find_by_id(user_id)
+                .await?
+                .ok_or(Error::NotFound)
+        }
+
+        // login() caches users by email; a stale copy would keep the old
+        // role, or let a deactivated user in
+        async fn save(&self, user: User) -> Result<User, Error> {
+            let user = self.repository.update(user).await?;
+            self.cache
+                .delete(&format!("user:email:{}", user.email))
+                .await;
+            Ok(user)
+        }
+    }
+
+    // 10. Domain events
+    // =================
+    //
+    // Services say what happened; whoever cares subscribes (the WebSocket
+    // and server-sent events handlers in http.rs, today). Publishers don't
+    // know the subscribers, so adding one never touches AuthService.
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum DomainEvent {
+        // A password check passed (token or session login)
+        LoggedIn { user_id: String },
+        // Password or role changed
+        ProfileUpdated { user_id: String },
+        // Logged out or deactivated
+        SessionRevoked { user_id: String },
+    }
+
+    impl DomainEvent {
+        pub fn user_id(&self) -> &str {
+            match self {
+                DomainEvent::LoggedIn { user_id }
+                | DomainEvent::ProfileUpdated { user_id }
+                | DomainEvent::SessionRevoked { user_id } => user_id,
+            }
+        }
+
+        // The serde tag: logged_in, profile_updated, ...
+        pub fn kind(&self) -> &'static str {
+            match self {
+                DomainEvent::LoggedIn { .. } => "logged_in",
+                DomainEvent::ProfileUpdated { .. } => "profile_updated",
+                DomainEvent::SessionRevoked { .. } => "session_revoked",
+            }
+        }
+    }
+
+    // An event and its place on the bus. Ids only grow, so "everything
+    // after 41" is a question the bus can answer. `cause` is the span that
+    // published it (the request, usually), for the spans that deliver it to
+    // link back to.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BusEvent {
+        pub id: u64,
+        pub event: DomainEvent,
+        pub cause: crate::logging::SpanLink,
+    }
+
+    // In-process only: another instance's subscribers hear nothing (that
+    // needs Redis pub/sub, like InvalidationBus in real_world_di.rs).
+    // Publishing never waits; a subscriber more than `capacity` events
+    // behind skips ahead and sees RecvError::Lagged. The last `capacity`
+    // events are also kept, so a reconnecting client can catch up.
+    pub struct EventBus {
+        sender: tokio::sync::broadcast::Sender<BusEvent>,
+        // The next id and the recent events, oldest first
+        recent: std::sync::Mutex<(u64, std::collections::VecDeque<BusEvent>)>,
+        capacity: usize,
+    }
+
+    impl EventBus {
+        pub const DEFAULT_CAPACITY: usize = 256;
+
+        pub fn new(capacity: usize) -> Self {
+            let capacity = capacity.max(1);
+            let (sender, _) = tokio::sync::broadcast::channel(capacity);
+            Self {
+                sender,
+                recent: std::sync::Mutex::new((1, std::collections::VecDeque::new())),
+                capacity,
+            }
+        }
+
+        // With no subscribers the event is only kept for replay, which is
+        // fine. Sent under the lock, so ids reach subscribers in order.
+        pub fn publish(&self, event: DomainEvent) -> u64 {
+            let mut recent = self.recent.lock().unwrap();
+            let (next_id, events) = &mut *recent;
+            let event = BusEvent {
+                id: *next_id,
+                event,
+                cause: crate::logging::SpanLink::current(),
+            };
+            *next_id += 1;
+            if events.len() == self.capacity {
+                events.pop_front();
+            }
+            events.push_back(event.clone());
+            let _ = self.sender.send(event.clone());
+            event.id
+        }
+
+        // Sees events published after this call, not before
+        pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<BusEvent> {
+            self.sender.subscribe()
+        }
+
+        // The kept events after `last_id`, and a receiver for the ones still
+        // to come. Taken under one lock, so none fall in between or arrive
+        // twice. Older events than the ones kept are gone.
+        pub fn subscribe_after(
+            &self,
+            last_id: u64,
+        ) -> (Vec<BusEvent>, tokio::sync::broadcast::Receiver<BusEvent>) {
+            let recent = self.recent.lock().unwrap();
+            let missed = recent
+                .1
+                .iter()
+                .filter(|event| event.id > last_id)
+                .cloned()
+                .collect();
+            (missed, self.sender.subscribe())
+        }
+    }
+
+    // 11. Profiles
+    // ============
+    //
+    // What users change about themselves. The file goes to StorageService,
+    // the URL it returns goes on the user through the repository, so neither
+    // backend knows about the other.
+
+    pub struct ProfileService {
+        repository: Arc<dyn UserRepository>,
+        cache: Arc<dyn CacheService>,
+        storage: Arc<dyn StorageService>,
+        events: Arc<EventBus>,
+    }
+
+    impl ProfileService {
+        // Content type -> file extension; anything else is refused
+        pub const AVATAR_TYPES: [(&str, &str); 3] = [
+            ("image/png", "png"),
+            ("image/jpeg", "jpg"),
+            ("image/webp", "webp"),
+        ];
+
+        // What the bytes actually are, from their first few, whatever the
+        // client said. One of AVATAR_TYPES, or None.
+        pub fn sniff_avatar_type(bytes: &[u8]) -> Option<&'static str> {
+            match bytes {
+                [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some("image/png"),
+                [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+                [
+                    b'R',
+                    b'I',
+                    b'F',
+                    b'F',
+                    _,
+                    _,
+                    _,
+                    _,
+                    b'W',
+                    b'E',
+                    b'B',
+                    b'P',
+                    ..,
+                ] => Some("image/webp"),
+                _ => None,
+            }
+        }
+
+        pub fn new(
+            repository: Arc<dyn UserRepository>,
+            cache: Arc<dyn CacheService>,
+            storage: Arc<dyn StorageService>,
+            events: Arc<EventBus>,
+        ) -> Self {
+            Self {
+                repository,
+                cache,
+                storage,
+                events,
+            }
+        }
+
+        // `content_type` is one of AVATAR_TYPES and matches the bytes; the
+        // HTTP layer checks both.
+        // One key per user and type, so a new upload replaces the old file.
+        pub async fn set_avatar(
+            &self,
+            user: &User,
+            content_type: &str,
+            bytes: Vec<u8>,
+        ) -> Result<User, Error> {
+            let extension = Self::AVATAR_TYPES
+                .iter()
+                .find(|(known, _)| *known == content_type)
+                .map(|(_, extension)| *extension)
+                .ok_or_else(|| {
+                    Error::internal(format!("unsupported avatar type {}", content_type))
+                })?;
+            let key = format!("avatars/{}.{}", user.id, extension);
+            let url = self
+                .storage
+                .put(&key, content_type, bytes)
+                .await
+                .with_context(|| format!("storing the avatar of user {}", user.id))?;
+
+            // Reload: the caller's copy may be stale
+            let current = self
+                .repository
+                .find_by_id(&user.id)
+                .await?
+                .ok_or(Error::NotFound)?;
+            let previous = current.avatar_url.clone();
+            let updated = self
+                .repository
+                .update(User {
+                    avatar_url: Some(url.clone()),
+                    ..current
+                })
+                .await?;
+            self.cache
+                .delete(&format!("user:email:{}", updated.email))
+                .await;
+
+            // A different type left the old file behind under another key
+            if let Some(previous) = previous.filter(|previous| *previous != url)
+                && let Some((_, extension)) = previous.rsplit_once('.')
+            {
+                let old_key = format!("avatars/{}.{}", user.id, extension);
+                if let Err(e) = self.storage.delete(&old_key).await {
+                    tracing::warn!(
+                        key = %old_key,
+                        error = %e.report(),
+                        "couldn't delete old avatar"
+                    );
+                }
+            }
+
+            self.events.publish(DomainEvent::ProfileUpdated {
+                user_id: updated.id.clone(),
+            });
+            Ok(updated)
+        }
+    }
+
+    // 12. Sessions
+    // ============
+    //
+    // The cookie alternative to bearer tokens, on the same services: the
+    // password check is AuthService's, the user lookup the repository's.
+    // What the client holds is a random id; the session itself lives in the
+    // SessionStore, so logging out deletes it instead of remembering a
+    // revoked token.
+
+    pub struct SessionService {
+        auth: Arc<AuthService>,
+        repository: Arc<dyn UserRepository>,
+        sessions: Arc<dyn SessionStore>,
+        events: Arc<EventBus>,
+        ttl_seconds: u64,
+    }
+
+    impl SessionService {
+        // Matches the SESSION_TTL_SECONDS default
+        pub const DEFAULT_TTL_SECONDS: u64 = 86400;
+
+        pub fn new(
+            auth: Arc<AuthService>,
+            repository: Arc<dyn UserRepository>,
+            sessions: Arc<dyn SessionStore>,
+            events: Arc<EventBus>,
+            ttl_seconds: u64,
+        ) -> Self {
+            Self {
+                auth,
+                repository,
+                sessions,
+                events,
+                ttl_seconds,
+            }
+        }
+
+        pub fn ttl_seconds(&self) -> u64 {
+            self.ttl_seconds
+        }
+
+        // The new session's id
+        pub async fn login(&self, email: &str, password: &str) -> Result<String, Error> {
+            let user = self.auth.authenticate(email, password).await?;
+            self.sessions.create(&user.id, self.ttl_seconds).await
+        }
+
+        // Unknown and expired sessions, and sessions of deactivated users,
+        // all come back as InvalidCredentials, like AuthService::current_user
+        pub async fn current_user(&self, session_id: &str) -> Result<User, Error> {
+            let user_id = self
+                .sessions
+                .get(session_id)
+                .await?
+                .ok_or(Error::InvalidCredentials)?;
+            self.repository
+                .find_by_id(&user_id)
+                .await?
+                .filter(|user| user.active)
+                .ok_or(Error::InvalidCredentials)
+        }
+
+        pub async fn logout(&self, session_id: &str) -> Result<(), Error> {
+            let user = self.current_user(session_id).await?;
+            self.sessions.delete(session_id).await?;
+            self.events
+                .publish(DomainEvent::SessionRevoked { user_id: user.id });
+            Ok(())
+        }
+    }
+}
+
+// ===================================================================
+// DEMONSTRATION & TESTS
 // ===================================================================
-// DEMONSTRATION & TESTS
-// ===================================================================
 
-#[tokio::main]
-async fn main() {
-    println!("=== Production Setup (AFTER) ===");
-    let service = after::AuthServiceFactory::create_production(
-        "postgresql://localhost".to_string(),
-        "redis://localhost".to_string(),
-        "secret".to_string(),
-    );
+// main() is the edge: everything below returns a typed error (Error,
+// ConfigErrors, io::Error) and this is the one place they become
+// anyhow::Error, with context on top and the causes kept underneath. An
+// error out of main prints as "Error: <context>" and a "Caused by:" list.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    println!("=== Production Setup (AFTER) ===");
+    // config.toml / dev.env / the environment / flags. With nothing set the
+    // dev profile runs in memory; anything invalid stops startup with the
+    // whole list of problems, not just the first one.
+    let cli = config::CliOverrides::parse();
+    let serve = cli.serve;
+    let (settings, config) = load_config(&cli)?;
+
+    // Everything from here on logs through tracing, as LOG_FORMAT says.
+    // RUST_LOG, when set, wins over LOG_LEVEL
+    // (e.g. RUST_LOG=info,refactoring::after=debug). Spans exported over
+    // OTLP are flushed when `_telemetry` drops, on every way out of main().
+    let _telemetry = logging::init(&settings.log).context("setting up logging")?;
+    tracing::info!(
+        profile = %settings.profile,
+        log_level = %settings.log.level,
+        "configuration loaded"
+    );
+
+    // Opt-in hot reload: only what subscribes sees edits to config.toml / dev.env
+    let _config_watcher = config::ConfigWatcher::watch(".", cli)
+        .ok()
+        .inspect(|watcher| {
+            let mut updates = watcher.subscribe();
+            tokio::spawn(async move {
+                while updates.changed().await.is_ok() {
+                    let level = updates.borrow_and_update().log.level.clone();
+                    tracing::info!(%level, "config reloaded");
+                }
+            });
+        });
+
+    let app = after::AuthServiceFactory::app(&config);
+    let cleanup = after::BackgroundTask::new(
+        "session_cleanup",
+        std::time::Duration::from_secs(60),
+        || tracing::info!("cleaning up expired sessions"),
+    )
+    .with_clock(app.clock());
+    let app = app.with_task(cleanup);
+    app.start().await.context("starting the app")?;
+
+    let report = app.health().readiness().await;
+    tracing::info!(ready = report.is_ready(), components = ?report.components, "readiness");
+
+    if serve {
+        let state = Arc::new(
+            http::AppState::new(app)
+                .with_auth_mode(settings.session.auth_mode)
+                .with_allowed_origins(settings.cors.allowed_origins.clone()),
+        );
+        // Same AppState, same services: only the transport differs
+        let (http_result, grpc_result) = tokio::join!(
+            http::serve(state.clone(), &settings),
+            grpc::serve(state.clone(), &settings),
+        );
+        // Shut down even if a server failed, then report the first failure
+        let shutdown = state.app.shutdown().await;
+        http_result.context("HTTP server")?;
+        grpc_result.context("gRPC server")?;
+        return shutdown.context("shutting down");
+    }
+
+    app.shutdown().await.context("shutting down")?;
+
+    println!("\n=== Test Setup (AFTER) ===");
+    let test_service = after::AuthServiceFactory::create_test();
+
+    match test_service
+        .register("test@example.com", "password123")
+        .await
+    {
+        Ok(user) => println!("Registered user: {:?}", user),
+        Err(e) => println!("Error: {:?}", e),
+    }
+
+    println!("\n=== Dynamic vs Static Dispatch ===");
+    let services: Vec<(&str, Box<dyn after::Authenticator>)> = vec![
+        (
+            "Arc<dyn>",
+            Box::new(after::AuthServiceFactory::create_test()),
+        ),
+        (
+            "generic",
+            Box::new(after::AuthServiceFactory::create_test_generic(Vec::new())),
+        ),
+    ];
+    for (name, service) in &services {
+        let user = service
+            .register("demo@example.com", "password123")
+            .await
+            .with_context(|| format!("registering with the {} service", name))?;
+        service
+            .change_password(&user.email, "password123", "correct horse battery")
+            .await
+            .with_context(|| format!("changing the password with the {} service", name))?;
+        match service
+            .login("demo@example.com", "correct horse battery")
+            .await
+        {
+            Ok(token) => {
+                let me = service.current_user(&token).await?;
+                println!("{}: logged in as {} with {}", name, me.email, token);
+                service.logout(&token).await?;
+            }
+            Err(e) => println!("{}: {:?}", name, e),
+        }
+    }
+    Ok(())
+}
+
+// Settings, then the backends they pick. A `?` on either keeps the
+// ConfigErrors whole, so the report lists every bad key.
+fn load_config(
+    cli: &config::CliOverrides,
+) -> anyhow::Result<(config::AppConfig, after::AppConfig)> {
+    use anyhow::Context;
+
+    let settings = config::AppConfig::load(cli).context("loading configuration")?;
+    let config = after::AppConfig::from_settings(&settings).context("choosing backends")?;
+    Ok((settings, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use after::*;
+    use serde::Deserialize;
+    use std::backtrace::Backtrace;
+    use test_support::*;
+    use tutorial_core::Failure;
+
+    #[tokio::test]
+    async fn test_register_new_user() {
+        let service = AuthServiceFactory::create_test();
+
+        let result = service.register("new@example.com", "password123").await;
+
+        assert!(result.is_ok());
+        let user = result.unwrap();
+        assert_eq!(user.email, "new@example.com");
+        assert_eq!(user.id, "id-1");
+    }
+
+    #[tokio::test]
+    async fn test_registered_ids_come_from_the_generator() {
+        let service = AuthServiceFactory::create_test();
+        let ann = service
+            .register("ann@example.com", "password123")
+            .await
+            .unwrap();
+        let bob = service
+            .register("bob@example.com", "password123")
+            .await
+            .unwrap();
+        assert_eq!((ann.id.as_str(), bob.id.as_str()), ("id-1", "id-2"));
+
+        // Without one, AuthService makes UUIDv7s, which sort by creation
+        let service = AuthService::new(
+            Arc::new(MockUserRepository::new()),
+            Arc::new(MockPasswordHasher),
+            Arc::new(MockTokenService),
+            Arc::new(MockCache::new()),
+        );
+        let mut ids = Vec::new();
+        for name in ["ann", "bob", "carol"] {
+            let email = format!("{}@example.com", name);
+            ids.push(service.register(&email, "password123").await.unwrap().id);
+        }
+        for id in &ids {
+            assert_eq!(uuid::Uuid::parse_str(id).unwrap().get_version_num(), 7);
+        }
+        assert!(ids.is_sorted());
+    }
+
+    #[tokio::test]
+    async fn test_register_duplicate_email() {
+        let existing_user = UserBuilder::new().email("existing@example.com").build();
+
+        let service = AuthServiceFactory::create_test_with_user(existing_user);
+
+        let result = service.register("existing@example.com", "password").await;
+
+        assert!(matches!(result, Err(Error::AlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn test_login_success() {
+        let user = UserBuilder::new().build();
+
+        let token = TokenBuilder::for_user(&user).build();
+        let service = AuthServiceFactory::create_test_with_user(user);
+
+        let result = service.login("test@example.com", "password123").await;
+
+        assert_eq!(result.unwrap(), token);
+    }
+
+    #[tokio::test]
+    async fn test_login_wrong_password() {
+        let user = UserBuilder::new().password("correct_password").build();
+
+        let service = AuthServiceFactory::create_test_with_user(user);
+
+        let result = service.login("test@example.com", "wrong_password").await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn test_password_policy_reports_every_broken_rule() {
+        let service = AuthServiceFactory::create_test();
+
+        let Err(Error::Validation(errors)) = service.register("bob@example.com", "bob").await
+        else {
+            panic!("expected a validation error");
+        };
+        let codes: Vec<&str> = errors.iter().map(|error| error.code.as_str()).collect();
+        assert_eq!(codes, ["length", "too_simple", "contains_email"]);
+        assert!(errors.iter().all(|error| error.field == "password"));
+
+        let strict = AuthService::new(
+            Arc::new(MockUserRepository::new()),
+            Arc::new(MockPasswordHasher),
+            Arc::new(MockTokenService),
+            Arc::new(MockCache::new()),
+        )
+        .with_password_policy(PasswordPolicy {
+            min_length: 12,
+            ..PasswordPolicy::default()
+        });
+        let result = strict.register("bob@example.com", "password123").await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        assert!(
+            strict
+                .register("bob@example.com", "correct horse")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_password_applies_the_policy() {
+        let service = AuthServiceFactory::create_test();
+        service
+            .register("carol@example.com", "password123")
+            .await
+            .unwrap();
+
+        let result = service
+            .change_password("carol@example.com", "password123", "Carol2024")
+            .await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        // Still the old password
+        assert!(
+            service
+                .login("carol@example.com", "password123")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_user_not_found() {
+        let service = AuthServiceFactory::create_test();
+
+        let result = service.login("nonexistent@example.com", "password").await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn test_change_password_success() {
+        let user = UserBuilder::new().password("old_password").build();
+
+        let service = AuthServiceFactory::create_test_with_user(user);
+
+        let result = service
+            .change_password("test@example.com", "old_password", "new_password")
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    // The login caches the user, hash included; the old password must stop
+    // working right away, not when that copy expires
+    #[tokio::test]
+    async fn test_change_password_drops_the_cached_hash() {
+        let user = UserBuilder::new().password("old_password").build();
+        let cache = Arc::new(MockCache::new());
+        let service = AuthService::new(
+            Arc::new(MockUserRepository::with_user(user)),
+            Arc::new(MockPasswordHasher),
+            Arc::new(MockTokenService),
+            cache.clone(),
+        );
+
+        service
+            .login("test@example.com", "old_password")
+            .await
+            .unwrap();
+        assert!(cache.get("user:email:test@example.com").await.is_some());
+
+        service
+            .change_password("test@example.com", "old_password", "new_password")
+            .await
+            .unwrap();
+        assert!(cache.get("user:email:test@example.com").await.is_none());
+        assert!(matches!(
+            service.login("test@example.com", "old_password").await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert!(
+            service
+                .login("test@example.com", "new_password")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_password_wrong_old_password() {
+        let user = UserBuilder::new().password("old_password").build();
+
+        let service = AuthServiceFactory::create_test_with_user(user);
+
+        let result = service
+            .change_password("test@example.com", "wrong_old_password", "new_password")
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn test_login_caches_user_for_next_login() {
+        let user = UserBuilder::new().build();
+
+        let cache = Arc::new(MockCache::new());
+        let service = AuthService::new(
+            Arc::new(MockUserRepository::with_user(user)),
+            Arc::new(MockPasswordHasher),
+            Arc::new(MockTokenService),
+            cache.clone(),
+        );
+
+        service
+            .login("test@example.com", "password123")
+            .await
+            .unwrap();
+
+        let cached = cache.get("user:email:test@example.com").await.unwrap();
+        assert!(cached.contains("\"id\":\"1\""));
+    }
+
+    #[test]
+    #[cfg(all(feature = "sqlite", feature = "redis"))]
+    fn test_backends_parse_from_config_values() {
+        assert_eq!("sqlite".parse(), Ok(RepositoryBackend::Sqlite));
+        assert_eq!("redis".parse(), Ok(CacheBackend::Redis));
+        assert_eq!("argon2".parse(), Ok(HasherKind::Argon2));
+        assert!("mongodb".parse::<RepositoryBackend>().is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "postgres"))]
+    fn test_disabled_backend_names_its_feature() {
+        let err = "postgres".parse::<RepositoryBackend>().unwrap_err();
+        assert!(err.contains("`postgres` feature"));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "memory")]
+    async fn test_from_config_with_memory_backends() {
+        let config = AppConfig {
+            repository: RepositoryBackend::Memory,
+            database_url: SecretString::default(),
+            slow_query_ms: DEFAULT_SLOW_QUERY_MS,
+            cache: CacheBackend::Memory,
+            redis_url: SecretString::default(),
+            hasher: HasherKind::Argon2,
+            jwt_secret: "secret".into(),
+            jwt_ttl_seconds: JwtTokenService::DEFAULT_TTL_SECONDS,
+            storage: StorageBackend::Local,
+            storage_url: "file://uploads".to_string(),
+            storage_public_url: "/uploads".to_string(),
+            session_ttl_seconds: SessionService::DEFAULT_TTL_SECONDS,
+            sentry_dsn: None,
+            metrics: config::MetricsBackend::None,
+            statsd_addr: String::new(),
+        };
+
+        let service = AuthServiceFactory::from_config(&config);
+
+        service
+            .register("new@example.com", "password123")
+            .await
+            .unwrap();
+        let token = service
+            .login("new@example.com", "password123")
+            .await
+            .unwrap();
+        // A signed JWT, which the same service reads back
+        assert_eq!(token.split('.').count(), 3);
+        assert_eq!(
+            service.current_user(&token).await.unwrap().email,
+            "new@example.com"
+        );
+    }
+
+    // Writing to the audit trail first still runs the migrations, and the
+    // entry lands in the users' file
+    #[tokio::test]
+    #[cfg(all(feature = "sqlite", feature = "memory"))]
+    async fn test_sqlite_backend_keeps_the_audit_trail_with_the_users() {
+        let dir = std::env::temp_dir().join(format!("audit-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database_url = format!("sqlite://{}", dir.join("users.db").display());
+        let config = AppConfig {
+            repository: RepositoryBackend::Sqlite,
+            database_url: database_url.clone().into(),
+            slow_query_ms: DEFAULT_SLOW_QUERY_MS,
+            cache: CacheBackend::Memory,
+            redis_url: SecretString::default(),
+            hasher: HasherKind::Argon2,
+            jwt_secret: "secret".into(),
+            jwt_ttl_seconds: JwtTokenService::DEFAULT_TTL_SECONDS,
+            storage: StorageBackend::Local,
+            storage_url: "file://uploads".to_string(),
+            storage_public_url: "/uploads".to_string(),
+            session_ttl_seconds: SessionService::DEFAULT_TTL_SECONDS,
+            sentry_dsn: None,
+            metrics: config::MetricsBackend::None,
+            statsd_addr: String::new(),
+        };
+        let scope = Arc::new(AuthServiceFactory::container(&config)).create_scope();
+        let audit: Arc<dyn AuditRepository> = scope.resolve().unwrap();
+        let users: Arc<dyn UserRepository> = scope.resolve().unwrap();
+
+        let entry = AuditEntry {
+            at: 1_700_000_000,
+            actor: "admin".to_string(),
+            action: "deactivated".to_string(),
+            target: "alice".to_string(),
+            detail: String::new(),
+            ip: None,
+        };
+        audit.append(entry.clone()).await.unwrap();
+        users
+            .create(UserBuilder::named("alice").build())
+            .await
+            .unwrap();
+        users.shutdown().await.unwrap();
+
+        let reopened = SqliteUserRepository::connect(&database_url).await.unwrap();
+        let page = reopened
+            .audit()
+            .query(&AuditFilter::default(), PageRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(page.items, [entry]);
+        assert!(reopened.find_by_id("alice").await.unwrap().is_some());
+        reopened.shutdown().await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_container_shares_singletons_across_scopes() {
+        let container = Arc::new(AuthServiceFactory::test_container(Vec::new()));
+        let request_a = container.create_scope();
+        let request_b = container.create_scope();
+
+        let service_a: Arc<AuthService> = request_a.resolve().unwrap();
+        let service_b: Arc<AuthService> = request_b.resolve().unwrap();
+
+        // Scoped: one AuthService per request...
+        assert!(Arc::ptr_eq(&service_a, &request_a.resolve().unwrap()));
+        assert!(!Arc::ptr_eq(&service_a, &service_b));
+
+        // ...singleton: one repository for everyone
+        let repo_a: Arc<dyn UserRepository> = request_a.resolve().unwrap();
+        let repo_b: Arc<dyn UserRepository> = request_b.resolve().unwrap();
+        assert!(Arc::ptr_eq(&repo_a, &repo_b));
 
-    println!("\n=== Test Setup (AFTER) ===");
-    let test_service = after::AuthServiceFactory::create_test();
+        service_a
+            .register("new@example.com", "password123")
+            .await
+            .unwrap();
+        assert!(
+            service_b
+                .login("new@example.com", "password123")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_repository_conforms() {
+        run_repository_conformance(|| async { MockUserRepository::new() }).await;
+    }
+
+    // A file per repository in the temp dir; no server needed
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_repository_conforms() {
+        let dir = std::env::temp_dir().join(format!("conformance-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let databases = std::sync::atomic::AtomicUsize::new(0);
+        run_repository_conformance(|| async {
+            let n = databases.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let path = format!("sqlite://{}", dir.join(format!("users-{}.db", n)).display());
+            let repository = SqliteUserRepository::connect(&path).await.unwrap();
+            repository.start().await.unwrap();
+            repository
+        })
+        .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mock_audit_repository_conforms() {
+        run_audit_conformance(|| async { MockAuditRepository::default() }).await;
+    }
+
+    // On the users' pool, so start() is what creates audit_log
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_audit_repository_conforms() {
+        let dir = std::env::temp_dir().join(format!("conformance-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let databases = std::sync::atomic::AtomicUsize::new(0);
+        run_audit_conformance(|| async {
+            let n = databases.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let path = format!("sqlite://{}", dir.join(format!("audit-{}.db", n)).display());
+            let repository = SqliteUserRepository::connect(&path).await.unwrap();
+            repository.start().await.unwrap();
+            repository.audit()
+        })
+        .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lazy_repository_connects_on_first_use() {
+        let connects = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = connects.clone();
+        let repository = LazyUserRepository(Lazy::new(move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { Ok(Arc::new(MockUserRepository::new()) as Arc<dyn UserRepository>) }
+        }));
+
+        // Building the service doesn't touch the database
+        let service = AuthService::new(
+            Arc::new(repository),
+            Arc::new(MockPasswordHasher),
+            Arc::new(MockTokenService),
+            Arc::new(MockCache::new()),
+        );
+        assert_eq!(connects.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        service
+            .register("new@example.com", "password123")
+            .await
+            .unwrap();
+        service
+            .login("new@example.com", "password123")
+            .await
+            .unwrap();
+        assert_eq!(connects.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    struct HangingCheck;
+
+    #[async_trait]
+    impl HealthCheck for HangingCheck {
+        async fn check(&self) -> Result<(), Error> {
+            std::future::pending::<Result<(), Error>>().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readiness_reports_every_registered_dependency() {
+        let container = Arc::new(AuthServiceFactory::test_container(Vec::new()));
+        let health: Arc<HealthService> = container.create_scope().resolve().unwrap();
+
+        let report = health.readiness().await;
+
+        assert!(report.is_ready());
+        let names: Vec<&str> = report.components.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["database", "cache", "token_service"]);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_fails_when_any_dependency_is_down() {
+        let health = HealthService::new()
+            .with_check("database", Arc::new(MockUserRepository::new()))
+            .with_check("cache", DownCheck)
+            .with_check("search", HangingCheck)
+            .with_timeout(std::time::Duration::from_millis(20));
+
+        let report = health.readiness().await;
+
+        assert!(!report.is_ready());
+        let failed: Vec<&str> = report.failures().map(|c| c.name.as_str()).collect();
+        assert_eq!(failed, ["cache", "search"]);
+    }
+
+    #[tokio::test]
+    async fn test_optional_dependencies_only_degrade() {
+        let health = HealthService::new()
+            .with_check("database", Arc::new(MockUserRepository::new()))
+            .with_optional_check("cache", DownCheck);
+        assert_eq!(health.readiness().await.status(), HealthStatus::Degraded);
+
+        let health = HealthService::new()
+            .with_check("database", DownCheck)
+            .with_optional_check("cache", DownCheck);
+        assert_eq!(health.readiness().await.status(), HealthStatus::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_auth_spans_keep_the_email_and_skip_the_password() {
+        let (logs, _guard) = CapturedLogs::install();
+        let service = AuthServiceFactory::create_test();
+
+        service
+            .register("ann@example.com", "correct-horse")
+            .await
+            .unwrap();
+        let token = service
+            .login("ann@example.com", "correct-horse")
+            .await
+            .unwrap();
+        service.logout(&token).await.unwrap();
+
+        let logs = logs.text();
+        assert!(
+            logs.contains(r#"register{email="ann@example.com"}"#),
+            "{}",
+            logs
+        );
+        assert!(
+            logs.contains(r#"login{email="ann@example.com"}"#),
+            "{}",
+            logs
+        );
+        assert!(logs.contains("logout"), "{}", logs);
+        // Nor its hash, nor the bearer token
+        assert!(!logs.contains("correct-horse"), "{}", logs);
+        assert!(!logs.contains(&token), "{}", logs);
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_credential_cache_keys_are_logged_by_prefix_only() {
+        assert_eq!(loggable_key("token:revoked:abc.def"), "token:revoked:");
+        assert_eq!(loggable_key("session:0193b6f2"), "session:");
+        assert_eq!(
+            loggable_key("user:email:ann@example.com"),
+            "user:email:ann@example.com"
+        );
+    }
+
+    // What main() gets from `?`: anyhow on top, every typed layer below
+    #[tokio::test]
+    async fn test_errors_reach_main_with_their_types() {
+        let repository = LazyUserRepository(Lazy::new(|| async {
+            let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+            Err::<Arc<dyn UserRepository>, _>(Error::with_source("postgres: connect", refused))
+        }));
+
+        let error = repository.find_by_id("1").await.unwrap_err();
+        let error = anyhow::Error::from(error).context("loading user 1");
+        assert!(error.downcast_ref::<Error>().unwrap().is_retryable());
+        assert!(
+            error
+                .chain()
+                .any(|cause| cause.downcast_ref::<std::io::Error>().is_some())
+        );
+        assert_eq!(
+            format!("{:#}", error),
+            "loading user 1: connecting to the database: postgres: connect: connection refused"
+        );
+
+        let errors = config::AppConfig::from_lookup(|key| {
+            (key == "SERVER_PORT").then(|| "http".to_string())
+        })
+        .unwrap_err();
+        let error = anyhow::Error::from(errors).context("loading configuration");
+        let errors = error.downcast_ref::<config::ConfigErrors>().unwrap();
+        assert_eq!(errors.0[0].key, "SERVER_PORT");
+    }
+
+    #[tokio::test]
+    async fn test_lazy_cache_reports_failed_connect() {
+        let (logs, _guard) = CapturedLogs::install();
+        let cache = LazyCache::new(Lazy::new(|| async {
+            let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+            Err::<Arc<dyn CacheService>, _>(Error::with_source("redis: connect", refused))
+        }));
+
+        // The io::Error comes back as the source, not as a string of it
+        let Err(error) = cache.check().await else {
+            panic!("the connect fails");
+        };
+        let cause = std::error::Error::source(&error).unwrap();
+        assert!(cause.downcast_ref::<std::io::Error>().is_some());
+        assert_eq!(error.report(), "redis: connect: connection refused");
+        // ...while requests still see an empty cache
+        assert_eq!(cache.get("key").await, None);
+
+        // and a storm of them logs a few, not one per request
+        for _ in 0..20 {
+            cache.get("key").await;
+        }
+        assert_eq!(logs.text().matches("cache unavailable").count(), 3);
+    }
+
+    // MockCache, with a note of which method each call reached
+    struct CallCountingCache {
+        inner: MockCache,
+        calls: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    impl CallCountingCache {
+        fn new() -> Self {
+            Self {
+                inner: MockCache::new(),
+                calls: std::sync::Mutex::new(Vec::new()),
+            }
+        }
+
+        fn record(&self, method: &'static str) {
+            self.calls.lock().unwrap().push(method);
+        }
+    }
+
+    #[async_trait]
+    impl CacheService for CallCountingCache {
+        async fn get(&self, key: &str) -> Option<String> {
+            self.record("get");
+            self.inner.get(key).await
+        }
+
+        async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>) {
+            self.record("set");
+            self.inner.set(key, value, ttl_seconds).await
+        }
+
+        async fn delete(&self, key: &str) {
+            self.record("delete");
+            self.inner.delete(key).await
+        }
+
+        async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+            self.record("incr");
+            self.inner.incr(key, by, ttl_seconds).await
+        }
+
+        async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+            self.record("get_many");
+            self.inner.get_many(keys).await
+        }
+
+        async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+            self.record("set_many");
+            self.inner.set_many(entries).await
+        }
+
+        async fn ttl(&self, key: &str) -> Option<u64> {
+            self.record("ttl");
+            self.inner.ttl(key).await
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for CallCountingCache {
+        async fn check(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Lifecycle for CallCountingCache {}
+
+    // The wrappers the factory puts around RedisCache pass batches on
+    // whole: one MGET, one pipeline, not a round trip per key
+    #[tokio::test]
+    async fn test_cache_wrappers_pass_batches_through() {
+        let backend = Arc::new(CallCountingCache::new());
+        let connected = backend.clone();
+        let lazy = LazyCache::new(Lazy::new(move || {
+            let connected = connected.clone();
+            async move { Ok(connected as Arc<dyn CacheService>) }
+        }));
+        let swappable: Arc<dyn CacheService> = Arc::new(Swappable::<dyn CacheService>::new(
+            Arc::new(lazy) as Arc<dyn CacheService>,
+        ));
+        let breaker = WithCircuitBreaker::<dyn CacheService>::new(swappable, test_breaker(3));
+        let cache = InstrumentedCache::new(Arc::new(breaker));
+
+        cache
+            .set_many(&[
+                ("a", "1".to_string(), Some(60)),
+                ("b", "2".to_string(), None),
+            ])
+            .await;
+        assert_eq!(
+            cache.get_many(&["a", "b", "c"]).await,
+            vec![Some("1".to_string()), Some("2".to_string()), None]
+        );
+        assert!(cache.ttl("a").await.is_some());
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            ["set_many", "get_many", "ttl"]
+        );
+        // Counted per key, not per batch
+        let stats = cache.stats();
+        assert_eq!(
+            stats,
+            crate::metrics::CacheStats {
+                hits: 2,
+                misses: 1,
+                sets: 2,
+                evictions: 0,
+            }
+        );
+        assert_eq!(stats.hit_ratio(), 2.0 / 3.0);
+
+        // A cache that can't connect misses every key, and writes go nowhere
+        let down = LazyCache::new(Lazy::new(|| async {
+            Err::<Arc<dyn CacheService>, _>(Error::internal("redis: connect"))
+        }));
+        down.set_many(&[("a", "1".to_string(), None)]).await;
+        assert_eq!(down.get_many(&["a", "b"]).await, vec![None, None]);
+        assert_eq!(down.ttl("a").await, None);
+    }
+
+    struct RecordingComponent {
+        name: &'static str,
+        events: Arc<std::sync::Mutex<Vec<String>>>,
+        fail_start: bool,
+    }
+
+    #[async_trait]
+    impl Lifecycle for RecordingComponent {
+        async fn start(&self) -> Result<(), Error> {
+            if self.fail_start {
+                return Err(Error::internal(format!("{} unavailable", self.name)));
+            }
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("start {}", self.name));
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> Result<(), Error> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("stop {}", self.name));
+            Ok(())
+        }
+    }
+
+    fn recording_app(events: &Arc<std::sync::Mutex<Vec<String>>>, failing: Option<&str>) -> App {
+        let mut app = App::new(Arc::new(AuthServiceFactory::test_container(Vec::new())));
+        for name in ["database", "cache", "worker"] {
+            let component = RecordingComponent {
+                name,
+                events: events.clone(),
+                fail_start: failing == Some(name),
+            };
+            app = app.with_component(name, Arc::new(component));
+        }
+        app
+    }
+
+    #[tokio::test]
+    async fn test_app_shuts_down_in_reverse_start_order() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let app = recording_app(&events, None);
+
+        app.start().await.unwrap();
+        app.shutdown().await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "start database",
+                "start cache",
+                "start worker",
+                "stop worker",
+                "stop cache",
+                "stop database",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_app_failed_start_rolls_back_started_components() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let app = recording_app(&events, Some("cache"));
+
+        assert!(matches!(app.start().await, Err(Error::Internal { .. })));
+        assert_eq!(*events.lock().unwrap(), ["start database", "stop database"]);
+    }
+
+    #[tokio::test]
+    async fn test_each_background_run_logs_under_its_own_id() {
+        let (logs, _guard) = CapturedLogs::install();
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(Vec::new()))).with_task(
+            BackgroundTask::new("ticker", std::time::Duration::from_millis(5), || {
+                tracing::info!("tick")
+            }),
+        );
+
+        app.start().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        app.shutdown().await.unwrap();
+
+        let logs = logs.text();
+        let ticks: Vec<&str> = logs.lines().filter(|line| line.ends_with("tick")).collect();
+        assert!(ticks.len() > 1, "{}", logs);
+        assert!(
+            ticks
+                .iter()
+                .all(|line| line.contains("task{name=ticker request_id="))
+        );
+        let ids: std::collections::HashSet<&str> = ticks
+            .iter()
+            .map(|line| line.split("request_id=").nth(1).unwrap())
+            .collect();
+        assert_eq!(ids.len(), ticks.len());
+    }
+
+    #[tokio::test]
+    async fn test_background_task_stops_on_shutdown() {
+        let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = runs.clone();
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(Vec::new()))).with_task(
+            BackgroundTask::new("ticker", std::time::Duration::from_millis(5), move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }),
+        );
+
+        app.start().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        app.shutdown().await.unwrap();
+
+        let after_shutdown = runs.load(std::sync::atomic::Ordering::SeqCst);
+        assert!(after_shutdown > 0);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(
+            runs.load(std::sync::atomic::Ordering::SeqCst),
+            after_shutdown
+        );
+    }
+
+    #[tokio::test]
+    async fn test_background_task_runs_on_its_clock() {
+        let clock = Arc::new(MockClock::new());
+        let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = runs.clone();
+        let task = BackgroundTask::new("ticker", std::time::Duration::from_secs(60), move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        })
+        .with_clock(clock.clone());
+        let ran = || runs.load(std::sync::atomic::Ordering::SeqCst);
+
+        task.start().await.unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(ran(), 0);
+
+        // A minute passes in no time at all
+        clock.advance(std::time::Duration::from_secs(59));
+        tokio::task::yield_now().await;
+        assert_eq!(ran(), 0);
+        clock.advance(std::time::Duration::from_secs(1));
+        tokio::task::yield_now().await;
+        tokio::task::yield_now().await;
+        assert_eq!(ran(), 1);
+
+        task.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fault_injector_spreads_failures_evenly() {
+        let faults = FaultInjector::new(FailureConfig::failing(25));
+
+        let mut outcomes = Vec::new();
+        for _ in 0..8 {
+            outcomes.push(faults.before_call("op").await.is_ok());
+        }
+
+        assert_eq!(outcomes, [true, true, true, false, true, true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_flaky_factory_fails_every_call_at_100_percent() {
+        let service = AuthServiceFactory::create_flaky_test(FailureConfig::failing(100));
+
+        let result = service.register("new@example.com", "password123").await;
+
+        assert!(
+            matches!(result, Err(Error::Internal { ref message, .. }) if message.contains("find_by_email"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_context_says_where_internal_errors_came_from() {
+        // Call 1 (find_by_email) works, call 2 (create) fails
+        let service = AuthServiceFactory::create_flaky_test(FailureConfig::failing(50));
+
+        let error = service
+            .register("new@example.com", "password123")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "creating user");
+        assert_eq!(error.report(), "creating user: injected failure: create");
+        // Still worth a retry
+        assert!(error.is_retryable());
+
+        let not_found: Result<(), Error> = Err(Error::NotFound);
+        assert!(matches!(
+            not_found.context("loading user"),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_are_reported_only_inside_a_request() {
+        let reporter = Arc::new(MockErrorReporter::default());
+        let error = Error::internal("connect")
+            .transient()
+            .context("loading user");
+
+        log_internal(&error);
+        assert!(reporter.events().is_empty());
+
+        reporting_errors_to(reporter.clone(), async { log_internal(&error) }).await;
+        let events = reporter.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message, "loading user: connect");
+        assert_eq!(events[0].code, "SYS001_INTERNAL");
+        assert!(events[0].transient);
+    }
+
+    #[cfg(feature = "sentry")]
+    #[test]
+    fn test_sentry_reporter_posts_to_the_projects_store_endpoint() {
+        let reporter = SentryErrorReporter::new("https://abc123@o1.ingest.sentry.io/42");
+        assert_eq!(
+            reporter.store_url,
+            "https://o1.ingest.sentry.io/api/42/store/"
+        );
+        assert!(reporter.auth.ends_with("sentry_key=abc123"));
+
+        let payload = SentryErrorReporter::payload(&ErrorEvent {
+            message: "loading user: connect".to_string(),
+            code: "SYS001_INTERNAL",
+            transient: true,
+            request_id: Some("3f0c".to_string()),
+            backtrace: None,
+        });
+        assert_eq!(payload["level"], "error");
+        assert_eq!(payload["message"]["formatted"], "loading user: connect");
+        assert_eq!(payload["tags"]["request_id"], "3f0c");
+        assert_eq!(payload["tags"]["transient"], "true");
+        assert_eq!(payload["event_id"].as_str().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_backtrace_is_found_under_context() {
+        // What Error::internal makes with RUST_BACKTRACE set, and without
+        let failed_at = |trace| Error::Internal {
+            message: "connect".to_string(),
+            source: None,
+            failure: Failure::Permanent,
+            trace: Box::new(trace),
+        };
+
+        let error = failed_at(Backtrace::force_capture()).context("loading user");
+        assert!(error.context("handling request").backtrace().is_some());
+
+        let error = failed_at(Backtrace::disabled()).context("loading user");
+        assert!(error.backtrace().is_none());
+        assert!(Error::NotFound.backtrace().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_flaky_factory_fails_token_generation_deterministically() {
+        let user = UserBuilder::new().build();
+        let container =
+            AuthServiceFactory::flaky_test_container(vec![user], FailureConfig::failing(50));
+        let service: Arc<AuthService> = Arc::new(container).create_scope().resolve().unwrap();
+
+        // The user is cached after the first lookup, so only the token
+        // service keeps being called: every second call fails
+        let mut outcomes = Vec::new();
+        for _ in 0..4 {
+            let result = service.login("test@example.com", "password123").await;
+            outcomes.push(result.is_ok());
+        }
+        assert_eq!(outcomes, [true, false, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_flaky_factory_adds_latency() {
+        let latency = std::time::Duration::from_millis(20);
+        let service =
+            AuthServiceFactory::create_flaky_test(FailureConfig::default().with_latency(latency));
+
+        let started = std::time::Instant::now();
+        service
+            .register("new@example.com", "password123")
+            .await
+            .unwrap();
+
+        // find_by_email + create
+        assert!(started.elapsed() >= latency * 2);
+    }
+
+    // Written once against the trait, run against both dispatch styles
+    async fn exercise_authenticator(service: &dyn Authenticator) {
+        service
+            .register("new@example.com", "password123")
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.register("new@example.com", "password123").await,
+            Err(Error::AlreadyExists)
+        ));
+
+        let token = service
+            .login("new@example.com", "password123")
+            .await
+            .unwrap();
+        assert!(token.starts_with("mock_token_"));
+        assert!(matches!(
+            service.login("new@example.com", "wrong").await,
+            Err(Error::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_and_static_dispatch_behave_the_same() {
+        exercise_authenticator(&AuthServiceFactory::create_test()).await;
+        exercise_authenticator(&AuthServiceFactory::create_test_generic(Vec::new())).await;
+    }
+
+    #[tokio::test]
+    async fn test_generic_register_takes_ids_from_its_generator() {
+        let service = AuthServiceFactory::create_test_generic(Vec::new());
+        let ann = service
+            .register("ann@example.com", "password123")
+            .await
+            .unwrap();
+        let bob = service
+            .register("bob@example.com", "password123")
+            .await
+            .unwrap();
+        assert_eq!((ann.id.as_str(), bob.id.as_str()), ("id-1", "id-2"));
+    }
+
+    #[tokio::test]
+    async fn test_generic_change_password_invalidates_cache() {
+        let user = UserBuilder::new().password("old_password").build();
+        let service = AuthServiceFactory::create_test_generic(vec![user]);
+        service
+            .login("test@example.com", "old_password")
+            .await
+            .unwrap();
+
+        service
+            .change_password("test@example.com", "old_password", "new_password")
+            .await
+            .unwrap();
+
+        assert!(
+            service
+                .login("test@example.com", "new_password")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generic_change_password_applies_the_policy() {
+        let service = AuthServiceFactory::create_test_generic(Vec::new());
+        service
+            .register("carol@example.com", "password123")
+            .await
+            .unwrap();
+
+        let result = service
+            .change_password("carol@example.com", "password123", "Carol2024")
+            .await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        // Still the old password
+        assert!(
+            service
+                .login("carol@example.com", "password123")
+                .await
+                .is_ok()
+        );
+    }
+
+    // Same scenarios as above, written as interaction tests with mockall:
+    // they check what AuthService asked of its dependencies, not just the result
+    mod with_mockall {
+        use super::*;
+        use after::generated;
+        use mockall::predicate::eq;
+
+        fn test_user() -> User {
+            UserBuilder::new().password_hash("hashed").build()
+        }
+
+        // An empty cache that accepts writes
+        fn cold_cache() -> generated::MockCacheService {
+            let mut cache = generated::MockCacheService::new();
+            cache.expect_get().returning(|_| None);
+            cache.expect_set().returning(|_, _, _| ());
+            cache
+        }
+
+        #[tokio::test]
+        async fn test_login_generates_token_for_the_found_user() {
+            let mut repository = generated::MockUserRepository::new();
+            repository
+                .expect_find_by_email()
+                .with(eq("test@example.com"))
+                .times(1)
+                .returning(|_| Ok(Some(test_user())));
+
+            let mut hasher = generated::MockPasswordHasher::new();
+            hasher
+                .expect_verify()
+                .with(eq("password123"), eq("hashed"))
+                .return_const(true);
+
+            let mut tokens = generated::MockTokenService::new();
+            tokens
+                .expect_generate()
+                .with(eq("1"))
+                .times(1)
+                .returning(|id| Ok(format!("token_{}", id)));
+
+            let service = AuthService::new(
+                Arc::new(repository),
+                Arc::new(hasher),
+                Arc::new(tokens),
+                Arc::new(cold_cache()),
+            );
+
+            let token = service
+                .login("test@example.com", "password123")
+                .await
+                .unwrap();
+            assert_eq!(token, "token_1");
+        }
+
+        #[tokio::test]
+        async fn test_login_with_wrong_password_never_issues_a_token() {
+            let mut repository = generated::MockUserRepository::new();
+            repository
+                .expect_find_by_email()
+                .returning(|_| Ok(Some(test_user())));
+
+            let mut hasher = generated::MockPasswordHasher::new();
+            hasher.expect_verify().return_const(false);
+
+            let mut tokens = generated::MockTokenService::new();
+            tokens.expect_generate().never();
+
+            let service = AuthService::new(
+                Arc::new(repository),
+                Arc::new(hasher),
+                Arc::new(tokens),
+                Arc::new(cold_cache()),
+            );
+
+            let result = service.login("test@example.com", "wrong").await;
+            assert!(matches!(result, Err(Error::InvalidCredentials)));
+        }
+
+        #[tokio::test]
+        async fn test_register_duplicate_never_hashes_or_creates() {
+            let mut repository = generated::MockUserRepository::new();
+            repository
+                .expect_find_by_email()
+                .returning(|_| Ok(Some(test_user())));
+            repository.expect_create().never();
+
+            let mut hasher = generated::MockPasswordHasher::new();
+            hasher.expect_hash().never();
+
+            let service = AuthService::new(
+                Arc::new(repository),
+                Arc::new(hasher),
+                Arc::new(generated::MockTokenService::new()),
+                Arc::new(generated::MockCacheService::new()),
+            );
+
+            let result = service.register("test@example.com", "password").await;
+            assert!(matches!(result, Err(Error::AlreadyExists)));
+        }
+
+        #[tokio::test]
+        async fn test_change_password_invalidates_cached_user() {
+            let mut repository = generated::MockUserRepository::new();
+            repository
+                .expect_find_by_email()
+                .returning(|_| Ok(Some(test_user())));
+            repository
+                .expect_update()
+                .withf(|user| user.password_hash == "hashed_new")
+                .times(1)
+                .returning(Ok);
+
+            let mut hasher = generated::MockPasswordHasher::new();
+            hasher.expect_verify().return_const(true);
+            hasher
+                .expect_hash()
+                .with(eq("new-password"))
+                .return_const("hashed_new".to_string());
+
+            let mut cache = generated::MockCacheService::new();
+            cache
+                .expect_delete()
+                .with(eq("user:email:test@example.com"))
+                .times(1)
+                .returning(|_| ());
+
+            let service = AuthService::new(
+                Arc::new(repository),
+                Arc::new(hasher),
+                Arc::new(generated::MockTokenService::new()),
+                Arc::new(cache),
+            );
+
+            service
+                .change_password("test@example.com", "old", "new-password")
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_avatar_types_are_sniffed_from_the_bytes() {
+        let sniff = ProfileService::sniff_avatar_type;
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0..JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        // A WAV file is RIFF too
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff(b"GIF89a"), None);
+        assert_eq!(sniff(b"\x89PNG"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[tokio::test]
+    async fn test_swapped_cache_is_picked_up_by_existing_services() {
+        let user = UserBuilder::new().build();
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(vec![user])));
+        let service = app.auth_service();
+
+        let replacement = Arc::new(MockCache::new());
+        app.swap_cache(replacement.clone());
+        service
+            .login("test@example.com", "password123")
+            .await
+            .unwrap();
+
+        // Resolved before the swap, still wrote to the new cache
+        assert!(
+            replacement
+                .get("user:email:test@example.com")
+                .await
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_fails_over_to_memory_when_down() {
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(Vec::new())));
+        app.swap_cache(Arc::new(DownCache));
+        assert!(!app.health().readiness().await.is_ready());
+
+        assert!(app.fail_over_cache_if_down().await);
+
+        assert!(app.health().readiness().await.is_ready());
+        assert!(!app.fail_over_cache_if_down().await);
+    }
+
+    fn test_breaker(threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "test",
+            CircuitBreakerConfig {
+                failure_threshold: threshold,
+                open_duration: std::time::Duration::from_millis(20),
+                call_timeout: std::time::Duration::from_millis(50),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_after_threshold_and_stops_calling_backend() {
+        let faults = Arc::new(FaultInjector::new(FailureConfig::failing(100)));
+        let repository = WithCircuitBreaker::<dyn UserRepository>::new(
+            Arc::new(FlakyUserRepository::new(
+                Arc::new(MockUserRepository::new()),
+                faults.clone(),
+            )),
+            test_breaker(3),
+        );
+
+        for _ in 0..5 {
+            assert!(repository.find_by_email("a@example.com").await.is_err());
+        }
+
+        // Calls 4 and 5 were rejected without reaching the backend
+        assert_eq!(faults.calls(), 3);
+        assert_eq!(repository.breaker().state(), CircuitState::Open);
+        assert_eq!(repository.breaker().metrics().rejected, 2);
+        assert!(repository.check().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_half_open_probe_closes_on_success() {
+        let breaker = test_breaker(1);
+        let fail = || async { Err::<(), _>(Error::internal("down").transient()) };
+        let succeed = || async { Ok::<(), Error>(()) };
+
+        assert!(breaker.call(fail).await.is_err());
+        assert_eq!(breaker.state(), CircuitState::Open);
 
-    match test_service.register("test@example.com", "password123").await {
-        Ok(user) => println!("Registered user: {:?}", user),
-        Err(e) => println!("Error: {:?}", e),
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        assert!(breaker.call(succeed).await.is_ok());
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(
+            breaker.metrics(),
+            CircuitMetrics {
+                opened: 1,
+                half_opened: 1,
+                closed: 1,
+                rejected: 0,
+            }
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use after::*;
+    #[tokio::test]
+    async fn test_circuit_failed_probe_reopens() {
+        let breaker = test_breaker(1);
+        let fail = || async { Err::<(), _>(Error::internal("down").transient()) };
+
+        let _ = breaker.call(fail).await;
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        let _ = breaker.call(fail).await;
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.metrics().opened, 2);
+    }
 
     #[tokio::test]
-    async fn test_register_new_user() {
-        let service = AuthServiceFactory::create_test();
+    async fn test_business_errors_do_not_trip_the_circuit() {
+        let breaker = test_breaker(1);
 
-        let result = service.register("new@example.com", "password123").await;
+        let result = breaker
+            .call(|| async { Err::<(), _>(Error::NotFound) })
+            .await;
 
-        assert!(result.is_ok());
-        let user = result.unwrap();
-        assert_eq!(user.email, "new@example.com");
+        assert!(matches!(result, Err(Error::NotFound)));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        // Neither do permanent internal ones: the backend answered
+        let result = breaker
+            .call(|| async { Err::<(), _>(Error::internal("syntax error in SQL")) })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(breaker.state(), CircuitState::Closed);
     }
 
     #[tokio::test]
-    async fn test_register_duplicate_email() {
-        let existing_user = User {
-            id: "1".to_string(),
-            email: "existing@example.com".to_string(),
-            password_hash: "hash".to_string(),
-            role: "user".to_string(),
+    async fn test_timeouts_are_retryable_and_answers_are_not() {
+        let breaker = test_breaker(1);
+        let slow = || async {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            Ok::<(), Error>(())
         };
+        let timed_out = breaker.call(slow).await.unwrap_err();
+        assert!(timed_out.is_retryable());
+        // ...and so is being turned away by the circuit it opened
+        let rejected = breaker.call(slow).await.unwrap_err();
+        assert!(rejected.is_retryable());
 
-        let service = AuthServiceFactory::create_test_with_user(existing_user);
+        for error in [
+            Error::NotFound,
+            Error::AlreadyExists,
+            Error::InvalidCredentials,
+            Error::internal("invalid storage key"),
+        ] {
+            assert!(!error.is_retryable(), "{}", error);
+            assert_eq!(error.failure(), Failure::Permanent);
+        }
+    }
 
-        let result = service.register("existing@example.com", "password").await;
+    #[tokio::test]
+    async fn test_retry_repeats_only_transient_failures() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(1),
+        };
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let count = || attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+
+        // Deadlocked twice, then through
+        let result = policy
+            .call(|| async {
+                match count() {
+                    1 | 2 => Err(Error::internal("deadlock detected").transient()),
+                    _ => Ok("done"),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(attempts.swap(0, std::sync::atomic::Ordering::SeqCst), 3);
 
+        // Still down after max_attempts: the last error comes back
+        let result = policy
+            .call(|| async {
+                count();
+                Err::<(), _>(Error::internal("timed out").transient())
+            })
+            .await;
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(attempts.swap(0, std::sync::atomic::Ordering::SeqCst), 3);
+
+        // A constraint violation is the same on every attempt: tried once
+        let result = policy
+            .call(|| async {
+                count();
+                Err::<(), _>(Error::AlreadyExists)
+            })
+            .await;
         assert!(matches!(result, Err(Error::AlreadyExists)));
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
     }
 
     #[tokio::test]
-    async fn test_login_success() {
-        let user = User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            password_hash: "mock_hash_password123".to_string(),
-            role: "user".to_string(),
+    async fn test_retry_rides_out_a_flaky_repository() {
+        // Every second call fails
+        let faults = Arc::new(FaultInjector::new(FailureConfig::failing(50)));
+        let repository = WithRetry::<dyn UserRepository>::new(
+            Arc::new(FlakyUserRepository::new(
+                Arc::new(MockUserRepository::new()),
+                faults.clone(),
+            )),
+            RetryPolicy {
+                max_attempts: 2,
+                base_delay: std::time::Duration::from_millis(1),
+            },
+        );
+
+        assert!(repository.find_by_email("a@example.com").await.is_ok());
+        assert!(repository.find_by_email("a@example.com").await.is_ok());
+        assert_eq!(faults.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_slow_queries_are_logged_with_method_and_args() {
+        let (logs, _guard) = CapturedLogs::install();
+        let slow = FailureConfig::default().with_latency(std::time::Duration::from_millis(20));
+        let repository = Arc::new(FlakyUserRepository::new(
+            Arc::new(MockUserRepository::new()),
+            Arc::new(FaultInjector::new(slow)),
+        )) as Arc<dyn UserRepository>;
+
+        WithSlowQueryLog::new(repository.clone(), std::time::Duration::from_secs(5))
+            .find_by_id("fast")
+            .await
+            .unwrap();
+        assert!(!logs.text().contains("slow query"));
+
+        WithSlowQueryLog::new(repository, std::time::Duration::from_millis(10))
+            .find_by_email("slow@example.com")
+            .await
+            .unwrap();
+        let logs = logs.text();
+        let line = logs
+            .lines()
+            .find(|line| line.contains("slow query"))
+            .unwrap();
+        assert!(line.contains("WARN"));
+        assert!(line.contains(r#"method="find_by_email""#));
+        assert!(line.contains("args=email=slow@example.com"));
+        assert!(line.contains("threshold_ms=10"));
+    }
+
+    #[tokio::test]
+    async fn test_logins_and_queries_show_up_in_prometheus() {
+        let metrics = Arc::new(PrometheusMetrics::default());
+        let repository = WithSlowQueryLog::<dyn UserRepository>::new(
+            Arc::new(MockUserRepository::new()),
+            std::time::Duration::from_secs(5),
+        )
+        .with_metrics(metrics.clone());
+        let service = AuthService::new(
+            Arc::new(repository),
+            Arc::new(MockPasswordHasher),
+            Arc::new(MockTokenService),
+            Arc::new(
+                InstrumentedCache::new(Arc::new(MockCache::new())).with_metrics(metrics.clone()),
+            ),
+        )
+        .with_metrics(metrics.clone());
+
+        service
+            .register("ann@example.com", "password123")
+            .await
+            .unwrap();
+        service
+            .login("ann@example.com", "password123")
+            .await
+            .unwrap();
+        service.login("ann@example.com", "wrong").await.unwrap_err();
+
+        let text = metrics.render();
+        assert!(
+            text.contains("# TYPE auth_logins_total counter\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("auth_logins_total{outcome=\"success\"} 1\n"));
+        assert!(text.contains("auth_logins_total{outcome=\"invalid_credentials\"} 1\n"));
+        assert!(text.contains("auth_registrations_total 1\n"));
+        // The first login missed the cache and filled it; the second hit
+        assert!(text.contains("# TYPE cache_hits_total counter\n"));
+        assert!(text.contains("cache_hits_total 1\n"));
+        assert!(text.contains("cache_misses_total 1\n"));
+        assert!(text.contains("cache_sets_total 1\n"));
+        // The second login was a cache hit: one lookup from each register
+        // and the first login, none from the second
+        assert!(text.contains("# TYPE db_query_duration_seconds histogram\n"));
+        assert!(text.contains(
+            "db_query_duration_seconds_bucket{method=\"find_by_email\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(text.contains("db_query_duration_seconds_count{method=\"create\"} 1\n"));
+    }
+
+    #[test]
+    fn test_statsd_sends_one_tagged_line_per_call() {
+        let agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent
+            .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .unwrap();
+        let statsd = StatsdMetrics::connect(&agent.local_addr().unwrap().to_string()).unwrap();
+
+        statsd.counter("auth_logins_total", &[("outcome", "success")], 1);
+        statsd.histogram("db_query_duration_seconds", &[], 0.25);
+
+        let mut buffer = [0; 512];
+        let mut received = || {
+            let len = agent.recv(&mut buffer).unwrap();
+            String::from_utf8_lossy(&buffer[..len]).into_owned()
         };
+        assert_eq!(received(), "auth_logins_total:1|c|#outcome:success");
+        assert_eq!(received(), "db_query_duration_seconds:0.25|h");
+    }
 
-        let service = AuthServiceFactory::create_test_with_user(user);
+    struct SlowCache;
 
-        let result = service.login("test@example.com", "password123").await;
+    #[async_trait]
+    impl CacheService for SlowCache {
+        async fn get(&self, _key: &str) -> Option<String> {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            Some("late".to_string())
+        }
 
-        assert!(result.is_ok());
-        let token = result.unwrap();
-        assert!(token.starts_with("mock_token_"));
+        async fn set(&self, _key: &str, _value: String, _ttl_seconds: Option<u64>) {}
+
+        async fn delete(&self, _key: &str) {}
+
+        async fn incr(&self, _key: &str, _by: i64, _ttl_seconds: Option<u64>) -> i64 {
+            0
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for SlowCache {
+        async fn check(&self) -> Result<(), Error> {
+            Ok(())
+        }
     }
 
+    impl Lifecycle for SlowCache {}
+
     #[tokio::test]
-    async fn test_login_wrong_password() {
-        let user = User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            password_hash: "mock_hash_correct_password".to_string(),
-            role: "user".to_string(),
+    async fn test_slow_cache_trips_circuit_and_reads_as_miss() {
+        let cache =
+            WithCircuitBreaker::<dyn CacheService>::new(Arc::new(SlowCache), test_breaker(2));
+
+        assert_eq!(cache.get("key").await, None);
+        assert_eq!(cache.get("key").await, None);
+
+        assert_eq!(cache.breaker().state(), CircuitState::Open);
+        // Now fails fast instead of waiting for the timeout
+        let started = std::time::Instant::now();
+        assert_eq!(cache.get("key").await, None);
+        assert!(started.elapsed() < std::time::Duration::from_millis(20));
+    }
+
+    #[test]
+    #[cfg(feature = "memory")]
+    fn test_app_config_from_validated_settings() {
+        let settings = config::AppConfig::from_lookup(|key| match key {
+            "DATABASE_URL" => Some("memory://".to_string()),
+            "JWT_SECRET" => Some("0123456789abcdef0123456789abcdef".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        let config = AppConfig::from_settings(&settings).unwrap();
+
+        assert_eq!(config.repository, RepositoryBackend::Memory);
+        assert_eq!(config.cache, CacheBackend::Memory);
+        assert_eq!(config.jwt_secret, settings.jwt.secret);
+        assert_eq!(config.hasher, HasherKind::Bcrypt);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "memory")]
+    async fn test_password_hasher_comes_from_config() {
+        let settings = config::AppConfig::from_lookup(|key| match key {
+            "PASSWORD_HASHER" => Some("argon2".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        let config = AppConfig::from_settings(&settings).unwrap();
+        assert_eq!(config.hasher, HasherKind::Argon2);
+
+        // And the factory wires the matching hasher
+        let service = AuthServiceFactory::from_config(&config);
+        let user = service
+            .register("argon@example.com", "password")
+            .await
+            .unwrap();
+        assert!(user.password_hash.starts_with("argon2_"));
+    }
+
+    fn profile_settings(profile: &str) -> config::AppConfig {
+        config::AppConfig::from_lookup(|key| match key {
+            "APP_ENV" => Some(profile.to_string()),
+            "DATABASE_URL" if profile != "dev" => Some("postgres://db/app".to_string()),
+            "REDIS_URL" if profile != "dev" => Some("redis://cache".to_string()),
+            "JWT_SECRET" if profile != "dev" => {
+                Some("0123456789abcdef0123456789abcdef".to_string())
+            }
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "memory")]
+    async fn test_dev_profile_wires_in_memory_backends() {
+        let config = AppConfig::from_settings(&profile_settings("dev")).unwrap();
+
+        assert_eq!(config.repository, RepositoryBackend::Memory);
+        assert_eq!(config.cache, CacheBackend::Memory);
+
+        // Nothing external to connect to, so the whole flow works as-is
+        let service = AuthServiceFactory::from_config(&config);
+        service
+            .register("dev@example.com", "password")
+            .await
+            .unwrap();
+        assert!(service.login("dev@example.com", "password").await.is_ok());
+    }
+
+    #[test]
+    #[cfg(all(feature = "postgres", feature = "redis"))]
+    fn test_prod_profile_wires_postgres_and_redis() {
+        for profile in ["staging", "prod"] {
+            let config = AppConfig::from_settings(&profile_settings(profile)).unwrap();
+
+            assert_eq!(config.repository, RepositoryBackend::Postgres);
+            assert_eq!(config.cache, CacheBackend::Redis);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "postgres"))]
+    fn test_prod_profile_needs_the_postgres_feature() {
+        let errors = AppConfig::from_settings(&profile_settings("prod")).unwrap_err();
+
+        assert_eq!(errors.0[0].key, "DATABASE_URL");
+        assert!(errors.0[0].message.contains("`postgres` feature"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "redis"))]
+    fn test_prod_profile_needs_the_redis_feature() {
+        let errors = AppConfig::from_settings(&profile_settings("prod")).unwrap_err();
+
+        let error = errors.0.iter().find(|e| e.key == "REDIS_URL").unwrap();
+        assert!(error.message.contains("`redis` feature"));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "memory")]
+    async fn test_dev_preset_with_an_overridden_repository() {
+        let mut container = AuthServiceFactory::for_profile(config::Profile::Dev).unwrap();
+        container.singleton::<dyn UserRepository, _>(|_| {
+            Arc::new(MockUserRepository::with_user(
+                UserBuilder::new()
+                    .email("seeded@example.com")
+                    .password_hash("hashed_password")
+                    .build(),
+            ))
+        });
+        let service: Arc<AuthService> = Arc::new(container).create_scope().resolve().unwrap();
+
+        // The seeded user only exists in the override; the preset's
+        // BcryptHasher still checks the password
+        assert!(
+            service
+                .login("seeded@example.com", "password")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "postgres", feature = "redis"))]
+    async fn test_staging_preset_keeps_redis_when_the_database_is_mocked() {
+        let mut container = AuthServiceFactory::for_profile(config::Profile::Staging).unwrap();
+        container.singleton::<dyn UserRepository, _>(|_| Arc::new(MockUserRepository::new()));
+        let container = Arc::new(container);
+
+        let service: Arc<AuthService> = container.create_scope().resolve().unwrap();
+        service
+            .register("new@example.com", "password")
+            .await
+            .unwrap();
+        assert!(service.login("new@example.com", "password").await.is_ok());
+
+        // Nothing listens on the preset's Redis URL here. That it's down,
+        // not missing, shows the cache is still Redis; the requests above
+        // worked without it.
+        let health: Arc<HealthService> = container.create_scope().resolve().unwrap();
+        let report = health.readiness().await;
+        assert_eq!(report.status(), HealthStatus::Degraded);
+        assert_eq!(report.failures().next().unwrap().name, "cache");
+    }
+
+    #[test]
+    #[cfg(not(feature = "postgres"))]
+    fn test_prod_preset_needs_the_postgres_feature() {
+        let Err(errors) = AuthServiceFactory::for_profile(config::Profile::Prod) else {
+            panic!("prod preset built without the postgres feature");
         };
 
-        let service = AuthServiceFactory::create_test_with_user(user);
+        assert_eq!(errors.0[0].key, "DATABASE_URL");
+    }
 
-        let result = service.login("test@example.com", "wrong_password").await;
+    #[test]
+    #[cfg(not(feature = "redis"))]
+    fn test_prod_preset_needs_the_redis_feature() {
+        let Err(errors) = AuthServiceFactory::for_profile(config::Profile::Prod) else {
+            panic!("prod preset built without the redis feature");
+        };
 
-        assert!(matches!(result, Err(Error::InvalidCredentials)));
+        assert!(errors.0.iter().any(|e| e.key == "REDIS_URL"));
     }
 
     #[tokio::test]
-    async fn test_login_user_not_found() {
+    async fn test_rate_limiter_counts_per_key() {
+        let limiter = RateLimiter::new(Arc::new(MockCache::new()), 2, 3600);
+
+        assert!(limiter.check("10.0.0.1").await.is_ok());
+        assert!(limiter.check("10.0.0.1").await.is_ok());
+        let limited = limiter.check("10.0.0.1").await.unwrap_err();
+        assert!((1..=3600).contains(&limited.retry_after_seconds));
+
+        // Another client has its own budget
+        assert!(limiter.check("10.0.0.2").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_window_ends_on_the_clock() {
+        let clock = Arc::new(MockClock::new());
+        let cache = Arc::new(MockCache::new().with_clock(clock.clone()));
+        let limiter = RateLimiter::new(cache, 1, 60).with_clock(clock.clone());
+
+        assert!(limiter.check("10.0.0.1").await.is_ok());
+        let limited = limiter.check("10.0.0.1").await.unwrap_err();
+        // MockClock::START is on a minute boundary
+        assert_eq!(limited.retry_after_seconds, 60);
+
+        clock.advance(std::time::Duration::from_secs(59));
+        assert!(limiter.check("10.0.0.1").await.is_err());
+        clock.advance(std::time::Duration::from_secs(1));
+        assert!(limiter.check("10.0.0.1").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_mock_cache_expires_entries_on_its_clock() {
+        let clock = Arc::new(MockClock::new());
+        let cache = MockCache::new().with_clock(clock.clone());
+
+        cache.set("kept", "a".to_string(), None).await;
+        cache.set("brief", "b".to_string(), Some(10)).await;
+        clock.advance(std::time::Duration::from_secs(9));
+        assert_eq!(cache.get("brief").await.as_deref(), Some("b"));
+        assert_eq!(cache.ttl("brief").await, Some(1));
+        assert_eq!(cache.ttl("kept").await, None);
+        clock.advance(std::time::Duration::from_secs(1));
+        assert_eq!(cache.get("brief").await, None);
+        assert_eq!(cache.get("kept").await.as_deref(), Some("a"));
+
+        // Like EXPIRE NX: the second TTL doesn't extend the first
+        assert_eq!(cache.incr("hits", 1, Some(10)).await, 1);
+        clock.advance(std::time::Duration::from_secs(5));
+        assert_eq!(cache.incr("hits", 2, Some(60)).await, 3);
+        clock.advance(std::time::Duration::from_secs(5));
+        assert_eq!(cache.incr("hits", 1, Some(10)).await, 1);
+
+        // Like INCRBY on a string through RedisCache: 0, and no change
+        assert_eq!(cache.incr("kept", 1, Some(10)).await, 0);
+        assert_eq!(cache.get("kept").await.as_deref(), Some("a"));
+        assert_eq!(cache.ttl("kept").await, None);
+    }
+
+    // Under contention
+    // ----------------
+    //
+    // The in-memory backends are a Mutex around a collection. These run
+    // many tasks on several threads against one instance: a lost update
+    // shows as a wrong count, a deadlock as the deadline passing.
+
+    async fn on_threads<T: Send + 'static>(
+        tasks: impl IntoIterator<Item = impl Future<Output = T> + Send + 'static>,
+    ) -> Vec<T> {
+        let mut running = tokio::task::JoinSet::new();
+        for task in tasks {
+            running.spawn(task);
+        }
+        let all = async {
+            let mut results = Vec::new();
+            while let Some(result) = running.join_next().await {
+                results.push(result.unwrap());
+            }
+            results
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), all)
+            .await
+            .expect("tasks finish; a deadlock would hang here")
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_mock_cache_loses_no_updates_under_contention() {
+        let cache = Arc::new(MockCache::new());
+        on_threads((0..8).map(|task| {
+            let cache = cache.clone();
+            async move {
+                for i in 0..500 {
+                    cache.incr("hits", 1, Some(60)).await;
+                    // Unrelated traffic on the same lock
+                    let key = format!("key:{}", i % 10);
+                    match (task + i) % 3 {
+                        0 => cache.set(&key, format!("{}:{}", task, i), Some(60)).await,
+                        1 => {
+                            // A whole value or none, never a torn one
+                            if let Some(value) = cache.get(&key).await {
+                                assert!(value.split_once(':').is_some(), "{}", value);
+                            }
+                        }
+                        _ => cache.delete(&key).await,
+                    }
+                }
+            }
+        }))
+        .await;
+
+        assert_eq!(cache.incr("hits", 0, None).await, 8 * 500);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_mock_repository_keeps_emails_unique_under_contention() {
+        let repository = Arc::new(MockUserRepository::new());
+
+        // Everyone wants the same email: exactly one gets it
+        let created = on_threads((0..16).map(|i| {
+            let repository = repository.clone();
+            async move {
+                let user = UserBuilder::named(&format!("ann{}", i))
+                    .email("ann@example.com")
+                    .build();
+                repository.create(user).await
+            }
+        }))
+        .await;
+        assert_eq!(created.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(
+            created
+                .iter()
+                .all(|result| matches!(result, Ok(_) | Err(Error::AlreadyExists)))
+        );
+
+        // Different emails all get in, while others read and update
+        on_threads((0..16).map(|i| {
+            let repository = repository.clone();
+            async move {
+                let name = format!("user{}", i);
+                repository
+                    .create(UserBuilder::named(&name).build())
+                    .await
+                    .unwrap();
+                let page = repository
+                    .list(&UserFilter::default(), PageRequest::new(1, 100))
+                    .await
+                    .unwrap();
+                assert_eq!(page.total, page.items.len() as u64);
+                let user = UserBuilder::named(&name).inactive().build();
+                repository.update(user).await.unwrap();
+            }
+        }))
+        .await;
+        let inactive = UserFilter {
+            active: Some(false),
+            ..UserFilter::default()
+        };
+        let page = repository
+            .list(&inactive, PageRequest::new(1, 100))
+            .await
+            .unwrap();
+        assert_eq!(page.total, 16);
+    }
+
+    // The check for a taken email and the insert are two calls. The
+    // repository's own uniqueness is what holds when two sign-ups race.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_racing_registrations_create_one_account() {
         let service = AuthServiceFactory::create_test();
+        let registered = on_threads((0..16).map(|_| {
+            let service = service.clone();
+            async move { service.register("ann@example.com", "password123").await }
+        }))
+        .await;
 
-        let result = service.login("nonexistent@example.com", "password").await;
+        assert_eq!(registered.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(
+            registered
+                .iter()
+                .all(|result| matches!(result, Ok(_) | Err(Error::AlreadyExists)))
+        );
+    }
 
-        assert!(matches!(result, Err(Error::InvalidCredentials)));
+    #[tokio::test]
+    async fn test_rate_limiter_fails_open_without_a_cache() {
+        let limiter = RateLimiter::new(Arc::new(DownCache), 1, 60);
+        for _ in 0..3 {
+            assert!(limiter.check("10.0.0.1").await.is_ok());
+        }
     }
 
     #[tokio::test]
-    async fn test_change_password_success() {
-        let user = User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            password_hash: "mock_hash_old_password".to_string(),
-            role: "user".to_string(),
+    async fn test_admin_changes_are_audited_and_invalidate_the_cache() {
+        let user = |id: &str, role: &str| {
+            UserBuilder::named(id)
+                .password("password")
+                .role(role)
+                .build()
         };
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(vec![
+            user("admin", "admin"),
+            user("alice", "user"),
+        ])));
+        let admin = Actor {
+            user_id: "admin".to_string(),
+            ip: Some("10.0.0.1".to_string()),
+        };
+        let auth = app.auth_service();
+        let service = app.admin_service();
 
-        let service = AuthServiceFactory::create_test_with_user(user);
+        // Puts alice in the login cache
+        let token = auth.login("alice@example.com", "password").await.unwrap();
 
-        let result = service
-            .change_password("test@example.com", "old_password", "new_password")
-            .await;
+        let promoted = service.change_role(&admin, "alice", "admin").await.unwrap();
+        assert_eq!(promoted.role, "admin");
+        // Unchanged, so not audited again
+        service.change_role(&admin, "alice", "admin").await.unwrap();
 
-        assert!(result.is_ok());
+        let admins = UserFilter {
+            role: Some("admin".to_string()),
+            ..UserFilter::default()
+        };
+        let page = service
+            .list_users(&admins, PageRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+
+        service.deactivate(&admin, "alice").await.unwrap();
+        assert!(matches!(
+            auth.login("alice@example.com", "password").await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert!(matches!(
+            auth.current_user(&token).await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert!(matches!(
+            service.deactivate(&admin, "nobody").await,
+            Err(Error::NotFound)
+        ));
+
+        let log = service
+            .audit_log(&AuditFilter::default(), PageRequest::default())
+            .await
+            .unwrap();
+        let actions: Vec<&str> = log.items.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["deactivated", "role_changed"]);
+        assert_eq!(log.items[1].detail, "user -> admin");
+        assert!(log.items.iter().all(|e| e.actor == "admin"
+            && e.target == "alice"
+            && e.ip.as_deref() == Some("10.0.0.1")));
     }
 
     #[tokio::test]
-    async fn test_change_password_wrong_old_password() {
-        let user = User {
-            id: "1".to_string(),
-            email: "test@example.com".to_string(),
-            password_hash: "mock_hash_old_password".to_string(),
-            role: "user".to_string(),
+    async fn test_audit_query_filters_and_pages_newest_first() {
+        let user = |id: &str| UserBuilder::named(id).build();
+        let admin = Actor {
+            user_id: "admin".to_string(),
+            ip: None,
         };
+        let audit = AuditService::new(Arc::new(MockAuditRepository::default()));
+        for (action, target) in [("first", "alice"), ("second", "bob"), ("third", "alice")] {
+            audit
+                .record(&admin, action, &user(target), String::new())
+                .await
+                .unwrap();
+        }
 
-        let service = AuthServiceFactory::create_test_with_user(user);
+        let alice = AuditFilter {
+            user: Some("alice".to_string()),
+            ..AuditFilter::default()
+        };
+        let page = audit
+            .query(
+                &alice,
+                PageRequest {
+                    page: 1,
+                    per_page: 1,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].action, "third");
+        let page = audit
+            .query(
+                &alice,
+                PageRequest {
+                    page: 2,
+                    per_page: 1,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.items[0].action, "first");
 
-        let result = service
-            .change_password("test@example.com", "wrong_old_password", "new_password")
-            .await;
+        // `user` matches the actor too
+        let by_admin = AuditFilter {
+            user: Some("admin".to_string()),
+            action: Some("second".to_string()),
+            ..AuditFilter::default()
+        };
+        let page = audit
+            .query(&by_admin, PageRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].target, "bob");
+    }
 
-        assert!(matches!(result, Err(Error::InvalidCredentials)));
+    #[tokio::test]
+    async fn test_services_publish_to_the_shared_event_bus() {
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(Vec::new())));
+        let mut events = app.events().subscribe();
+
+        // Two scopes, one bus
+        let user = app
+            .auth_service()
+            .register("events@example.com", "password")
+            .await
+            .unwrap();
+        let token = app
+            .auth_service()
+            .login("events@example.com", "password")
+            .await
+            .unwrap();
+        app.auth_service().logout(&token).await.unwrap();
+        app.admin_service()
+            .deactivate(&Actor::from(&user), &user.id)
+            .await
+            .unwrap();
+
+        let revoked = DomainEvent::SessionRevoked {
+            user_id: user.id.clone(),
+        };
+        assert_eq!(
+            events.recv().await.unwrap().event,
+            DomainEvent::LoggedIn {
+                user_id: user.id.clone()
+            }
+        );
+        assert_eq!(events.recv().await.unwrap().event, revoked);
+        assert_eq!(events.recv().await.unwrap().event, revoked);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_avatar_type_replaces_the_old_file() {
+        let storage = Arc::new(MockStorage::new());
+        let app = {
+            let mut container = AuthServiceFactory::test_container(Vec::new());
+            let storage = storage.clone();
+            container.singleton::<dyn StorageService, _>(move |_| storage.clone());
+            App::new(Arc::new(container))
+        };
+        let user = app
+            .auth_service()
+            .register("pic@example.com", "password")
+            .await
+            .unwrap();
+        let profiles = app.profile_service();
+
+        profiles
+            .set_avatar(&user, "image/png", vec![1])
+            .await
+            .unwrap();
+        let updated = profiles
+            .set_avatar(&user, "image/webp", vec![2])
+            .await
+            .unwrap();
+
+        let webp = format!("avatars/{}.webp", user.id);
+        assert_eq!(updated.avatar_url, Some(format!("memory://{}", webp)));
+        assert_eq!(
+            storage.get(&webp),
+            Some(("image/webp".to_string(), vec![2]))
+        );
+        assert_eq!(storage.get(&format!("avatars/{}.png", user.id)), None);
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_writes_under_its_root() {
+        let root = std::env::temp_dir().join(format!("storage-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(&root, "/uploads/");
+
+        let url = storage
+            .put("avatars/a.png", "image/png", vec![7])
+            .await
+            .unwrap();
+        assert_eq!(url, "/uploads/avatars/a.png");
+        assert_eq!(std::fs::read(root.join("avatars/a.png")).unwrap(), vec![7]);
+
+        assert!(
+            storage
+                .put("../escape.png", "image/png", vec![])
+                .await
+                .is_err()
+        );
+        storage.delete("avatars/a.png").await.unwrap();
+        // Already gone is fine
+        storage.delete("avatars/a.png").await.unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_keep_their_source() {
+        // A file where the storage root should be: create_dir_all fails
+        let root = std::env::temp_dir().join(format!("storage-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&root, b"").unwrap();
+        let storage = LocalStorage::new(&root, "/uploads/");
+
+        let error = storage
+            .put("avatars/a.png", "image/png", vec![7])
+            .await
+            .unwrap_err();
+        let cause = std::error::Error::source(&error).unwrap();
+        assert!(cause.downcast_ref::<std::io::Error>().is_some());
+
+        // Display is what we were doing; report() adds why
+        let dir = root.join("avatars");
+        assert_eq!(error.to_string(), format!("creating {}", dir.display()));
+        assert_eq!(
+            error.report(),
+            format!("creating {}: {}", dir.display(), cause)
+        );
+        std::fs::remove_file(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sessions_end_on_logout_and_deactivation() {
+        let user = UserBuilder::new().password("password").build();
+        let admin = UserBuilder::new().id("2").role_admin().build();
+        let app = App::new(Arc::new(AuthServiceFactory::test_container(vec![user])));
+        let sessions = app.session_service();
+
+        assert!(matches!(
+            sessions.login("test@example.com", "wrong").await,
+            Err(Error::InvalidCredentials)
+        ));
+        let first = sessions
+            .login("test@example.com", "password")
+            .await
+            .unwrap();
+        let second = sessions
+            .login("test@example.com", "password")
+            .await
+            .unwrap();
+        assert_ne!(first, second);
+        assert_eq!(sessions.current_user(&first).await.unwrap().id, "1");
+
+        // Ending one session leaves the other alone
+        let mut events = app.events().subscribe();
+        sessions.logout(&first).await.unwrap();
+        assert!(sessions.current_user(&first).await.is_err());
+        assert!(sessions.current_user(&second).await.is_ok());
+        assert_eq!(
+            events.recv().await.unwrap().event,
+            DomainEvent::SessionRevoked {
+                user_id: "1".to_string()
+            }
+        );
+
+        app.admin_service()
+            .deactivate(&Actor::from(&admin), "1")
+            .await
+            .unwrap();
+        assert!(matches!(
+            sessions.current_user(&second).await,
+            Err(Error::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sessions_need_an_active_user_that_still_exists() {
+        let inactive = UserBuilder::named("carol").inactive().build();
+        let container = Arc::new(AuthServiceFactory::test_container(vec![
+            UserBuilder::new().build(),
+            inactive.clone(),
+        ]));
+        let store: Arc<dyn SessionStore> = container.create_scope().resolve().unwrap();
+        let sessions = App::new(container).session_service();
+
+        let session = SessionBuilder::new().create(store.as_ref()).await;
+        assert_eq!(sessions.current_user(&session).await.unwrap().id, "1");
+        for session in [
+            SessionBuilder::for_user(&inactive)
+                .create(store.as_ref())
+                .await,
+            SessionBuilder::new()
+                .user_id("deleted")
+                .ttl_seconds(60)
+                .create(store.as_ref())
+                .await,
+        ] {
+            assert!(matches!(
+                sessions.current_user(&session).await,
+                Err(Error::InvalidCredentials)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_event_bus_replays_what_a_client_missed() {
+        let bus = EventBus::new(3);
+        let ids: Vec<u64> = (0..5)
+            .map(|n| {
+                bus.publish(DomainEvent::ProfileUpdated {
+                    user_id: n.to_string(),
+                })
+            })
+            .collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+
+        // Only the last three are kept
+        let (missed, _) = bus.subscribe_after(0);
+        let missed: Vec<u64> = missed.iter().map(|event| event.id).collect();
+        assert_eq!(missed, [3, 4, 5]);
+
+        let (missed, mut live) = bus.subscribe_after(4);
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].event.user_id(), "4");
+        assert_eq!(
+            bus.publish(DomainEvent::LoggedIn {
+                user_id: "x".to_string()
+            }),
+            6
+        );
+        assert_eq!(live.try_recv().unwrap().id, 6);
+    }
+
+    #[tokio::test]
+    async fn test_jwt_tokens_expire_after_their_ttl() {
+        let clock = Arc::new(MockClock::new());
+        let tokens = JwtTokenService::new("secret".into(), 3600, clock.clone());
+
+        let token = tokens.generate("1").await.unwrap();
+        clock.advance(std::time::Duration::from_secs(3599));
+        assert_eq!(tokens.validate(&token).await.unwrap(), "1");
+        clock.advance(std::time::Duration::from_secs(1));
+        assert!(matches!(
+            tokens.validate(&token).await,
+            Err(Error::InvalidCredentials)
+        ));
+
+        // Nor can anyone without the secret sign a longer-lived one
+        let other = JwtTokenService::new("other secret".into(), 7200, clock.clone());
+        let forged = other.generate("1").await.unwrap();
+        assert!(matches!(
+            tokens.validate(&forged).await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert_eq!(other.validate(&forged).await.unwrap(), "1");
+    }
+
+    #[test]
+    fn test_jwt_errors_split_client_faults_from_ours() {
+        use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Claims {
+            sub: String,
+            exp: u64,
+        }
+        let decode = |token: &str, secret: &[u8]| {
+            jsonwebtoken::decode::<Claims>(
+                token,
+                &DecodingKey::from_secret(secret),
+                &Validation::default(),
+            )
+            .unwrap_err()
+        };
+        let claims = |exp| Claims {
+            sub: "1".to_string(),
+            exp,
+        };
+        let key = EncodingKey::from_secret(b"secret");
+
+        let expired = jsonwebtoken::encode(&Header::default(), &claims(1), &key).unwrap();
+        let error = Error::from(decode(&expired, b"secret"));
+        assert!(matches!(error, Error::InvalidCredentials));
+
+        let forged = jsonwebtoken::encode(&Header::default(), &claims(u64::MAX), &key).unwrap();
+        let error = Error::from(decode(&forged, b"other secret"));
+        assert!(matches!(error, Error::InvalidCredentials));
+
+        // A key that doesn't parse is a deployment problem, not a bad login
+        let Err(error) = EncodingKey::from_rsa_pem(b"not a key") else {
+            panic!("not a key parsed");
+        };
+        let error = Error::from(error);
+        assert!(matches!(error, Error::Internal { .. }));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    #[test]
+    fn test_sqlx_errors_become_domain_errors() {
+        // What the Postgres driver hands back, down to the SQLSTATE
+        #[derive(Debug)]
+        struct PgError {
+            code: &'static str,
+        }
+
+        impl std::fmt::Display for PgError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "SQLSTATE {}", self.code)
+            }
+        }
+
+        impl std::error::Error for PgError {}
+
+        impl sqlx::error::DatabaseError for PgError {
+            fn message(&self) -> &str {
+                "database error"
+            }
+
+            fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+                Some(self.code.into())
+            }
+
+            fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+                self
+            }
+
+            fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+                self
+            }
+
+            fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+                self
+            }
+
+            fn kind(&self) -> sqlx::error::ErrorKind {
+                match self.code {
+                    "23505" => sqlx::error::ErrorKind::UniqueViolation,
+                    "23503" => sqlx::error::ErrorKind::ForeignKeyViolation,
+                    _ => sqlx::error::ErrorKind::Other,
+                }
+            }
+        }
+
+        let pg = |code| sqlx::Error::Database(Box::new(PgError { code }));
+
+        assert!(matches!(
+            Error::from(sqlx::Error::RowNotFound),
+            Error::NotFound
+        ));
+        assert!(matches!(Error::from(pg("23505")), Error::AlreadyExists));
+
+        // Everything else is ours, with the sqlx error underneath
+        for error in [pg("23503"), sqlx::Error::PoolTimedOut] {
+            let error = Error::from(error);
+            assert!(matches!(error, Error::Internal { .. }));
+            let source = std::error::Error::source(&error).unwrap();
+            assert!(source.downcast_ref::<sqlx::Error>().is_some());
+        }
+
+        // Worth another try: the pool was busy, or Postgres said so
+        for error in [sqlx::Error::PoolTimedOut, pg("40P01"), pg("40001")] {
+            assert!(Error::from(error).is_retryable());
+        }
+        // Not worth it: the same row violates the same constraint again
+        for error in [pg("23505"), pg("23503"), sqlx::Error::RowNotFound] {
+            assert!(!Error::from(error).is_retryable());
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_redis_errors_keep_their_source() {
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        let error = Error::from(redis::RedisError::from(refused));
+
+        assert!(matches!(error, Error::Internal { .. }));
+        assert!(error.report().starts_with("cache error: "));
+        assert!(error.is_retryable());
     }
 }
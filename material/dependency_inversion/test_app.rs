@@ -0,0 +1,262 @@
+// Test App
+// ========
+//
+// The app in tests: in process through the router (TestApp), or on a
+// loopback socket (TestServer). Declared by refactoring_with_di.rs with
+// `#[cfg(test)] mod test_app;`; a test module brings it in with
+// `use crate::test_app::*;`.
+//
+// Builders, log capture and the backends that are down are in the
+// test-support crate, since they only need tutorial-core. This is what
+// needs this example's App, AppState and router.
+
+use crate::after::*;
+use crate::http::{self, AppState};
+use crate::{User, config};
+use axum::body::Body;
+use axum::http::{Request, StatusCode, header};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+// The whole app, in process
+// --------------------------
+//
+// AppState on test_container's in-memory backends, behind the same
+// router and middleware `--serve` mounts (CORS, request IDs, rate limits,
+// problem details). Requests go through tower's oneshot, so an end-to-end
+// test needs no port, network or container:
+//
+//     let app = TestApp::new();
+//     app.post_json("/api/v1/register", json!({ ... })).await;
+//     let token = app.login("new@example.com", "password123").await;
+//     assert_eq!(app.get_auth("/api/v1/me", &token).await.status, StatusCode::OK);
+
+pub struct TestApp {
+    router: axum::Router,
+    state: Arc<AppState>,
+}
+
+// What came back, with the body parsed as JSON (Null when it isn't)
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: axum::http::HeaderMap,
+    pub body: Value,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_users(Vec::new())
+    }
+
+    pub fn with_users(users: Vec<User>) -> Self {
+        Self::configured(&[], users)
+    }
+
+    // `vars` are the environment the server would read, e.g.
+    // [("AUTH_MODE", "cookie")]; anything unset has its default
+    pub fn configured(vars: &[(&str, &str)], users: Vec<User>) -> Self {
+        let (settings, state) = test_state(vars, users);
+        Self {
+            router: http::app(state.clone(), &settings),
+            state,
+        }
+    }
+
+    // The services behind the routes, to set up or check what HTTP can't
+    pub fn app(&self) -> &App {
+        &self.state.app
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.send(Request::get(uri), None, None).await
+    }
+
+    pub async fn get_auth(&self, uri: &str, token: &str) -> TestResponse {
+        self.send(Request::get(uri), Some(token), None).await
+    }
+
+    pub async fn post_json(&self, uri: &str, body: Value) -> TestResponse {
+        self.send(Request::post(uri), None, Some(body)).await
+    }
+
+    pub async fn post_auth(&self, uri: &str, token: &str, body: Option<Value>) -> TestResponse {
+        self.send(Request::post(uri), Some(token), body).await
+    }
+
+    // POST /api/v1/login; the access token, or a panic naming the status
+    pub async fn login(&self, email: &str, password: &str) -> String {
+        let credentials = json!({ "email": email, "password": password });
+        let response = self.post_json("/api/v1/login", credentials).await;
+        assert_eq!(response.status, StatusCode::OK, "login: {}", response.body);
+        response.body["access_token"]
+            .as_str()
+            .expect("login answers with an access_token")
+            .to_string()
+    }
+
+    // Anything the helpers above don't build: other methods, cookies,
+    // other content types
+    pub async fn request(&self, request: Request<Body>) -> TestResponse {
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("the router's error type is Infallible");
+        let (parts, body) = response.into_parts();
+        let bytes = body
+            .collect()
+            .await
+            .expect("in-memory bodies don't fail")
+            .to_bytes();
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        }
+    }
+
+    async fn send(
+        &self,
+        request: axum::http::request::Builder,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> TestResponse {
+        let mut request = request;
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        self.request(request.expect("a valid request")).await
+    }
+}
+
+impl Default for TestApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Settings from `vars`, and AppState on test_container's backends
+fn test_state(vars: &[(&str, &str)], users: Vec<User>) -> (config::AppConfig, Arc<AppState>) {
+    let settings = config::AppConfig::from_lookup(|key| {
+        vars.iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.to_string())
+    })
+    .expect("test settings are valid");
+    let container = AuthServiceFactory::test_container(users);
+    let state = Arc::new(
+        AppState::new(App::new(Arc::new(container)))
+            .with_auth_mode(settings.session.auth_mode)
+            .with_allowed_origins(settings.cors.allowed_origins.clone()),
+    );
+    (settings, state)
+}
+
+// The app on a socket
+// -------------------
+//
+// What TestApp skips: TCP, hyper parsing the bytes, the peer address the
+// rate limiter keys on, a client that keeps cookies. TestServer runs
+// http::serve_with on 127.0.0.1, on a port the OS picks, so tests can run
+// side by side. `client` is a reqwest client with a cookie jar, like a
+// browser: what the server sets, it sends back.
+//
+//     let server = TestServer::start().await;
+//     let response = server.client.get(server.url("/healthz")).send().await.unwrap();
+//     server.stop().await.unwrap();
+//
+// Needs reqwest's `cookies` feature under [dev-dependencies].
+
+pub struct TestServer {
+    pub client: reqwest::Client,
+    addr: std::net::SocketAddr,
+    jar: Arc<reqwest::cookie::Jar>,
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+    server: tokio::task::JoinHandle<std::io::Result<()>>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::configured(&[], Vec::new()).await
+    }
+
+    // `vars` and `users` as for TestApp::configured
+    pub async fn configured(vars: &[(&str, &str)], users: Vec<User>) -> Self {
+        let (settings, state) = test_state(vars, users);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("binding a loopback port");
+        let addr = listener
+            .local_addr()
+            .expect("a bound listener has an address");
+
+        let (shutdown, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(http::serve_with(
+            listener,
+            http::app(state, &settings),
+            async move {
+                let _ = stopped.await;
+            },
+            std::time::Duration::from_secs(5),
+        ));
+
+        let jar = Arc::new(reqwest::cookie::Jar::default());
+        let client = reqwest::Client::builder()
+            .cookie_provider(jar.clone())
+            .build()
+            .expect("a client with default TLS settings");
+        Self {
+            client,
+            addr,
+            jar,
+            shutdown: Some(shutdown),
+            server,
+        }
+    }
+
+    // http://127.0.0.1:<port><path>
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    // The value of a cookie the client would send with its next request
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        use reqwest::cookie::CookieStore;
+
+        let url = self.url("/").parse().expect("a valid URL");
+        let header = self.jar.cookies(&url)?;
+        header
+            .to_str()
+            .ok()?
+            .split("; ")
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_string)
+    }
+
+    // Stops accepting connections, lets in-flight requests finish, and
+    // returns how the server ended
+    pub async fn stop(mut self) -> std::io::Result<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        (&mut self.server)
+            .await
+            .expect("the server task doesn't panic")
+    }
+}
+
+// A test that fails before stop() doesn't leave the server running
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
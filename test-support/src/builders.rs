@@ -0,0 +1,173 @@
+// Test Data Builders
+// ==================
+//
+// Valid unless a test says otherwise: user "1", test@example.com, an
+// active "user" whose password is password123 to the examples'
+// MockPasswordHasher. Set only the fields the test is about.
+
+use tutorial_core::{SessionStore, User};
+
+// How MockPasswordHasher hashes, so a built user can log in through it
+fn mock_hash(password: &str) -> String {
+    format!("mock_hash_{}", password)
+}
+
+pub struct UserBuilder {
+    user: User,
+}
+
+impl UserBuilder {
+    pub fn new() -> Self {
+        Self {
+            user: User {
+                id: "1".to_string(),
+                email: "test@example.com".to_string(),
+                password_hash: mock_hash("password123"),
+                role: "user".to_string(),
+                active: true,
+                avatar_url: None,
+            },
+        }
+    }
+
+    // Id `name`, email name@example.com
+    pub fn named(name: &str) -> Self {
+        Self::new().id(name).email(format!("{}@example.com", name))
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.user.id = id.into();
+        self
+    }
+
+    pub fn email(mut self, email: impl Into<String>) -> Self {
+        self.user.email = email.into();
+        self
+    }
+
+    // Hashed the way MockPasswordHasher checks it
+    pub fn password(mut self, password: &str) -> Self {
+        self.user.password_hash = mock_hash(password);
+        self
+    }
+
+    // As stored, for hashers other than MockPasswordHasher
+    pub fn password_hash(mut self, password_hash: impl Into<String>) -> Self {
+        self.user.password_hash = password_hash.into();
+        self
+    }
+
+    pub fn role(mut self, role: &str) -> Self {
+        self.user.role = role.to_string();
+        self
+    }
+
+    pub fn role_admin(self) -> Self {
+        self.role("admin")
+    }
+
+    pub fn inactive(mut self) -> Self {
+        self.user.active = false;
+        self
+    }
+
+    pub fn build(self) -> User {
+        self.user
+    }
+}
+
+impl Default for UserBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A token MockTokenService accepts for user "1", or one it rejects
+pub struct TokenBuilder {
+    user_id: String,
+    forged: bool,
+}
+
+impl TokenBuilder {
+    pub fn new() -> Self {
+        Self {
+            user_id: "1".to_string(),
+            forged: false,
+        }
+    }
+
+    pub fn for_user(user: &User) -> Self {
+        Self::new().user_id(&user.id)
+    }
+
+    pub fn user_id(mut self, user_id: &str) -> Self {
+        self.user_id = user_id.to_string();
+        self
+    }
+
+    pub fn forged(mut self) -> Self {
+        self.forged = true;
+        self
+    }
+
+    pub fn build(self) -> String {
+        if self.forged {
+            format!("forged_token_{}", self.user_id)
+        } else {
+            format!("mock_token_{}", self.user_id)
+        }
+    }
+}
+
+impl Default for TokenBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A session for user "1", put straight into a SessionStore without
+// going through a login
+pub struct SessionBuilder {
+    user_id: String,
+    ttl_seconds: u64,
+}
+
+impl SessionBuilder {
+    // A day, as SessionService's sessions last
+    pub const DEFAULT_TTL_SECONDS: u64 = 86400;
+
+    pub fn new() -> Self {
+        Self {
+            user_id: "1".to_string(),
+            ttl_seconds: Self::DEFAULT_TTL_SECONDS,
+        }
+    }
+
+    pub fn for_user(user: &User) -> Self {
+        Self::new().user_id(&user.id)
+    }
+
+    pub fn user_id(mut self, user_id: &str) -> Self {
+        self.user_id = user_id.to_string();
+        self
+    }
+
+    pub fn ttl_seconds(mut self, ttl_seconds: u64) -> Self {
+        self.ttl_seconds = ttl_seconds;
+        self
+    }
+
+    // The session id
+    pub async fn create(self, store: &dyn SessionStore) -> String {
+        store
+            .create(&self.user_id, self.ttl_seconds)
+            .await
+            .expect("session store accepts writes")
+    }
+}
+
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,323 @@
+// Repository Conformance
+// ======================
+//
+// What every UserRepository must do, whatever is behind it. Each backend's
+// tests call this with a factory that makes a new, empty repository; each
+// case gets its own. UserRepository has no delete: a deactivation is an
+// update, and is covered as one.
+
+use crate::UserBuilder;
+use std::future::Future;
+use tutorial_core::{
+    AuditEntry, AuditFilter, AuditRepository, Error, PageRequest, UserFilter, UserRepository,
+};
+
+pub async fn run_repository_conformance<R, F, Fut>(factory: F)
+where
+    R: UserRepository,
+    F: Fn() -> Fut,
+    Fut: Future<Output = R>,
+{
+    creates_and_finds(&factory().await).await;
+    rejects_taken_ids_and_emails(&factory().await).await;
+    updates_in_place(&factory().await).await;
+    filters(&factory().await).await;
+    pages_in_insertion_order(&factory().await).await;
+}
+
+async fn creates_and_finds(repository: &impl UserRepository) {
+    let ann = UserBuilder::named("ann").build();
+    assert_eq!(repository.create(ann.clone()).await.unwrap(), ann);
+
+    let found = repository.find_by_id("ann").await.unwrap();
+    assert_eq!(found.as_ref(), Some(&ann), "find_by_id");
+    let found = repository.find_by_email("ann@example.com").await.unwrap();
+    assert_eq!(found.as_ref(), Some(&ann), "find_by_email");
+
+    // Not found is None, not an error; emails match exactly
+    assert_eq!(repository.find_by_id("bob").await.unwrap(), None);
+    assert_eq!(
+        repository.find_by_email("bob@example.com").await.unwrap(),
+        None
+    );
+    assert_eq!(
+        repository.find_by_email("ANN@example.com").await.unwrap(),
+        None
+    );
+}
+
+async fn rejects_taken_ids_and_emails(repository: &impl UserRepository) {
+    let ann = UserBuilder::named("ann").build();
+    repository.create(ann.clone()).await.unwrap();
+
+    let same_email = UserBuilder::named("ann2").email("ann@example.com").build();
+    assert!(
+        matches!(
+            repository.create(same_email).await,
+            Err(Error::AlreadyExists)
+        ),
+        "a taken email"
+    );
+    let same_id = UserBuilder::named("ann").email("other@example.com").build();
+    assert!(
+        matches!(repository.create(same_id).await, Err(Error::AlreadyExists)),
+        "a taken id"
+    );
+    // Neither attempt touched the first
+    assert_eq!(repository.find_by_id("ann").await.unwrap(), Some(ann));
+    assert_eq!(
+        repository.find_by_email("other@example.com").await.unwrap(),
+        None
+    );
+}
+
+async fn updates_in_place(repository: &impl UserRepository) {
+    repository
+        .create(UserBuilder::named("ann").build())
+        .await
+        .unwrap();
+    repository
+        .create(UserBuilder::named("bob").build())
+        .await
+        .unwrap();
+
+    // Every field but the id can change
+    let mut changed = UserBuilder::named("ann")
+        .email("ann@example.org")
+        .password("changed")
+        .role_admin()
+        .inactive()
+        .build();
+    changed.avatar_url = Some("/avatars/ann.png".to_string());
+    assert_eq!(repository.update(changed.clone()).await.unwrap(), changed);
+    assert_eq!(
+        repository.find_by_id("ann").await.unwrap(),
+        Some(changed.clone())
+    );
+    assert_eq!(
+        repository.find_by_email("ann@example.com").await.unwrap(),
+        None
+    );
+
+    let ghost = UserBuilder::named("ghost").build();
+    assert!(
+        matches!(repository.update(ghost).await, Err(Error::NotFound)),
+        "an update to a user that isn't there"
+    );
+    assert_eq!(repository.find_by_id("ghost").await.unwrap(), None);
+
+    let taken = UserBuilder::named("ann").email("bob@example.com").build();
+    assert!(
+        matches!(repository.update(taken).await, Err(Error::AlreadyExists)),
+        "an update to a taken email"
+    );
+    assert_eq!(repository.find_by_id("ann").await.unwrap(), Some(changed));
+}
+
+async fn filters(repository: &impl UserRepository) {
+    for user in [
+        UserBuilder::named("ann").build(),
+        UserBuilder::named("bob").role_admin().build(),
+        UserBuilder::named("carol").inactive().build(),
+        UserBuilder::named("dave").role_admin().inactive().build(),
+        UserBuilder::named("erin")
+            .email("Erin_Smith@Example.com")
+            .build(),
+    ] {
+        repository.create(user).await.unwrap();
+    }
+    let ids = |filter: UserFilter| async move {
+        let page = repository
+            .list(&filter, PageRequest::new(1, 10))
+            .await
+            .unwrap();
+        assert_eq!(
+            page.total,
+            page.items.len() as u64,
+            "total for {:?}",
+            filter
+        );
+        page.items
+            .into_iter()
+            .map(|user| user.id)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(ids(UserFilter::default()).await.len(), 5);
+    let admins = UserFilter {
+        role: Some("admin".to_string()),
+        ..UserFilter::default()
+    };
+    assert_eq!(ids(admins.clone()).await, ["bob", "dave"]);
+    let inactive = UserFilter {
+        active: Some(false),
+        ..UserFilter::default()
+    };
+    assert_eq!(ids(inactive).await, ["carol", "dave"]);
+    // Set fields combine with AND
+    let active_admins = UserFilter {
+        active: Some(true),
+        ..admins
+    };
+    assert_eq!(ids(active_admins).await, ["bob"]);
+
+    // A case-insensitive substring, in which _ and % mean themselves
+    let email = |email: &str| UserFilter {
+        email: Some(email.to_string()),
+        ..UserFilter::default()
+    };
+    assert_eq!(ids(email("AR")).await, ["carol"]);
+    assert_eq!(ids(email("erin_smith@example")).await, ["erin"]);
+    assert_eq!(ids(email("_")).await, ["erin"]);
+    assert!(ids(email("%")).await.is_empty());
+}
+
+async fn pages_in_insertion_order(repository: &impl UserRepository) {
+    // Not alphabetical, so an ORDER BY id would show
+    for name in ["erin", "bob", "dave", "ann", "carol"] {
+        repository
+            .create(UserBuilder::named(name).build())
+            .await
+            .unwrap();
+    }
+    let page = |number, size| async move {
+        let page = repository
+            .list(&UserFilter::default(), PageRequest::new(number, size))
+            .await
+            .unwrap();
+        assert_eq!((page.page, page.per_page), (number, size));
+        let ids: Vec<String> = page.items.into_iter().map(|user| user.id).collect();
+        (ids, page.total)
+    };
+
+    assert_eq!(page(1, 2).await, (vec!["erin".into(), "bob".into()], 5));
+    assert_eq!(page(2, 2).await, (vec!["dave".into(), "ann".into()], 5));
+    assert_eq!(page(3, 2).await, (vec!["carol".into()], 5));
+    // Past the end: no items, but still the total
+    assert_eq!(page(4, 2).await, (Vec::new(), 5));
+}
+
+// What every AuditRepository must do, the same way: a factory that makes a
+// new, empty repository, one per case.
+pub async fn run_audit_conformance<R, F, Fut>(factory: F)
+where
+    R: AuditRepository,
+    F: Fn() -> Fut,
+    Fut: Future<Output = R>,
+{
+    stores_every_field(&factory().await).await;
+    filters_entries(&factory().await).await;
+    pages_newest_first(&factory().await).await;
+}
+
+fn entry(at: u64, actor: &str, action: &str, target: &str) -> AuditEntry {
+    AuditEntry {
+        at,
+        actor: actor.to_string(),
+        action: action.to_string(),
+        target: target.to_string(),
+        detail: String::new(),
+        ip: None,
+    }
+}
+
+async fn stores_every_field(repository: &impl AuditRepository) {
+    let with_ip = AuditEntry {
+        detail: "user -> admin".to_string(),
+        ip: Some("10.0.0.1".to_string()),
+        ..entry(1_700_000_000, "admin", "role_changed", "ann")
+    };
+    let without_ip = entry(1_700_000_001, "admin", "deactivated", "ann");
+    repository.append(with_ip.clone()).await.unwrap();
+    repository.append(without_ip.clone()).await.unwrap();
+
+    let page = repository
+        .query(&AuditFilter::default(), PageRequest::new(1, 10))
+        .await
+        .unwrap();
+    assert_eq!(page.items, [without_ip, with_ip]);
+    assert_eq!(page.total, 2);
+}
+
+async fn filters_entries(repository: &impl AuditRepository) {
+    for entry in [
+        entry(1, "admin", "role_changed", "ann"),
+        entry(2, "admin", "deactivated", "bob"),
+        entry(3, "ann", "role_changed", "bob"),
+        entry(4, "bob", "deactivated", "carol"),
+    ] {
+        repository.append(entry).await.unwrap();
+    }
+    let times = |filter: AuditFilter| async move {
+        let page = repository
+            .query(&filter, PageRequest::new(1, 10))
+            .await
+            .unwrap();
+        assert_eq!(
+            page.total,
+            page.items.len() as u64,
+            "total for {:?}",
+            filter
+        );
+        page.items
+            .into_iter()
+            .map(|entry| entry.at)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(times(AuditFilter::default()).await, [4, 3, 2, 1]);
+    let by_admin = AuditFilter {
+        actor: Some("admin".to_string()),
+        ..AuditFilter::default()
+    };
+    assert_eq!(times(by_admin.clone()).await, [2, 1]);
+    let on_bob = AuditFilter {
+        target: Some("bob".to_string()),
+        ..AuditFilter::default()
+    };
+    assert_eq!(times(on_bob).await, [3, 2]);
+    // `user` is either side
+    let ann = AuditFilter {
+        user: Some("ann".to_string()),
+        ..AuditFilter::default()
+    };
+    assert_eq!(times(ann).await, [3, 1]);
+    // Set fields combine with AND
+    let admin_deactivations = AuditFilter {
+        action: Some("deactivated".to_string()),
+        ..by_admin
+    };
+    assert_eq!(times(admin_deactivations).await, [2]);
+    let nobody = AuditFilter {
+        user: Some("dave".to_string()),
+        ..AuditFilter::default()
+    };
+    assert!(times(nobody).await.is_empty());
+}
+
+async fn pages_newest_first(repository: &impl AuditRepository) {
+    // Two in the same second: the later append still comes first
+    for (at, target) in [(10, "ann"), (20, "bob"), (20, "carol"), (30, "dave")] {
+        repository
+            .append(entry(at, "admin", "deactivated", target))
+            .await
+            .unwrap();
+    }
+    let page = |number, size| async move {
+        let page = repository
+            .query(&AuditFilter::default(), PageRequest::new(number, size))
+            .await
+            .unwrap();
+        assert_eq!((page.page, page.per_page), (number, size));
+        let targets: Vec<String> = page.items.into_iter().map(|entry| entry.target).collect();
+        (targets, page.total)
+    };
+
+    assert_eq!(
+        page(1, 3).await,
+        (vec!["dave".into(), "carol".into(), "bob".into()], 4)
+    );
+    assert_eq!(page(2, 3).await, (vec!["ann".into()], 4));
+    // Past the end: no items, but still the total
+    assert_eq!(page(3, 3).await, (Vec::new(), 4));
+}
@@ -0,0 +1,43 @@
+// Backends That Are Down
+// ======================
+
+use async_trait::async_trait;
+use tutorial_core::{CacheService, Error, HealthCheck, Lifecycle};
+
+// A dependency that's down, for readiness checks
+pub struct DownCheck;
+
+#[async_trait]
+impl HealthCheck for DownCheck {
+    async fn check(&self) -> Result<(), Error> {
+        Err(Error::internal("connection refused"))
+    }
+}
+
+// A cache that's down: every read misses, writes go nowhere, and its
+// health check fails
+pub struct DownCache;
+
+#[async_trait]
+impl CacheService for DownCache {
+    async fn get(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    async fn set(&self, _key: &str, _value: String, _ttl_seconds: Option<u64>) {}
+
+    async fn delete(&self, _key: &str) {}
+
+    async fn incr(&self, _key: &str, _by: i64, _ttl_seconds: Option<u64>) -> i64 {
+        0
+    }
+}
+
+#[async_trait]
+impl HealthCheck for DownCache {
+    async fn check(&self) -> Result<(), Error> {
+        Err(Error::internal("connection refused"))
+    }
+}
+
+impl Lifecycle for DownCache {}
@@ -0,0 +1,21 @@
+//! What the dependency_inversion tests share: test data builders, log
+//! capture, backends that are down, and the conformance suites every
+//! UserRepository and AuditRepository runs. Everything here is written against tutorial-core's
+//! model and traits, so any example can use it, whatever backends it has.
+//!
+//! ```toml
+//! [dev-dependencies]
+//! test-support.workspace = true
+//! ```
+//!
+//! and in a test module, `use test_support::*;`.
+
+mod builders;
+mod conformance;
+mod down;
+mod logs;
+
+pub use builders::{SessionBuilder, TokenBuilder, UserBuilder};
+pub use conformance::{run_audit_conformance, run_repository_conformance};
+pub use down::{DownCache, DownCheck};
+pub use logs::CapturedLogs;
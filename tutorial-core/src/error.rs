@@ -0,0 +1,326 @@
+// Errors
+// ======
+//
+// One Error for every layer below main(). Domain answers (NotFound,
+// AlreadyExists...) are variants callers match on; everything else is
+// Internal, with the cause kept whole as its source.
+
+use crate::ValidationError;
+use serde::Serialize;
+use std::backtrace::{Backtrace, BacktraceStatus};
+
+// Causes are boxed so Error is the same type whichever drivers
+// (sqlx, redis, jsonwebtoken) the features compile in
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("resource not found")]
+    NotFound,
+    #[error("resource already exists")]
+    AlreadyExists,
+    #[error("invalid email or password")]
+    InvalidCredentials,
+    // Input that breaks a rule (the password policy, say), every broken
+    // rule at once so the client can fix them in one go
+    #[error("{} invalid field(s)", .0.len())]
+    Validation(Vec<ValidationError>),
+    // Our side broke. `message` says what we were doing; `source` is the
+    // error that stopped us, kept whole instead of formatted into a string
+    #[error("{message}")]
+    Internal {
+        message: String,
+        #[source]
+        source: Option<BoxError>,
+        failure: Failure,
+        // Where it was made. Only captured with RUST_BACKTRACE=1 (or
+        // RUST_LIB_BACKTRACE=1); otherwise free. Boxed to keep Error small.
+        trace: Box<Backtrace>,
+    },
+}
+
+// Whether the same call could work if made again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    // Timeouts, dropped connections, deadlocks, an open circuit: the
+    // backend is struggling, and a later attempt may well get through
+    Transient,
+    // Bad keys, broken invariants, and every domain answer (NotFound,
+    // AlreadyExists...): retrying gets the same result, only slower
+    Permanent,
+}
+
+impl Error {
+    // Nothing underneath: a timeout, an open circuit, a bad key.
+    // Permanent unless marked `.transient()`
+    pub fn internal(message: impl Into<String>) -> Self {
+        Error::Internal {
+            message: message.into(),
+            source: None,
+            failure: Failure::Permanent,
+            trace: Box::new(Backtrace::capture()),
+        }
+    }
+
+    pub fn with_source(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Error::Internal {
+            message: message.into(),
+            source: Some(source.into()),
+            failure: Failure::Permanent,
+            trace: Box::new(Backtrace::capture()),
+        }
+    }
+
+    // Says what we were doing when an Internal error happened, keeping the
+    // error as the source: "creating user: database error: ...". Domain
+    // errors pass through untouched, since callers match on them.
+    pub fn context(self, what: impl Into<String>) -> Self {
+        match self {
+            error @ Error::Internal { .. } => Error::Internal {
+                message: what.into(),
+                failure: error.failure(),
+                source: Some(Box::new(error)),
+                // The one underneath already knows where it was made
+                trace: Box::new(Backtrace::disabled()),
+            },
+            error => error,
+        }
+    }
+
+    // Where the innermost Internal error was made, if it was captured
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        let Error::Internal { source, trace, .. } = self else {
+            return None;
+        };
+        if trace.status() == BacktraceStatus::Captured {
+            return Some(trace);
+        }
+        source.as_deref()?.downcast_ref::<Error>()?.backtrace()
+    }
+
+    // `Error::internal("postgres: timed out").transient()`
+    pub fn transient(mut self) -> Self {
+        if let Error::Internal { failure, .. } = &mut self {
+            *failure = Failure::Transient;
+        }
+        self
+    }
+
+    pub fn failure(&self) -> Failure {
+        match self {
+            Error::Internal { failure, .. } => *failure,
+            _ => Failure::Permanent,
+        }
+    }
+
+    // What RetryPolicy and CircuitBreaker ask before trying again or
+    // counting a failure against the backend
+    pub fn is_retryable(&self) -> bool {
+        self.failure() == Failure::Transient
+    }
+
+    // A database failure with no more specific meaning; what
+    // From<sqlx::Error> falls back to, and what lazy connects report
+    pub fn database(source: impl Into<BoxError>) -> Self {
+        Self::with_source("database error", source)
+    }
+
+    // The message and every cause under it, for the log:
+    // "creating uploads/a: Permission denied (os error 13)"
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            report.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        report
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::NotFound => ErrorCode::NotFound,
+            Error::AlreadyExists => ErrorCode::AlreadyExists,
+            Error::InvalidCredentials => ErrorCode::InvalidCredentials,
+            Error::Validation(_) => ErrorCode::ValidationFailed,
+            Error::Internal { .. } => ErrorCode::Internal,
+        }
+    }
+}
+
+// Stable codes for clients and tests to branch on. Messages get reworded;
+// a code keeps its meaning once shipped. Domain errors get theirs from
+// Error::code(); the rest are for errors the transports raise themselves
+// (a missing token, a malformed body, a rate limit).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    // Who you are and what you may do
+    InvalidCredentials,
+    MissingCredentials,
+    InvalidToken,
+    Forbidden,
+    CsrfFailed,
+    // The thing asked about
+    NotFound,
+    AlreadyExists,
+    // The request itself
+    InvalidBody,
+    InvalidQuery,
+    ValidationFailed,
+    UnsupportedMediaType,
+    MissingFile,
+    RateLimited,
+    // Our side
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidCredentials => "AUTH001_INVALID_CREDENTIALS",
+            ErrorCode::MissingCredentials => "AUTH002_MISSING_CREDENTIALS",
+            ErrorCode::InvalidToken => "AUTH003_INVALID_TOKEN",
+            ErrorCode::Forbidden => "AUTH004_FORBIDDEN",
+            ErrorCode::CsrfFailed => "AUTH005_CSRF_FAILED",
+            ErrorCode::NotFound => "RES001_NOT_FOUND",
+            ErrorCode::AlreadyExists => "RES002_ALREADY_EXISTS",
+            ErrorCode::InvalidBody => "REQ001_INVALID_BODY",
+            ErrorCode::InvalidQuery => "REQ002_INVALID_QUERY",
+            ErrorCode::ValidationFailed => "REQ003_VALIDATION_FAILED",
+            ErrorCode::UnsupportedMediaType => "REQ004_UNSUPPORTED_MEDIA_TYPE",
+            ErrorCode::MissingFile => "REQ005_MISSING_FILE",
+            ErrorCode::RateLimited => "REQ006_RATE_LIMITED",
+            ErrorCode::Internal => "SYS001_INTERNAL",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// Driver errors convert themselves, so a repository method is a query and
+// a `?`. Each impl decides once what a driver error means for the domain;
+// anything it doesn't recognise is Internal, with the driver error kept as
+// the source.
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for Error {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            // fetch_one found nothing
+            sqlx::Error::RowNotFound => Error::NotFound,
+            // The UNIQUE index on users.email, not a prior SELECT, is what
+            // stops two concurrent registrations with one address
+            sqlx::Error::Database(db) if db.is_unique_violation() => Error::AlreadyExists,
+            // Serialization failure and deadlock: Postgres itself says retry
+            sqlx::Error::Database(db)
+                if matches!(db.code().as_deref(), Some("40001" | "40P01")) =>
+            {
+                Error::database(error).transient()
+            }
+            sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => Error::database(error).transient(),
+            // Other constraint violations, bad SQL, decode errors
+            _ => Error::database(error),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for Error {
+    fn from(error: redis::RedisError) -> Self {
+        let transient = error.is_timeout()
+            || error.is_connection_dropped()
+            || error.is_connection_refusal()
+            || error.is_io_error();
+        let error = Error::with_source("cache error", error);
+        if transient { error.transient() } else { error }
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        use jsonwebtoken::errors::ErrorKind;
+        match error.kind() {
+            // The token is the client's problem: expired, tampered with,
+            // not yet valid, or not a JWT at all
+            ErrorKind::InvalidToken
+            | ErrorKind::InvalidSignature
+            | ErrorKind::ExpiredSignature
+            | ErrorKind::ImmatureSignature
+            | ErrorKind::InvalidAudience
+            | ErrorKind::InvalidIssuer
+            | ErrorKind::InvalidSubject
+            | ErrorKind::MissingRequiredClaim(_)
+            | ErrorKind::InvalidAlgorithm
+            | ErrorKind::Base64(_)
+            | ErrorKind::Json(_)
+            | ErrorKind::Utf8(_) => Error::InvalidCredentials,
+            // Bad keys and crypto failures are ours
+            _ => Error::with_source("token error", error),
+        }
+    }
+}
+
+// `.context("creating user")?` instead of a bare `?` in the service, so
+// the log says where an Internal error came from, not just the leaf
+// message. A trait of its own, imported where it is used, so main() can use
+// anyhow::Context without the two `.context`s clashing.
+pub trait ErrorContext<T> {
+    fn context(self, what: &'static str) -> Result<T, Error>;
+    // For a message that costs something to build
+    fn with_context(self, what: impl FnOnce() -> String) -> Result<T, Error>;
+}
+
+impl<T> ErrorContext<T> for Result<T, Error> {
+    fn context(self, what: &'static str) -> Result<T, Error> {
+        self.map_err(|error| error.context(what))
+    }
+
+    fn with_context(self, what: impl FnOnce() -> String) -> Result<T, Error> {
+        self.map_err(|error| error.context(what()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_keeps_the_cause_and_the_failure() {
+        let error = Error::internal("connection refused")
+            .transient()
+            .context("loading user");
+        assert_eq!(error.report(), "loading user: connection refused");
+        assert!(error.is_retryable());
+        assert_eq!(error.code(), ErrorCode::Internal);
+    }
+
+    #[test]
+    fn test_domain_errors_pass_through_context() {
+        let result: Result<(), Error> = Err(Error::NotFound);
+        let error = result.context("loading user").unwrap_err();
+        assert!(matches!(error, Error::NotFound));
+        assert_eq!(error.failure(), Failure::Permanent);
+        assert_eq!(error.code().to_string(), "RES001_NOT_FOUND");
+    }
+
+    #[test]
+    fn test_validation_lists_every_field() {
+        let error = Error::Validation(vec![
+            ValidationError::new("email", "email", "must be a valid email address"),
+            ValidationError::new("password", "length", "must be 8 to 128 characters"),
+        ]);
+        assert_eq!(error.to_string(), "2 invalid field(s)");
+        assert_eq!(error.code(), ErrorCode::ValidationFailed);
+    }
+}
@@ -0,0 +1,23 @@
+//! What the dependency_inversion examples and the day_* samples share: the
+//! domain model, the error type, and the traits services depend on.
+//!
+//! Only the abstractions live here. The implementations (Postgres, Redis,
+//! the in-memory fakes) stay in the examples that teach them.
+//!
+//! ```toml
+//! [dependencies]
+//! tutorial-core.workspace = true
+//! ```
+
+mod error;
+mod model;
+mod traits;
+mod validation;
+
+pub use error::{BoxError, Error, ErrorCode, ErrorContext, Failure};
+pub use model::{AuditEntry, AuditFilter, Page, PageRequest, User, UserFilter};
+pub use traits::{
+    AuditRepository, CacheService, CacheServiceExt, Clock, HealthCheck, IdGenerator, Lifecycle,
+    Metrics, PasswordHasher, SessionStore, StorageService, TokenService, UserRepository,
+};
+pub use validation::ValidationError;
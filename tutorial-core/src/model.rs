@@ -0,0 +1,172 @@
+// Models
+// ======
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub password_hash: String,
+    pub role: String,
+    // Deactivated users can't log in, and their tokens stop working
+    pub active: bool,
+    // Where StorageService put their avatar, if they uploaded one
+    pub avatar_url: Option<String>,
+}
+
+// One page of a listing. Pages are 1-based, and the size is clamped
+// here so no caller can ask a repository for a million rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl PageRequest {
+    pub const DEFAULT_PER_PAGE: u32 = 20;
+    pub const MAX_PER_PAGE: u32 = 100;
+
+    pub fn new(page: u32, per_page: u32) -> Self {
+        Self {
+            page: page.max(1),
+            per_page: per_page.clamp(1, Self::MAX_PER_PAGE),
+        }
+    }
+
+    // Rows to skip (SQL OFFSET)
+    pub fn offset(&self) -> usize {
+        (self.page as usize - 1) * self.per_page as usize
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self::new(1, Self::DEFAULT_PER_PAGE)
+    }
+}
+
+// What a listing is narrowed down to. Set fields combine with AND; the
+// default matches everyone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserFilter {
+    pub role: Option<String>,
+    pub active: Option<bool>,
+    // Case-insensitive substring
+    pub email: Option<String>,
+}
+
+impl UserFilter {
+    // For backends that filter in memory; SQL ones turn it into a WHERE
+    pub fn matches(&self, user: &User) -> bool {
+        self.role.as_ref().is_none_or(|role| &user.role == role)
+            && self.active.is_none_or(|active| user.active == active)
+            && self
+                .email
+                .as_ref()
+                .is_none_or(|email| user.email.to_lowercase().contains(&email.to_lowercase()))
+    }
+}
+
+// Which audit entries to show. Set fields combine with AND, like
+// UserFilter; `user` is either side, so `user: Some(id)` is everything
+// that account did or had done to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub user: Option<String>,
+    pub action: Option<String>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor
+            .as_ref()
+            .is_none_or(|actor| &entry.actor == actor)
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|target| &entry.target == target)
+            && self
+                .user
+                .as_ref()
+                .is_none_or(|user| &entry.actor == user || &entry.target == user)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| &entry.action == action)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub per_page: u32,
+    // Across all pages, so clients can render "page 2 of 7"
+    pub total: u64,
+}
+
+impl<T> Page<T> {
+    pub fn empty(request: PageRequest) -> Self {
+        Self {
+            items: Vec::new(),
+            page: request.page,
+            per_page: request.per_page,
+            total: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    // Unix seconds
+    pub at: u64,
+    // User id of the admin
+    pub actor: String,
+    pub action: String,
+    // User id of the account changed
+    pub target: String,
+    pub detail: String,
+    pub ip: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(email: &str, role: &str, active: bool) -> User {
+        User {
+            id: email.to_string(),
+            email: email.to_string(),
+            password_hash: String::new(),
+            role: role.to_string(),
+            active,
+            avatar_url: None,
+        }
+    }
+
+    #[test]
+    fn test_page_request_is_clamped() {
+        assert_eq!(PageRequest::new(0, 0), PageRequest::new(1, 1));
+        assert_eq!(
+            PageRequest::new(3, 1000).per_page,
+            PageRequest::MAX_PER_PAGE
+        );
+        assert_eq!(PageRequest::new(3, 20).offset(), 40);
+    }
+
+    #[test]
+    fn test_user_filter_combines_fields() {
+        let filter = UserFilter {
+            role: Some("admin".to_string()),
+            email: Some("EXAMPLE".to_string()),
+            ..UserFilter::default()
+        };
+        assert!(filter.matches(&user("an@example.com", "admin", false)));
+        assert!(!filter.matches(&user("an@example.com", "user", true)));
+        assert!(!filter.matches(&user("an@test.org", "admin", true)));
+        assert!(UserFilter::default().matches(&user("an@test.org", "user", false)));
+    }
+}
@@ -0,0 +1,223 @@
+// Abstractions
+// ============
+//
+// The traits services depend on. Each example implements them for its own
+// backends and fakes.
+
+use crate::{AuditEntry, AuditFilter, Error, Page, PageRequest, User, UserFilter};
+use async_trait::async_trait;
+use serde::{Serialize, de::DeserializeOwned};
+use std::future::Future;
+use std::sync::Arc;
+
+// Anything that talks to the outside world can report whether it's
+// reachable. Err carries the reason; HealthService turns it into the
+// text of the readiness report.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    async fn check(&self) -> Result<(), Error>;
+}
+
+// Lets an `Arc<dyn UserRepository>` be handed to HealthService as-is
+#[async_trait]
+impl<T: HealthCheck + ?Sized> HealthCheck for Arc<T> {
+    async fn check(&self) -> Result<(), Error> {
+        (**self).check().await
+    }
+}
+
+// Components that hold resources (pools, connections, background tasks)
+// get a chance to set them up before traffic and to release them on exit.
+// Both default to no-ops so stateless implementations opt out for free.
+#[async_trait]
+pub trait Lifecycle: Send + Sync {
+    async fn start(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Lifecycle + ?Sized> Lifecycle for Arc<T> {
+    async fn start(&self) -> Result<(), Error> {
+        (**self).start().await
+    }
+
+    async fn shutdown(&self) -> Result<(), Error> {
+        (**self).shutdown().await
+    }
+}
+
+#[async_trait]
+pub trait UserRepository: HealthCheck + Lifecycle {
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error>;
+    async fn find_by_id(&self, id: &str) -> Result<Option<User>, Error>;
+    async fn list(&self, filter: &UserFilter, page: PageRequest) -> Result<Page<User>, Error>;
+    async fn create(&self, user: User) -> Result<User, Error>;
+    async fn update(&self, user: User) -> Result<User, Error>;
+}
+
+// Append-only: nothing here changes or deletes an entry
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    async fn append(&self, entry: AuditEntry) -> Result<(), Error>;
+    // Newest first
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        page: PageRequest,
+    ) -> Result<Page<AuditEntry>, Error>;
+}
+
+#[async_trait]
+pub trait PasswordHasher: Send + Sync {
+    fn hash(&self, password: &str) -> String;
+    fn verify(&self, password: &str, hash: &str) -> bool;
+}
+
+#[async_trait]
+pub trait TokenService: HealthCheck {
+    async fn generate(&self, user_id: &str) -> Result<String, Error>;
+    async fn validate(&self, token: &str) -> Result<String, Error>;
+}
+
+// Somewhere to keep uploaded files. `put` overwrites and returns the URL
+// clients fetch the file from; deleting a missing key is not an error.
+#[async_trait]
+pub trait StorageService: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, bytes: Vec<u8>) -> Result<String, Error>;
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+}
+
+// Server-side sessions: an opaque id the client holds (in a cookie) and
+// the user id it stands for. Unlike a token, deleting the entry ends the
+// session everywhere at once.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn create(&self, user_id: &str, ttl_seconds: u64) -> Result<String, Error>;
+    // None for unknown and expired ids
+    async fn get(&self, session_id: &str) -> Result<Option<String>, Error>;
+    async fn delete(&self, session_id: &str) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait CacheService: HealthCheck + Lifecycle {
+    async fn get(&self, key: &str) -> Option<String>;
+    async fn set(&self, key: &str, value: String, ttl_seconds: Option<u64>);
+    async fn delete(&self, key: &str);
+
+    // Batch operations: one round-trip instead of N. The defaults just
+    // loop; backends that can do better (MGET, a pipeline) override them.
+    async fn get_many(&self, keys: &[&str]) -> Vec<Option<String>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key).await);
+        }
+        values
+    }
+
+    async fn set_many(&self, entries: &[(&str, String, Option<u64>)]) {
+        for (key, value, ttl_seconds) in entries {
+            self.set(key, value.clone(), *ttl_seconds).await;
+        }
+    }
+
+    // Adds `by` and returns the new value, atomically (Redis INCRBY), so
+    // two instances counting the same key never lose a hit. The TTL is
+    // set when the counter is created and not extended afterwards. A
+    // value that isn't a number is left alone and 0 comes back, which is
+    // how RedisCache reads the error Redis gives for it.
+    // There is no default: a get + set fallback would lose updates.
+    async fn incr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64;
+
+    async fn decr(&self, key: &str, by: i64, ttl_seconds: Option<u64>) -> i64 {
+        self.incr(key, -by, ttl_seconds).await
+    }
+
+    // Whole seconds until `key` expires (Redis TTL), so a copy of it can be
+    // made not to outlive it. None for a missing key, a key without a TTL,
+    // and by default: a backend that can't tell says so.
+    async fn ttl(&self, _key: &str) -> Option<u64> {
+        None
+    }
+}
+
+// Cache-aside in one call, available on every CacheService. Public with
+// a plain `async fn`, which the lint below warns about: callers can't
+// require the future to be Send. Nobody needs to; it's awaited in place.
+#[allow(async_fn_in_trait)]
+pub trait CacheServiceExt: CacheService {
+    // Values are stored as JSON, so any serde type works
+    async fn set_json<T: Serialize + Sync>(&self, key: &str, value: &T, ttl_seconds: Option<u64>) {
+        if let Ok(json) = serde_json::to_string(value) {
+            self.set(key, json, ttl_seconds).await;
+        }
+    }
+
+    async fn get_or_set_with<T, E, F, Fut>(
+        &self,
+        key: &str,
+        ttl_seconds: Option<u64>,
+        load: F,
+    ) -> Result<Option<T>, E>
+    where
+        T: Serialize + DeserializeOwned + Sync,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<T>, E>>,
+    {
+        // A value we can't decode is treated as a miss
+        if let Some(cached) = self.get(key).await
+            && let Ok(value) = serde_json::from_str(&cached)
+        {
+            return Ok(Some(value));
+        }
+
+        let value = load().await?;
+        if let Some(ref value) = value {
+            self.set_json(key, value, ttl_seconds).await;
+        }
+
+        Ok(value)
+    }
+}
+
+impl<T: CacheService + ?Sized> CacheServiceExt for T {}
+
+// Numbers about what the app is doing, recorded as it happens. Names
+// follow Prometheus (`auth_logins_total`, `..._seconds`); labels are a
+// few fixed keys with a small set of values each: an email or a user
+// ID as a label value makes a new series per user.
+pub trait Metrics: Send + Sync {
+    // Only goes up: logins, registrations
+    fn counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64);
+    // The latest value wins: open connections, queue depth
+    fn gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64);
+    // A distribution of observations: durations, sizes
+    fn histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64);
+}
+
+// What time it is, and waiting. Whatever expires or runs on a schedule
+// (tokens, cache entries, rate-limit windows, background tasks) asks
+// this instead of the OS, so a test can move time on with
+// MockClock::advance instead of sleeping through it.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> std::time::SystemTime;
+    async fn sleep(&self, duration: std::time::Duration);
+
+    // For TTLs and windows, which count in whole seconds
+    fn unix_seconds(&self) -> u64 {
+        self.now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+// Where new records get their IDs, so a test can know them in advance
+pub trait IdGenerator: Send + Sync {
+    fn next_id(&self) -> String;
+}
@@ -0,0 +1,42 @@
+// Validation Errors
+// =================
+//
+// One shape for "this input broke a rule", whoever checked it: the
+// password policy in AuthService, a DTO's #[validate] rules in http.rs, or
+// the config loader.
+//
+//     { "field": "password", "code": "length", "message": "must be 8 to 128 characters" }
+//
+// `field` names the input (a JSON field, a config key), `code` says which
+// rule failed and is stable for clients to match on, `message` is for
+// people and may be reworded.
+
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            field: field.into(),
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}